@@ -0,0 +1,66 @@
+//! Checks on resources after their actors abandon writes to them on purpose
+//! (`--abandon-requests`), as a client that disconnects before the response
+//! arrives would.
+//!
+//! An abandoned write may or may not have taken effect, so the actor can't
+//! know what state its resource should be in. What it can check is that the
+//! resource doesn't get stuck: whatever the write did, its saga should finish
+//! (or unwind) without the client around, leaving the resource out of any
+//! transitional state within `--abandon-settle-timeout`. Whether the resource
+//! should exist at all is checked by the expected-state model
+//! (`--check-model`), which treats abandoned creates and deletes as maybe
+//! having happened.
+
+use std::fmt::Debug;
+use std::future::Future;
+use std::time::{Duration, Instant};
+
+use tracing::{info, trace};
+
+use super::AntagonistError;
+
+/// How long to wait between checks on a resource that hasn't settled.
+const POLL_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Polls `resource` with `poll` until `settled` says that it's settled, after
+/// a write to `endpoint` was abandoned. Returns an error if it's still
+/// unsettled after --abandon-settle-timeout.
+pub(super) async fn settle<S, F>(
+    endpoint: &'static str,
+    resource: &str,
+    mut poll: impl FnMut() -> F,
+    settled: impl Fn(Option<&S>) -> bool,
+) -> Result<(), AntagonistError>
+where
+    S: Debug,
+    F: Future<Output = Result<Option<S>, AntagonistError>>,
+{
+    let timeout = crate::config().abandon_settle_timeout;
+    let start = Instant::now();
+    loop {
+        let state = poll().await?;
+        if settled(state.as_ref()) {
+            info!(
+                endpoint,
+                resource,
+                ?state,
+                elapsed = ?start.elapsed(),
+                "resource settled after abandoned request"
+            );
+            return Ok(());
+        }
+
+        if start.elapsed() >= timeout {
+            return Err(AntagonistError::CheckFailed(format!(
+                "{} was still {:?} {:?} after an abandoned {}",
+                resource,
+                state.expect("missing resources are settled"),
+                timeout,
+                endpoint
+            )));
+        }
+
+        trace!(endpoint, resource, ?state, "waiting for resource to settle");
+        crate::util::nap(POLL_INTERVAL).await;
+    }
+}
@@ -0,0 +1,168 @@
+//! The backing-disk manager, which hands snapshot antagonists the disks they
+//! snapshot.
+//!
+//! An antagonist takes out a lease on its disk when it's created. The first
+//! lease holder to find the disk missing creates it while any others sharing
+//! it wait, so that antagonists sharing a disk don't race to create it. The
+//! manager counts each disk's lease holders, and the last one to drain deletes
+//! the disk.
+
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex, OnceLock};
+
+use oxide::types::{BlockSize, ByteCount, DiskCreate, DiskSource, Name};
+use oxide::{ClientDisksExt, ResponseValue};
+use tracing::info;
+
+use crate::util::{ok_if_not_found, unwrap_oxide_api_error, OxideApiError};
+
+/// Returns the name of the backing disk for snapshot `snapshot` in a group
+/// whose disks are named after `disk_name`, with all names starting with
+/// `prefix`. If `thread` is set, the disk is that thread's own; otherwise, all
+/// the snapshot's threads share it.
+pub fn name(
+    prefix: &str,
+    disk_name: &str,
+    snapshot: usize,
+    thread: Option<usize>,
+) -> String {
+    match thread {
+        Some(thread) => {
+            format!("{}{}{}{}", prefix, disk_name, snapshot, thread)
+        }
+        None => format!("{}{}{}", prefix, disk_name, snapshot),
+    }
+}
+
+/// A backing disk that some antagonists hold leases on.
+#[derive(Default)]
+struct BackingDisk {
+    /// How many leases there are on the disk.
+    users: usize,
+
+    /// Held by the lease holder that's checking for the disk, and creating it
+    /// if it's missing.
+    creating: Arc<tokio::sync::Mutex<()>>,
+}
+
+static DISKS: OnceLock<Mutex<HashMap<String, BackingDisk>>> = OnceLock::new();
+
+fn disks() -> &'static Mutex<HashMap<String, BackingDisk>> {
+    DISKS.get_or_init(Default::default)
+}
+
+/// An antagonist's lease on a backing disk. The lease is given up when this
+/// is released or dropped, including when an actor task unwinds from a panic.
+#[derive(Debug)]
+pub(super) struct Lease {
+    name: String,
+    released: bool,
+}
+
+impl Lease {
+    /// Takes out a lease on the backing disk named `name`.
+    pub(super) fn new(name: String) -> Self {
+        disks().lock().unwrap().entry(name.clone()).or_default().users += 1;
+        Self { name, released: false }
+    }
+
+    /// Returns the name of the leased disk.
+    pub(super) fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Makes sure the leased disk exists in `project`, creating it if it
+    /// doesn't.
+    pub(super) async fn ensure(
+        &self,
+        client: &oxide::Client,
+        project: &str,
+    ) -> Result<(), OxideApiError> {
+        let creating = disks()
+            .lock()
+            .unwrap()
+            .entry(self.name.clone())
+            .or_default()
+            .creating
+            .clone();
+        let _creating = creating.lock().await;
+
+        let res = crate::middleware::call("disk_view", || {
+            client.disk_view().project(project).disk(&self.name).send()
+        })
+        .await;
+        match res {
+            Ok(_) => return Ok(()),
+            Err(oxide::Error::ErrorResponse(rv))
+                if rv.status() == http::StatusCode::NOT_FOUND => {}
+            Err(e) => return Err(e),
+        }
+
+        let body = DiskCreate {
+            description: crate::run_info::description(&self.name),
+            disk_source: DiskSource::Blank {
+                block_size: BlockSize::try_from(512_i64).unwrap(),
+            },
+            name: Name::try_from(&self.name).unwrap(),
+            size: ByteCount::from(1024 * 1024 * 1024_u64),
+        };
+
+        info!(body = ?body, "sending disk create request");
+        let res = crate::middleware::call("disk_create", || {
+            client.disk_create().project(project).body(body.clone()).send()
+        })
+        .await;
+        unwrap_oxide_api_error(res)
+    }
+
+    /// Gives up the lease. Returns true if it was the last one on the disk.
+    pub(super) fn release(mut self) -> bool {
+        self.released = true;
+        release(&self.name)
+    }
+
+    /// Gives up the lease and, if it was the last one on the disk, deletes
+    /// the disk from `project`.
+    pub(super) async fn release_and_delete(
+        self,
+        client: &oxide::Client,
+        project: &str,
+    ) -> Result<(), OxideApiError> {
+        let name = self.name.clone();
+        if !self.release() {
+            return Ok(());
+        }
+
+        info!(disk_name = name, "deleting backing disk");
+        let res = crate::middleware::call("disk_delete", || {
+            client.disk_delete().project(project).disk(&name).send()
+        })
+        .await;
+        ok_if_not_found(res.map(|_: ResponseValue<()>| ()))
+    }
+}
+
+impl Drop for Lease {
+    fn drop(&mut self) {
+        if !self.released {
+            release(&self.name);
+        }
+    }
+}
+
+/// Gives up a lease on the disk named `name`. Returns true if it was the last
+/// one.
+fn release(name: &str) -> bool {
+    let mut disks = disks().lock().unwrap();
+    let Some(disk) = disks.get_mut(name) else {
+        return true;
+    };
+
+    disk.users -= 1;
+    if disk.users > 0 {
+        return false;
+    }
+
+    disks.remove(name);
+    true
+}
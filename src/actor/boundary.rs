@@ -0,0 +1,293 @@
+//! An antagonist that probes the exact limits on what can be created: instance
+//! memory at its minimum and maximum, disk sizes a byte either side of a
+//! block-size multiple and at the maximum, names of the maximum length, and
+//! instances with the most NICs and disks allowed. Each value at a limit
+//! should be accepted (or turned away only for lack of capacity), and each
+//! value just past one should be cleanly rejected with a 4xx. Edge values
+//! under concurrency are a classic source of accounting bugs, so this is best
+//! run alongside other actors and `--utilization-audit`.
+//!
+//! The limits mirror Nexus's and need updating if Nexus's change. Values past
+//! them can't be expressed with the SDK's types, so requests are sent raw (see
+//! `super::raw`). Whatever an accepted request creates is deleted right away.
+
+use async_trait::async_trait;
+use core::result::Result;
+use rand::rngs::StdRng;
+use rand::Rng;
+use serde_json::{json, Value};
+use tracing::info;
+
+use super::raw::{self, Target};
+use crate::actor::AntagonistError;
+use crate::util::OxideApiError;
+
+const GIB: u64 = 1024 * 1024 * 1024;
+
+/// The least memory an instance may have.
+const MIN_INSTANCE_MEMORY: u64 = GIB;
+
+/// The most memory an instance may have.
+const MAX_INSTANCE_MEMORY: u64 = 256 * GIB;
+
+/// The largest a disk may be.
+const MAX_DISK_SIZE: u64 = 1023 * GIB;
+
+/// The longest a name may be.
+const MAX_NAME_LEN: usize = 63;
+
+/// The most NICs an instance may have.
+const MAX_NICS: usize = 8;
+
+/// The most disks an instance may have.
+const MAX_DISKS: usize = 8;
+
+/// The longest to wait between requests, in milliseconds.
+const MAX_THINK_MILLIS: u64 = 1000;
+
+/// The parameters used to configure a boundary prober.
+pub struct Params {
+    /// The name of the project to create this prober's resources in.
+    pub project: String,
+
+    /// The name of the resources this prober creates. Names for the NICs and
+    /// disks it creates along with instances are derived from it.
+    pub resource_name: String,
+}
+
+/// Whether Nexus should accept a request.
+#[derive(Clone, Copy, Debug)]
+enum Expect {
+    /// The request is at a limit, so it should be accepted.
+    Accept,
+
+    /// The request is just past a limit, so it should be rejected.
+    Reject,
+}
+
+/// A create request with a value at or just past a limit.
+struct Case {
+    name: &'static str,
+    target: Target,
+    expect: Expect,
+
+    /// Edits a valid create body for a resource named with the supplied name
+    /// to have the value.
+    edit: fn(&mut Value, &str),
+}
+
+const fn case(
+    name: &'static str,
+    target: Target,
+    expect: Expect,
+    edit: fn(&mut Value, &str),
+) -> Case {
+    Case { name, target, expect, edit }
+}
+
+/// Returns a name for a resource made of `name`, padded or cut to `len`
+/// characters.
+fn name_of_len(name: &str, len: usize) -> String {
+    let mut name: String = name.chars().take(len).collect();
+    while name.len() < len {
+        name.push('x');
+    }
+    name
+}
+
+/// Returns the NICs for an instance named `name` to create with it, in the
+/// project's default subnet.
+fn nics(name: &str, count: usize) -> Value {
+    let nics: Vec<_> = (0..count)
+        .map(|i| {
+            json!({
+                "name": format!("{}-nic{}", name, i),
+                "description": crate::run_info::description(name),
+                "vpc_name": "default",
+                "subnet_name": "default",
+            })
+        })
+        .collect();
+    json!({ "type": "create", "params": nics })
+}
+
+/// Returns the disks for an instance named `name` to create with it.
+fn disks(name: &str, count: usize) -> Value {
+    (0..count)
+        .map(|i| {
+            json!({
+                "type": "create",
+                "name": format!("{}-disk{}", name, i),
+                "description": crate::run_info::description(name),
+                "disk_source": { "type": "blank", "block_size": 512 },
+                "size": GIB,
+            })
+        })
+        .collect()
+}
+
+/// Every case a prober picks from.
+const CASES: &[Case] = &[
+    case("minimum memory", Target::Instance, Expect::Accept, |body, _| {
+        body["memory"] = json!(MIN_INSTANCE_MEMORY);
+    }),
+    case(
+        "a byte under the minimum memory",
+        Target::Instance,
+        Expect::Reject,
+        |body, _| body["memory"] = json!(MIN_INSTANCE_MEMORY - 1),
+    ),
+    case(
+        "a byte over the minimum memory",
+        Target::Instance,
+        Expect::Reject,
+        |body, _| body["memory"] = json!(MIN_INSTANCE_MEMORY + 1),
+    ),
+    case("maximum memory", Target::Instance, Expect::Accept, |body, _| {
+        body["memory"] = json!(MAX_INSTANCE_MEMORY);
+    }),
+    case(
+        "a GiB over the maximum memory",
+        Target::Instance,
+        Expect::Reject,
+        |body, _| body["memory"] = json!(MAX_INSTANCE_MEMORY + GIB),
+    ),
+    case("maximum NICs", Target::Instance, Expect::Accept, |body, name| {
+        body["network_interfaces"] = nics(name, MAX_NICS);
+    }),
+    case(
+        "a NIC over the maximum",
+        Target::Instance,
+        Expect::Reject,
+        |body, name| body["network_interfaces"] = nics(name, MAX_NICS + 1),
+    ),
+    case("maximum disks", Target::Instance, Expect::Accept, |body, name| {
+        body["disks"] = disks(name, MAX_DISKS);
+    }),
+    case(
+        "a disk over the maximum",
+        Target::Instance,
+        Expect::Reject,
+        |body, name| body["disks"] = disks(name, MAX_DISKS + 1),
+    ),
+    case("minimum disk size", Target::Disk, Expect::Accept, |body, _| {
+        body["size"] = json!(GIB);
+    }),
+    case(
+        "a byte under a block-size multiple",
+        Target::Disk,
+        Expect::Reject,
+        |body, _| body["size"] = json!(2 * GIB - 1),
+    ),
+    case(
+        "a byte over a block-size multiple",
+        Target::Disk,
+        Expect::Reject,
+        |body, _| body["size"] = json!(GIB + 1),
+    ),
+    case("maximum disk size", Target::Disk, Expect::Accept, |body, _| {
+        body["size"] = json!(MAX_DISK_SIZE);
+    }),
+    case(
+        "a block over the maximum disk size",
+        Target::Disk,
+        Expect::Reject,
+        |body, _| body["size"] = json!(MAX_DISK_SIZE + 512),
+    ),
+    case("maximum-length name", Target::Disk, Expect::Accept, |body, name| {
+        body["name"] = json!(name_of_len(name, MAX_NAME_LEN));
+    }),
+    case(
+        "a character over the maximum name length",
+        Target::Disk,
+        Expect::Reject,
+        |body, name| body["name"] = json!(name_of_len(name, MAX_NAME_LEN + 1)),
+    ),
+];
+
+/// Returns a description of what's wrong with `result`, the result of a
+/// request at a limit, or `None` if Nexus accepted it or turned it away for
+/// lack of capacity.
+fn check_accepted(
+    result: &Result<oxide::ResponseValue<Value>, OxideApiError>,
+) -> Option<String> {
+    match result {
+        Ok(_) => None,
+        Err(oxide::Error::ErrorResponse(rv))
+            if rv.status() == http::StatusCode::INSUFFICIENT_STORAGE =>
+        {
+            info!(message = rv.message, "no capacity for request at limit");
+            None
+        }
+        Err(oxide::Error::ErrorResponse(rv)) => Some(format!(
+            "rejected with status {} ({})",
+            rv.status(),
+            rv.message
+        )),
+        Err(e) => Some(format!("failed without an error response: {}", e)),
+    }
+}
+
+/// The internal state for a boundary prober.
+#[derive(Debug)]
+pub(super) struct BoundaryActor {
+    client: oxide::Client,
+    project: String,
+    resource_name: String,
+    rng: StdRng,
+}
+
+impl BoundaryActor {
+    /// Creates a new boundary prober.
+    pub(super) fn new(params: Params, rng: StdRng) -> anyhow::Result<Self> {
+        Ok(Self {
+            client: crate::client::get_client(crate::config())?,
+            project: params.project,
+            resource_name: params.resource_name,
+            rng,
+        })
+    }
+}
+
+#[async_trait]
+impl super::Antagonist for BoundaryActor {
+    fn set_client(&mut self, client: oxide::Client) {
+        self.client = client;
+    }
+
+    #[tracing::instrument(level = "info", skip(self), fields(resource_name = self.resource_name))]
+    async fn step(&mut self) -> Result<(), AntagonistError> {
+        let case = &CASES[self.rng.gen_range(0..CASES.len())];
+        let mut body = case.target.valid_body(&self.resource_name);
+        (case.edit)(&mut body, &self.resource_name);
+        let body = body.to_string();
+
+        info!(
+            target = ?case.target,
+            case = case.name,
+            expect = ?case.expect,
+            "sending request at limit"
+        );
+        let result =
+            raw::create(&self.client, &self.project, case.target, body.clone())
+                .await;
+
+        if result.is_ok() {
+            raw::clean_up(&self.client, &self.project, case.target, &body)
+                .await;
+        }
+
+        crate::util::sleep_random_ms(&mut self.rng, MAX_THINK_MILLIS).await;
+        let problem = match case.expect {
+            Expect::Accept => check_accepted(&result),
+            Expect::Reject => raw::check_rejected(&result),
+        };
+        match problem {
+            Some(problem) => Err(AntagonistError::CheckFailed(format!(
+                "{:?} create with {}: {}",
+                case.target, case.name, problem
+            ))),
+            None => Ok(()),
+        }
+    }
+}
@@ -0,0 +1,98 @@
+//! Cached resource state (`--state-refresh-steps`), which lets an actor skip
+//! viewing its resource before every step.
+//!
+//! Without the cache, most of the load an instance, disk, or snapshot actor
+//! offers is views of its own resource, which dilutes the write-path load the
+//! run is meant to put on Nexus. With it, an actor views its resource once
+//! every N steps, and in between assumes that the resource is in the state
+//! its last view found, updated with what each of its successful actions
+//! should have done. Any error means the assumption may be wrong, so it
+//! throws the cached state away.
+
+/// An actor's cached view of its resource's state, where `None` means the
+/// resource doesn't exist.
+#[derive(Debug)]
+pub(super) struct CachedState<S> {
+    /// The cached state, or `None` if there isn't one.
+    state: Option<Option<S>>,
+
+    /// How many steps have used the cached state since it was observed,
+    /// counting the step that observed it.
+    uses: u32,
+}
+
+impl<S> Default for CachedState<S> {
+    fn default() -> Self {
+        Self { state: None, uses: 0 }
+    }
+}
+
+impl<S: Clone> CachedState<S> {
+    /// Returns the cached state for this step to use, or `None` if the
+    /// resource needs viewing, either because there's no cached state, it's
+    /// been used for --state-refresh-steps steps, or caching is off.
+    pub fn get(&mut self) -> Option<Option<S>> {
+        let refresh_steps = crate::config().state_refresh_steps?;
+        let state = self.state.as_ref()?;
+        if self.uses >= refresh_steps {
+            return None;
+        }
+
+        self.uses += 1;
+        Some(state.clone())
+    }
+
+    /// Records that the resource was just viewed and found in `state`.
+    pub fn observed(&mut self, state: Option<S>) {
+        self.state = Some(state);
+        self.uses = 1;
+    }
+
+    /// Records that an action succeeded that should have left the resource in
+    /// `state`.
+    pub fn expect(&mut self, state: Option<S>) {
+        if let Some(cached) = &mut self.state {
+            *cached = state;
+        }
+    }
+
+    /// Throws away the cached state, so that the next step views the
+    /// resource.
+    pub fn invalidate(&mut self) {
+        self.state = None;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn cached_state_is_used_until_refresh() {
+        // --state-refresh-steps=3: the observing step and two more.
+        crate::config::init_for_tests();
+        let mut cache = CachedState::default();
+        assert_eq!(cache.get(), None);
+
+        cache.observed(Some("running"));
+        assert_eq!(cache.get(), Some(Some("running")));
+        cache.expect(None);
+        assert_eq!(cache.get(), Some(None));
+        assert_eq!(cache.get(), None);
+    }
+
+    #[test]
+    fn invalidated_state_is_viewed_again() {
+        crate::config::init_for_tests();
+        let mut cache = CachedState::default();
+        cache.observed(Some("running"));
+        cache.invalidate();
+        assert_eq!(cache.get(), None);
+
+        // Nothing is cached to update until the next view.
+        cache.expect(Some("stopped"));
+        assert_eq!(cache.get(), None);
+        cache.observed(Some("stopped"));
+        assert_eq!(cache.get(), Some(Some("stopped")));
+    }
+}
@@ -0,0 +1,409 @@
+//! An antagonist that walks the full disk derivation chain on each iteration:
+//! it creates a blank disk, snapshots it, creates an image from the snapshot,
+//! and creates a new disk from the image. It then deletes all four objects in a
+//! random order to stress volume reference counting under interleaved deletes.
+//!
+//! Each creation and deletion is a separate antagonist step, so the actor can
+//! be paused or halted anywhere along the chain.
+
+use async_trait::async_trait;
+use core::result::Result;
+use oxide::types::BlockSize;
+use oxide::types::ByteCount;
+use oxide::types::DiskCreate;
+use oxide::types::DiskSource;
+use oxide::types::DiskState;
+use oxide::types::ImageCreate;
+use oxide::types::ImageSource;
+use oxide::types::Name;
+use oxide::types::SnapshotCreate;
+use oxide::types::SnapshotState;
+use oxide::ClientDisksExt;
+use oxide::ClientImagesExt;
+use oxide::ClientSnapshotsExt;
+use rand::rngs::StdRng;
+use rand::seq::SliceRandom;
+use std::time::{Duration, Instant};
+use tracing::{info, trace};
+use uuid::Uuid;
+
+use crate::actor::AntagonistError;
+use crate::util::ok_if_not_found;
+use crate::util::unwrap_oxide_api_error;
+use crate::util::OxideApiError;
+
+/// How long to wait for an object in the chain to become usable.
+const STEP_TIMEOUT: Duration = Duration::from_secs(300);
+
+/// The parameters used to configure a derivation chain antagonist.
+pub struct Params {
+    /// The name of the project to create this antagonist's resources in.
+    pub project: String,
+
+    /// The base name for this antagonist's resources.
+    pub chain_name: String,
+}
+
+/// The objects in a derivation chain.
+#[derive(Clone, Copy, Debug)]
+enum Link {
+    SourceDisk,
+    Snapshot,
+    Image,
+    DerivedDisk,
+}
+
+/// Where a derivation chain antagonist is in its workload.
+#[derive(Clone, Debug)]
+enum Phase {
+    /// Objects from a previous chain might exist and need to be cleaned up,
+    /// e.g. because the previous iteration failed partway through.
+    Teardown,
+
+    /// The next step is to create the blank source disk.
+    CreateSourceDisk,
+
+    /// The next step is to snapshot the source disk.
+    CreateSnapshot,
+
+    /// The next step is to create an image from the snapshot.
+    CreateImage { snapshot_id: Uuid },
+
+    /// The next step is to create a disk from the image.
+    CreateDerivedDisk { image_id: Uuid },
+
+    /// The next steps are to delete these objects, in this order.
+    Delete { remaining: Vec<Link> },
+}
+
+/// The internal state for a derivation chain antagonist.
+#[derive(Debug)]
+pub(super) struct ChainActor {
+    client: oxide::Client,
+    project: String,
+    chain_name: String,
+    source_disk_name: String,
+    snapshot_name: String,
+    image_name: String,
+    derived_disk_name: String,
+    phase: Phase,
+    rng: StdRng,
+}
+
+impl ChainActor {
+    /// Creates a new derivation chain antagonist.
+    pub(super) fn new(params: Params, rng: StdRng) -> anyhow::Result<Self> {
+        Ok(Self {
+            client: crate::client::get_client(crate::config())?,
+            project: params.project,
+            source_disk_name: source_disk_name(&params.chain_name),
+            snapshot_name: snapshot_name(&params.chain_name),
+            image_name: image_name(&params.chain_name),
+            derived_disk_name: derived_disk_name(&params.chain_name),
+            chain_name: params.chain_name,
+            phase: Phase::Teardown,
+            rng,
+        })
+    }
+
+    /// Creates a disk named `name` from `disk_source` and waits for it to
+    /// become detached.
+    async fn create_disk(
+        &self,
+        name: &str,
+        disk_source: DiskSource,
+    ) -> Result<(), AntagonistError> {
+        let body = DiskCreate {
+            description: crate::run_info::description(&self.chain_name),
+            disk_source,
+            name: Name::try_from(name).unwrap(),
+            size: ByteCount::from(1024 * 1024 * 1024_u64),
+        };
+
+        info!(body = ?body, "sending disk create request");
+        let res = crate::middleware::call("disk_create", || {
+            self.client
+                .disk_create()
+                .project(&self.project)
+                .body(body.clone())
+                .send()
+        })
+        .await;
+
+        unwrap_oxide_api_error(res)?;
+
+        let start = Instant::now();
+        loop {
+            let state = crate::middleware::call("disk_view", || {
+                self.client.disk_view().project(&self.project).disk(name).send()
+            })
+            .await?
+            .into_inner()
+            .state;
+
+            if matches!(state, DiskState::Detached) {
+                return Ok(());
+            }
+
+            if start.elapsed() > STEP_TIMEOUT {
+                return Err(AntagonistError::InvalidState(format!(
+                    "disk {} not detached after {:?} (state: {:?})",
+                    name, STEP_TIMEOUT, state,
+                )));
+            }
+
+            trace!(?state, "waiting for disk");
+            tokio::time::sleep(Duration::from_secs(1)).await;
+        }
+    }
+
+    /// Snapshots the source disk and waits for the snapshot to become ready.
+    /// Returns the snapshot's ID.
+    async fn create_snapshot(&self) -> Result<Uuid, AntagonistError> {
+        let body = SnapshotCreate {
+            name: Name::try_from(&self.snapshot_name).unwrap(),
+            description: crate::run_info::description(&self.chain_name),
+            disk: self.source_disk_name.clone().try_into().unwrap(),
+        };
+
+        info!(body = ?body, "sending snapshot create request");
+        let res = crate::middleware::call("snapshot_create", || {
+            self.client
+                .snapshot_create()
+                .project(&self.project)
+                .body(body.clone())
+                .send()
+        })
+        .await;
+
+        let id = res?.into_inner().id;
+
+        let start = Instant::now();
+        loop {
+            let state = crate::middleware::call("snapshot_view", || {
+                self.client
+                    .snapshot_view()
+                    .project(&self.project)
+                    .snapshot(&self.snapshot_name)
+                    .send()
+            })
+            .await?
+            .into_inner()
+            .state;
+
+            if state == SnapshotState::Ready {
+                return Ok(id);
+            }
+
+            if start.elapsed() > STEP_TIMEOUT {
+                return Err(AntagonistError::InvalidState(format!(
+                    "snapshot {} not ready after {:?} (state: {:?})",
+                    self.snapshot_name, STEP_TIMEOUT, state,
+                )));
+            }
+
+            trace!(?state, "waiting for snapshot");
+            tokio::time::sleep(Duration::from_secs(1)).await;
+        }
+    }
+
+    /// Creates an image from the snapshot with the supplied ID. Returns the
+    /// image's ID.
+    async fn create_image(
+        &self,
+        snapshot_id: Uuid,
+    ) -> Result<Uuid, AntagonistError> {
+        let body = ImageCreate {
+            description: crate::run_info::description(&self.chain_name),
+            name: Name::try_from(&self.image_name).unwrap(),
+            os: "none".to_string(),
+            version: "0".to_string(),
+            source: ImageSource::Snapshot { id: snapshot_id },
+        };
+
+        info!(body = ?body, "sending image create request");
+        let res = crate::middleware::call("image_create", || {
+            self.client
+                .image_create()
+                .project(&self.project)
+                .body(body.clone())
+                .send()
+        })
+        .await;
+
+        Ok(res?.into_inner().id)
+    }
+
+    /// Asks to delete one object in the chain.
+    async fn delete(&self, link: Link) -> Result<(), OxideApiError> {
+        info!(?link, "deleting chain object");
+        match link {
+            Link::SourceDisk | Link::DerivedDisk => {
+                let name = match link {
+                    Link::SourceDisk => &self.source_disk_name,
+                    _ => &self.derived_disk_name,
+                };
+
+                let res = crate::middleware::call("disk_delete", || {
+                    self.client
+                        .disk_delete()
+                        .project(&self.project)
+                        .disk(name)
+                        .send()
+                })
+                .await;
+
+                unwrap_oxide_api_error(res)
+            }
+
+            Link::Snapshot => {
+                let res = crate::middleware::call("snapshot_delete", || {
+                    self.client
+                        .snapshot_delete()
+                        .project(&self.project)
+                        .snapshot(&self.snapshot_name)
+                        .send()
+                })
+                .await;
+
+                unwrap_oxide_api_error(res)
+            }
+
+            Link::Image => {
+                let res = crate::middleware::call("image_delete", || {
+                    self.client
+                        .image_delete()
+                        .project(&self.project)
+                        .image(&self.image_name)
+                        .send()
+                })
+                .await;
+
+                unwrap_oxide_api_error(res)
+            }
+        }
+    }
+
+    /// Deletes every object in the chain in the supplied order, ignoring
+    /// objects that don't exist. All deletions are attempted even if some
+    /// fail; the first failure is returned.
+    async fn delete_all(&self, order: &[Link]) -> Result<(), OxideApiError> {
+        let mut result = Ok(());
+        for link in order {
+            let res = ok_if_not_found(self.delete(*link).await);
+            if result.is_ok() {
+                result = res;
+            }
+        }
+
+        result
+    }
+}
+
+/// Returns the name of the blank disk at the start of the chain named `chain`.
+pub fn source_disk_name(chain: &str) -> String {
+    format!("{}-src", chain)
+}
+
+/// Returns the name of the snapshot in the chain named `chain`.
+pub fn snapshot_name(chain: &str) -> String {
+    format!("{}-snap", chain)
+}
+
+/// Returns the name of the image in the chain named `chain`.
+pub fn image_name(chain: &str) -> String {
+    format!("{}-img", chain)
+}
+
+/// Returns the name of the disk at the end of the chain named `chain`.
+pub fn derived_disk_name(chain: &str) -> String {
+    format!("{}-dst", chain)
+}
+
+#[async_trait]
+impl super::Antagonist for ChainActor {
+    fn set_client(&mut self, client: oxide::Client) {
+        self.client = client;
+    }
+
+    #[tracing::instrument(level = "info", skip(self), fields(chain_name = self.chain_name, phase = ?self.phase))]
+    async fn step(&mut self) -> Result<(), AntagonistError> {
+        // If a step fails, clean up whatever is left of the chain before
+        // starting over.
+        let phase = std::mem::replace(&mut self.phase, Phase::Teardown);
+        self.phase = match phase {
+            Phase::Teardown => {
+                // Delete derived objects before the objects they came from.
+                self.delete_all(&[
+                    Link::DerivedDisk,
+                    Link::Image,
+                    Link::Snapshot,
+                    Link::SourceDisk,
+                ])
+                .await?;
+                Phase::CreateSourceDisk
+            }
+
+            Phase::CreateSourceDisk => {
+                self.create_disk(
+                    &self.source_disk_name,
+                    DiskSource::Blank {
+                        block_size: BlockSize::try_from(512_i64).unwrap(),
+                    },
+                )
+                .await?;
+                Phase::CreateSnapshot
+            }
+
+            Phase::CreateSnapshot => {
+                let snapshot_id = self.create_snapshot().await?;
+                Phase::CreateImage { snapshot_id }
+            }
+
+            Phase::CreateImage { snapshot_id } => {
+                let image_id = self.create_image(snapshot_id).await?;
+                Phase::CreateDerivedDisk { image_id }
+            }
+
+            Phase::CreateDerivedDisk { image_id } => {
+                self.create_disk(
+                    &self.derived_disk_name,
+                    DiskSource::Image { image_id },
+                )
+                .await?;
+
+                let mut remaining = vec![
+                    Link::SourceDisk,
+                    Link::Snapshot,
+                    Link::Image,
+                    Link::DerivedDisk,
+                ];
+                remaining.shuffle(&mut self.rng);
+                trace!(order = ?remaining, "deleting chain");
+                Phase::Delete { remaining }
+            }
+
+            Phase::Delete { mut remaining } => match remaining.pop() {
+                Some(link) => {
+                    self.delete(link).await?;
+                    Phase::Delete { remaining }
+                }
+                None => Phase::CreateSourceDisk,
+            },
+        };
+
+        Ok(())
+    }
+
+    #[tracing::instrument(level = "info", skip(self), fields(chain_name = self.chain_name))]
+    async fn drain(&mut self) -> Result<(), AntagonistError> {
+        self.delete_all(&[
+            Link::DerivedDisk,
+            Link::Image,
+            Link::Snapshot,
+            Link::SourceDisk,
+        ])
+        .await?;
+        Ok(())
+    }
+}
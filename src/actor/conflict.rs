@@ -0,0 +1,453 @@
+//! An antagonist that checks how Nexus resolves conflicting requests for the
+//! same resource. On each step it races requests for one disk: a storm of
+//! identical creates, a storm of identical deletes, or a create and a delete.
+//! It then checks that the responses are consistent with each other and with
+//! the disk's state afterward:
+//!
+//! - Of a storm of identical creates, exactly one should succeed, and the rest
+//!   should fail with a well-formed 400 (Nexus's ObjectAlreadyExists) or 409.
+//! - Of a storm of identical deletes, exactly one should succeed, and the rest
+//!   should fail with a well-formed 4xx. The disk should be gone afterward.
+//! - A delete racing a create can only succeed if the create did, and the
+//!   disk should exist afterward exactly when the create succeeded and the
+//!   delete didn't.
+//!
+//! Storms have `--conflict-storm-size` requests. How many of them Nexus
+//! serialized correctly is tallied for the end-of-run summary.
+//!
+//! Requests that fail without an error response (e.g. because the connection
+//! dropped) say nothing about conflict handling, so they're returned as
+//! ordinary API errors instead of failing the check.
+
+use async_trait::async_trait;
+use core::result::Result;
+use oxide::types::BlockSize;
+use oxide::types::ByteCount;
+use oxide::types::DiskCreate;
+use oxide::types::DiskSource;
+use oxide::types::DiskState;
+use oxide::types::Name;
+use oxide::ClientDisksExt;
+use rand::rngs::StdRng;
+use rand::Rng;
+use serde::Serialize;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{Duration, Instant};
+use tracing::{info, trace};
+
+use crate::actor::AntagonistError;
+use crate::util::ok_if_not_found;
+use crate::util::unwrap_oxide_api_error;
+use crate::util::OxideApiError;
+
+/// How long to wait for the disk to become deletable.
+const STEP_TIMEOUT: Duration = Duration::from_secs(300);
+
+/// The parameters used to configure a conflict checker.
+pub struct Params {
+    /// The name of the project to create this checker's disk in.
+    pub project: String,
+
+    /// The name of the disk this checker races requests for.
+    pub disk_name: String,
+}
+
+/// The requests a conflict checker races.
+#[derive(Clone, Copy, Debug)]
+enum Race {
+    DuplicateCreate,
+    DuplicateDelete,
+    CreateVsDelete,
+}
+
+/// How the storms of identical requests turned out.
+#[derive(Clone, Debug, Default, Serialize)]
+pub struct Summary {
+    /// The storms whose outcome said something about how Nexus serialized
+    /// them.
+    pub storms: u64,
+
+    /// The storms in which exactly one request succeeded and the rest failed
+    /// cleanly.
+    pub serialized: u64,
+
+    /// The storms in which a request failed without an error response, so
+    /// that whether Nexus serialized them couldn't be told.
+    pub inconclusive: u64,
+}
+
+static STORMS: AtomicU64 = AtomicU64::new(0);
+static SERIALIZED: AtomicU64 = AtomicU64::new(0);
+static INCONCLUSIVE: AtomicU64 = AtomicU64::new(0);
+
+/// Returns how the storms of identical requests have turned out so far.
+pub fn summary() -> Summary {
+    Summary {
+        storms: STORMS.load(Ordering::Relaxed),
+        serialized: SERIALIZED.load(Ordering::Relaxed),
+        inconclusive: INCONCLUSIVE.load(Ordering::Relaxed),
+    }
+}
+
+/// Logs the conflict storm section of the end-of-run summary.
+pub fn log() {
+    let Summary { storms, serialized, inconclusive } = summary();
+    if storms == 0 && inconclusive == 0 {
+        return;
+    }
+
+    let percent = if storms == 0 {
+        100.0
+    } else {
+        serialized as f64 * 100.0 / storms as f64
+    };
+    info!(
+        size = crate::config().conflict_storm_size,
+        storms,
+        serialized,
+        inconclusive,
+        "Conflict storms ({:.1}% serialized correctly)",
+        percent
+    );
+}
+
+/// Parses a `--conflict-storm-size`, which must be at least 2.
+pub fn parse_storm_size(s: &str) -> Result<usize, String> {
+    match s.parse::<usize>() {
+        Ok(size) if size >= 2 => Ok(size),
+        Ok(_) => Err("a storm needs at least 2 requests".to_string()),
+        Err(e) => Err(e.to_string()),
+    }
+}
+
+/// The internal state for a conflict checker.
+#[derive(Debug)]
+pub(super) struct ConflictActor {
+    client: oxide::Client,
+    project: String,
+    disk_name: String,
+    rng: StdRng,
+}
+
+/// Returns a description of what's wrong with `err` if it's an error response
+/// that isn't well-formed or whose status isn't one of `expected`, or `None`
+/// if there's nothing wrong with it.
+fn check_error_response(
+    err: &OxideApiError,
+    expected: &[http::StatusCode],
+) -> Option<String> {
+    let oxide::Error::ErrorResponse(rv) = err else {
+        return None;
+    };
+
+    if !expected.contains(&rv.status()) {
+        return Some(format!("unexpected status {}", rv.status()));
+    }
+
+    // The error code is optional, so only the message and request ID have to
+    // be there.
+    if rv.message.is_empty() || rv.request_id.is_empty() {
+        return Some(format!("malformed error body {:?}", rv));
+    }
+
+    None
+}
+
+impl ConflictActor {
+    /// Creates a new conflict checker.
+    pub(super) fn new(params: Params, rng: StdRng) -> anyhow::Result<Self> {
+        Ok(Self {
+            client: crate::client::get_client(crate::config())?,
+            project: params.project,
+            disk_name: params.disk_name,
+            rng,
+        })
+    }
+
+    /// Returns a check failure for a race that ended in `outcome`.
+    fn violation(&self, race: Race, outcome: &str) -> AntagonistError {
+        AntagonistError::CheckFailed(format!(
+            "{:?} race on disk {}: {}",
+            race, self.disk_name, outcome
+        ))
+    }
+
+    /// Asks to create this checker's disk.
+    async fn create_disk(&self) -> Result<(), OxideApiError> {
+        let body = DiskCreate {
+            description: crate::run_info::description("conflict checker disk"),
+            disk_source: DiskSource::Blank {
+                block_size: BlockSize::try_from(512_i64).unwrap(),
+            },
+            name: Name::try_from(&self.disk_name).unwrap(),
+            size: ByteCount::from(1024 * 1024 * 1024_u64),
+        };
+
+        let res = crate::middleware::call("disk_create", || {
+            self.client
+                .disk_create()
+                .project(&self.project)
+                .body(body.clone())
+                .send()
+        })
+        .await;
+
+        unwrap_oxide_api_error(res)
+    }
+
+    /// Asks to delete this checker's disk.
+    async fn delete_disk(&self) -> Result<(), OxideApiError> {
+        let res = crate::middleware::call("disk_delete", || {
+            self.client
+                .disk_delete()
+                .project(&self.project)
+                .disk(&self.disk_name)
+                .send()
+        })
+        .await;
+
+        unwrap_oxide_api_error(res)
+    }
+
+    /// Returns the state of this checker's disk, or `None` if it doesn't
+    /// exist.
+    async fn get_disk_state(&self) -> Result<Option<DiskState>, OxideApiError> {
+        let res = crate::middleware::call("disk_view", || {
+            self.client
+                .disk_view()
+                .project(&self.project)
+                .disk(&self.disk_name)
+                .send()
+        })
+        .await;
+
+        match res {
+            Ok(disk) => Ok(Some(disk.into_inner().state)),
+            Err(oxide::Error::ErrorResponse(rv))
+                if rv.status() == http::StatusCode::NOT_FOUND =>
+            {
+                Ok(None)
+            }
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Waits for this checker's disk to become deletable. Returns whether it
+    /// exists.
+    async fn wait_deletable(&self) -> Result<bool, AntagonistError> {
+        let start = Instant::now();
+        loop {
+            match self.get_disk_state().await? {
+                None => return Ok(false),
+                Some(DiskState::Detached | DiskState::Faulted) => {
+                    return Ok(true)
+                }
+                Some(state) => {
+                    if start.elapsed() > STEP_TIMEOUT {
+                        return Err(AntagonistError::InvalidState(format!(
+                            "disk {} not deletable after {:?} (state: {:?})",
+                            self.disk_name, STEP_TIMEOUT, state,
+                        )));
+                    }
+
+                    trace!(?state, "waiting for disk to become deletable");
+                    tokio::time::sleep(Duration::from_secs(1)).await;
+                }
+            }
+        }
+    }
+
+    /// Deletes this checker's disk, if it exists, waiting for it to become
+    /// deletable first.
+    async fn ensure_deleted(&self) -> Result<(), AntagonistError> {
+        if self.wait_deletable().await? {
+            ok_if_not_found(self.delete_disk().await)?;
+        }
+
+        Ok(())
+    }
+
+    /// Checks the `results` of a storm of identical requests, of which exactly
+    /// one should have succeeded and the rest failed with one of `expected`,
+    /// and tallies the outcome.
+    fn check_storm(
+        &self,
+        race: Race,
+        results: Vec<Result<(), OxideApiError>>,
+        expected: &[http::StatusCode],
+    ) -> Result<(), AntagonistError> {
+        let size = results.len();
+        let (succeeded, failed): (Vec<_>, Vec<_>) =
+            results.into_iter().partition(Result::is_ok);
+        let mut errors = failed.into_iter().filter_map(Result::err);
+
+        // If none of the requests worked, there was no conflict to resolve.
+        if succeeded.is_empty() {
+            let err = errors.next().expect("a storm has requests");
+            if !matches!(err, oxide::Error::ErrorResponse(_)) {
+                INCONCLUSIVE.fetch_add(1, Ordering::Relaxed);
+            }
+            return Err(err.into());
+        }
+
+        let mut problem = None;
+        for err in errors {
+            if !matches!(err, oxide::Error::ErrorResponse(_)) {
+                INCONCLUSIVE.fetch_add(1, Ordering::Relaxed);
+                return Err(err.into());
+            }
+
+            if problem.is_none() {
+                problem = check_error_response(&err, expected);
+            }
+        }
+
+        STORMS.fetch_add(1, Ordering::Relaxed);
+        if succeeded.len() > 1 {
+            return Err(self.violation(
+                race,
+                &format!("{} of {} requests succeeded", succeeded.len(), size),
+            ));
+        }
+
+        if let Some(problem) = problem {
+            return Err(self.violation(
+                race,
+                &format!("losing request failed with {}", problem),
+            ));
+        }
+
+        SERIALIZED.fetch_add(1, Ordering::Relaxed);
+        Ok(())
+    }
+
+    /// Races a storm of identical creates.
+    async fn duplicate_create(&self) -> Result<(), AntagonistError> {
+        let size = crate::config().conflict_storm_size;
+        let results =
+            futures::future::join_all((0..size).map(|_| self.create_disk()))
+                .await;
+
+        self.check_storm(
+            Race::DuplicateCreate,
+            results,
+            &[http::StatusCode::BAD_REQUEST, http::StatusCode::CONFLICT],
+        )
+    }
+
+    /// Creates this checker's disk, then races a storm of identical deletes of
+    /// it.
+    async fn duplicate_delete(&self) -> Result<(), AntagonistError> {
+        let race = Race::DuplicateDelete;
+        self.create_disk().await?;
+        if !self.wait_deletable().await? {
+            return Err(AntagonistError::InvalidState(format!(
+                "disk {} vanished after it was created",
+                self.disk_name
+            )));
+        }
+
+        let size = crate::config().conflict_storm_size;
+        let results =
+            futures::future::join_all((0..size).map(|_| self.delete_disk()))
+                .await;
+
+        // A losing delete may find the disk gone, or find the winner still
+        // deleting it.
+        let expected = [
+            http::StatusCode::NOT_FOUND,
+            http::StatusCode::BAD_REQUEST,
+            http::StatusCode::CONFLICT,
+        ];
+        self.check_storm(race, results, &expected)?;
+
+        if self.get_disk_state().await?.is_some() {
+            return Err(
+                self.violation(race, "disk exists after a delete succeeded")
+            );
+        }
+
+        Ok(())
+    }
+
+    /// Races a create with a delete.
+    async fn create_vs_delete(&self) -> Result<(), AntagonistError> {
+        let race = Race::CreateVsDelete;
+        let (create, delete) =
+            futures::join!(self.create_disk(), self.delete_disk());
+
+        // The delete may have gone first and found nothing, or found the
+        // disk still being created.
+        if let Err(e) = &delete {
+            let expected = [
+                http::StatusCode::NOT_FOUND,
+                http::StatusCode::BAD_REQUEST,
+                http::StatusCode::CONFLICT,
+            ];
+            if let Some(problem) = check_error_response(e, &expected) {
+                return Err(self.violation(
+                    race,
+                    &format!("delete failed with {}", problem),
+                ));
+            }
+        }
+
+        let created = match create {
+            Ok(()) => true,
+            Err(e) if delete.is_ok() => {
+                return Err(self.violation(
+                    race,
+                    &format!("delete succeeded, but create failed: {}", e),
+                ));
+            }
+            Err(e) => return Err(e.into()),
+        };
+
+        let exists = self.get_disk_state().await?.is_some();
+        let should_exist = created && delete.is_err();
+        trace!(created, deleted = delete.is_ok(), exists, "race finished");
+        if exists != should_exist {
+            return Err(self.violation(
+                race,
+                &format!(
+                    "disk {} after create {} and delete {}",
+                    if exists { "exists" } else { "doesn't exist" },
+                    if created { "succeeded" } else { "failed" },
+                    if delete.is_ok() { "succeeded" } else { "failed" },
+                ),
+            ));
+        }
+
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl super::Antagonist for ConflictActor {
+    fn set_client(&mut self, client: oxide::Client) {
+        self.client = client;
+    }
+
+    #[tracing::instrument(level = "info", skip(self), fields(disk_name = self.disk_name))]
+    async fn step(&mut self) -> Result<(), AntagonistError> {
+        self.ensure_deleted().await?;
+
+        let race = match self.rng.gen_range(0..3) {
+            0 => Race::DuplicateCreate,
+            1 => Race::DuplicateDelete,
+            _ => Race::CreateVsDelete,
+        };
+
+        info!(?race, "racing requests");
+        match race {
+            Race::DuplicateCreate => self.duplicate_create().await,
+            Race::DuplicateDelete => self.duplicate_delete().await,
+            Race::CreateVsDelete => self.create_vs_delete().await,
+        }
+    }
+
+    #[tracing::instrument(level = "info", skip(self), fields(disk_name = self.disk_name))]
+    async fn drain(&mut self) -> Result<(), AntagonistError> {
+        self.ensure_deleted().await
+    }
+}
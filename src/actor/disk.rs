@@ -1,5 +1,8 @@
 //! An antagonist that exercises disk lifecycle commands (create, delete).
 
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::Instant;
+
 use async_trait::async_trait;
 use core::result::Result;
 use oxide::types::BlockSize;
@@ -9,12 +12,18 @@ use oxide::types::DiskSource;
 use oxide::types::DiskState;
 use oxide::types::Name;
 use oxide::ClientDisksExt;
+use rand::rngs::StdRng;
+use serde::{Deserialize, Serialize};
 use tracing::{info, trace, warn};
 
+use crate::actor::ownership::ResourceKind;
 use crate::actor::AntagonistError;
-use crate::util::sleep_random_ms;
+use crate::sla::Progress;
+use crate::util::ok_if_error_response;
+use crate::util::sleep_random;
 use crate::util::unwrap_oxide_api_error;
 use crate::util::OxideApiError;
+use crate::util::SleepRange;
 
 #[derive(Debug, Clone)]
 enum BailReason {
@@ -38,6 +47,124 @@ pub struct Params {
 
     /// The name of the disk this antagonist should act on.
     pub disk_name: String,
+
+    /// The relative likelihood of each action in each disk state.
+    pub weights: Weights,
+
+    /// How long to sleep before and after each action.
+    pub think_time: SleepRange,
+}
+
+/// The relative weights of the actions a disk antagonist can take from one
+/// state. Actions that are left out of a scenario file get weight 0.
+#[derive(Clone, Copy, Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ActionWeights {
+    pub wait: u32,
+    pub create: u32,
+    pub delete: u32,
+}
+
+impl ActionWeights {
+    pub const fn new(wait: u32, create: u32, delete: u32) -> Self {
+        Self { wait, create, delete }
+    }
+
+    fn table(&self) -> [u32; 3] {
+        [self.wait, self.create, self.delete]
+    }
+}
+
+/// The action weights a disk antagonist uses for each state it can find its
+/// disk in. States that are left out of a scenario file keep their default
+/// weights. Attached disks can't be deleted, and attach and detach
+/// transitions are driven by whoever is attaching the disk, so disk
+/// antagonists always just wait for attached disks to come back.
+#[derive(Clone, Copy, Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Weights {
+    /// Weights for disks that are being created.
+    pub creating: ActionWeights,
+
+    /// Weights for detached disks.
+    pub detached: ActionWeights,
+
+    /// Weights for faulted disks.
+    pub faulted: ActionWeights,
+}
+
+impl Default for Weights {
+    fn default() -> Self {
+        Self {
+            // If the disk is still starting up, favour politely waiting for it
+            // to finish most of the time, but slightly favour asking for it to
+            // be deleted.
+            creating: ActionWeights::new(70, 10, 20),
+
+            // If the disk is detached, equally perform any action on it.
+            detached: ActionWeights::new(35, 30, 35),
+
+            // A faulted disk can't be used for anything, so mostly delete it
+            // so that it can be recreated.
+            faulted: ActionWeights::new(20, 0, 80),
+        }
+    }
+}
+
+impl Weights {
+    /// Returns an error if every action in some state has weight 0.
+    pub fn validate(&self) -> anyhow::Result<()> {
+        for (state, weights) in [
+            ("creating", &self.creating),
+            ("detached", &self.detached),
+            ("faulted", &self.faulted),
+        ] {
+            anyhow::ensure!(
+                weights.table().iter().any(|w| *w > 0),
+                "disk weights for the {} state are all zero",
+                state
+            );
+        }
+
+        Ok(())
+    }
+}
+
+/// How often disk antagonists' disks faulted, and how many of the faulted
+/// disks were replaced.
+#[derive(Clone, Debug, Default, Serialize)]
+pub struct Summary {
+    /// The times a disk antagonist found its disk newly faulted.
+    pub faults: u64,
+
+    /// The faulted disks that were deleted.
+    pub deleted: u64,
+
+    /// The faulted disks that were deleted and then created again.
+    pub recreated: u64,
+}
+
+static FAULTS: AtomicU64 = AtomicU64::new(0);
+static DELETED: AtomicU64 = AtomicU64::new(0);
+static RECREATED: AtomicU64 = AtomicU64::new(0);
+
+/// Returns how often disks have faulted and been replaced so far.
+pub fn summary() -> Summary {
+    Summary {
+        faults: FAULTS.load(Ordering::Relaxed),
+        deleted: DELETED.load(Ordering::Relaxed),
+        recreated: RECREATED.load(Ordering::Relaxed),
+    }
+}
+
+/// Logs the faulted disk section of the end-of-run summary.
+pub fn log() {
+    let Summary { faults, deleted, recreated } = summary();
+    if faults == 0 {
+        return;
+    }
+
+    warn!(faults, deleted, recreated, "Disks faulted");
 }
 
 /// The internal state for a disk antagonist.
@@ -45,37 +172,109 @@ pub struct Params {
 pub(super) struct DiskActor {
     client: oxide::Client,
     project: String,
+
+    /// The name of the disk this actor is currently acting on. In unique-name
+    /// mode, this is the current generation of `base_name`.
     disk_name: String,
+    base_name: String,
+
+    /// In unique-name mode, the generation of the disk named by `disk_name`.
+    generation: Option<u64>,
+    weights: Weights,
+    think_time: SleepRange,
+
+    /// True if the disk was faulted the last time this actor looked at it.
+    was_faulted: bool,
+
+    /// True if this actor deleted its disk because it was faulted and hasn't
+    /// created it again yet.
+    replacing: bool,
+
+    /// The disk's state as of the last step, for --state-refresh-steps.
+    state: super::cache::CachedState<DiskState>,
+
+    /// The disk's size in bytes, as of the last time this actor created or
+    /// looked at it.
+    size: Option<u64>,
+    rng: StdRng,
 }
 
 impl DiskActor {
     /// Creates a new disk antagonist.
-    pub(super) fn new(params: Params) -> anyhow::Result<Self> {
+    pub(super) fn new(params: Params, rng: StdRng) -> anyhow::Result<Self> {
         Ok(Self {
             client: crate::client::get_client(crate::config())?,
             project: params.project,
-            disk_name: params.disk_name,
+            disk_name: params.disk_name.clone(),
+            base_name: params.disk_name,
+            generation: crate::config().unique_names.then_some(0),
+            weights: params.weights,
+            think_time: params.think_time,
+            was_faulted: false,
+            replacing: false,
+            state: Default::default(),
+            size: None,
+            rng,
         })
     }
 
+    /// In unique-name mode, points this actor at the current generation of its
+    /// disk, which another actor sharing it may have moved forward.
+    fn refresh_generation(&mut self) {
+        if self.generation.is_some() {
+            let generation = super::current_generation(&self.base_name);
+            self.generation = Some(generation);
+            self.disk_name =
+                super::generation_name(&self.base_name, generation);
+        }
+    }
+
+    /// In unique-name mode, moves this actor on to a new generation of its
+    /// disk after finding that the current one doesn't exist, so that the next
+    /// create uses a fresh name.
+    fn retire_generation(&mut self) {
+        if let Some(generation) = self.generation {
+            let generation =
+                super::retire_generation(&self.base_name, generation);
+            self.generation = Some(generation);
+            self.disk_name =
+                super::generation_name(&self.base_name, generation);
+        }
+    }
+
     /// Gets this actor's disk's current state.
     ///
     /// # Return value
     ///
     /// - Ok(Some(state)) if the query succeeded.
     /// - Ok(None) if the query failed with a "not found" error.
-    /// - Err if the query failed for any other reason.
-    async fn get_disk_state(&self) -> Result<Option<DiskState>, OxideApiError> {
-        let res = self
-            .client
-            .disk_view()
-            .project(&self.project)
-            .disk(&self.disk_name)
-            .send()
-            .await;
-
-        match res {
-            Ok(response_value) => Ok(Some(response_value.into_inner().state)),
+    /// - Err if the query failed for any other reason, or if its result
+    ///   contradicts the expected-state model.
+    async fn get_disk_state(
+        &self,
+    ) -> Result<Option<DiskState>, AntagonistError> {
+        Ok(self.get_disk().await?.map(|(state, _)| state))
+    }
+
+    /// Like `get_disk_state`, but also returns the disk's size in bytes.
+    async fn get_disk(
+        &self,
+    ) -> Result<Option<(DiskState, u64)>, AntagonistError> {
+        let sent = std::time::Instant::now();
+        let res = crate::middleware::call("disk_view", || {
+            self.client
+                .disk_view()
+                .project(&self.project)
+                .disk(&self.disk_name)
+                .send()
+        })
+        .await;
+
+        let found = match res {
+            Ok(response_value) => {
+                let disk = response_value.into_inner();
+                Ok(Some((disk.state, disk.id, disk.size.0)))
+            }
 
             Err(e) => match &e {
                 oxide::Error::InvalidRequest(_)
@@ -98,70 +297,148 @@ impl DiskActor {
                     }
                 }
             },
-        }
+        }?;
+
+        let id = match &found {
+            Some((DiskState::Destroyed, _, _)) | None => None,
+            Some((_, id, _)) => Some(*id),
+        };
+        super::model::observe(ResourceKind::Disk, &self.disk_name, sent, id)?;
+        Ok(found.map(|(state, _, size)| (state, size)))
     }
 
-    /// Asks to create this actor's disk. The created disk size is 1 GB.
-    async fn create_disk(&self) -> Result<(), OxideApiError> {
+    /// Asks to create this actor's disk, with one of the --disk-sizes picked
+    /// at random. Returns the disk's size in bytes.
+    async fn create_disk(&mut self) -> Result<u64, OxideApiError> {
+        use rand::seq::SliceRandom;
+
+        let size = *crate::config().disk_sizes.choose(&mut self.rng).unwrap()
+            * 1024
+            * 1024
+            * 1024;
         let body = DiskCreate {
-            description: self.disk_name.to_owned(),
+            description: crate::run_info::description(&self.disk_name),
             disk_source: DiskSource::Blank {
                 block_size: BlockSize::try_from(512_i64).unwrap(),
             },
             name: Name::try_from(&self.disk_name).unwrap(),
-            size: ByteCount::from(1024 * 1024 * 1024_u64),
+            size: ByteCount::from(size),
         };
 
         info!(body = ?body, "sending disk create request");
-        let res = self
-            .client
-            .disk_create()
-            .project(&self.project)
-            .body(body)
-            .send()
-            .await;
-
-        if res.is_err() {
-            warn!(result = ?res, "disk create request returned");
-        } else {
-            info!(result = ?res, "disk create request returned");
+        let pending = super::model::begin(
+            ResourceKind::Disk,
+            &self.disk_name,
+            super::model::Op::Create,
+        );
+        let res = crate::middleware::call("disk_create", || {
+            self.client
+                .disk_create()
+                .project(&self.project)
+                .body(body.clone())
+                .send()
+        })
+        .await;
+        pending.finish_create(res.as_ref().ok().map(|rv| rv.id));
+
+        unwrap_oxide_api_error(res)?;
+        self.size = Some(size);
+        Ok(size)
+    }
+
+    /// If --disk-create-sla or --measure-transitions is set, waits for this
+    /// actor's disk, which was just created with `size` bytes by a request
+    /// sent at `sent`, to reach Detached, and records an SLA violation if it
+    /// takes longer than the SLA, or how long it took. Disks that fault count
+    /// as failed creates. Gives up without a verdict if the disk is changed
+    /// or deleted by another actor in the meantime.
+    async fn settle_create(
+        &self,
+        sent: Instant,
+        size: u64,
+    ) -> Result<(), AntagonistError> {
+        crate::sla::settle(
+            "disk_create",
+            &self.disk_name,
+            crate::config().disk_create_sla,
+            Some(crate::sla::Measure::since(sent).with_size(size)),
+            || self.get_disk_state(),
+            |state| match state {
+                Some(DiskState::Detached) => Progress::Done,
+                Some(DiskState::Creating) => Progress::Pending,
+                Some(DiskState::Faulted) => Progress::Failed,
+                _ => Progress::Interrupted,
+            },
+        )
+        .await
+    }
+
+    /// If --measure-transitions is set, waits for this actor's disk, which was
+    /// just deleted by a request sent at `sent`, to stop being found, and
+    /// records how long that took, under the size the disk was last seen
+    /// with.
+    async fn settle_delete(
+        &self,
+        sent: Instant,
+    ) -> Result<(), AntagonistError> {
+        let mut measure = crate::sla::Measure::since(sent);
+        if let Some(size) = self.size {
+            measure = measure.with_size(size);
         }
-        unwrap_oxide_api_error(res)
+
+        crate::sla::settle(
+            "disk_delete",
+            &self.disk_name,
+            None,
+            Some(measure),
+            || self.get_disk_state(),
+            |state| match state {
+                None => Progress::Done,
+                Some(DiskState::Destroyed) => Progress::Pending,
+                _ => Progress::Interrupted,
+            },
+        )
+        .await
     }
 
     /// Asks to delete this actor's disk.
     async fn delete_disk(&self) -> Result<(), OxideApiError> {
         info!("sending disk delete request");
-        let res = self
-            .client
-            .disk_delete()
-            .project(&self.project)
-            .disk(&self.disk_name)
-            .send()
-            .await;
-
-        if res.is_err() {
-            warn!(result = ?res, "disk delete request returned");
-        } else {
-            info!(result = ?res, "disk delete request returned");
-        }
+        let pending = super::model::begin(
+            ResourceKind::Disk,
+            &self.disk_name,
+            super::model::Op::Delete,
+        );
+        let res = crate::middleware::call("disk_delete", || {
+            self.client
+                .disk_delete()
+                .project(&self.project)
+                .disk(&self.disk_name)
+                .send()
+        })
+        .await;
+        pending.finish(res.is_ok());
+
         unwrap_oxide_api_error(res)
     }
 
     /// Selects an action for this antagonist to take given that its disk was
     /// observed to be in the supplied `state`.
-    fn get_next_action(&self, state: DiskState) -> Action {
+    fn get_next_action(&mut self, state: DiskState) -> Action {
         use rand::prelude::Distribution;
         let actions = [Action::Wait, Action::Create, Action::Delete];
 
         let weights = match state {
-            // If the disk is still starting up, favour politely waiting for it
-            // to finish most of the time, but slightly favour asking for it to
-            // be deleted.
-            DiskState::Creating => [70, 10, 20],
+            DiskState::Creating => self.weights.creating,
+            DiskState::Detached => self.weights.detached,
+            DiskState::Faulted => self.weights.faulted,
 
-            // If the disk is detached, equally perform any action on it.
-            DiskState::Detached => [35, 30, 35],
+            // Attached disks can't be deleted, and attach and detach
+            // transitions are driven by whoever is attaching the disk, so just
+            // wait for the disk to come back.
+            DiskState::Attaching { .. }
+            | DiskState::Attached { .. }
+            | DiskState::Detaching { .. } => ActionWeights::new(100, 0, 0),
 
             _ => {
                 return Action::Bail {
@@ -172,21 +449,45 @@ impl DiskActor {
 
         // `new` returns an error if the iterator is empty, if any weight is <
         // 0, or if its total value is 0.
-        let dist = rand::distributions::WeightedIndex::new(weights).unwrap();
-        let mut rng = rand::thread_rng();
-        actions[dist.sample(&mut rng)].clone()
+        let dist =
+            rand::distributions::WeightedIndex::new(weights.table()).unwrap();
+        actions[dist.sample(&mut self.rng)].clone()
     }
-}
 
-#[async_trait]
-impl super::Antagonist for DiskActor {
-    #[tracing::instrument(level = "info", skip(self), fields(disk_name = self.disk_name))]
-    async fn antagonize(&self) -> Result<(), AntagonistError> {
-        trace!("querying disk state");
-        let state = match self.get_disk_state().await? {
+    /// Takes one step: views the disk (or uses its cached state), then acts
+    /// on it.
+    async fn take_step(&mut self) -> Result<(), AntagonistError> {
+        let name = self.disk_name.clone();
+        self.refresh_generation();
+        if self.disk_name != name {
+            self.state.invalidate();
+        }
+
+        let state = match self.state.get() {
+            Some(state) => {
+                trace!(?state, "using cached disk state");
+                state
+            }
+            None => {
+                trace!("querying disk state");
+                let disk = self.get_disk().await?;
+                if let Some((_, size)) = disk {
+                    self.size = Some(size);
+                }
+                let state = disk.map(|(state, _)| state);
+                super::report_disk_state(&self.disk_name, state.clone());
+                self.state.observed(state.clone());
+                state
+            }
+        };
+        let state = match state {
             None => {
                 info!("disk doesn't exist, will try to create it");
-                return self.create_disk().await.map_err(Into::into);
+                self.retire_generation();
+                let sent = Instant::now();
+                let size = self.create_disk().await?;
+                self.state.expect(Some(DiskState::Creating));
+                return self.settle_create(sent, size).await;
             }
             Some(state) => {
                 trace!(?state, "got disk state");
@@ -194,13 +495,40 @@ impl super::Antagonist for DiskActor {
             }
         };
 
-        sleep_random_ms(100).await;
+        sleep_random(&mut self.rng, self.think_time).await;
+
+        // Count each fault once, no matter how many times the faulted disk is
+        // observed before it gets deleted.
+        let faulted = matches!(state, DiskState::Faulted);
+        if faulted && !self.was_faulted {
+            let faults = FAULTS.fetch_add(1, Ordering::Relaxed) + 1;
+            warn!(faults, "disk became faulted");
+        }
+        self.was_faulted = faulted;
 
-        let action = self.get_next_action(state);
+        let action = self.get_next_action(state.clone());
         trace!(?action, "selected action");
+        crate::journal::note_action(&action, Some(&state));
+        let deleting = matches!(action, Action::Delete);
+        let deleted_id = deleting
+            .then(|| super::model::id(ResourceKind::Disk, &self.disk_name))
+            .flatten();
+
+        // The state each action should leave the disk in, if it succeeds.
+        let expected = match &action {
+            Action::Wait | Action::Bail { .. } => None,
+            Action::Create => Some(Some(DiskState::Creating)),
+            Action::Delete => Some(None),
+        };
+        let sent = Instant::now();
+
+        // The size of the disk that a successful create made.
+        let mut size = None;
         let result = match action {
             Action::Wait => Ok(()),
-            Action::Create => self.create_disk().await,
+            Action::Create => self.create_disk().await.map(|created| {
+                size = Some(created);
+            }),
             Action::Delete => self.delete_disk().await,
             Action::Bail { reason } => match reason {
                 BailReason::InvalidState { state } => {
@@ -211,9 +539,110 @@ impl super::Antagonist for DiskActor {
                 }
             },
         };
+        if let (Some(state), true) = (expected, result.is_ok()) {
+            self.state.expect(state);
+        }
+
+        if result.is_ok() && deleting && faulted {
+            DELETED.fetch_add(1, Ordering::Relaxed);
+            self.replacing = true;
+        } else if result.is_ok() && size.is_some() && self.replacing {
+            RECREATED.fetch_add(1, Ordering::Relaxed);
+            self.replacing = false;
+        }
+
+        if let Some(size) = size {
+            self.settle_create(sent, size).await?;
+        }
+
+        if deleting && result.is_ok() {
+            self.settle_delete(sent).await?;
+            super::verify::deleted(
+                &self.client,
+                &self.project,
+                ResourceKind::Disk,
+                &self.disk_name,
+                deleted_id,
+            )
+            .await?;
+        }
 
-        sleep_random_ms(100).await;
+        sleep_random(&mut self.rng, self.think_time).await;
 
         result.map_err(Into::into)
     }
 }
+
+#[async_trait]
+impl super::Antagonist for DiskActor {
+    fn set_client(&mut self, client: oxide::Client) {
+        self.client = client;
+    }
+
+    fn set_profile(&mut self, profile: crate::profile::Profile) {
+        self.weights = profile.disk_weights();
+    }
+
+    #[tracing::instrument(level = "info", skip(self), fields(disk_name = self.base_name))]
+    async fn step(&mut self) -> Result<(), AntagonistError> {
+        let result = self.take_step().await;
+        if result.is_err() {
+            self.state.invalidate();
+        }
+
+        result
+    }
+
+    #[tracing::instrument(level = "info", skip(self), fields(disk_name = self.base_name))]
+    async fn check_abandoned(
+        &mut self,
+        endpoint: &'static str,
+    ) -> Result<(), AntagonistError> {
+        let name = self.disk_name.clone();
+        super::abandon::settle(
+            endpoint,
+            &name,
+            || self.get_disk_state(),
+            |state| {
+                !matches!(
+                    state,
+                    Some(
+                        DiskState::Creating
+                            | DiskState::Attaching { .. }
+                            | DiskState::Detaching { .. }
+                    )
+                )
+            },
+        )
+        .await
+    }
+
+    #[tracing::instrument(level = "info", skip(self), fields(disk_name = self.base_name))]
+    async fn drain(&mut self) -> Result<(), AntagonistError> {
+        // Other actors sharing this disk may be draining it too, so requests
+        // that fail because its state changed are expected.
+        self.refresh_generation();
+        let start = std::time::Instant::now();
+        loop {
+            let state = match self.get_disk_state().await? {
+                None => return Ok(()),
+                Some(state) => state,
+            };
+
+            if matches!(state, DiskState::Detached | DiskState::Faulted) {
+                ok_if_error_response(self.delete_disk().await)?;
+            }
+
+            if start.elapsed() > super::DRAIN_TIMEOUT {
+                return Err(AntagonistError::InvalidState(format!(
+                    "disk {} not deleted after {:?} (state: {:?})",
+                    self.disk_name,
+                    super::DRAIN_TIMEOUT,
+                    state,
+                )));
+            }
+
+            tokio::time::sleep(std::time::Duration::from_secs(1)).await;
+        }
+    }
+}
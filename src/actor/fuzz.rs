@@ -0,0 +1,231 @@
+//! An antagonist that sends deliberately invalid create requests (bad names,
+//! absurd sizes, negative counts, wrong block sizes, overlong descriptions,
+//! and bodies that aren't even JSON) and checks that Nexus rejects each one
+//! cleanly: with a 4xx status and an error body that matches the API's error
+//! type. A 5xx, a malformed error body, a dropped connection, or an accepted
+//! request is a finding.
+//!
+//! The SDK's types won't let most of these requests be built, so the fuzzer
+//! sends them raw (see `super::raw`).
+
+use async_trait::async_trait;
+use core::result::Result;
+use rand::rngs::StdRng;
+use rand::Rng;
+use serde_json::{json, Value};
+use tracing::info;
+
+use super::raw::{self, Target};
+use crate::actor::AntagonistError;
+
+/// The longest to wait between requests, in milliseconds. Rejections come
+/// back quickly, so without a pause a fuzzer would send little but these.
+const MAX_THINK_MILLIS: u64 = 1000;
+
+/// The parameters used to configure a fuzzer.
+pub struct Params {
+    /// The name of the project to send this fuzzer's requests to.
+    pub project: String,
+
+    /// The name to give the resources in requests whose names aren't what's
+    /// being fuzzed.
+    pub resource_name: String,
+}
+
+/// What a fuzz case does to a valid create body.
+#[derive(Clone, Copy)]
+enum Mutation {
+    /// Sets a field to a value.
+    Set(&'static str, fn() -> Value),
+
+    /// Removes a field.
+    Remove(&'static str),
+
+    /// Replaces the whole body with something that isn't JSON.
+    Raw(&'static str),
+}
+
+/// A deliberately invalid create request.
+struct Case {
+    /// What's wrong with the request.
+    name: &'static str,
+    target: Target,
+    mutation: Mutation,
+}
+
+const fn case(name: &'static str, target: Target, mutation: Mutation) -> Case {
+    Case { name, target, mutation }
+}
+
+/// Every case a fuzzer picks from.
+const CASES: &[Case] = &[
+    case(
+        "name with invalid characters",
+        Target::Disk,
+        Mutation::Set("name", || json!("Not A Valid Name!")),
+    ),
+    case("empty name", Target::Disk, Mutation::Set("name", || json!(""))),
+    case(
+        "overlong name",
+        Target::Disk,
+        Mutation::Set("name", || json!("a".repeat(64))),
+    ),
+    case(
+        "name starting with a digit",
+        Target::Disk,
+        Mutation::Set("name", || json!("1disk")),
+    ),
+    case("zero size", Target::Disk, Mutation::Set("size", || json!(0))),
+    case("negative size", Target::Disk, Mutation::Set("size", || json!(-1))),
+    case(
+        "absurd size",
+        Target::Disk,
+        Mutation::Set("size", || json!(u64::MAX)),
+    ),
+    case(
+        "size not a multiple of the block size",
+        Target::Disk,
+        Mutation::Set("size", || json!(1000)),
+    ),
+    case(
+        "size of the wrong type",
+        Target::Disk,
+        Mutation::Set("size", || json!("big")),
+    ),
+    case("missing size", Target::Disk, Mutation::Remove("size")),
+    case(
+        "unsupported block size",
+        Target::Disk,
+        Mutation::Set(
+            "disk_source",
+            || json!({ "type": "blank", "block_size": 513 }),
+        ),
+    ),
+    case(
+        "zero block size",
+        Target::Disk,
+        Mutation::Set(
+            "disk_source",
+            || json!({ "type": "blank", "block_size": 0 }),
+        ),
+    ),
+    case(
+        "unknown disk source",
+        Target::Disk,
+        Mutation::Set("disk_source", || json!({ "type": "bogus" })),
+    ),
+    case(
+        "overlong description",
+        Target::Disk,
+        Mutation::Set("description", || json!("x".repeat(10_000))),
+    ),
+    case("malformed JSON", Target::Disk, Mutation::Raw(r#"{"name": "#)),
+    case(
+        "name with invalid characters",
+        Target::Instance,
+        Mutation::Set("name", || json!("Not A Valid Name!")),
+    ),
+    case(
+        "invalid hostname",
+        Target::Instance,
+        Mutation::Set("hostname", || json!("not a hostname!")),
+    ),
+    case("zero vCPUs", Target::Instance, Mutation::Set("ncpus", || json!(0))),
+    case(
+        "negative vCPUs",
+        Target::Instance,
+        Mutation::Set("ncpus", || json!(-1)),
+    ),
+    case(
+        "absurd vCPU count",
+        Target::Instance,
+        Mutation::Set("ncpus", || json!(1_000_000)),
+    ),
+    case("zero memory", Target::Instance, Mutation::Set("memory", || json!(0))),
+    case(
+        "negative memory",
+        Target::Instance,
+        Mutation::Set("memory", || json!(-1)),
+    ),
+    case(
+        "absurd memory",
+        Target::Instance,
+        Mutation::Set("memory", || json!(u64::MAX)),
+    ),
+    case(
+        "unaligned memory",
+        Target::Instance,
+        Mutation::Set("memory", || json!(1_000_001)),
+    ),
+    case(
+        "overlong description",
+        Target::Instance,
+        Mutation::Set("description", || json!("x".repeat(10_000))),
+    ),
+    case("malformed JSON", Target::Instance, Mutation::Raw(r#"{"ncpus": 1,"#)),
+];
+
+/// The internal state for a fuzzer.
+#[derive(Debug)]
+pub(super) struct FuzzActor {
+    client: oxide::Client,
+    project: String,
+    resource_name: String,
+    rng: StdRng,
+}
+
+impl FuzzActor {
+    /// Creates a new fuzzer.
+    pub(super) fn new(params: Params, rng: StdRng) -> anyhow::Result<Self> {
+        Ok(Self {
+            client: crate::client::get_client(crate::config())?,
+            project: params.project,
+            resource_name: params.resource_name,
+            rng,
+        })
+    }
+}
+
+#[async_trait]
+impl super::Antagonist for FuzzActor {
+    fn set_client(&mut self, client: oxide::Client) {
+        self.client = client;
+    }
+
+    #[tracing::instrument(level = "info", skip(self), fields(resource_name = self.resource_name))]
+    async fn step(&mut self) -> Result<(), AntagonistError> {
+        let case = &CASES[self.rng.gen_range(0..CASES.len())];
+        let mut body = case.target.valid_body(&self.resource_name);
+        let body = match case.mutation {
+            Mutation::Set(field, value) => {
+                body[field] = value();
+                body.to_string()
+            }
+            Mutation::Remove(field) => {
+                body.as_object_mut().unwrap().remove(field);
+                body.to_string()
+            }
+            Mutation::Raw(text) => text.to_owned(),
+        };
+
+        info!(target = ?case.target, case = case.name, "sending invalid request");
+        let result =
+            raw::create(&self.client, &self.project, case.target, body.clone())
+                .await;
+
+        // Don't leave behind anything an accepted request created.
+        if result.is_ok() {
+            raw::clean_up(&self.client, &self.project, case.target, &body)
+                .await;
+        }
+
+        crate::util::sleep_random_ms(&mut self.rng, MAX_THINK_MILLIS).await;
+        match raw::check_rejected(&result) {
+            Some(problem) => Err(AntagonistError::CheckFailed(format!(
+                "invalid {:?} create ({}): {}",
+                case.target, case.name, problem
+            ))),
+            None => Ok(()),
+        }
+    }
+}
@@ -1,16 +1,25 @@
 //! An antagonist that exercises instance lifecycle commands (create, start,
 //! stop, destroy).
 
+use std::time::{Duration, Instant};
+
 use async_trait::async_trait;
 use core::result::Result;
 use oxide::types::InstanceState;
 use oxide::ClientInstancesExt;
+use rand::rngs::StdRng;
+use serde::Deserialize;
 use tracing::{info, trace, warn};
 
+use crate::actor::ownership::ResourceKind;
 use crate::actor::AntagonistError;
-use crate::util::sleep_random_ms;
+use crate::sla::Progress;
+use crate::util::ok_if_error_response;
+use crate::util::ok_if_not_found;
+use crate::util::sleep_random;
 use crate::util::unwrap_oxide_api_error;
 use crate::util::OxideApiError;
+use crate::util::SleepRange;
 
 #[derive(Debug, Clone)]
 enum BailReason {
@@ -34,8 +43,166 @@ pub struct Params {
     /// The name of the project to create this antagonist's instance in.
     pub project: String,
 
-    /// The name of the instance this antagonist should act on.
-    pub instance_name: String,
+    /// The names of the instances this antagonist should act on. It picks
+    /// one at random for each step.
+    pub instance_names: Vec<String>,
+
+    /// If true, skip querying the instance's state and sleeping between
+    /// actions, and instead fire lifecycle requests back-to-back.
+    pub storm: bool,
+
+    /// The relative likelihood of each action in each instance state.
+    pub weights: Weights,
+
+    /// How often this antagonist's creates ask for networking.
+    pub networking: Networking,
+
+    /// How long to sleep before and after each action.
+    pub think_time: SleepRange,
+}
+
+/// How often an instance antagonist asks for networking when it creates its
+/// instance. Creates without it skip much of the instance create saga.
+#[derive(Clone, Copy, Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Networking {
+    /// The fraction of creates that ask for a default NIC.
+    pub nic: f64,
+
+    /// The fraction of creates that ask for an ephemeral external IP from the
+    /// default pool. These also ask for a default NIC, which the IP needs.
+    pub external_ip: f64,
+}
+
+impl Networking {
+    /// Returns an error if either fraction isn't between 0 and 1.
+    pub fn validate(&self) -> anyhow::Result<()> {
+        for (what, fraction) in
+            [("nic", self.nic), ("external_ip", self.external_ip)]
+        {
+            anyhow::ensure!(
+                (0.0..=1.0).contains(&fraction),
+                "instance networking {} must be from 0 to 1, not {}",
+                what,
+                fraction
+            );
+        }
+
+        Ok(())
+    }
+}
+
+/// The relative weights of the actions an instance antagonist can take from
+/// one state. Actions that are left out of a scenario file get weight 0.
+#[derive(Clone, Copy, Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ActionWeights {
+    pub wait: u32,
+    pub create: u32,
+    pub start: u32,
+    pub stop: u32,
+    pub destroy: u32,
+}
+
+impl ActionWeights {
+    pub const fn new(
+        wait: u32,
+        create: u32,
+        start: u32,
+        stop: u32,
+        destroy: u32,
+    ) -> Self {
+        Self { wait, create, start, stop, destroy }
+    }
+
+    fn table(&self) -> [u32; 5] {
+        [self.wait, self.create, self.start, self.stop, self.destroy]
+    }
+}
+
+/// The action weights an instance antagonist uses for each state it can find
+/// its instance in. States that are left out of a scenario file keep their
+/// default weights.
+#[derive(Clone, Copy, Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Weights {
+    /// Weights for instances that are creating or starting.
+    pub starting: ActionWeights,
+
+    /// Weights for instances that are running, rebooting, or stopping.
+    pub running: ActionWeights,
+
+    /// Weights for stopped instances.
+    pub stopped: ActionWeights,
+
+    /// Weights for failed instances.
+    pub failed: ActionWeights,
+}
+
+impl Default for Weights {
+    fn default() -> Self {
+        Self {
+            // If the instance is still starting up, favor politely waiting for
+            // it to finish.
+            starting: ActionWeights::new(60, 10, 10, 10, 10),
+
+            // If the instance is running or winding down, give it a mix of
+            // operations that favors asking to start or stop it again.
+            running: ActionWeights::new(35, 5, 25, 25, 10),
+
+            // If the instance is already stopped, favor starting it again, but
+            // give it a modest chance of being destroyed.
+            stopped: ActionWeights::new(25, 5, 40, 10, 20),
+
+            // The control plane can legitimately move an instance to Failed,
+            // so mostly try to delete it (so that it can be recreated), and
+            // otherwise wait to see if it is recovered by someone else.
+            failed: ActionWeights::new(20, 0, 0, 0, 80),
+        }
+    }
+}
+
+impl Weights {
+    /// Returns an error if every action in some state has weight 0.
+    pub fn validate(&self) -> anyhow::Result<()> {
+        for (state, weights) in [
+            ("starting", &self.starting),
+            ("running", &self.running),
+            ("stopped", &self.stopped),
+            ("failed", &self.failed),
+        ] {
+            anyhow::ensure!(
+                weights.table().iter().any(|w| *w > 0),
+                "instance weights for the {} state are all zero",
+                state
+            );
+        }
+
+        Ok(())
+    }
+}
+
+/// Views the instance named `name` in `project`.
+///
+/// # Return value
+///
+/// - Ok(Some(instance)) if the query succeeded.
+/// - Ok(None) if the query failed with a "not found" error.
+/// - Err if the query failed for any other reason.
+pub(super) async fn view(
+    client: &oxide::Client,
+    project: &str,
+    name: &str,
+) -> Result<Option<oxide::types::Instance>, OxideApiError> {
+    let res = crate::middleware::call("instance_view", || {
+        client.instance_view().project(project).instance(name).send()
+    })
+    .await;
+
+    match res {
+        Ok(rv) => Ok(Some(rv.into_inner())),
+        Err(e) => ok_if_not_found(Err(e)).map(|()| None),
+    }
 }
 
 /// The internal state for an instance antagonist.
@@ -43,72 +210,168 @@ pub struct Params {
 pub(super) struct InstanceActor {
     client: oxide::Client,
     project: String,
+
+    /// The name of the instance this actor is currently acting on. In
+    /// unique-name mode, this is the current generation of `base_name`.
+    instance_name: String,
+    base_name: String,
+
+    /// In unique-name mode, the generation of the instance named by
+    /// `instance_name`.
+    generation: Option<u64>,
+    storm: bool,
+    weights: Weights,
+    networking: Networking,
+    think_time: SleepRange,
+
+    /// The instance's state as of the last step, for --state-refresh-steps.
+    state: super::cache::CachedState<InstanceState>,
+
+    /// The other instances this actor acts on (--instances-per-actor), which
+    /// trade places with the current one when it picks them.
+    others: Vec<Target>,
+    rng: StdRng,
+}
+
+/// One of an instance antagonist's instances, while it isn't the one the
+/// antagonist is acting on.
+#[derive(Debug)]
+struct Target {
     instance_name: String,
+    base_name: String,
+    generation: Option<u64>,
+    state: super::cache::CachedState<InstanceState>,
+}
+
+impl Target {
+    fn new(base_name: String) -> Self {
+        Self {
+            instance_name: base_name.clone(),
+            base_name,
+            generation: crate::config().unique_names.then_some(0),
+            state: Default::default(),
+        }
+    }
 }
 
 impl InstanceActor {
     /// Creates a new instance antagonist.
-    pub(super) fn new(params: Params) -> anyhow::Result<Self> {
+    pub(super) fn new(params: Params, rng: StdRng) -> anyhow::Result<Self> {
+        let mut targets = params.instance_names.into_iter().map(Target::new);
+        let first = targets
+            .next()
+            .ok_or_else(|| anyhow::anyhow!("no instance names"))?;
         Ok(Self {
             client: crate::client::get_client(crate::config())?,
             project: params.project,
-            instance_name: params.instance_name,
+            instance_name: first.instance_name,
+            base_name: first.base_name,
+            generation: first.generation,
+            storm: params.storm,
+            weights: params.weights,
+            networking: params.networking,
+            think_time: params.think_time,
+            state: first.state,
+            others: targets.collect(),
+            rng,
         })
     }
 
+    /// Trades the current instance for `self.others[i]`.
+    fn swap_target(&mut self, i: usize) {
+        let other = &mut self.others[i];
+        std::mem::swap(&mut self.instance_name, &mut other.instance_name);
+        std::mem::swap(&mut self.base_name, &mut other.base_name);
+        std::mem::swap(&mut self.generation, &mut other.generation);
+        std::mem::swap(&mut self.state, &mut other.state);
+    }
+
+    /// Picks which of this actor's instances to act on next, at random.
+    fn pick_target(&mut self) {
+        use rand::Rng;
+
+        let i = self.rng.gen_range(0..=self.others.len());
+        if i < self.others.len() {
+            self.swap_target(i);
+        }
+    }
+
+    /// In unique-name mode, points this actor at the current generation of its
+    /// instance, which another actor sharing it may have moved forward.
+    fn refresh_generation(&mut self) {
+        if self.generation.is_some() {
+            let generation = super::current_generation(&self.base_name);
+            self.generation = Some(generation);
+            self.instance_name =
+                super::generation_name(&self.base_name, generation);
+        }
+    }
+
+    /// In unique-name mode, moves this actor on to a new generation of its
+    /// instance after finding that the current one doesn't exist, so that the
+    /// next create uses a fresh name.
+    fn retire_generation(&mut self) {
+        if let Some(generation) = self.generation {
+            let generation =
+                super::retire_generation(&self.base_name, generation);
+            self.generation = Some(generation);
+            self.instance_name =
+                super::generation_name(&self.base_name, generation);
+        }
+    }
+
     /// Gets this actor's instance's current state.
     ///
     /// # Return value
     ///
     /// - Ok(Some(state)) if the query succeeded.
     /// - Ok(None) if the query failed with a "not found" error.
-    /// - Err if the query failed for any other reason.
+    /// - Err if the query failed for any other reason, or if its result
+    ///   contradicts the expected-state model.
     async fn get_instance_state(
         &self,
-    ) -> Result<Option<InstanceState>, OxideApiError> {
-        let res = self
-            .client
-            .instance_view()
-            .project(&self.project)
-            .instance(&self.instance_name)
-            .send()
-            .await;
-
-        match res {
-            Ok(response_value) => {
-                Ok(Some(response_value.into_inner().run_state))
-            }
-            Err(e) => match &e {
-                oxide::Error::InvalidRequest(_)
-                | oxide::Error::CommunicationError(_)
-                | oxide::Error::InvalidResponsePayload(_, _)
-                | oxide::Error::UnexpectedResponse(_)
-                | oxide::Error::InvalidUpgrade(_)
-                | oxide::Error::ResponseBodyError(_)
-                | oxide::Error::PreHookError(_) => Err(e),
-
-                oxide::Error::ErrorResponse(response_value) => {
-                    let status = response_value.status();
-
-                    // It's OK if the instance just isn't there. Any other error
-                    // is unexpected.
-                    if status == http::StatusCode::NOT_FOUND {
-                        Ok(None)
-                    } else {
-                        Err(e)
-                    }
-                }
-            },
-        }
+    ) -> Result<Option<InstanceState>, AntagonistError> {
+        let sent = Instant::now();
+        let found = view(&self.client, &self.project, &self.instance_name)
+            .await?
+            .map(|instance| (instance.run_state, instance.id));
+
+        let id = match &found {
+            Some((InstanceState::Destroyed, _)) | None => None,
+            Some((_, id)) => Some(*id),
+        };
+        super::model::observe(
+            ResourceKind::Instance,
+            &self.instance_name,
+            sent,
+            id,
+        )?;
+        Ok(found.map(|(state, _)| state))
     }
 
     /// Asks to create this actor's instance. The created instance has 1 vCPU,
-    /// 1 GB RAM, and no disks or NICs.
-    async fn create_instance(&self) -> Result<(), OxideApiError> {
-        let body = oxide::types::InstanceCreate {
-            description: self.instance_name.to_owned(),
+    /// 1 GB RAM, and no disks. It has a default NIC and an ephemeral external
+    /// IP as often as --instance-nic-fraction and
+    /// --instance-external-ip-fraction (or the scenario file) say. With
+    /// --vary-instance-creates, the body's optional fields vary from one
+    /// create to the next (see `super::vary`).
+    ///
+    /// Returns the state the create should leave the instance in.
+    async fn create_instance(
+        &mut self,
+    ) -> Result<InstanceState, OxideApiError> {
+        use rand::Rng;
+
+        let external_ip = self.rng.gen_bool(self.networking.external_ip);
+        let nic = external_ip || self.rng.gen_bool(self.networking.nic);
+        let mut body = oxide::types::InstanceCreate {
+            description: crate::run_info::description(&self.instance_name),
             disks: vec![],
-            external_ips: vec![],
+            external_ips: if external_ip {
+                vec![oxide::types::ExternalIpCreate::Ephemeral { pool: None }]
+            } else {
+                vec![]
+            },
             hostname: self.instance_name.parse().map_err(|e| {
                 OxideApiError::InvalidRequest(format!(
                     "{} is not a valid hostname: {e}",
@@ -118,91 +381,184 @@ impl InstanceActor {
             memory: oxide::types::ByteCount(1024 * 1024 * 1024),
             name: oxide::types::Name::try_from(&self.instance_name).unwrap(),
             ncpus: oxide::types::InstanceCpuCount(1),
-            network_interfaces:
-                oxide::types::InstanceNetworkInterfaceAttachment::None,
+            network_interfaces: if nic {
+                oxide::types::InstanceNetworkInterfaceAttachment::Default
+            } else {
+                oxide::types::InstanceNetworkInterfaceAttachment::None
+            },
             start: true,
             user_data: String::new(),
             ssh_public_keys: None,
         };
+        if crate::config().vary_instance_creates {
+            super::vary::instance_create(
+                &mut body,
+                &self.instance_name,
+                &mut self.rng,
+            );
+        }
+        let created = if body.start {
+            InstanceState::Starting
+        } else {
+            InstanceState::Stopped
+        };
 
         info!(body = ?body, "sending instance create request");
-        let res = self
-            .client
-            .instance_create()
-            .project(&self.project)
-            .body(body)
-            .send()
-            .await;
-
-        if res.is_err() {
-            warn!(result = ?res, "instance create request returned");
-        } else {
-            info!(result = ?res, "instance create request returned");
-        }
+        let pending = super::model::begin(
+            ResourceKind::Instance,
+            &self.instance_name,
+            super::model::Op::Create,
+        );
+        let res = crate::middleware::call("instance_create", || {
+            self.client
+                .instance_create()
+                .project(&self.project)
+                .body(body.clone())
+                .send()
+        })
+        .await;
+        pending.finish_create(res.as_ref().ok().map(|rv| rv.id));
 
-        unwrap_oxide_api_error(res)
+        unwrap_oxide_api_error(res).map(|()| created)
     }
 
     /// Asks to start this actor's instance.
     async fn start_instance(&self) -> Result<(), OxideApiError> {
         info!("sending instance start request");
-        let res = self
-            .client
-            .instance_start()
-            .project(&self.project)
-            .instance(&self.instance_name)
-            .send()
-            .await;
-
-        if res.is_err() {
-            warn!(result = ?res, "instance start request returned");
-        } else {
-            info!(result = ?res, "instance start request returned");
-        }
+        let res = crate::middleware::call("instance_start", || {
+            self.client
+                .instance_start()
+                .project(&self.project)
+                .instance(&self.instance_name)
+                .send()
+        })
+        .await;
+
         unwrap_oxide_api_error(res)
     }
 
     /// Asks to stop this actor's instance.
     async fn stop_instance(&self) -> Result<(), OxideApiError> {
         info!("sending instance stop request");
-        let res = self
-            .client
-            .instance_stop()
-            .project(&self.project)
-            .instance(&self.instance_name)
-            .send()
-            .await;
-
-        if res.is_err() {
-            warn!(result = ?res, "instance stop request returned");
-        } else {
-            info!(result = ?res, "instance stop request returned");
-        }
+        let res = crate::middleware::call("instance_stop", || {
+            self.client
+                .instance_stop()
+                .project(&self.project)
+                .instance(&self.instance_name)
+                .send()
+        })
+        .await;
+
         unwrap_oxide_api_error(res)
     }
 
+    /// If --instance-create-sla or --measure-transitions is set, waits for
+    /// this actor's instance, which was just created by a request sent at
+    /// `sent`, to reach Running, and records an SLA violation if it takes
+    /// longer than the SLA, or how long it took. Gives up without a verdict if
+    /// the instance is stopped or deleted by another actor in the meantime.
+    async fn settle_create(
+        &self,
+        sent: Instant,
+    ) -> Result<(), AntagonistError> {
+        self.settle_running(
+            "instance_create",
+            crate::config().instance_create_sla,
+            sent,
+        )
+        .await
+    }
+
+    /// If --measure-transitions is set, waits for this actor's instance,
+    /// which was just asked to start by a request sent at `sent`, to reach
+    /// Running, and records how long that took.
+    async fn settle_start(&self, sent: Instant) -> Result<(), AntagonistError> {
+        self.settle_running("instance_start", None, sent).await
+    }
+
+    /// Waits for this actor's instance to reach Running after a successful
+    /// `operation` (see `crate::sla::settle`).
+    async fn settle_running(
+        &self,
+        operation: &'static str,
+        sla: Option<Duration>,
+        sent: Instant,
+    ) -> Result<(), AntagonistError> {
+        crate::sla::settle(
+            operation,
+            &self.instance_name,
+            sla,
+            Some(crate::sla::Measure::since(sent)),
+            || self.get_instance_state(),
+            |state| match state {
+                Some(InstanceState::Running) => Progress::Done,
+                Some(InstanceState::Creating | InstanceState::Starting) => {
+                    Progress::Pending
+                }
+                Some(InstanceState::Failed) => Progress::Failed,
+                _ => Progress::Interrupted,
+            },
+        )
+        .await
+    }
+
+    /// If --stop-sla or --measure-transitions is set, waits for this actor's
+    /// instance, which was just asked to stop by a request sent at `sent`, to
+    /// reach Stopped, and records an SLA violation if it takes longer than
+    /// the SLA, or how long it took. Gives up without a verdict if the
+    /// instance is started or deleted by another actor in the meantime.
+    async fn settle_stop(&self, sent: Instant) -> Result<(), AntagonistError> {
+        let mut seen_stopping = false;
+        crate::sla::settle(
+            "instance_stop",
+            &self.instance_name,
+            crate::config().stop_sla,
+            Some(crate::sla::Measure::since(sent)),
+            || self.get_instance_state(),
+            |state| match state {
+                Some(InstanceState::Stopped) => Progress::Done,
+                Some(InstanceState::Failed) => Progress::Failed,
+                Some(InstanceState::Stopping) => {
+                    seen_stopping = true;
+                    Progress::Pending
+                }
+
+                // The stop may not have taken effect yet, but once the
+                // instance has been seen stopping, Running means someone
+                // started it again.
+                Some(InstanceState::Running) if !seen_stopping => {
+                    Progress::Pending
+                }
+                _ => Progress::Interrupted,
+            },
+        )
+        .await
+    }
+
     /// Asks to delete this actor's instance.
     async fn delete_instance(&self) -> Result<(), OxideApiError> {
         info!("sending instance delete request");
-        let res = self
-            .client
-            .instance_delete()
-            .project(&self.project)
-            .instance(&self.instance_name)
-            .send()
-            .await;
-
-        if res.is_err() {
-            warn!(result = ?res, "instance delete request returned");
-        } else {
-            info!(result = ?res, "instance delete request returned");
-        }
+        let pending = super::model::begin(
+            ResourceKind::Instance,
+            &self.instance_name,
+            super::model::Op::Delete,
+        );
+        let res = crate::middleware::call("instance_delete", || {
+            self.client
+                .instance_delete()
+                .project(&self.project)
+                .instance(&self.instance_name)
+                .send()
+        })
+        .await;
+        pending.finish(res.is_ok());
+
         unwrap_oxide_api_error(res)
     }
 
     /// Selects an action for this antagonist to take given that its instance
     /// was observed to be in the supplied `state`.
-    fn get_next_action(&self, state: InstanceState) -> Action {
+    fn get_next_action(&mut self, state: InstanceState) -> Action {
         use rand::prelude::Distribution;
         let actions = [
             Action::Wait,
@@ -213,27 +569,20 @@ impl InstanceActor {
         ];
 
         let weights = match state {
-            // If the instance is still starting up, favor politely waiting for
-            // it to finish.
             InstanceState::Creating | InstanceState::Starting => {
-                [60, 10, 10, 10, 10]
+                self.weights.starting
             }
-            // If the instance is running or winding down, give it a mix of
-            // operations that favors asking to start or stop it again.
             InstanceState::Running
             | InstanceState::Rebooting
-            | InstanceState::Stopping => [35, 5, 25, 25, 10],
-
-            // If the instance is already stopped, favor starting it again, but
-            // give it a modest chance of being destroyed.
-            InstanceState::Stopped => [25, 5, 40, 10, 20],
+            | InstanceState::Stopping => self.weights.running,
+            InstanceState::Stopped => self.weights.stopped,
+            InstanceState::Failed => self.weights.failed,
 
             // Raise errors for things that shouldn't happen or unrecoverable
             // conditions.
             InstanceState::Migrating
             | InstanceState::Repairing
-            | InstanceState::Destroyed
-            | InstanceState::Failed => {
+            | InstanceState::Destroyed => {
                 return Action::Bail {
                     reason: BailReason::InvalidState { state },
                 };
@@ -242,21 +591,122 @@ impl InstanceActor {
 
         // `new` returns an error if the iterator is empty, if any weight is <
         // 0, or if its total value is 0.
+        let dist =
+            rand::distributions::WeightedIndex::new(weights.table()).unwrap();
+        actions[dist.sample(&mut self.rng)].clone()
+    }
+
+    /// Selects an action for a storm antagonist, which doesn't know what state
+    /// its instance is in. Creation is rare since it is also done whenever an
+    /// action finds that the instance doesn't exist.
+    fn get_storm_action(&mut self) -> Action {
+        use rand::prelude::Distribution;
+        let actions =
+            [Action::Create, Action::Start, Action::Stop, Action::Destroy];
+        let weights = [5, 35, 35, 25];
+
         let dist = rand::distributions::WeightedIndex::new(weights).unwrap();
-        let mut rng = rand::thread_rng();
-        actions[dist.sample(&mut rng)].clone()
+        actions[dist.sample(&mut self.rng)].clone()
     }
-}
 
-#[async_trait]
-impl super::Antagonist for InstanceActor {
-    #[tracing::instrument(level = "info", skip(self), fields(instance_name = self.instance_name))]
-    async fn antagonize(&self) -> Result<(), AntagonistError> {
-        trace!("querying instance state");
-        let state = match self.get_instance_state().await? {
+    /// Fires a single lifecycle request at this actor's instance without
+    /// checking its state first, creating the instance if the request found
+    /// that it doesn't exist.
+    async fn storm(&mut self) -> Result<(), AntagonistError> {
+        let action = self.get_storm_action();
+        trace!(?action, "selected storm action");
+        crate::journal::note_action(&action, None);
+        let creating = matches!(action, Action::Create);
+        let stopping = matches!(action, Action::Stop);
+        let deleting = matches!(action, Action::Destroy);
+        let deleted_id = deleting
+            .then(|| {
+                super::model::id(ResourceKind::Instance, &self.instance_name)
+            })
+            .flatten();
+        let starting = matches!(action, Action::Start);
+        let sent = Instant::now();
+        let result = match action {
+            Action::Create => self.create_instance().await.map(|_| ()),
+            Action::Start => self.start_instance().await,
+            Action::Stop => self.stop_instance().await,
+            Action::Destroy => self.delete_instance().await,
+            Action::Wait | Action::Bail { .. } => {
+                unreachable!("storm actions never wait or bail")
+            }
+        };
+
+        if creating && result.is_ok() {
+            self.settle_create(sent).await?;
+        }
+
+        if starting && result.is_ok() {
+            self.settle_start(sent).await?;
+        }
+
+        if stopping && result.is_ok() {
+            self.settle_stop(sent).await?;
+        }
+
+        if deleting && result.is_ok() {
+            super::verify::deleted(
+                &self.client,
+                &self.project,
+                ResourceKind::Instance,
+                &self.instance_name,
+                deleted_id,
+            )
+            .await?;
+        }
+
+        match result {
+            Err(oxide::Error::ErrorResponse(rv))
+                if rv.status() == http::StatusCode::NOT_FOUND =>
+            {
+                info!("instance doesn't exist, will try to create it");
+                self.retire_generation();
+                let sent = Instant::now();
+                self.create_instance().await?;
+                self.settle_create(sent).await
+            }
+            result => result.map_err(Into::into),
+        }
+    }
+
+    /// Takes one step: views the instance (or uses its cached state), then
+    /// acts on it.
+    async fn take_step(&mut self) -> Result<(), AntagonistError> {
+        self.pick_target();
+        let name = self.instance_name.clone();
+        self.refresh_generation();
+        if self.instance_name != name {
+            self.state.invalidate();
+        }
+
+        if self.storm {
+            return self.storm().await;
+        }
+
+        let state = match self.state.get() {
+            Some(state) => {
+                trace!(?state, "using cached instance state");
+                state
+            }
+            None => {
+                trace!("querying instance state");
+                let state = self.get_instance_state().await?;
+                self.state.observed(state);
+                state
+            }
+        };
+        let state = match state {
             None => {
                 info!("instance doesn't exist, will try to create it");
-                return self.create_instance().await.map_err(Into::into);
+                self.retire_generation();
+                let sent = Instant::now();
+                let created = self.create_instance().await?;
+                self.state.expect(Some(created));
+                return self.settle_create(sent).await;
             }
             Some(state) => {
                 trace!(?state, "got instance state");
@@ -264,15 +714,62 @@ impl super::Antagonist for InstanceActor {
             }
         };
 
-        sleep_random_ms(100).await;
+        sleep_random(&mut self.rng, self.think_time).await;
+
+        let failed = matches!(state, InstanceState::Failed);
+        if failed {
+            warn!("instance is in the Failed state");
+        }
 
         let action = self.get_next_action(state);
         trace!(?action, "selected action");
+        crate::journal::note_action(&action, Some(&state));
+        let creating = matches!(action, Action::Create);
+        let stopping = matches!(action, Action::Stop);
+        let deleting = matches!(action, Action::Destroy);
+        let deleted_id = deleting
+            .then(|| {
+                super::model::id(ResourceKind::Instance, &self.instance_name)
+            })
+            .flatten();
+        let starting = matches!(action, Action::Start);
+
+        // The state each action should leave the instance in, if it succeeds.
+        let mut expected = match &action {
+            Action::Wait | Action::Bail { .. } => None,
+            Action::Create | Action::Start => {
+                Some(Some(InstanceState::Starting))
+            }
+            Action::Stop => Some(Some(InstanceState::Stopping)),
+            Action::Destroy => Some(None),
+        };
+        let sent = Instant::now();
         let result = match action {
             Action::Wait => Ok(()),
-            Action::Create => self.create_instance().await,
+            Action::Create => self.create_instance().await.map(|created| {
+                expected = Some(Some(created));
+            }),
             Action::Start => self.start_instance().await,
             Action::Stop => self.stop_instance().await,
+            Action::Destroy if failed => {
+                // Failed instances are expected, but they must at least be
+                // deletable. Another actor may have deleted this one first, so
+                // only a failure other than "not found" is fatal.
+                match self.delete_instance().await {
+                    Err(oxide::Error::ErrorResponse(rv))
+                        if rv.status() == http::StatusCode::NOT_FOUND =>
+                    {
+                        Ok(())
+                    }
+                    Err(e) => {
+                        return Err(AntagonistError::InvalidState(format!(
+                            "failed instance {} could not be deleted: {}",
+                            self.instance_name, e,
+                        )));
+                    }
+                    Ok(()) => Ok(()),
+                }
+            }
             Action::Destroy => self.delete_instance().await,
             Action::Bail { reason } => match reason {
                 BailReason::InvalidState { state } => {
@@ -283,9 +780,130 @@ impl super::Antagonist for InstanceActor {
                 }
             },
         };
+        if let (Some(state), true) = (expected, result.is_ok()) {
+            self.state.expect(state);
+        }
+
+        if creating && result.is_ok() {
+            self.settle_create(sent).await?;
+        }
+
+        if starting && result.is_ok() {
+            self.settle_start(sent).await?;
+        }
 
-        sleep_random_ms(100).await;
+        if stopping && result.is_ok() {
+            self.settle_stop(sent).await?;
+        }
+
+        if deleting && result.is_ok() {
+            super::verify::deleted(
+                &self.client,
+                &self.project,
+                ResourceKind::Instance,
+                &self.instance_name,
+                deleted_id,
+            )
+            .await?;
+        }
+
+        sleep_random(&mut self.rng, self.think_time).await;
 
         result.map_err(Into::into)
     }
+
+    /// Deletes the current instance, stopping it first if need be.
+    async fn drain_instance(&mut self) -> Result<(), AntagonistError> {
+        // Other actors sharing this instance may be draining it too, so
+        // requests that fail because its state changed are expected.
+        self.refresh_generation();
+        let start = Instant::now();
+        loop {
+            let state = match self.get_instance_state().await? {
+                None => return Ok(()),
+                Some(state) => state,
+            };
+
+            let res = match state {
+                InstanceState::Running | InstanceState::Starting => {
+                    self.stop_instance().await
+                }
+                InstanceState::Stopped | InstanceState::Failed => {
+                    self.delete_instance().await
+                }
+                _ => Ok(()),
+            };
+            ok_if_error_response(res)?;
+
+            if start.elapsed() > super::DRAIN_TIMEOUT {
+                return Err(AntagonistError::InvalidState(format!(
+                    "instance {} not deleted after {:?} (state: {:?})",
+                    self.instance_name,
+                    super::DRAIN_TIMEOUT,
+                    state,
+                )));
+            }
+
+            tokio::time::sleep(Duration::from_secs(1)).await;
+        }
+    }
+}
+
+#[async_trait]
+impl super::Antagonist for InstanceActor {
+    fn set_client(&mut self, client: oxide::Client) {
+        self.client = client;
+    }
+
+    fn set_profile(&mut self, profile: crate::profile::Profile) {
+        self.weights = profile.instance_weights();
+    }
+
+    #[tracing::instrument(level = "info", skip(self), fields(instance_name = self.base_name))]
+    async fn step(&mut self) -> Result<(), AntagonistError> {
+        let result = self.take_step().await;
+        if result.is_err() {
+            self.state.invalidate();
+        }
+
+        result
+    }
+
+    #[tracing::instrument(level = "info", skip(self), fields(instance_name = self.base_name))]
+    async fn check_abandoned(
+        &mut self,
+        endpoint: &'static str,
+    ) -> Result<(), AntagonistError> {
+        let name = self.instance_name.clone();
+        super::abandon::settle(
+            endpoint,
+            &name,
+            || self.get_instance_state(),
+            |state| {
+                !matches!(
+                    state,
+                    Some(
+                        InstanceState::Creating
+                            | InstanceState::Starting
+                            | InstanceState::Stopping
+                            | InstanceState::Rebooting
+                    )
+                )
+            },
+        )
+        .await
+    }
+
+    #[tracing::instrument(level = "info", skip(self), fields(instance_name = self.base_name))]
+    async fn drain(&mut self) -> Result<(), AntagonistError> {
+        self.drain_instance().await?;
+        for i in 0..self.others.len() {
+            self.swap_target(i);
+            let result = self.drain_instance().await;
+            self.swap_target(i);
+            result?;
+        }
+
+        Ok(())
+    }
 }
@@ -0,0 +1,311 @@
+//! A janitor antagonist that periodically deletes resources in the stress
+//! project that no live actor owns. Only resources whose names start with the
+//! run's --name-prefix are touched, since the project may be shared (see
+//! --project).
+//!
+//! Instances have to be stopped before they can be deleted, so an orphaned
+//! running instance is stopped on one pass and deleted on a later one.
+//!
+//! Deletes are recorded in the model (see --check-model), since an actor can
+//! claim a name again after the janitor has deleted its debris.
+
+use async_trait::async_trait;
+use core::result::Result;
+use futures::TryStreamExt;
+use oxide::types::{DiskState, InstanceState, SnapshotState};
+use oxide::{
+    ClientDisksExt, ClientImagesExt, ClientInstancesExt, ClientSnapshotsExt,
+};
+use std::time::{Duration, Instant};
+use tracing::{info, trace};
+
+use crate::actor::ownership::{self, ResourceKind};
+use crate::actor::AntagonistError;
+use crate::util::unwrap_oxide_api_error;
+use crate::util::OxideApiError;
+
+/// The parameters used to configure an orphaned resource janitor.
+pub struct Params {
+    /// The name of the project to clean up.
+    pub project: String,
+
+    /// How often to look for orphaned resources.
+    pub interval: Duration,
+}
+
+/// The internal state for an orphaned resource janitor.
+#[derive(Debug)]
+pub(super) struct JanitorActor {
+    client: oxide::Client,
+    project: String,
+    interval: Duration,
+
+    /// The time at which this janitor last ran a cleanup pass.
+    last_pass: Option<Instant>,
+}
+
+impl JanitorActor {
+    /// Creates a new orphaned resource janitor.
+    pub(super) fn new(params: Params) -> anyhow::Result<Self> {
+        Ok(Self {
+            client: crate::client::get_client(crate::config())?,
+            project: params.project,
+            interval: params.interval,
+            last_pass: None,
+        })
+    }
+}
+
+/// Returns true if the resource named `name` is one the harness might have
+/// created: if its name has the run's name prefix.
+fn has_prefix(name: &str) -> bool {
+    name.starts_with(crate::util::name_prefix())
+}
+
+/// Makes one cleanup pass over the supplied project, stopping or deleting
+/// every resource with the run's name prefix that no live actor owns. Returns
+/// a description of each such resource found (e.g. `disk foo (Attached)`),
+/// including those that were busy and left for a later pass.
+pub(crate) async fn sweep(
+    client: &oxide::Client,
+    project: &str,
+) -> Result<Vec<String>, OxideApiError> {
+    // Images and snapshots go first so that the disks they came from are more
+    // likely to be deletable by the time the disk pass runs.
+    let mut found = Vec::new();
+    clean_images(client, project, &mut found).await?;
+    clean_snapshots(client, project, &mut found).await?;
+    clean_instances(client, project, &mut found).await?;
+    clean_disks(client, project, &mut found).await?;
+    Ok(found)
+}
+
+/// Stops or deletes orphaned instances, depending on their states.
+async fn clean_instances(
+    client: &oxide::Client,
+    project: &str,
+    found: &mut Vec<String>,
+) -> Result<(), OxideApiError> {
+    let instances: Vec<_> = crate::middleware::list("instance_list", || {
+        client.instance_list().project(project).stream().try_collect()
+    })
+    .await?;
+
+    for instance in instances {
+        if !has_prefix(&instance.name)
+            || ownership::is_owned(ResourceKind::Instance, &instance.name)
+        {
+            continue;
+        }
+
+        found.push(format!(
+            "instance {} ({:?})",
+            instance.name, instance.run_state
+        ));
+
+        let res = match instance.run_state {
+            InstanceState::Running | InstanceState::Starting => {
+                info!(name = %instance.name, "stopping orphaned instance");
+                crate::middleware::call("instance_stop", || {
+                    client
+                        .instance_stop()
+                        .project(project)
+                        .instance(instance.id)
+                        .send()
+                })
+                .await
+                .map(|_| ())
+            }
+
+            InstanceState::Stopped | InstanceState::Failed => {
+                info!(name = %instance.name, "deleting orphaned instance");
+                let pending = super::model::begin(
+                    ResourceKind::Instance,
+                    &instance.name,
+                    super::model::Op::Delete,
+                );
+                let res = crate::middleware::call("instance_delete", || {
+                    client
+                        .instance_delete()
+                        .project(project)
+                        .instance(instance.id)
+                        .send()
+                })
+                .await;
+                pending.finish(res.is_ok());
+                res.map(|_| ())
+            }
+
+            state => {
+                trace!(name = %instance.name, ?state, "orphaned instance busy");
+                continue;
+            }
+        };
+
+        res?;
+    }
+
+    Ok(())
+}
+
+/// Deletes orphaned disks that are in a deletable state.
+async fn clean_disks(
+    client: &oxide::Client,
+    project: &str,
+    found: &mut Vec<String>,
+) -> Result<(), OxideApiError> {
+    let disks: Vec<_> = crate::middleware::list("disk_list", || {
+        client.disk_list().project(project).stream().try_collect()
+    })
+    .await?;
+
+    for disk in disks {
+        if !has_prefix(&disk.name)
+            || ownership::is_owned(ResourceKind::Disk, &disk.name)
+        {
+            continue;
+        }
+
+        found.push(format!("disk {} ({:?})", disk.name, disk.state));
+        if !matches!(disk.state, DiskState::Detached | DiskState::Faulted) {
+            trace!(name = %disk.name, state = ?disk.state, "orphaned disk busy");
+            continue;
+        }
+
+        info!(name = %disk.name, "deleting orphaned disk");
+        let pending = super::model::begin(
+            ResourceKind::Disk,
+            &disk.name,
+            super::model::Op::Delete,
+        );
+        let res = crate::middleware::call("disk_delete", || {
+            client.disk_delete().project(project).disk(disk.id).send()
+        })
+        .await;
+        pending.finish(res.is_ok());
+
+        unwrap_oxide_api_error(res)?;
+    }
+
+    Ok(())
+}
+
+/// Deletes orphaned project images.
+async fn clean_images(
+    client: &oxide::Client,
+    project: &str,
+    found: &mut Vec<String>,
+) -> Result<(), OxideApiError> {
+    let images: Vec<_> = crate::middleware::list("image_list", || {
+        client.image_list().project(project).stream().try_collect()
+    })
+    .await?;
+
+    for image in images {
+        if !has_prefix(&image.name)
+            || ownership::is_owned(ResourceKind::Image, &image.name)
+        {
+            continue;
+        }
+
+        found.push(format!("image {}", image.name));
+
+        info!(name = %image.name, "deleting orphaned image");
+        let pending = super::model::begin(
+            ResourceKind::Image,
+            &image.name,
+            super::model::Op::Delete,
+        );
+        let res = crate::middleware::call("image_delete", || {
+            client.image_delete().project(project).image(image.id).send()
+        })
+        .await;
+        pending.finish(res.is_ok());
+
+        unwrap_oxide_api_error(res)?;
+    }
+
+    Ok(())
+}
+
+/// Deletes orphaned snapshots that are in a deletable state.
+async fn clean_snapshots(
+    client: &oxide::Client,
+    project: &str,
+    found: &mut Vec<String>,
+) -> Result<(), OxideApiError> {
+    let snapshots: Vec<_> = crate::middleware::list("snapshot_list", || {
+        client.snapshot_list().project(project).stream().try_collect()
+    })
+    .await?;
+
+    for snapshot in snapshots {
+        if !has_prefix(&snapshot.name)
+            || ownership::is_owned(ResourceKind::Snapshot, &snapshot.name)
+        {
+            continue;
+        }
+
+        found
+            .push(format!("snapshot {} ({:?})", snapshot.name, snapshot.state));
+        if !matches!(
+            snapshot.state,
+            SnapshotState::Ready | SnapshotState::Faulted
+        ) {
+            trace!(
+                name = %snapshot.name,
+                state = ?snapshot.state,
+                "orphaned snapshot busy"
+            );
+            continue;
+        }
+
+        info!(name = %snapshot.name, "deleting orphaned snapshot");
+        let pending = super::model::begin(
+            ResourceKind::Snapshot,
+            &snapshot.name,
+            super::model::Op::Delete,
+        );
+        let res = crate::middleware::call("snapshot_delete", || {
+            client
+                .snapshot_delete()
+                .project(project)
+                .snapshot(snapshot.id)
+                .send()
+        })
+        .await;
+        pending.finish(res.is_ok());
+
+        unwrap_oxide_api_error(res)?;
+    }
+
+    Ok(())
+}
+
+#[async_trait]
+impl super::Antagonist for JanitorActor {
+    fn set_client(&mut self, client: oxide::Client) {
+        self.client = client;
+    }
+
+    #[tracing::instrument(level = "info", skip(self))]
+    async fn step(&mut self) -> Result<(), AntagonistError> {
+        // Wait in short steps instead of sleeping for the whole interval so
+        // that the actor loop can still pause or halt this actor promptly.
+        let due = match self.last_pass {
+            Some(last) => last.elapsed() >= self.interval,
+            None => true,
+        };
+
+        if !due {
+            tokio::time::sleep(Duration::from_secs(1)).await;
+            return Ok(());
+        }
+
+        self.last_pass = Some(Instant::now());
+
+        trace!("looking for orphaned resources");
+        sweep(&self.client, &self.project).await?;
+        Ok(())
+    }
+}
@@ -3,13 +3,167 @@
 
 use anyhow::Result;
 use async_trait::async_trait;
-use tracing::{info, info_span, Instrument};
+use oxide::types::DiskState;
+use rand::rngs::StdRng;
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::sync::{Arc, Mutex, OnceLock};
+use std::time::Duration;
+use tokio_util::sync::CancellationToken;
+use tracing::{info, info_span, warn, Instrument};
 
+mod abandon;
+pub mod backing_disk;
+pub mod boundary;
+mod cache;
+pub mod chain;
+pub mod conflict;
 pub mod disk;
+pub mod fuzz;
 pub mod instance;
+pub mod janitor;
+mod model;
+pub mod ownership;
+pub mod pool;
+mod raw;
+pub mod reachability;
+pub mod scenario;
 pub mod snapshot;
+pub mod snapshot_gc;
+pub mod snapshot_limit;
+mod vary;
+mod verify;
 
 use crate::util::OxideApiError;
+use ownership::{OwnedResource, ResourceKind};
+
+/// What actors have most recently observed about the resources they manage.
+/// Other actors consult this to find resources to act on instead of having
+/// them wired up by name.
+#[derive(Default)]
+struct Registry {
+    /// The last observed state of each disk managed by a disk actor.
+    disks: BTreeMap<String, DiskState>,
+
+    /// The current generation of each resource that actors share by base
+    /// name: in unique-name mode, of instances and disks, and always, of
+    /// snapshots.
+    generations: BTreeMap<String, u64>,
+}
+
+static REGISTRY: OnceLock<Mutex<Registry>> = OnceLock::new();
+
+fn registry() -> &'static Mutex<Registry> {
+    REGISTRY.get_or_init(Default::default)
+}
+
+/// Records that the disk named `name` was observed in `state`, or that it
+/// doesn't exist if `state` is `None`.
+pub(crate) fn report_disk_state(name: &str, state: Option<DiskState>) {
+    let mut registry = registry().lock().unwrap();
+    match state {
+        Some(state) => {
+            registry.disks.insert(name.to_owned(), state);
+        }
+        None => {
+            registry.disks.remove(name);
+        }
+    }
+}
+
+/// Returns the name of a randomly-chosen disk that was detached when it was
+/// last observed, if there is one.
+pub(crate) fn find_detached_disk(rng: &mut impl rand::Rng) -> Option<String> {
+    use rand::seq::IteratorRandom;
+    let registry = registry().lock().unwrap();
+    registry
+        .disks
+        .iter()
+        .filter(|(_, state)| matches!(state, DiskState::Detached))
+        .map(|(name, _)| name.clone())
+        .choose(rng)
+}
+
+/// Returns the name of generation `generation` of the resource whose base name
+/// is `base`.
+pub(crate) fn generation_name(base: &str, generation: u64) -> String {
+    format!("{}-{}", base, generation)
+}
+
+/// Returns the current generation of the resource whose base name is `base`.
+pub(crate) fn current_generation(base: &str) -> u64 {
+    let registry = registry().lock().unwrap();
+    registry.generations.get(base).copied().unwrap_or(0)
+}
+
+/// Records that generation `generation` of the resource whose base name is
+/// `base` is gone, so that the next one should be created under a fresh name.
+/// Returns the new current generation. If several actors sharing the resource
+/// see the same generation disappear, only the first one moves the generation
+/// forward.
+pub(crate) fn retire_generation(base: &str, generation: u64) -> u64 {
+    let mut registry = registry().lock().unwrap();
+    let current = registry.generations.entry(base.to_owned()).or_insert(0);
+    if *current == generation {
+        *current += 1;
+    }
+
+    *current
+}
+
+/// The actor on whose behalf the current task is running.
+#[derive(Clone)]
+pub(crate) struct CurrentActor {
+    pub name: String,
+
+    /// The name of the actor's kind.
+    pub kind: &'static str,
+
+    /// The actor's tracing span.
+    pub span: tracing::Span,
+
+    /// The Nexus instance the actor sends its requests to.
+    pub nexus: crate::client::Endpoint,
+
+    /// The identity the actor acts as, if there's an identity pool.
+    pub identity: Option<crate::client::Identity>,
+
+    /// The resources the actor manages, as `kind/name`.
+    pub resources: Arc<[String]>,
+
+    /// The random number generator for the middleware's choices about the
+    /// actor's API calls (e.g. retry jitter), kept apart from the antagonist's
+    /// so that those choices don't change the antagonist's.
+    pub request_rng: Arc<Mutex<StdRng>>,
+}
+
+impl CurrentActor {
+    /// Returns the Nexus to attribute the actor's errors to, if there's more
+    /// than one.
+    pub fn nexus_label(&self) -> Option<String> {
+        crate::client::multiple_endpoints().then(|| self.nexus.to_string())
+    }
+}
+
+tokio::task_local! {
+    static CURRENT_ACTOR: CurrentActor;
+}
+
+/// Returns the actor whose task is currently running, or `None` if this isn't
+/// an actor task.
+pub(crate) fn current_actor() -> Option<CurrentActor> {
+    CURRENT_ACTOR.try_with(Clone::clone).ok()
+}
+
+/// Calls `f` with the current actor's request random number generator, or
+/// returns `None` if this isn't an actor task.
+pub(crate) fn with_request_rng<R>(
+    f: impl FnOnce(&mut StdRng) -> R,
+) -> Option<R> {
+    CURRENT_ACTOR
+        .try_with(|current| f(&mut current.request_rng.lock().unwrap()))
+        .ok()
+}
 
 /// The kinds of actors this module can instantiate.
 pub enum ActorKind {
@@ -21,6 +175,151 @@ pub enum ActorKind {
 
     /// Creates and deletes snapshots.
     Snapshot(snapshot::Params),
+
+    /// Derives a disk from an image from a snapshot of a disk, then deletes
+    /// the whole chain in a random order.
+    Chain(chain::Params),
+
+    /// Races conflicting requests for a disk and checks how they resolve.
+    Conflict(conflict::Params),
+
+    /// Sends invalid create requests and checks that they're cleanly
+    /// rejected.
+    Fuzz(fuzz::Params),
+
+    /// Creates resources with values at and just past their limits and
+    /// checks that they're accepted or rejected accordingly.
+    Boundary(boundary::Params),
+
+    /// Creates a disk, instance, and snapshot together, then tears them down.
+    Scenario(scenario::Params),
+
+    /// Periodically deletes old snapshots.
+    SnapshotGc(snapshot_gc::Params),
+
+    /// Periodically deletes resources that no live actor owns.
+    Janitor(janitor::Params),
+
+    /// Probes running instances' external IPs for network reachability.
+    Reachability(reachability::Params),
+}
+
+impl ActorKind {
+    /// The names of each kind of actor, as used in scenario files and on the
+    /// command line.
+    pub const NAMES: &'static [&'static str] = &[
+        "instance",
+        "disk",
+        "snapshot",
+        "chain",
+        "conflict",
+        "fuzz",
+        "boundary",
+        "scenario",
+        "snapshot-gc",
+        "janitor",
+        "reachability",
+    ];
+
+    /// Parses an actor kind name from the command line.
+    pub fn parse_name(s: &str) -> Result<String, String> {
+        if !Self::NAMES.contains(&s) {
+            return Err(format!(
+                "unknown actor kind {:?} (expected one of {})",
+                s,
+                Self::NAMES.join(", ")
+            ));
+        }
+
+        Ok(s.to_owned())
+    }
+
+    /// Returns the name of this kind of actor.
+    pub fn name(&self) -> &'static str {
+        match self {
+            ActorKind::Instance(_) => "instance",
+            ActorKind::Disk(_) => "disk",
+            ActorKind::Snapshot(_) => "snapshot",
+            ActorKind::Chain(_) => "chain",
+            ActorKind::Conflict(_) => "conflict",
+            ActorKind::Fuzz(_) => "fuzz",
+            ActorKind::Boundary(_) => "boundary",
+            ActorKind::Scenario(_) => "scenario",
+            ActorKind::SnapshotGc(_) => "snapshot-gc",
+            ActorKind::Janitor(_) => "janitor",
+            ActorKind::Reachability(_) => "reachability",
+        }
+    }
+
+    /// Returns the resources an actor of this kind creates and manages.
+    pub(crate) fn owned_resources(&self) -> Vec<OwnedResource> {
+        match self {
+            ActorKind::Instance(params) => params
+                .instance_names
+                .iter()
+                .map(|name| OwnedResource::new(ResourceKind::Instance, name))
+                .collect(),
+            ActorKind::Disk(params) => {
+                vec![OwnedResource::new(ResourceKind::Disk, &params.disk_name)]
+            }
+            ActorKind::Snapshot(params) => {
+                let mut owned = vec![OwnedResource::new(
+                    ResourceKind::Snapshot,
+                    &params.snapshot_name,
+                )];
+                if let snapshot::SourceDisk::Named(disk_name) = &params.disk {
+                    owned.push(OwnedResource::new(
+                        ResourceKind::Disk,
+                        disk_name,
+                    ));
+                }
+                owned
+            }
+            ActorKind::Scenario(params) => vec![
+                OwnedResource::new(
+                    ResourceKind::Instance,
+                    scenario::instance_name(&params.scenario_name),
+                ),
+                OwnedResource::new(
+                    ResourceKind::Disk,
+                    scenario::disk_name(&params.scenario_name),
+                ),
+                OwnedResource::new(
+                    ResourceKind::Snapshot,
+                    scenario::snapshot_name(&params.scenario_name),
+                ),
+            ],
+            ActorKind::Chain(params) => vec![
+                OwnedResource::new(
+                    ResourceKind::Disk,
+                    chain::source_disk_name(&params.chain_name),
+                ),
+                OwnedResource::new(
+                    ResourceKind::Snapshot,
+                    chain::snapshot_name(&params.chain_name),
+                ),
+                OwnedResource::new(
+                    ResourceKind::Image,
+                    chain::image_name(&params.chain_name),
+                ),
+                OwnedResource::new(
+                    ResourceKind::Disk,
+                    chain::derived_disk_name(&params.chain_name),
+                ),
+            ],
+            ActorKind::Conflict(params) => {
+                vec![OwnedResource::new(ResourceKind::Disk, &params.disk_name)]
+            }
+            ActorKind::Boundary(boundary::Params { resource_name, .. })
+            | ActorKind::Fuzz(fuzz::Params { resource_name, .. }) => vec![
+                OwnedResource::new(ResourceKind::Disk, resource_name),
+                OwnedResource::new(ResourceKind::Instance, resource_name),
+            ],
+            ActorKind::SnapshotGc(_)
+            | ActorKind::Janitor(_)
+            | ActorKind::Reachability(_) => vec![],
+        }
+    }
 }
 
 /// An individual actor task.
@@ -28,23 +327,66 @@ pub struct Actor {
     /// The actor's name
     name: String,
 
+    /// The name of the actor's kind.
+    kind: &'static str,
+
+    /// The Nexus instance the actor sends its requests to.
+    nexus: crate::client::Endpoint,
+
+    /// The resources the actor manages, as `kind/name`.
+    resources: Arc<[String]>,
+
     /// The tracing span to use for actions taken by this actor.
     span: tracing::Span,
 
-    /// A handle to the actor's internal task.
-    task: tokio::task::JoinHandle<()>,
+    /// What drives the actor's steps.
+    driver: Driver,
+
+    /// True if the actor has been paused and not yet resumed.
+    paused: bool,
+
+    /// When the actor was last known to be working: when it was scheduled to
+    /// start or was last resumed. The supervisor measures stalls from this or
+    /// the end of the actor's last step, whichever is later.
+    live_since: std::time::Instant,
+
+    /// Cancelled when the actor is halted, to abandon its running step's API
+    /// calls rather than wait for them.
+    cancel: CancellationToken,
+}
+
+/// What drives an actor's steps.
+enum Driver {
+    /// The actor has a task of its own.
+    Task {
+        /// A handle to the actor's internal task.
+        task: tokio::task::JoinHandle<()>,
 
-    /// The sender side of a channel used to pause the actor task. The protocol
-    /// is to send `true` through this channel, then receive from `paused_rx`,
-    /// then send `false` through this channel to unpause.
-    pause_tx: tokio::sync::mpsc::Sender<bool>,
+        /// The sender side of a channel used to pause the actor task. The
+        /// protocol is to send `true` through this channel, then receive from
+        /// `paused_rx`, then send `false` through this channel to unpause.
+        pause_tx: tokio::sync::mpsc::Sender<bool>,
 
-    /// Receives a message from the actor task when it has successfully paused.
-    paused_rx: tokio::sync::mpsc::Receiver<()>,
+        /// Receives a message from the actor task when it has successfully
+        /// paused.
+        paused_rx: tokio::sync::mpsc::Receiver<()>,
 
-    /// Sending to this channel directs the actor task to halt at the next
-    /// available opportunity.
-    halt_tx: tokio::sync::oneshot::Sender<()>,
+        /// Sends the actor task a profile whose action weights to switch to.
+        profile_tx: tokio::sync::watch::Sender<Option<crate::profile::Profile>>,
+
+        /// Sending to this channel directs the actor task to halt at the next
+        /// available opportunity, first draining its resources if the value
+        /// sent is true.
+        halt_tx: tokio::sync::oneshot::Sender<bool>,
+    },
+
+    /// The actor is a row in the worker pool's table (`--actor-workers`).
+    Pooled {
+        id: usize,
+
+        /// Receives how the actor's steps ended once it leaves the table.
+        done: tokio::sync::oneshot::Receiver<pool::Exit>,
+    },
 }
 
 #[derive(thiserror::Error, Debug)]
@@ -55,95 +397,574 @@ pub enum AntagonistError {
     #[error("oxide api error: {0}")]
     ApiError(#[from] OxideApiError),
 
+    #[error("instance unreachable: {0}")]
+    Unreachable(String),
+
+    /// A correctness check (e.g. --check-model or --verify-deletes) failed.
+    #[error("check failed: {0}")]
+    CheckFailed(String),
+
     #[error("antagonist {name} disconnected its error channel")]
     DisconnectedErrorChannel { name: String },
 }
 
+impl AntagonistError {
+    /// Returns the ID Nexus assigned to the request that failed, if this error
+    /// is an error response from Nexus.
+    pub fn request_id(&self) -> Option<&str> {
+        match self {
+            AntagonistError::ApiError(oxide::Error::ErrorResponse(rv)) => {
+                Some(&rv.request_id)
+            }
+            _ => None,
+        }
+    }
+}
+
+/// An error an actor reported to the harness, with what the actor was doing
+/// when it hit it.
+#[derive(Debug)]
+pub struct ActorError {
+    pub error: AntagonistError,
+    pub context: ErrorContext,
+}
+
+/// What an actor was doing when it hit an error, for triage.
+#[derive(Clone, Debug, Serialize)]
+pub struct ErrorContext {
+    pub actor: String,
+    pub kind: &'static str,
+
+    /// The Nexus instance the actor is pinned to, if the run has more than
+    /// one.
+    pub nexus: Option<String>,
+
+    /// The resources the actor manages, as `kind/name`.
+    pub resources: Vec<String>,
+
+    /// The API call that failed, if the error came from one, and its status
+    /// code and request ID, if it got a response.
+    pub endpoint: Option<String>,
+    pub status: Option<u16>,
+    pub request_id: Option<String>,
+
+    /// How many times the failed call was retried before the harness heard
+    /// about it.
+    pub attempt: u32,
+
+    /// How long the failed step ran.
+    pub elapsed_ms: Option<f64>,
+}
+
+impl ErrorContext {
+    /// Returns the context of `error`, which the step `current` was taking
+    /// failed with after running for `elapsed`.
+    fn of_step(
+        current: &CurrentActor,
+        error: &AntagonistError,
+        elapsed: Duration,
+    ) -> Self {
+        let call = crate::request_log::failed_call(&current.name, error);
+        Self {
+            endpoint: call.as_ref().map(|c| c.endpoint.clone()),
+            status: call.as_ref().and_then(|c| c.status),
+            attempt: call.as_ref().map_or(0, |c| c.attempt),
+            request_id: error
+                .request_id()
+                .map(str::to_owned)
+                .or_else(|| call.and_then(|c| c.request_id)),
+            elapsed_ms: Some(elapsed.as_secs_f64() * 1000.0),
+            ..Self::of_actor(current)
+        }
+    }
+
+    /// Returns the context of an error that `current` hit outside of any step,
+    /// e.g. its task dying.
+    fn of_actor(current: &CurrentActor) -> Self {
+        Self::bare(
+            &current.name,
+            current.kind,
+            current.nexus_label(),
+            &current.resources,
+        )
+    }
+
+    fn bare(
+        actor: &str,
+        kind: &'static str,
+        nexus: Option<String>,
+        resources: &[String],
+    ) -> Self {
+        Self {
+            actor: actor.to_owned(),
+            kind,
+            nexus,
+            resources: resources.to_vec(),
+            endpoint: None,
+            status: None,
+            request_id: None,
+            attempt: 0,
+            elapsed_ms: None,
+        }
+    }
+}
+
+impl std::fmt::Display for ErrorContext {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "actor={} kind={}", self.actor, self.kind)?;
+        if let Some(nexus) = &self.nexus {
+            write!(f, " nexus={}", nexus)?;
+        }
+        if !self.resources.is_empty() {
+            write!(f, " resources={}", self.resources.join(","))?;
+        }
+        if let Some(endpoint) = &self.endpoint {
+            write!(f, " endpoint={}", endpoint)?;
+        }
+        if let Some(status) = self.status {
+            write!(f, " status={}", status)?;
+        }
+        if let Some(request_id) = &self.request_id {
+            write!(f, " request_id={}", request_id)?;
+        }
+        write!(f, " attempt={}", self.attempt)?;
+        if let Some(elapsed_ms) = self.elapsed_ms {
+            write!(f, " elapsed_ms={:.0}", elapsed_ms)?;
+        }
+
+        Ok(())
+    }
+}
+
 /// A trait implemented by each kind of antagonist actor.
+///
+/// The actor loop calls `step` repeatedly, checking for pause and halt
+/// requests between calls. Simple antagonists do a whole iteration of their
+/// workload in each step. Antagonists with multi-step workloads keep track of
+/// where they are in `self` and do one piece of the workload per step, so that
+/// they can be paused or halted partway through.
 #[async_trait]
-trait Antagonist: Send + Sync + 'static {
-    async fn antagonize(&self) -> Result<(), AntagonistError>;
+trait Antagonist: Send + 'static {
+    async fn step(&mut self) -> Result<(), AntagonistError>;
+
+    /// Drives this antagonist's resources to a quiescent state (usually by
+    /// deleting them) after its last step, when the harness is draining on
+    /// exit. Antagonists that don't manage resources have nothing to do.
+    async fn drain(&mut self) -> Result<(), AntagonistError> {
+        Ok(())
+    }
+
+    /// Checks that this antagonist's resource settles after a step abandoned
+    /// a write to `endpoint` on purpose (--abandon-requests), whether or not
+    /// the write took effect. Antagonists that don't manage resources of their
+    /// own have nothing to check.
+    async fn check_abandoned(
+        &mut self,
+        _endpoint: &'static str,
+    ) -> Result<(), AntagonistError> {
+        Ok(())
+    }
+
+    /// Replaces this antagonist's API client, e.g. with one that has fresh
+    /// credentials.
+    fn set_client(&mut self, client: oxide::Client);
+
+    /// Switches this antagonist to `profile`'s action weights. Antagonists
+    /// that don't choose their actions by weight ignore this.
+    fn set_profile(&mut self, _profile: crate::profile::Profile) {}
 }
 
+/// How long an antagonist may take to drain its resources.
+const DRAIN_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(300);
+
 /// Creates an antagonist of the specified kind.
-fn make_antagonist(kind: ActorKind) -> Result<Box<dyn Antagonist>> {
+fn make_antagonist(
+    kind: ActorKind,
+    rng: StdRng,
+) -> Result<Box<dyn Antagonist>> {
     match kind {
         ActorKind::Instance(params) => {
-            Ok(Box::new(instance::InstanceActor::new(params)?))
+            Ok(Box::new(instance::InstanceActor::new(params, rng)?))
         }
 
-        ActorKind::Disk(params) => Ok(Box::new(disk::DiskActor::new(params)?)),
+        ActorKind::Disk(params) => {
+            Ok(Box::new(disk::DiskActor::new(params, rng)?))
+        }
 
         ActorKind::Snapshot(params) => {
-            Ok(Box::new(snapshot::SnapshotActor::new(params)?))
+            Ok(Box::new(snapshot::SnapshotActor::new(params, rng)?))
+        }
+
+        ActorKind::Chain(params) => {
+            Ok(Box::new(chain::ChainActor::new(params, rng)?))
+        }
+
+        ActorKind::Conflict(params) => {
+            Ok(Box::new(conflict::ConflictActor::new(params, rng)?))
+        }
+
+        ActorKind::Fuzz(params) => {
+            Ok(Box::new(fuzz::FuzzActor::new(params, rng)?))
+        }
+
+        ActorKind::Boundary(params) => {
+            Ok(Box::new(boundary::BoundaryActor::new(params, rng)?))
+        }
+
+        ActorKind::Scenario(params) => {
+            Ok(Box::new(scenario::ScenarioActor::new(params, rng)?))
+        }
+
+        ActorKind::SnapshotGc(params) => {
+            Ok(Box::new(snapshot_gc::SnapshotGcActor::new(params)?))
+        }
+
+        ActorKind::Janitor(params) => {
+            Ok(Box::new(janitor::JanitorActor::new(params)?))
+        }
+
+        ActorKind::Reachability(params) => {
+            Ok(Box::new(reachability::ReachabilityActor::new(params, rng)?))
+        }
+    }
+}
+
+/// Rebuilds `antagonist`'s API client with the current credentials.
+fn reconnect(antagonist: &mut Box<dyn Antagonist>) {
+    match crate::client::get_client(crate::config()) {
+        Ok(client) => antagonist.set_client(client),
+        Err(e) => warn!("failed to rebuild client: {:#}", e),
+    }
+}
+
+/// Takes one step with `antagonist`, an actor of kind `kind`, and records how
+/// long it took, which it also returns. The step's API calls are abandoned if
+/// `cancel` is cancelled, in which case the step isn't recorded, since it
+/// didn't really happen.
+///
+/// A step that fails because it abandoned a write on purpose
+/// (--abandon-requests) failed as intended, so instead of its error, this
+/// returns the result of checking that the write's effect settles.
+async fn timed_step(
+    antagonist: &mut Box<dyn Antagonist>,
+    kind: &'static str,
+    cancel: &CancellationToken,
+) -> (Result<(), AntagonistError>, Duration) {
+    let start = std::time::Instant::now();
+    let step =
+        crate::middleware::cancellable(cancel.clone(), antagonist.step());
+    let result = match crate::middleware::abandonable(step).await {
+        (Err(e), Some(endpoint)) if !cancel.is_cancelled() => {
+            info!(endpoint, error = %e, "step abandoned a request");
+            let check = antagonist.check_abandoned(endpoint);
+            crate::middleware::cancellable(cancel.clone(), check).await
         }
+        (result, _) => result,
+    };
+    let elapsed = start.elapsed();
+    if cancel.is_cancelled() {
+        crate::journal::discard();
+        return (result, elapsed);
+    }
+
+    crate::journal::finish(
+        elapsed,
+        result.as_ref().err().map(|e| e.to_string()),
+    );
+    crate::stats::record_step(kind, elapsed, result.is_ok());
+    crate::status::record_step(result.is_ok());
+    (result, elapsed)
+}
+
+/// Tells the harness that an actor task has paused, and waits to be told to
+/// resume. Returns `Err` with whether to drain if the task should leave
+/// instead: if the harness has stopped listening, halted the actor, or exited.
+async fn stay_paused(
+    paused_tx: &tokio::sync::mpsc::Sender<()>,
+    pause_rx: &mut tokio::sync::mpsc::Receiver<bool>,
+    halt_rx: &mut tokio::sync::oneshot::Receiver<bool>,
+) -> Result<(), bool> {
+    if paused_tx.send(()).await.is_err() {
+        return Err(false);
+    }
+
+    match pause_rx.recv().await {
+        Some(should_pause) => {
+            assert!(!should_pause, "should only ask to unpause when paused");
+            Ok(())
+        }
+        None => Err(halt_rx.try_recv().unwrap_or(false)),
     }
 }
 
+/// Pauses every actor in `actors` and waits until they all have, so that
+/// none of them makes another API call until resumed.
+///
+/// This is the one way the harness pauses actors, whatever the reason (an
+/// outage, maintenance, or an operator's request). It can't deadlock against
+/// an actor that's blocked reporting an error: actors answer pause requests
+/// while they wait to report one.
+pub async fn pause_all<'a>(actors: impl IntoIterator<Item = &'a mut Actor>) {
+    futures::future::join_all(actors.into_iter().map(|a| a.pause())).await;
+}
+
+/// Resumes every paused actor in `actors`.
+pub async fn resume_all<'a>(actors: impl IntoIterator<Item = &'a mut Actor>) {
+    futures::future::join_all(actors.into_iter().map(|a| a.resume())).await;
+}
+
 impl Actor {
-    /// Creates a new actor with the specified actor `name` and `kind`.
+    /// Creates a new actor with the specified actor `name` and `kind`. The
+    /// actor takes its first step after `start_delay` has passed.
     ///
     /// # Return value
     ///
     /// A tuple containing the new `Actor` and the receiver side of a channel
-    /// that will be sent any errors generated by the task's antagonist.
+    /// that will be sent any errors generated by the task's antagonist. If the
+    /// actor is driven by the worker pool, its errors go to the pool's error
+    /// channel instead, and there's no receiver.
     pub fn new(
         name: String,
         kind: ActorKind,
-    ) -> Result<(Self, tokio::sync::mpsc::Receiver<AntagonistError>)> {
-        let span = info_span!("actor", name = &name);
+        start_delay: std::time::Duration,
+    ) -> Result<(Self, Option<tokio::sync::mpsc::Receiver<ActorError>>)> {
+        let span = info_span!(
+            "actor",
+            name = &name,
+            nexus = tracing::field::Empty,
+            identity = tracing::field::Empty,
+            request_id = tracing::field::Empty
+        );
+        let kind_name = kind.name();
+        let claim = ownership::Claim::new(kind.owned_resources());
+        let rng = crate::util::actor_rng(&name);
+        let (nexus, identity) = crate::client::assign();
+        let resources: Arc<[String]> = kind
+            .owned_resources()
+            .iter()
+            .map(|r| format!("{}/{}", r.kind.name(), r.name))
+            .collect();
+        let current = CurrentActor {
+            name: name.clone(),
+            kind: kind_name,
+            span: span.clone(),
+            nexus: nexus.clone(),
+            identity,
+            resources: resources.clone(),
+            request_rng: Arc::new(Mutex::new(crate::util::actor_rng(
+                &format!("{}/requests", name),
+            ))),
+        };
+
+        // If there's more than one Nexus or identity, say which ones this
+        // actor is pinned to on everything it logs.
+        if crate::client::multiple_endpoints() {
+            span.record("nexus", tracing::field::display(&current.nexus));
+        }
+        if let (Some(identity), true) =
+            (&current.identity, crate::client::multiple_identities())
+        {
+            span.record("identity", identity.name.as_str());
+        }
+
+        // Make the antagonist as this actor so that its client talks to the
+        // actor's Nexus instance.
+        let mut generation = crate::client::credentials_generation();
+        let mut antagonist = CURRENT_ACTOR
+            .sync_scope(current.clone(), || make_antagonist(kind, rng))?;
+        let live_since = std::time::Instant::now() + start_delay;
+        let cancel = CancellationToken::new();
+
+        if let Some(pool) = pool::get() {
+            let (id, done) = pool.add(
+                current,
+                antagonist,
+                claim,
+                generation,
+                cancel.clone(),
+                start_delay,
+            );
+            return Ok((
+                Self {
+                    name,
+                    kind: kind_name,
+                    nexus,
+                    resources,
+                    span,
+                    driver: Driver::Pooled { id, done },
+                    paused: false,
+                    live_since,
+                    cancel,
+                },
+                None,
+            ));
+        }
+
         let (error_tx, error_rx) = tokio::sync::mpsc::channel(1);
         let (pause_tx, mut pause_rx) = tokio::sync::mpsc::channel::<bool>(1);
         let (paused_tx, paused_rx) = tokio::sync::mpsc::channel(1);
         let (halt_tx, mut halt_rx) = tokio::sync::oneshot::channel();
-
-        let antagonist = make_antagonist(kind)?;
-
+        let (profile_tx, mut profile_rx) = tokio::sync::watch::channel(None);
+        let halt_cancel = cancel.clone();
         let task = tokio::spawn(
-            async move {
-                loop {
-                    // If the harness asked this actor to stop, then stop.
-                    if halt_rx.try_recv().is_ok() {
-                        break;
+            CURRENT_ACTOR.scope(
+                current,
+                async move {
+                    // Hold this actor's resource claims for as long as its task
+                    // is alive.
+                    let _claim = claim;
+
+                    // Wait to start if the harness is ramping up its load, but
+                    // don't wait to halt.
+                    tokio::select! {
+                        _ = tokio::time::sleep(start_delay) => {}
+                        _ = &mut halt_rx => return,
                     }
 
-                    // If the harness asked to pause, then pause.
-                    if let Ok(should_pause) = pause_rx.try_recv() {
-                        assert!(
-                            should_pause,
-                            "should only ask to pause when unpaused"
-                        );
-
-                        // Tell the harness that this actor is paused, leaving
-                        // if the harness is no longer around to listen.
-                        if paused_tx.send(()).await.is_err() {
-                            break;
+                    let drain = 'steps: loop {
+                        // If the harness asked this actor to stop, then stop.
+                        if let Ok(drain) = halt_rx.try_recv() {
+                            break drain;
                         }
 
-                        // Wait to be told to unpause. If the channel goes away,
-                        // the harness exited, so just leave.
-                        if let Some(should_unpause) = pause_rx.recv().await {
+                        // If the harness asked to pause, then pause.
+                        if let Ok(should_pause) = pause_rx.try_recv() {
                             assert!(
-                                should_unpause,
-                                "should only ask to unpause when paused"
+                                should_pause,
+                                "should only ask to pause when unpaused"
                             );
-                        } else {
-                            break;
+                            if let Err(drain) = stay_paused(
+                                &paused_tx,
+                                &mut pause_rx,
+                                &mut halt_rx,
+                            )
+                            .await
+                            {
+                                break drain;
+                            }
                         }
-                    }
 
-                    let result = antagonist.antagonize().await;
-                    if let Err(e) = result {
-                        if error_tx.send(e).await.is_err() {
-                            break;
+                        // Switch action weights if the harness asked to.
+                        if profile_rx.has_changed().unwrap_or(false) {
+                            if let Some(profile) =
+                                *profile_rx.borrow_and_update()
+                            {
+                                antagonist.set_profile(profile);
+                            }
+                        }
+
+                        // Pick up credentials that were refreshed after
+                        // another actor's token was rejected.
+                        if crate::client::credentials_generation() != generation
+                        {
+                            generation =
+                                crate::client::credentials_generation();
+                            reconnect(&mut antagonist);
+                        }
+
+                        let (mut result, mut elapsed) =
+                            timed_step(&mut antagonist, kind_name, &cancel)
+                                .await;
+
+                        // If this actor's token was rejected, try again with
+                        // fresh credentials if there are any.
+                        if result
+                            .as_ref()
+                            .is_err_and(crate::client::is_unauthorized)
+                            && crate::client::refresh_credentials(generation)
+                                .await
+                        {
+                            generation =
+                                crate::client::credentials_generation();
+                            reconnect(&mut antagonist);
+                            (result, elapsed) =
+                                timed_step(&mut antagonist, kind_name, &cancel)
+                                    .await;
+                        }
+
+                        // A step that was cancelled because this actor is
+                        // halting failed on purpose, so don't report it.
+                        if let (Err(error), false) =
+                            (result, cancel.is_cancelled())
+                        {
+                            let context = CURRENT_ACTOR.with(|current| {
+                                ErrorContext::of_step(current, &error, elapsed)
+                            });
+                            let e = ActorError { error, context };
+
+                            // Keep answering pause and halt requests while
+                            // waiting to report the error. The harness stops
+                            // taking errors while it pauses every actor (e.g.
+                            // to ride out an outage), so an actor that only
+                            // waited would never pause.
+                            let permit = loop {
+                                tokio::select! {
+                                    permit = error_tx.reserve() => break permit,
+                                    Some(should_pause) = pause_rx.recv() => {
+                                        assert!(
+                                            should_pause,
+                                            "should only ask to pause when \
+                                            unpaused"
+                                        );
+                                        if let Err(drain) = stay_paused(
+                                            &paused_tx,
+                                            &mut pause_rx,
+                                            &mut halt_rx,
+                                        )
+                                        .await
+                                        {
+                                            break 'steps drain;
+                                        }
+                                    }
+                                    drain = &mut halt_rx => {
+                                        break 'steps drain.unwrap_or(false);
+                                    }
+                                }
+                            };
+                            match permit {
+                                Ok(permit) => permit.send(e),
+                                // The harness stopped listening, most likely
+                                // because it's halting this actor; drain if it
+                                // asked to.
+                                Err(_) => {
+                                    break halt_rx.try_recv().unwrap_or(false)
+                                }
+                            }
+                        }
+                    };
+
+                    // Errors from here on have no one to go to, so just log them.
+                    if drain {
+                        info!("draining resources");
+                        if let Err(e) = antagonist.drain().await {
+                            warn!(error = %e, "failed to drain resources");
                         }
                     }
                 }
-            }
-            .instrument(span.clone()),
+                .instrument(span.clone()),
+            ),
         );
 
-        Ok((Self { name, span, task, pause_tx, paused_rx, halt_tx }, error_rx))
+        Ok((
+            Self {
+                name,
+                kind: kind_name,
+                nexus,
+                resources,
+                span,
+                driver: Driver::Task {
+                    task,
+                    pause_tx,
+                    paused_rx,
+                    profile_tx,
+                    halt_tx,
+                },
+                paused: false,
+                live_since,
+                cancel: halt_cancel,
+            },
+            Some(error_rx),
+        ))
     }
 
     /// Return this actor's name
@@ -151,30 +972,168 @@ impl Actor {
         &self.name
     }
 
+    /// Returns the name of this actor's kind.
+    pub fn kind(&self) -> &'static str {
+        self.kind
+    }
+
+    /// Returns the Nexus instance this actor sends its requests to.
+    pub fn nexus(&self) -> &crate::client::Endpoint {
+        &self.nexus
+    }
+
+    /// Returns the context of an error this actor hit outside of any step,
+    /// e.g. its task dying.
+    pub fn error_context(&self) -> ErrorContext {
+        let nexus =
+            crate::client::multiple_endpoints().then(|| self.nexus.to_string());
+        ErrorContext::bare(&self.name, self.kind, nexus, &self.resources)
+    }
+
+    /// Returns true if this actor is paused.
+    pub fn is_paused(&self) -> bool {
+        self.paused
+    }
+
+    /// Returns true if this actor's task has exited (or, for a pooled actor,
+    /// it has left the pool's table), e.g. because its antagonist panicked.
+    pub fn is_finished(&self) -> bool {
+        match &self.driver {
+            Driver::Task { task, .. } => task.is_finished(),
+            Driver::Pooled { id, .. } => {
+                pool::get().map_or(true, |pool| !pool.contains(*id))
+            }
+        }
+    }
+
+    /// Returns when this actor was last known to be working, other than by
+    /// finishing a step: when it was scheduled to start or was last resumed.
+    pub fn live_since(&self) -> std::time::Instant {
+        self.live_since
+    }
+
     /// Directs this actor to pause and waits for it to report that it has done
-    /// so.
-    #[allow(dead_code)]
+    /// so. Returns immediately if the actor is already paused or its task has
+    /// already exited.
     pub async fn pause(&mut self) {
+        if self.paused {
+            return;
+        }
+
         let _span = self.span.enter();
         info!("sending pause request");
-        self.pause_tx.send(true).await.unwrap();
-        info!("waiting for task to pause");
-        self.paused_rx.recv().await.unwrap();
+        match &mut self.driver {
+            Driver::Task { pause_tx, paused_rx, .. } => {
+                if pause_tx.send(true).await.is_err() {
+                    return;
+                }
+                self.paused = true;
+                info!("waiting for task to pause");
+                let _ = paused_rx.recv().await;
+            }
+            Driver::Pooled { id, .. } => {
+                self.paused = true;
+                info!("waiting for step to end");
+                if let Some(pool) = pool::get() {
+                    pool.pause(*id).await;
+                }
+            }
+        }
     }
 
-    /// Directs this actor to resume.
-    #[allow(dead_code)]
-    pub async fn resume(&self) {
+    /// Directs this actor to resume, if it's paused.
+    pub async fn resume(&mut self) {
+        if !self.paused {
+            return;
+        }
+
         let _span = self.span.enter();
         info!("sending resume request");
-        self.pause_tx.send(false).await.unwrap();
+        self.paused = false;
+        self.live_since = std::time::Instant::now();
+        match &self.driver {
+            Driver::Task { pause_tx, .. } => {
+                let _ = pause_tx.send(false).await;
+            }
+            Driver::Pooled { id, .. } => {
+                if let Some(pool) = pool::get() {
+                    pool.resume(*id);
+                }
+            }
+        }
+    }
+
+    /// Directs this actor to switch to `profile`'s action weights before its
+    /// next step.
+    pub fn set_profile(&self, profile: crate::profile::Profile) {
+        let _span = self.span.enter();
+        info!(?profile, "sending profile change");
+        match &self.driver {
+            Driver::Task { profile_tx, .. } => {
+                let _ = profile_tx.send(Some(profile));
+            }
+            Driver::Pooled { id, .. } => {
+                if let Some(pool) = pool::get() {
+                    pool.set_profile(*id, profile);
+                }
+            }
+        }
     }
 
     /// Directs this actor to halt.
     pub async fn halt(self) -> tokio::task::JoinHandle<()> {
+        self.halt_with(false)
+    }
+
+    /// Directs this actor to halt after driving its resources to a quiescent
+    /// state.
+    pub async fn drain(self) -> tokio::task::JoinHandle<()> {
+        self.halt_with(true)
+    }
+
+    /// Aborts this actor's task without waiting for it to reach a point where
+    /// it can halt, and returns the task's handle.
+    pub fn abort(self) -> tokio::task::JoinHandle<()> {
         let _span = self.span.enter();
-        info!("sending halt request");
-        let _ = self.halt_tx.send(());
-        self.task
+        info!("aborting task");
+        match self.driver {
+            Driver::Task { task, .. } => {
+                task.abort();
+                task
+            }
+            Driver::Pooled { id, done } => {
+                if let Some(pool) = pool::get() {
+                    pool.abort(id);
+                }
+                pool::join(id, done)
+            }
+        }
+    }
+
+    /// Sends a halt request, asking the actor to drain first if `drain` is
+    /// true, cancels the actor's running step, and returns the actor's task
+    /// handle. A pooled actor has no task, so it gets one that finishes when
+    /// the actor does.
+    fn halt_with(self, drain: bool) -> tokio::task::JoinHandle<()> {
+        let _span = self.span.enter();
+        info!(drain, "sending halt request");
+        let cancel = self.cancel.clone();
+        let task = match self.driver {
+            Driver::Task { task, halt_tx, .. } => {
+                let _ = halt_tx.send(drain);
+                task
+            }
+            Driver::Pooled { id, done } => {
+                if let Some(pool) = pool::get() {
+                    pool.halt(id, drain);
+                }
+                pool::join(id, done)
+            }
+        };
+
+        // Cancel after asking to halt, so that the actor sees the halt
+        // request as soon as its step ends.
+        cancel.cancel();
+        task
     }
 }
@@ -0,0 +1,257 @@
+//! A lightweight client-side model of whether each actor-managed resource
+//! should exist, based on the responses to the actors' own create and delete
+//! requests, used to catch lost updates and stale reads (`--check-model`). The
+//! model also catches deleted resources that reappear, so `--verify-deletes`
+//! turns it on too.
+//!
+//! The model is shared by every actor that acts on a resource, since actors can
+//! share resources. To avoid flagging ordinary races between actors, an
+//! observation only contradicts the model if the request that made the
+//! observation was sent after the contradicting create or delete finished, and
+//! no request that could have changed the outcome was in flight or started in
+//! the meantime. For example, a view that finds a resource after a successful
+//! delete is a violation only if no create of the resource was in flight or
+//! started after the delete finished.
+//!
+//! The model also tracks the ID each successful create returns, to catch
+//! datastore bugs around soft deletes and name reuse: a view that finds a
+//! resource with a different ID than the last create of its name returned
+//! (under the same rules as above), and a create that returns an ID some
+//! earlier create already returned. The latter is only noticed when the
+//! create finishes, so it's reported by the next observation of the resource.
+
+use std::collections::HashMap;
+use std::sync::{Mutex, OnceLock};
+use std::time::Instant;
+
+use uuid::Uuid;
+
+use super::ownership::ResourceKind;
+use super::AntagonistError;
+
+/// Returns true if the model is in use.
+fn enabled() -> bool {
+    let config = crate::config();
+    config.check_model || config.verify_deletes.is_some()
+}
+
+/// An operation that can change whether a resource exists.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(super) enum Op {
+    Create,
+    Delete,
+}
+
+/// What's known about one kind of operation on one resource.
+#[derive(Default)]
+struct OpHistory {
+    /// When the most recent request was sent.
+    last_started: Option<Instant>,
+
+    /// When the most recent successful request finished.
+    last_succeeded: Option<Instant>,
+
+    /// How many requests are in flight.
+    in_flight: usize,
+}
+
+/// What's known about one resource.
+#[derive(Default)]
+struct ResourceHistory {
+    creates: OpHistory,
+    deletes: OpHistory,
+
+    /// The ID returned by the most recent successful create.
+    id: Option<Uuid>,
+
+    /// A problem with a create that hasn't been reported yet.
+    unreported: Option<String>,
+}
+
+impl ResourceHistory {
+    fn op(&mut self, op: Op) -> &mut OpHistory {
+        match op {
+            Op::Create => &mut self.creates,
+            Op::Delete => &mut self.deletes,
+        }
+    }
+}
+
+type Key = (ResourceKind, String);
+
+#[derive(Default)]
+struct Model {
+    resources: HashMap<Key, ResourceHistory>,
+
+    /// The resource each ID was returned for, by a successful create.
+    ids: HashMap<Uuid, Key>,
+}
+
+static MODEL: OnceLock<Mutex<Model>> = OnceLock::new();
+
+fn model() -> &'static Mutex<Model> {
+    MODEL.get_or_init(Default::default)
+}
+
+/// A create or delete request that's in flight. Call `finish` once it has a
+/// response; if it's dropped instead, the request is assumed to have failed.
+pub(super) struct Pending {
+    key: Option<Key>,
+    op: Op,
+}
+
+/// Records that a request to perform `op` on the resource of the supplied
+/// `kind` and `name` is about to be sent.
+pub(super) fn begin(kind: ResourceKind, name: &str, op: Op) -> Pending {
+    if !enabled() {
+        return Pending { key: None, op };
+    }
+
+    let key = (kind, name.to_owned());
+    let mut model = model().lock().unwrap();
+    let history = model.resources.entry(key.clone()).or_default().op(op);
+    history.last_started = Some(Instant::now());
+    history.in_flight += 1;
+    Pending { key: Some(key), op }
+}
+
+impl Pending {
+    /// Records that the request finished, successfully or not.
+    pub(super) fn finish(mut self, succeeded: bool) {
+        self.end(succeeded, None);
+    }
+
+    /// Records that a create request finished, along with the ID of the
+    /// resource it created if it succeeded.
+    pub(super) fn finish_create(mut self, id: Option<Uuid>) {
+        self.end(id.is_some(), id);
+    }
+
+    fn end(&mut self, succeeded: bool, id: Option<Uuid>) {
+        let Some(key) = self.key.take() else {
+            return;
+        };
+
+        let mut model = model().lock().unwrap();
+        let reused = id.and_then(|id| model.ids.insert(id, key.clone()));
+        let resource = model.resources.entry(key).or_default();
+        let history = resource.op(self.op);
+        history.in_flight -= 1;
+        if succeeded {
+            history.last_succeeded = Some(Instant::now());
+        }
+
+        if let Some(id) = id {
+            resource.id = Some(id);
+        }
+
+        if let (Some(id), Some((kind, name))) = (id, reused) {
+            resource.unreported = Some(format!(
+                "create returned ID {}, which an earlier create of {:?} {} \
+                also returned",
+                id, kind, name
+            ));
+        }
+    }
+}
+
+impl Drop for Pending {
+    fn drop(&mut self) {
+        self.end(false, None);
+    }
+}
+
+/// Returns the ID the most recent successful create of the resource of the
+/// supplied `kind` and `name` returned, if the model knows it.
+pub(super) fn id(kind: ResourceKind, name: &str) -> Option<Uuid> {
+    if !enabled() {
+        return None;
+    }
+
+    let model = model().lock().unwrap();
+    model.resources.get(&(kind, name.to_owned())).and_then(|h| h.id)
+}
+
+/// Checks an observation, made by a request sent at `sent`, that the resource
+/// of the supplied `kind` and `name` exists with the ID `found`, or doesn't
+/// exist if `found` is `None`. Returns an error if the observation contradicts
+/// the model.
+pub(super) fn observe(
+    kind: ResourceKind,
+    name: &str,
+    sent: Instant,
+    found: Option<Uuid>,
+) -> Result<(), AntagonistError> {
+    if !enabled() {
+        return Ok(());
+    }
+
+    let mut model = model().lock().unwrap();
+    let Some(history) = model.resources.get_mut(&(kind, name.to_owned()))
+    else {
+        return Ok(());
+    };
+
+    if let Some(problem) = history.unreported.take() {
+        return Err(AntagonistError::CheckFailed(format!(
+            "{:?} {}: {}",
+            kind, name, problem
+        )));
+    }
+
+    // A resource that exists should have the ID the most recent create
+    // returned, unless another create might have replaced it since.
+    let creates = &history.creates;
+    if let (Some(found), Some(expected), Some(finished)) =
+        (found, history.id, creates.last_succeeded)
+    {
+        if found != expected
+            && finished < sent
+            && creates.in_flight == 0
+            && creates.last_started.map_or(true, |t| t < finished)
+        {
+            // Report each mismatch once.
+            history.id = None;
+            return Err(AntagonistError::CheckFailed(format!(
+                "{:?} {} has ID {}, but its last create returned {}",
+                kind, name, found, expected
+            )));
+        }
+    }
+
+    let exists = found.is_some();
+
+    // If the resource exists, look for a delete that should have removed it,
+    // and vice versa.
+    let (op, undo) = if exists {
+        (Op::Delete, Op::Create)
+    } else {
+        (Op::Create, Op::Delete)
+    };
+
+    let Some(finished) = history.op(op).last_succeeded else {
+        return Ok(());
+    };
+
+    let undo_history = history.op(undo);
+    let before_finished = |t: Option<Instant>| t.map_or(true, |t| t < finished);
+    let contradiction = finished < sent
+        && undo_history.in_flight == 0
+        && before_finished(undo_history.last_started)
+        && before_finished(undo_history.last_succeeded);
+
+    if !contradiction {
+        return Ok(());
+    }
+
+    // Report each contradiction once, rather than on every later observation.
+    history.op(op).last_succeeded = None;
+    Err(AntagonistError::CheckFailed(format!(
+        "{:?} {} {} after a successful {:?} with no intervening {:?}",
+        kind,
+        name,
+        if exists { "exists" } else { "doesn't exist" },
+        op,
+        undo,
+    )))
+}
@@ -0,0 +1,136 @@
+//! Tracks which resources in the stress project belong to live actors.
+//!
+//! Actors claim the resources they manage when their tasks start and release
+//! their claims when their tasks exit, so that janitors can distinguish
+//! resources that some actor is still working on from debris left behind by
+//! actors that are gone.
+
+use std::collections::HashMap;
+use std::sync::{Mutex, OnceLock};
+
+/// The kinds of resources an actor can own.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum ResourceKind {
+    Instance,
+    Disk,
+    Snapshot,
+    Image,
+}
+
+impl ResourceKind {
+    /// Returns the kind's name, e.g. `instance`.
+    pub fn name(&self) -> &'static str {
+        match self {
+            ResourceKind::Instance => "instance",
+            ResourceKind::Disk => "disk",
+            ResourceKind::Snapshot => "snapshot",
+            ResourceKind::Image => "image",
+        }
+    }
+}
+
+/// A resource (or family of resources) owned by an actor.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct OwnedResource {
+    pub kind: ResourceKind,
+
+    /// The owned resource's name. For snapshots, this is the base name to
+    /// which the owning actor may append a generation counter; in unique-name
+    /// mode, the same is true of instances and disks.
+    pub name: String,
+}
+
+impl OwnedResource {
+    pub fn new(kind: ResourceKind, name: impl Into<String>) -> Self {
+        Self { kind, name: name.into() }
+    }
+
+    /// Returns true if a resource of the supplied `kind` and `name` falls
+    /// under this ownership claim.
+    fn covers(&self, kind: ResourceKind, name: &str) -> bool {
+        if self.kind != kind {
+            return false;
+        }
+
+        match kind {
+            // In unique-name mode, instances and disks are named
+            // `<name>-<generation>`.
+            ResourceKind::Instance | ResourceKind::Disk => {
+                name == self.name
+                    || name
+                        .strip_prefix(self.name.as_str())
+                        .and_then(|rest| rest.strip_prefix('-'))
+                        .is_some_and(|generation| {
+                            !generation.is_empty()
+                                && generation
+                                    .chars()
+                                    .all(|c| c.is_ascii_digit())
+                        })
+            }
+            ResourceKind::Image => self.name == name,
+            ResourceKind::Snapshot => {
+                name.strip_prefix(self.name.as_str()).is_some_and(|counter| {
+                    counter.chars().all(|c| c.is_ascii_digit())
+                })
+            }
+        }
+    }
+}
+
+/// The registry of owned resources, mapping each claim to the number of live
+/// actors holding it.
+static OWNERS: OnceLock<Mutex<HashMap<OwnedResource, usize>>> = OnceLock::new();
+
+fn owners() -> &'static Mutex<HashMap<OwnedResource, usize>> {
+    OWNERS.get_or_init(Default::default)
+}
+
+/// A set of ownership claims held by a live actor. The claims are released
+/// when this is dropped, including when an actor task unwinds from a panic.
+#[derive(Debug)]
+pub struct Claim {
+    resources: Vec<OwnedResource>,
+}
+
+impl Claim {
+    /// Records that a live actor owns each of the supplied resources.
+    pub fn new(resources: Vec<OwnedResource>) -> Self {
+        let mut owners = owners().lock().unwrap();
+        for r in &resources {
+            *owners.entry(r.clone()).or_default() += 1;
+        }
+
+        Self { resources }
+    }
+}
+
+impl Drop for Claim {
+    fn drop(&mut self) {
+        let mut owners = owners().lock().unwrap();
+        for r in &self.resources {
+            if let Some(count) = owners.get_mut(r) {
+                *count -= 1;
+                if *count == 0 {
+                    owners.remove(r);
+                }
+            }
+        }
+    }
+}
+
+/// Returns true if some live actor owns the resource with the supplied `kind`
+/// and `name`.
+pub fn is_owned(kind: ResourceKind, name: &str) -> bool {
+    owners().lock().unwrap().keys().any(|r| r.covers(kind, name))
+}
+
+/// Returns true if some live actor owns the resource with the supplied `kind`
+/// and `name` under a claim other than the one named `own`, i.e. if it belongs
+/// to someone other than the actors sharing `own`.
+pub fn is_owned_by_other(kind: ResourceKind, name: &str, own: &str) -> bool {
+    owners()
+        .lock()
+        .unwrap()
+        .keys()
+        .any(|r| r.name != own && r.covers(kind, name))
+}
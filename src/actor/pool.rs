@@ -0,0 +1,489 @@
+//! The pooled actor engine (`--actor-workers`), in which a fixed pool of
+//! worker tasks drives every actor, instead of each actor having a task and
+//! channels of its own. This lets a run simulate thousands of API clients.
+//!
+//! Each actor is a row in its worker's table, holding the actor's antagonist
+//! and what the harness has asked of it (to pause, halt, or switch profiles).
+//! Actors are dealt out to workers by ID. A worker starts a step for each of
+//! its actors that's due one and polls all the steps it has started together,
+//! so an actor that's waiting on Nexus or sleeping between actions costs a
+//! future rather than a task.
+
+use std::collections::BTreeMap;
+use std::future::Future;
+use std::panic::AssertUnwindSafe;
+use std::pin::Pin;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Mutex, MutexGuard, OnceLock};
+use std::time::Duration;
+
+use futures::future::{AbortHandle, Abortable, Aborted};
+use futures::stream::FuturesUnordered;
+use futures::{FutureExt, StreamExt};
+use tokio::sync::{mpsc, oneshot, Notify};
+use tokio::task::JoinHandle;
+use tokio::time::Instant;
+use tokio_util::sync::CancellationToken;
+use tracing::{info, warn, Instrument};
+
+use super::ownership::Claim;
+use super::{
+    reconnect, timed_step, ActorError, Antagonist, AntagonistError,
+    CurrentActor, ErrorContext, CURRENT_ACTOR,
+};
+
+/// How a pooled actor's steps ended: normally, or with its antagonist
+/// panicking.
+pub(super) type Exit = std::thread::Result<()>;
+
+/// An actor's row in its worker's table.
+struct Slot {
+    current: CurrentActor,
+
+    /// The actor's antagonist, or `None` while one of its steps is running.
+    antagonist: Option<Box<dyn Antagonist>>,
+
+    /// The actor's resource claims, held for as long as it's in the table.
+    _claim: Claim,
+
+    /// True once the actor has taken its first step.
+    started: bool,
+    paused: bool,
+
+    /// If the actor has been asked to halt, whether to drain its resources
+    /// first.
+    halt: Option<bool>,
+
+    /// A profile whose action weights to switch to before the next step.
+    profile: Option<crate::profile::Profile>,
+
+    /// The credentials generation the antagonist's client was built with.
+    generation: u64,
+
+    /// When the actor's next step is due.
+    next_step: Instant,
+
+    /// Cancelled when the actor is halted, to abandon its running step's API
+    /// calls.
+    cancel: CancellationToken,
+
+    /// Aborts the actor's running step, if it has one.
+    abort: Option<AbortHandle>,
+
+    /// Sent how the actor's steps ended once it leaves the table.
+    done: Option<oneshot::Sender<Exit>>,
+}
+
+impl Drop for Slot {
+    fn drop(&mut self) {
+        if let Some(done) = self.done.take() {
+            let _ = done.send(Ok(()));
+        }
+    }
+}
+
+/// What a step (or drain) left behind.
+enum Finished {
+    Stepped { antagonist: Box<dyn Antagonist>, generation: u64 },
+    Drained,
+}
+
+type Job = Pin<
+    Box<
+        dyn Future<
+                Output = (
+                    usize,
+                    Result<std::thread::Result<Finished>, Aborted>,
+                ),
+            > + Send,
+    >,
+>;
+
+/// The worker pool.
+pub(super) struct Pool {
+    /// Each worker's actors, keyed by actor ID.
+    tables: Vec<Mutex<BTreeMap<usize, Slot>>>,
+
+    /// Wakes each worker to look for actors that are due a step.
+    wake: Vec<Notify>,
+
+    /// Notified whenever a step ends, for callers waiting to pause an actor
+    /// partway through one.
+    step_done: Notify,
+    next_id: AtomicUsize,
+
+    /// Where actors' errors go, with their context. This never blocks, so
+    /// that a step that fails can always end (and its actor halt) even when
+    /// the harness isn't reading errors.
+    error_tx: mpsc::UnboundedSender<ActorError>,
+}
+
+static POOL: OnceLock<Pool> = OnceLock::new();
+
+/// Starts `workers` worker tasks to drive every actor created from now on,
+/// forwarding the actors' errors to `error_tx`.
+pub fn start(workers: usize, error_tx: mpsc::Sender<ActorError>) {
+    let workers = workers.max(1);
+    let (pool_tx, mut pool_rx) = mpsc::unbounded_channel();
+    tokio::spawn(async move {
+        while let Some(e) = pool_rx.recv().await {
+            if error_tx.send(e).await.is_err() {
+                break;
+            }
+        }
+    });
+
+    let pool = Pool {
+        tables: (0..workers).map(|_| Default::default()).collect(),
+        wake: (0..workers).map(|_| Notify::new()).collect(),
+        step_done: Notify::new(),
+        next_id: AtomicUsize::new(0),
+        error_tx: pool_tx,
+    };
+    if POOL.set(pool).is_err() {
+        panic!("actor pool started twice");
+    }
+
+    info!(workers, "Starting actor worker pool");
+    for worker in 0..workers {
+        tokio::spawn(work(worker));
+    }
+}
+
+/// Returns the worker pool, if there is one.
+pub(super) fn get() -> Option<&'static Pool> {
+    POOL.get()
+}
+
+/// Returns a task that finishes when the pooled actor with ID `id`, whose
+/// exit is sent to `done`, leaves the table. The task panics if the actor's
+/// antagonist did, and aborting it removes the actor from the table, so that
+/// it behaves like the task of an actor with a task of its own.
+pub(super) fn join(id: usize, done: oneshot::Receiver<Exit>) -> JoinHandle<()> {
+    /// Removes an actor from the table when dropped, e.g. because the task
+    /// waiting on it was aborted. Does nothing if the actor is already gone.
+    struct AbortOnDrop(usize);
+
+    impl Drop for AbortOnDrop {
+        fn drop(&mut self) {
+            if let Some(pool) = POOL.get() {
+                pool.abort(self.0);
+            }
+        }
+    }
+
+    tokio::spawn(async move {
+        let _guard = AbortOnDrop(id);
+        if let Ok(Err(panic)) = done.await {
+            std::panic::resume_unwind(panic);
+        }
+    })
+}
+
+/// Takes one step with `antagonist`, as the actor task's loop would.
+async fn step(
+    id: usize,
+    current: CurrentActor,
+    mut antagonist: Box<dyn Antagonist>,
+    profile: Option<crate::profile::Profile>,
+    mut generation: u64,
+    cancel: CancellationToken,
+) -> Finished {
+    let span = current.span.clone();
+    let kind = current.kind;
+    let body = async {
+        if let Some(profile) = profile {
+            antagonist.set_profile(profile);
+        }
+
+        if crate::client::credentials_generation() != generation {
+            generation = crate::client::credentials_generation();
+            reconnect(&mut antagonist);
+        }
+
+        let (mut result, mut elapsed) =
+            timed_step(&mut antagonist, kind, &cancel).await;
+
+        if result.as_ref().is_err_and(crate::client::is_unauthorized)
+            && crate::client::refresh_credentials(generation).await
+        {
+            generation = crate::client::credentials_generation();
+            reconnect(&mut antagonist);
+            (result, elapsed) =
+                timed_step(&mut antagonist, kind, &cancel).await;
+        }
+
+        // Errors from actors that have been asked to halt have no one to go
+        // to, as with an actor task whose error forwarder is gone.
+        let pool = POOL.get().unwrap();
+        if let (Err(error), false) = (result, pool.halting(id)) {
+            let context = ErrorContext::of_step(&current, &error, elapsed);
+            let _ = pool.error_tx.send(ActorError { error, context });
+        }
+    };
+    CURRENT_ACTOR.scope(current.clone(), body.instrument(span)).await;
+
+    Finished::Stepped { antagonist, generation }
+}
+
+/// Drives `antagonist`'s resources to a quiescent state before its actor
+/// leaves the table.
+async fn drain(
+    current: CurrentActor,
+    mut antagonist: Box<dyn Antagonist>,
+) -> Finished {
+    let span = current.span.clone();
+    let body = async move {
+        info!("draining resources");
+        if let Err(e) = antagonist.drain().await {
+            warn!(error = %e, "failed to drain resources");
+        }
+    };
+    CURRENT_ACTOR.scope(current, body.instrument(span)).await;
+    Finished::Drained
+}
+
+/// Runs worker `worker`, which drives every actor in its table.
+async fn work(worker: usize) {
+    let pool = POOL.get().unwrap();
+    let mut running = FuturesUnordered::new();
+    loop {
+        let (jobs, next_due) = pool.take_due(worker);
+        running.extend(jobs);
+        tokio::select! {
+            Some((id, result)) = running.next(), if !running.is_empty() => {
+                pool.finish(worker, id, result);
+            }
+            _ = crate::sleep_or_pend(next_due) => {}
+            _ = pool.wake[worker].notified() => {}
+        }
+    }
+}
+
+impl Pool {
+    /// Returns the table that holds the actor with ID `id`.
+    fn table(&self, id: usize) -> MutexGuard<'_, BTreeMap<usize, Slot>> {
+        self.tables[id % self.tables.len()].lock().unwrap()
+    }
+
+    /// Wakes the worker that drives the actor with ID `id`.
+    fn wake(&self, id: usize) {
+        self.wake[id % self.wake.len()].notify_one();
+    }
+
+    /// Adds an actor that takes its first step after `start_delay`. Returns
+    /// the actor's ID and a channel that's sent how its steps ended once it
+    /// leaves the table.
+    pub(super) fn add(
+        &self,
+        current: CurrentActor,
+        antagonist: Box<dyn Antagonist>,
+        claim: Claim,
+        generation: u64,
+        cancel: CancellationToken,
+        start_delay: Duration,
+    ) -> (usize, oneshot::Receiver<Exit>) {
+        let id = self.next_id.fetch_add(1, Ordering::Relaxed);
+        let (done_tx, done_rx) = oneshot::channel();
+        self.table(id).insert(
+            id,
+            Slot {
+                current,
+                antagonist: Some(antagonist),
+                _claim: claim,
+                started: false,
+                paused: false,
+                halt: None,
+                profile: None,
+                generation,
+                next_step: Instant::now() + start_delay,
+                cancel,
+                abort: None,
+                done: Some(done_tx),
+            },
+        );
+        self.wake(id);
+        (id, done_rx)
+    }
+
+    /// Returns true if the actor with ID `id` is still in the table.
+    pub(super) fn contains(&self, id: usize) -> bool {
+        self.table(id).contains_key(&id)
+    }
+
+    /// Pauses the actor with ID `id`, waiting for its running step (if any)
+    /// to end.
+    pub(super) async fn pause(&self, id: usize) {
+        loop {
+            let notified = self.step_done.notified();
+            tokio::pin!(notified);
+            notified.as_mut().enable();
+            match self.table(id).get_mut(&id) {
+                None => return,
+                Some(slot) => {
+                    slot.paused = true;
+                    if slot.antagonist.is_some() {
+                        return;
+                    }
+                }
+            }
+
+            notified.await;
+        }
+    }
+
+    /// Resumes the actor with ID `id`.
+    pub(super) fn resume(&self, id: usize) {
+        if let Some(slot) = self.table(id).get_mut(&id) {
+            slot.paused = false;
+        }
+        self.wake(id);
+    }
+
+    /// Switches the actor with ID `id` to `profile`'s action weights before
+    /// its next step.
+    pub(super) fn set_profile(
+        &self,
+        id: usize,
+        profile: crate::profile::Profile,
+    ) {
+        if let Some(slot) = self.table(id).get_mut(&id) {
+            slot.profile = Some(profile);
+        }
+    }
+
+    /// Halts the actor with ID `id` once its running step (if any) ends,
+    /// first draining its resources if `drain` is true.
+    pub(super) fn halt(&self, id: usize, drain: bool) {
+        if let Some(slot) = self.table(id).get_mut(&id) {
+            slot.halt = Some(drain);
+        }
+        self.wake(id);
+    }
+
+    /// Returns true if the actor with ID `id` has been asked to halt or is
+    /// gone.
+    fn halting(&self, id: usize) -> bool {
+        self.table(id).get(&id).map_or(true, |slot| slot.halt.is_some())
+    }
+
+    /// Removes the actor with ID `id` from the table at once, abandoning its
+    /// running step.
+    pub(super) fn abort(&self, id: usize) {
+        let slot = self.table(id).remove(&id);
+        if let Some(abort) = slot.as_ref().and_then(|s| s.abort.as_ref()) {
+            abort.abort();
+        }
+    }
+
+    /// Starts a step (or drain) for each of worker `worker`'s actors that's
+    /// due one, and removes the actors that have halted. Returns the jobs
+    /// started and when the next step is due, if any are scheduled.
+    fn take_due(&self, worker: usize) -> (Vec<Job>, Option<Instant>) {
+        let now = Instant::now();
+        let mut table = self.tables[worker].lock().unwrap();
+        let mut jobs = Vec::new();
+        let mut next_due: Option<Instant> = None;
+        let mut halted = Vec::new();
+        for (&id, slot) in table.iter_mut() {
+            // Skip actors that are already taking a step.
+            if slot.antagonist.is_none() {
+                continue;
+            }
+
+            // Actors that are halted before they start don't drain, since
+            // they haven't created anything.
+            let draining = match slot.halt {
+                Some(true) if slot.started => true,
+                Some(_) => {
+                    halted.push(id);
+                    continue;
+                }
+                None if slot.paused => continue,
+                None if slot.next_step > now => {
+                    next_due = Some(
+                        next_due
+                            .map_or(slot.next_step, |n| n.min(slot.next_step)),
+                    );
+                    continue;
+                }
+                None => false,
+            };
+
+            let antagonist = slot.antagonist.take().unwrap();
+            let current = slot.current.clone();
+            let job: Pin<Box<dyn Future<Output = Finished> + Send>> =
+                if draining {
+                    Box::pin(drain(current, antagonist))
+                } else {
+                    slot.started = true;
+                    Box::pin(step(
+                        id,
+                        current,
+                        antagonist,
+                        slot.profile.take(),
+                        slot.generation,
+                        slot.cancel.clone(),
+                    ))
+                };
+            let (abort, registration) = AbortHandle::new_pair();
+            slot.abort = Some(abort);
+            let job = Abortable::new(
+                AssertUnwindSafe(job).catch_unwind(),
+                registration,
+            );
+            jobs.push(Box::pin(job.map(move |result| (id, result))) as Job);
+        }
+
+        for id in halted {
+            table.remove(&id);
+        }
+
+        (jobs, next_due)
+    }
+
+    /// Records that the job that worker `worker` started for the actor with
+    /// ID `id` ended with `result`.
+    fn finish(
+        &self,
+        worker: usize,
+        id: usize,
+        result: Result<std::thread::Result<Finished>, Aborted>,
+    ) {
+        let mut table = self.tables[worker].lock().unwrap();
+        match result {
+            Ok(Ok(Finished::Stepped { antagonist, generation })) => {
+                if let Some(slot) = table.get_mut(&id) {
+                    slot.antagonist = Some(antagonist);
+                    slot.generation = generation;
+                    slot.abort = None;
+                    slot.next_step = Instant::now();
+                }
+            }
+
+            Ok(Ok(Finished::Drained)) | Err(Aborted) => {
+                table.remove(&id);
+            }
+
+            Ok(Err(panic)) => {
+                if let Some(mut slot) = table.remove(&id) {
+                    if let Some(done) = slot.done.take() {
+                        let _ = done.send(Err(panic));
+                    }
+
+                    // Tell the harness the actor is gone, as the actor's
+                    // error channel disconnecting would if it had its own
+                    // task.
+                    let error = AntagonistError::DisconnectedErrorChannel {
+                        name: slot.current.name.clone(),
+                    };
+                    let context = ErrorContext::of_actor(&slot.current);
+                    let _ = self.error_tx.send(ActorError { error, context });
+                }
+            }
+        }
+
+        drop(table);
+        self.step_done.notify_waiters();
+    }
+}
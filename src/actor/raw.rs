@@ -0,0 +1,216 @@
+//! Raw create requests, for antagonists that send bodies the SDK's types
+//! won't let them build (`fuzz` and `boundary`). Requests are sent with the
+//! SDK client's HTTP client, so they carry its credentials, and responses are
+//! parsed the way the SDK would parse them.
+
+use core::result::Result;
+use oxide::ResponseValue;
+use serde_json::{json, Value};
+use tracing::warn;
+
+use crate::util::ok_if_not_found;
+use crate::util::OxideApiError;
+
+/// The kinds of resources these antagonists ask to create.
+#[derive(Clone, Copy, Debug)]
+pub(super) enum Target {
+    Disk,
+    Instance,
+}
+
+impl Target {
+    /// The path segment of this kind's collection, e.g. `disks`.
+    fn collection(&self) -> &'static str {
+        match self {
+            Target::Disk => "disks",
+            Target::Instance => "instances",
+        }
+    }
+
+    fn create_endpoint(&self) -> &'static str {
+        match self {
+            Target::Disk => "disk_create",
+            Target::Instance => "instance_create",
+        }
+    }
+
+    fn delete_endpoint(&self) -> &'static str {
+        match self {
+            Target::Disk => "disk_delete",
+            Target::Instance => "instance_delete",
+        }
+    }
+
+    /// Returns a valid create body for a resource named `name`. Instances
+    /// aren't started, so that creating one doesn't need room on a sled.
+    pub(super) fn valid_body(&self, name: &str) -> Value {
+        match self {
+            Target::Disk => json!({
+                "name": name,
+                "description": crate::run_info::description(name),
+                "disk_source": { "type": "blank", "block_size": 512 },
+                "size": 1024 * 1024 * 1024_u64,
+            }),
+            Target::Instance => json!({
+                "name": name,
+                "description": crate::run_info::description(name),
+                "hostname": name,
+                "memory": 1024 * 1024 * 1024_u64,
+                "ncpus": 1,
+                "disks": [],
+                "external_ips": [],
+                "network_interfaces": { "type": "none" },
+                "start": false,
+                "user_data": "",
+            }),
+        }
+    }
+}
+
+/// Sends a `method` request with `body`, if set, to the path made of
+/// `segments`, in `project`. Parses the response the way the SDK would, except
+/// that successful responses' bodies are left as JSON (or `null`, if they
+/// aren't JSON).
+async fn send(
+    client: &oxide::Client,
+    project: &str,
+    method: reqwest::Method,
+    segments: &[&str],
+    body: Option<String>,
+) -> Result<ResponseValue<Value>, OxideApiError> {
+    let mut url = reqwest::Url::parse(client.baseurl())
+        .map_err(|e| OxideApiError::InvalidRequest(e.to_string()))?;
+    url.path_segments_mut()
+        .map_err(|()| {
+            OxideApiError::InvalidRequest("base URL can't have a path".into())
+        })?
+        .pop_if_empty()
+        .push("v1")
+        .extend(segments);
+    url.query_pairs_mut().append_pair("project", project);
+
+    let mut request = client.client().request(method, url);
+    if let Some(body) = body {
+        request = request
+            .header(http::header::CONTENT_TYPE, "application/json")
+            .body(body);
+    }
+
+    let response =
+        request.send().await.map_err(OxideApiError::CommunicationError)?;
+    let status = response.status();
+    let headers = response.headers().clone();
+    if status.is_success() {
+        let body =
+            response.bytes().await.map_err(OxideApiError::ResponseBodyError)?;
+        let body = serde_json::from_slice(&body).unwrap_or(Value::Null);
+        return Ok(ResponseValue::new(body, status, headers));
+    }
+
+    if !status.is_client_error() && !status.is_server_error() {
+        return Err(OxideApiError::UnexpectedResponse(response));
+    }
+
+    let body =
+        response.bytes().await.map_err(OxideApiError::ResponseBodyError)?;
+    match serde_json::from_slice::<oxide::types::Error>(&body) {
+        Ok(error) => Err(OxideApiError::ErrorResponse(ResponseValue::new(
+            error, status, headers,
+        ))),
+        Err(e) => Err(OxideApiError::InvalidResponsePayload(body, e)),
+    }
+}
+
+/// Asks to create a `target` in `project` with `body`, which needn't be
+/// valid, or even JSON.
+pub(super) async fn create(
+    client: &oxide::Client,
+    project: &str,
+    target: Target,
+    body: String,
+) -> Result<ResponseValue<Value>, OxideApiError> {
+    let segments = [target.collection()];
+    crate::middleware::call(target.create_endpoint(), || {
+        send(
+            client,
+            project,
+            reqwest::Method::POST,
+            &segments,
+            Some(body.clone()),
+        )
+    })
+    .await
+}
+
+/// Deletes the `target` named `name`, logging any failure other than its not
+/// existing.
+async fn delete(
+    client: &oxide::Client,
+    project: &str,
+    target: Target,
+    name: &str,
+) {
+    let segments = [target.collection(), name];
+    let result = crate::middleware::call(target.delete_endpoint(), || {
+        send(client, project, reqwest::Method::DELETE, &segments, None)
+    })
+    .await;
+
+    if let Err(e) = ok_if_not_found(result.map(|_| ())) {
+        warn!(?target, name, "failed to delete resource: {}", e);
+    }
+}
+
+/// Deletes what a successful create of a `target` with `body` created: the
+/// resource itself and, for an instance, any disks it created along with it.
+pub(super) async fn clean_up(
+    client: &oxide::Client,
+    project: &str,
+    target: Target,
+    body: &str,
+) {
+    let Ok(body) = serde_json::from_str::<Value>(body) else {
+        return;
+    };
+
+    if let Some(name) = body["name"].as_str() {
+        delete(client, project, target, name).await;
+    }
+
+    // An instance's disks outlive it, so delete them after it's gone.
+    for disk in body["disks"].as_array().into_iter().flatten() {
+        if let ("create", Some(name)) =
+            (disk["type"].as_str().unwrap_or_default(), disk["name"].as_str())
+        {
+            delete(client, project, Target::Disk, name).await;
+        }
+    }
+}
+
+/// Returns a description of what's wrong with `result`, the result of a
+/// request that Nexus should have rejected, or `None` if Nexus rejected it
+/// cleanly: with a 4xx status and a well-formed error body. Nexus leaves the
+/// error code out when it can't deserialize a request at all, which is how it
+/// rejects most fuzzed bodies, so a missing code is fine.
+pub(super) fn check_rejected(
+    result: &Result<ResponseValue<Value>, OxideApiError>,
+) -> Option<String> {
+    match result {
+        Ok(rv) => Some(format!("accepted with status {}", rv.status())),
+        Err(e @ oxide::Error::ErrorResponse(rv))
+            if rv.status().is_client_error() =>
+        {
+            crate::error_schema::malformed(e).map(|problem| {
+                format!(
+                    "{} with a malformed error body ({})",
+                    rv.status(),
+                    problem
+                )
+            })
+        }
+        Err(oxide::Error::ErrorResponse(rv)) => {
+            Some(format!("failed with status {}", rv.status()))
+        }
+        Err(e) => Some(format!("failed without an error response: {}", e)),
+    }
+}
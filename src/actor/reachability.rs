@@ -0,0 +1,200 @@
+//! An antagonist that checks that running instances with external IPs are
+//! reachable over the network.
+//!
+//! The probe makes a TCP connection to a well-known port on each of the
+//! instance's external IPs, and skips instances that don't have any. Instance
+//! antagonists only ask for external IPs as often as their group's
+//! `networking.external_ip` says, so a workload won't load unless the probed
+//! instances' group asks for some (see `crate::workload`). Even then, probing
+//! is only meaningful for instances whose guests actually listen on the port;
+//! a diskless instance with no boot image will never be reachable.
+
+use async_trait::async_trait;
+use core::result::Result;
+use oxide::types::ExternalIp;
+use oxide::types::InstanceState;
+use oxide::ClientInstancesExt;
+use rand::rngs::StdRng;
+use std::net::{IpAddr, SocketAddr};
+use std::time::{Duration, Instant};
+use tracing::{info, trace, warn};
+
+use crate::actor::AntagonistError;
+use crate::util::sleep_random_ms;
+use crate::util::OxideApiError;
+
+/// The parameters used to configure a reachability antagonist.
+pub struct Params {
+    /// The name of the project containing the instance to probe.
+    pub project: String,
+
+    /// The name of the instance this antagonist should probe.
+    pub instance_name: String,
+
+    /// The TCP port to connect to on each of the instance's external IPs.
+    pub port: u16,
+
+    /// How long a running instance may remain unreachable before the probe
+    /// reports an error.
+    pub grace_period: Duration,
+}
+
+/// The internal state for a reachability antagonist.
+#[derive(Debug)]
+pub(super) struct ReachabilityActor {
+    client: oxide::Client,
+    project: String,
+
+    /// The name of the instance this actor probes. In unique-name mode, this
+    /// is the current generation of `base_name`.
+    instance_name: String,
+    base_name: String,
+    unique_names: bool,
+    port: u16,
+    grace_period: Duration,
+
+    /// The time at which the instance was first observed to be running with
+    /// an external IP and unreachable, along with the IPs that were probed at
+    /// that time. Cleared whenever the instance is reachable, stops running, or
+    /// its set of external IPs changes.
+    unreachable_since: Option<(Instant, Vec<IpAddr>)>,
+    rng: StdRng,
+}
+
+impl ReachabilityActor {
+    /// Creates a new reachability antagonist.
+    pub(super) fn new(params: Params, rng: StdRng) -> anyhow::Result<Self> {
+        Ok(Self {
+            client: crate::client::get_client(crate::config())?,
+            project: params.project,
+            instance_name: params.instance_name.clone(),
+            base_name: params.instance_name,
+            unique_names: crate::config().unique_names,
+            port: params.port,
+            grace_period: params.grace_period,
+            unreachable_since: None,
+            rng,
+        })
+    }
+
+    /// Gets the external IPs currently attached to this actor's instance.
+    async fn get_external_ips(&self) -> Result<Vec<IpAddr>, OxideApiError> {
+        let ips = crate::middleware::call("instance_external_ip_list", || {
+            self.client
+                .instance_external_ip_list()
+                .project(&self.project)
+                .instance(&self.instance_name)
+                .send()
+        })
+        .await?
+        .into_inner();
+
+        let mut ips: Vec<IpAddr> = ips
+            .items
+            .into_iter()
+            .map(|ip| match ip {
+                ExternalIp::Ephemeral { ip, .. } => ip,
+                ExternalIp::Floating { ip, .. } => ip,
+            })
+            .collect();
+
+        ips.sort();
+        Ok(ips)
+    }
+
+    /// Attempts a TCP connection to the probe port on `ip`, returning `true`
+    /// if the connection succeeded.
+    async fn probe(&self, ip: IpAddr) -> bool {
+        let addr = SocketAddr::new(ip, self.port);
+        let connect = tokio::net::TcpStream::connect(addr);
+        match tokio::time::timeout(Duration::from_secs(5), connect).await {
+            Ok(Ok(_)) => {
+                trace!(%addr, "probe connected");
+                true
+            }
+            Ok(Err(e)) => {
+                info!(%addr, error = %e, "probe failed to connect");
+                false
+            }
+            Err(_) => {
+                info!(%addr, "probe timed out");
+                false
+            }
+        }
+    }
+}
+
+#[async_trait]
+impl super::Antagonist for ReachabilityActor {
+    fn set_client(&mut self, client: oxide::Client) {
+        self.client = client;
+    }
+
+    #[tracing::instrument(level = "info", skip(self), fields(instance_name = self.base_name))]
+    async fn step(&mut self) -> Result<(), AntagonistError> {
+        sleep_random_ms(&mut self.rng, 1000).await;
+
+        if self.unique_names {
+            let generation = super::current_generation(&self.base_name);
+            self.instance_name =
+                super::generation_name(&self.base_name, generation);
+        }
+
+        trace!("querying instance state");
+        let state = super::instance::view(
+            &self.client,
+            &self.project,
+            &self.instance_name,
+        )
+        .await?
+        .map(|instance| instance.run_state);
+        if state != Some(InstanceState::Running) {
+            trace!(?state, "instance isn't running, won't probe");
+            self.unreachable_since = None;
+            return Ok(());
+        }
+
+        let ips = self.get_external_ips().await?;
+        if ips.is_empty() {
+            trace!("instance has no external IPs, won't probe");
+            self.unreachable_since = None;
+            return Ok(());
+        }
+
+        let mut unreachable = Vec::new();
+        for ip in &ips {
+            if !self.probe(*ip).await {
+                unreachable.push(*ip);
+            }
+        }
+
+        if unreachable.is_empty() {
+            self.unreachable_since = None;
+            return Ok(());
+        }
+
+        // Restart the grace period if the instance's IPs changed since the
+        // last failed probe, since a newly-attached IP may take some time to
+        // become routable.
+        let first_failure = match &self.unreachable_since {
+            Some((first, probed)) if *probed == ips => *first,
+            _ => {
+                let now = Instant::now();
+                self.unreachable_since = Some((now, ips));
+                now
+            }
+        };
+
+        let elapsed = first_failure.elapsed();
+        if elapsed > self.grace_period {
+            warn!(?unreachable, ?elapsed, "instance unreachable");
+            self.unreachable_since = None;
+            return Err(AntagonistError::Unreachable(format!(
+                "running instance {} unreachable at {:?} port {} for {:?}",
+                self.instance_name, unreachable, self.port, elapsed,
+            )));
+        }
+
+        Ok(())
+    }
+}
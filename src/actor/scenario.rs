@@ -0,0 +1,566 @@
+//! An antagonist that runs a multi-resource scenario on each iteration: create
+//! a disk, create an instance with that disk and an extra NIC, start it,
+//! snapshot the disk, stop the instance, and tear everything down again.
+//!
+//! Each step of the scenario is a separate antagonist step, so the actor can be
+//! paused or halted partway through. Each step also has a chance of aborting
+//! the scenario early and skipping straight to teardown, so that resources get
+//! torn down from every intermediate state.
+//!
+//! With --boot-image, the disk is created from a real image, and with
+//! --boot-sla the actor also checks that the guest boots once the instance is
+//! reported Running, by watching its serial console for --boot-marker.
+
+use async_trait::async_trait;
+use core::result::Result;
+use oxide::types::BlockSize;
+use oxide::types::ByteCount;
+use oxide::types::DiskCreate;
+use oxide::types::DiskSource;
+use oxide::types::DiskState;
+use oxide::types::InstanceDiskAttachment;
+use oxide::types::InstanceNetworkInterfaceAttachment;
+use oxide::types::InstanceNetworkInterfaceCreate;
+use oxide::types::InstanceState;
+use oxide::types::Name;
+use oxide::types::SnapshotCreate;
+use oxide::ClientDisksExt;
+use oxide::ClientImagesExt;
+use oxide::ClientInstancesExt;
+use oxide::ClientSnapshotsExt;
+use rand::rngs::StdRng;
+use rand::Rng;
+use std::time::{Duration, Instant};
+use tracing::{info, trace};
+
+use crate::actor::AntagonistError;
+use crate::util::ok_if_not_found;
+use crate::util::unwrap_oxide_api_error;
+use crate::util::OxideApiError;
+
+/// How long to wait for a resource to reach the state a step is waiting for.
+const STEP_TIMEOUT: Duration = Duration::from_secs(300);
+
+/// The probability with which the scenario aborts before each step.
+const ABORT_PROBABILITY: f64 = 0.1;
+
+/// Disk sizes have to be a multiple of this.
+const GIB: u64 = 1024 * 1024 * 1024;
+
+/// The most serial console output to read at once.
+const MAX_CONSOLE_READ: u64 = 64 * 1024;
+
+/// The parameters used to configure a scenario antagonist.
+pub struct Params {
+    /// The name of the project to create this antagonist's resources in.
+    pub project: String,
+
+    /// The base name for this antagonist's resources.
+    pub scenario_name: String,
+}
+
+/// Where a scenario antagonist is in its workload.
+#[derive(Clone, Copy, Debug)]
+enum Phase {
+    /// Resources from a previous scenario might exist and need to be torn
+    /// down, e.g. because the scenario finished, aborted, or failed partway
+    /// through.
+    Teardown,
+
+    /// The next step to run is `STEPS[i]`.
+    Run(usize),
+}
+
+/// The steps in a scenario, in the order they are executed.
+#[derive(Clone, Copy, Debug)]
+enum Step {
+    CreateDisk,
+    CreateInstance,
+    StartInstance,
+    Snapshot,
+    StopInstance,
+}
+
+const STEPS: [Step; 5] = [
+    Step::CreateDisk,
+    Step::CreateInstance,
+    Step::StartInstance,
+    Step::Snapshot,
+    Step::StopInstance,
+];
+
+/// The internal state for a scenario antagonist.
+#[derive(Debug)]
+pub(super) struct ScenarioActor {
+    client: oxide::Client,
+    project: String,
+    scenario_name: String,
+    disk_name: String,
+    instance_name: String,
+    snapshot_name: String,
+    phase: Phase,
+    rng: StdRng,
+}
+
+impl ScenarioActor {
+    /// Creates a new scenario antagonist.
+    pub(super) fn new(params: Params, rng: StdRng) -> anyhow::Result<Self> {
+        Ok(Self {
+            client: crate::client::get_client(crate::config())?,
+            project: params.project,
+            disk_name: disk_name(&params.scenario_name),
+            instance_name: instance_name(&params.scenario_name),
+            snapshot_name: snapshot_name(&params.scenario_name),
+            scenario_name: params.scenario_name,
+            phase: Phase::Teardown,
+            rng,
+        })
+    }
+
+    /// Gets the scenario's instance's state, or `None` if it doesn't exist.
+    async fn get_instance_state(
+        &self,
+    ) -> Result<Option<InstanceState>, OxideApiError> {
+        let instance = super::instance::view(
+            &self.client,
+            &self.project,
+            &self.instance_name,
+        )
+        .await?;
+        Ok(instance.map(|instance| instance.run_state))
+    }
+
+    /// Gets the scenario's disk's state, or `None` if it doesn't exist.
+    async fn get_disk_state(&self) -> Result<Option<DiskState>, OxideApiError> {
+        let res = crate::middleware::call("disk_view", || {
+            self.client
+                .disk_view()
+                .project(&self.project)
+                .disk(&self.disk_name)
+                .send()
+        })
+        .await;
+
+        match res {
+            Ok(rv) => Ok(Some(rv.into_inner().state)),
+            Err(e) => ok_if_not_found(Err(e)).map(|()| None),
+        }
+    }
+
+    /// Polls the scenario's instance until `done` returns true for its state
+    /// (`None` if the instance doesn't exist).
+    async fn wait_for_instance(
+        &self,
+        what: &str,
+        done: impl Fn(Option<InstanceState>) -> bool,
+    ) -> Result<(), AntagonistError> {
+        let start = Instant::now();
+        loop {
+            let state = self.get_instance_state().await?;
+            if done(state) {
+                return Ok(());
+            }
+
+            if start.elapsed() > STEP_TIMEOUT {
+                return Err(AntagonistError::InvalidState(format!(
+                    "instance {} not {} after {:?} (state: {:?})",
+                    self.instance_name, what, STEP_TIMEOUT, state,
+                )));
+            }
+
+            trace!(?state, what, "waiting for instance");
+            tokio::time::sleep(Duration::from_secs(1)).await;
+        }
+    }
+
+    /// Polls the scenario's disk until `done` returns true for its state
+    /// (`None` if the disk doesn't exist).
+    async fn wait_for_disk(
+        &self,
+        what: &str,
+        done: impl Fn(&Option<DiskState>) -> bool,
+    ) -> Result<(), AntagonistError> {
+        let start = Instant::now();
+        loop {
+            let state = self.get_disk_state().await?;
+            if done(&state) {
+                return Ok(());
+            }
+
+            if start.elapsed() > STEP_TIMEOUT {
+                return Err(AntagonistError::InvalidState(format!(
+                    "disk {} not {} after {:?} (state: {:?})",
+                    self.disk_name, what, STEP_TIMEOUT, state,
+                )));
+            }
+
+            trace!(?state, what, "waiting for disk");
+            tokio::time::sleep(Duration::from_secs(1)).await;
+        }
+    }
+
+    /// Returns the source and size of the scenario's disk: blank, or made
+    /// from --boot-image and big enough to hold it.
+    async fn disk_source(
+        &self,
+    ) -> Result<(DiskSource, ByteCount), AntagonistError> {
+        let Some(image_id) = crate::config().boot_image else {
+            let block_size = BlockSize::try_from(512_i64).unwrap();
+            return Ok((
+                DiskSource::Blank { block_size },
+                ByteCount::from(GIB),
+            ));
+        };
+
+        let image = crate::middleware::call("image_view", || {
+            self.client.image_view().image(image_id).send()
+        })
+        .await?
+        .into_inner();
+
+        let size = ((*image.size + GIB - 1) / GIB).max(1) * GIB;
+        Ok((DiskSource::Image { image_id }, ByteCount::from(size)))
+    }
+
+    /// If --boot-sla is set, watches the serial console of
+    /// the scenario's instance, which was just reported Running, for
+    /// --boot-marker, and records an SLA violation if it doesn't show up in
+    /// time. This tells a guest that actually came up from an instance the
+    /// control plane merely says is running.
+    async fn check_boot(&self) -> Result<(), AntagonistError> {
+        let config = crate::config();
+        let Some(sla) = config.boot_sla else {
+            return Ok(());
+        };
+
+        let marker = config.boot_marker.as_bytes();
+        let start = Instant::now();
+        let mut offset = 0;
+        let mut tail: Vec<u8> = Vec::new();
+        loop {
+            let output =
+                crate::middleware::call("instance_serial_console", || {
+                    self.client
+                        .instance_serial_console()
+                        .project(&self.project)
+                        .instance(&self.instance_name)
+                        .from_start(offset)
+                        .max_bytes(MAX_CONSOLE_READ)
+                        .send()
+                })
+                .await?
+                .into_inner();
+
+            offset = output.last_byte_offset;
+            tail.extend_from_slice(&output.data);
+            if marker.is_empty()
+                || tail.windows(marker.len()).any(|w| w == marker)
+            {
+                info!(elapsed = ?start.elapsed(), "guest booted");
+                return Ok(());
+            }
+
+            // Keep just enough output to find a marker split across reads.
+            let keep = marker.len() - 1;
+            if tail.len() > keep {
+                tail.drain(..tail.len() - keep);
+            }
+
+            if start.elapsed() >= sla {
+                return crate::sla::violated(
+                    "guest_boot",
+                    &self.instance_name,
+                    sla,
+                    "Running, no boot marker on serial console".to_owned(),
+                );
+            }
+
+            trace!(offset, "waiting for boot marker");
+            tokio::time::sleep(Duration::from_secs(2)).await;
+        }
+    }
+
+    /// Executes a single scenario step.
+    async fn run_step(&self, step: Step) -> Result<(), AntagonistError> {
+        match step {
+            Step::CreateDisk => {
+                let (disk_source, size) = self.disk_source().await?;
+                let body = DiskCreate {
+                    description: crate::run_info::description(
+                        &self.scenario_name,
+                    ),
+                    disk_source,
+                    name: Name::try_from(&self.disk_name).unwrap(),
+                    size,
+                };
+
+                info!(body = ?body, "sending disk create request");
+                let res = crate::middleware::call("disk_create", || {
+                    self.client
+                        .disk_create()
+                        .project(&self.project)
+                        .body(body.clone())
+                        .send()
+                })
+                .await;
+
+                unwrap_oxide_api_error(res)?;
+                self.wait_for_disk("detached", |s| {
+                    matches!(s, Some(DiskState::Detached))
+                })
+                .await
+            }
+
+            Step::CreateInstance => {
+                let nic = |name: String| InstanceNetworkInterfaceCreate {
+                    description: crate::run_info::description(&name),
+                    ip: None,
+                    name: Name::try_from(name).unwrap(),
+                    subnet_name: Name::try_from("default").unwrap(),
+                    vpc_name: Name::try_from("default").unwrap(),
+                };
+
+                let body = oxide::types::InstanceCreate {
+                    description: crate::run_info::description(
+                        &self.scenario_name,
+                    ),
+                    disks: vec![InstanceDiskAttachment::Attach {
+                        name: Name::try_from(&self.disk_name).unwrap(),
+                    }],
+                    external_ips: vec![],
+                    hostname: self.instance_name.parse().map_err(|e| {
+                        OxideApiError::InvalidRequest(format!(
+                            "{} is not a valid hostname: {e}",
+                            self.instance_name,
+                        ))
+                    })?,
+                    memory: oxide::types::ByteCount(1024 * 1024 * 1024),
+                    name: Name::try_from(&self.instance_name).unwrap(),
+                    ncpus: oxide::types::InstanceCpuCount(1),
+                    network_interfaces:
+                        InstanceNetworkInterfaceAttachment::Create(vec![
+                            nic(format!("{}-nic0", self.scenario_name)),
+                            nic(format!("{}-nic1", self.scenario_name)),
+                        ]),
+                    start: false,
+                    user_data: String::new(),
+                    ssh_public_keys: None,
+                };
+
+                info!(body = ?body, "sending instance create request");
+                let res = crate::middleware::call("instance_create", || {
+                    self.client
+                        .instance_create()
+                        .project(&self.project)
+                        .body(body.clone())
+                        .send()
+                })
+                .await;
+
+                unwrap_oxide_api_error(res)?;
+                Ok(())
+            }
+
+            Step::StartInstance => {
+                info!("sending instance start request");
+                let res = crate::middleware::call("instance_start", || {
+                    self.client
+                        .instance_start()
+                        .project(&self.project)
+                        .instance(&self.instance_name)
+                        .send()
+                })
+                .await;
+
+                unwrap_oxide_api_error(res)?;
+                self.wait_for_instance("running", |s| {
+                    s == Some(InstanceState::Running)
+                })
+                .await?;
+                self.check_boot().await
+            }
+
+            Step::Snapshot => {
+                let body = SnapshotCreate {
+                    name: Name::try_from(&self.snapshot_name).unwrap(),
+                    description: crate::run_info::description(
+                        &self.scenario_name,
+                    ),
+                    disk: self.disk_name.clone().try_into().unwrap(),
+                };
+
+                info!(body = ?body, "sending snapshot create request");
+                let res = crate::middleware::call("snapshot_create", || {
+                    self.client
+                        .snapshot_create()
+                        .project(&self.project)
+                        .body(body.clone())
+                        .send()
+                })
+                .await;
+
+                unwrap_oxide_api_error(res)?;
+                Ok(())
+            }
+
+            Step::StopInstance => {
+                info!("sending instance stop request");
+                let res = crate::middleware::call("instance_stop", || {
+                    self.client
+                        .instance_stop()
+                        .project(&self.project)
+                        .instance(&self.instance_name)
+                        .send()
+                })
+                .await;
+
+                unwrap_oxide_api_error(res)?;
+                self.wait_for_instance("stopped", |s| {
+                    s == Some(InstanceState::Stopped)
+                })
+                .await
+            }
+        }
+    }
+
+    /// Deletes whatever scenario resources currently exist, stopping the
+    /// instance first if needed. Resources that are already gone are ignored.
+    async fn teardown(&self) -> Result<(), AntagonistError> {
+        info!("tearing down scenario");
+        let res = crate::middleware::call("snapshot_delete", || {
+            self.client
+                .snapshot_delete()
+                .project(&self.project)
+                .snapshot(&self.snapshot_name)
+                .send()
+        })
+        .await;
+
+        ok_if_not_found(unwrap_oxide_api_error(res))?;
+
+        // Wait out any in-progress transition, then stop the instance if it's
+        // running so that it can be deleted.
+        self.wait_for_instance("settled", |s| {
+            !matches!(
+                s,
+                Some(
+                    InstanceState::Creating
+                        | InstanceState::Starting
+                        | InstanceState::Stopping
+                        | InstanceState::Rebooting
+                )
+            )
+        })
+        .await?;
+
+        if self.get_instance_state().await? == Some(InstanceState::Running) {
+            let res = crate::middleware::call("instance_stop", || {
+                self.client
+                    .instance_stop()
+                    .project(&self.project)
+                    .instance(&self.instance_name)
+                    .send()
+            })
+            .await;
+
+            ok_if_not_found(unwrap_oxide_api_error(res))?;
+        }
+
+        self.wait_for_instance("stopped", |s| {
+            matches!(
+                s,
+                None | Some(InstanceState::Stopped | InstanceState::Failed)
+            )
+        })
+        .await?;
+
+        let res = crate::middleware::call("instance_delete", || {
+            self.client
+                .instance_delete()
+                .project(&self.project)
+                .instance(&self.instance_name)
+                .send()
+        })
+        .await;
+
+        ok_if_not_found(unwrap_oxide_api_error(res))?;
+
+        // Deleting the instance detaches its disk, but not necessarily right
+        // away.
+        self.wait_for_disk("detached", |s| {
+            matches!(s, None | Some(DiskState::Detached | DiskState::Faulted))
+        })
+        .await?;
+
+        let res = crate::middleware::call("disk_delete", || {
+            self.client
+                .disk_delete()
+                .project(&self.project)
+                .disk(&self.disk_name)
+                .send()
+        })
+        .await;
+
+        ok_if_not_found(unwrap_oxide_api_error(res))?;
+        Ok(())
+    }
+}
+
+/// Returns the name of the disk belonging to the scenario named `scenario`.
+pub fn disk_name(scenario: &str) -> String {
+    format!("{}-disk", scenario)
+}
+
+/// Returns the name of the instance belonging to the scenario named
+/// `scenario`.
+pub fn instance_name(scenario: &str) -> String {
+    format!("{}-inst", scenario)
+}
+
+/// Returns the name of the snapshot belonging to the scenario named
+/// `scenario`.
+pub fn snapshot_name(scenario: &str) -> String {
+    format!("{}-snap", scenario)
+}
+
+#[async_trait]
+impl super::Antagonist for ScenarioActor {
+    fn set_client(&mut self, client: oxide::Client) {
+        self.client = client;
+    }
+
+    #[tracing::instrument(level = "info", skip(self), fields(scenario_name = self.scenario_name, phase = ?self.phase))]
+    async fn step(&mut self) -> Result<(), AntagonistError> {
+        match self.phase {
+            Phase::Teardown => {
+                self.teardown().await?;
+                self.phase = Phase::Run(0);
+            }
+
+            Phase::Run(i) => {
+                // Tear down after the last step, after an early abort, or if
+                // this step fails.
+                self.phase = Phase::Teardown;
+                let Some(&step) = STEPS.get(i) else {
+                    return Ok(());
+                };
+
+                if self.rng.gen_bool(ABORT_PROBABILITY) {
+                    info!(?step, "aborting scenario early");
+                    return Ok(());
+                }
+
+                trace!(?step, "running scenario step");
+                self.run_step(step).await?;
+                self.phase = Phase::Run(i + 1);
+            }
+        }
+
+        Ok(())
+    }
+
+    #[tracing::instrument(level = "info", skip(self), fields(scenario_name = self.scenario_name))]
+    async fn drain(&mut self) -> Result<(), AntagonistError> {
+        self.teardown().await
+    }
+}
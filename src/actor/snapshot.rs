@@ -1,22 +1,25 @@
 //! An antagonist that exercises snapshot lifecycle commands (create, delete).
 
+use std::time::Instant;
+
 use async_trait::async_trait;
 use core::result::Result;
-use oxide::types::BlockSize;
-use oxide::types::ByteCount;
-use oxide::types::DiskCreate;
-use oxide::types::DiskSource;
 use oxide::types::Name;
 use oxide::types::SnapshotCreate;
 use oxide::types::SnapshotState;
-use oxide::ClientDisksExt;
 use oxide::ClientSnapshotsExt;
-use tracing::{info, trace, warn};
+use rand::rngs::StdRng;
+use serde::Deserialize;
+use tracing::{info, trace};
 
+use crate::actor::ownership::ResourceKind;
 use crate::actor::AntagonistError;
-use crate::util::sleep_random_ms;
+use crate::sla::Progress;
+use crate::util::ok_if_error_response;
+use crate::util::sleep_random;
 use crate::util::unwrap_oxide_api_error;
 use crate::util::OxideApiError;
+use crate::util::SleepRange;
 
 #[derive(Debug, Clone)]
 enum BailReason {
@@ -38,11 +41,106 @@ pub struct Params {
     /// The name of the project to create this antagonist's snapshots in.
     pub project: String,
 
-    /// The name of the disk this antagonist should act on.
-    pub disk_name: String,
+    /// The disk this antagonist should snapshot.
+    pub disk: SourceDisk,
 
     /// The name of the snapshot this antagonist should act on.
     pub snapshot_name: String,
+
+    /// The relative likelihood of each action in each snapshot state.
+    pub weights: Weights,
+
+    /// How long to sleep before and after each action.
+    pub think_time: SleepRange,
+}
+
+/// The relative weights of the actions a snapshot antagonist can take from one
+/// state. Actions that are left out of a scenario file get weight 0.
+#[derive(Clone, Copy, Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ActionWeights {
+    pub wait: u32,
+    pub create: u32,
+    pub delete: u32,
+}
+
+impl ActionWeights {
+    pub const fn new(wait: u32, create: u32, delete: u32) -> Self {
+        Self { wait, create, delete }
+    }
+
+    fn table(&self) -> [u32; 3] {
+        [self.wait, self.create, self.delete]
+    }
+}
+
+/// The action weights a snapshot antagonist uses for each state it can find
+/// its snapshot in. States that are left out of a scenario file keep their
+/// default weights.
+#[derive(Clone, Copy, Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Weights {
+    /// Weights for snapshots that are being created.
+    pub creating: ActionWeights,
+
+    /// Weights for ready snapshots.
+    pub ready: ActionWeights,
+
+    /// Weights for destroyed snapshots.
+    pub destroyed: ActionWeights,
+}
+
+impl Default for Weights {
+    fn default() -> Self {
+        Self {
+            // If the snapshot is still starting up, favour politely waiting for
+            // it to finish most of the time, but slightly favour asking for it
+            // to be deleted.
+            creating: ActionWeights::new(70, 10, 20),
+
+            // If the snapshot is ready, equally perform any action on it.
+            ready: ActionWeights::new(35, 30, 35),
+
+            // If the snapshot is destroyed, the antagonist moves on to a new
+            // snapshot name, then equally performs any action on it.
+            destroyed: ActionWeights::new(35, 30, 35),
+        }
+    }
+}
+
+impl Weights {
+    /// Returns an error if every action in some state has weight 0.
+    pub fn validate(&self) -> anyhow::Result<()> {
+        for (state, weights) in [
+            ("creating", &self.creating),
+            ("ready", &self.ready),
+            ("destroyed", &self.destroyed),
+        ] {
+            anyhow::ensure!(
+                weights.table().iter().any(|w| *w > 0),
+                "snapshot weights for the {} state are all zero",
+                state
+            );
+        }
+
+        Ok(())
+    }
+}
+
+/// The disk a snapshot antagonist snapshots.
+pub enum SourceDisk {
+    /// A disk with this name, which the backing-disk manager creates if it
+    /// doesn't exist.
+    Named(String),
+
+    /// A disk with this name that disk antagonists are creating and deleting.
+    /// The snapshot antagonist never creates it, and snapshots it whatever
+    /// state it's in. In unique-name mode, this is the disk's base name, and
+    /// the antagonist snapshots its current generation.
+    Attacked(String),
+
+    /// Any disk that a disk antagonist last saw in the Detached state.
+    Discovered,
 }
 
 /// The internal state for a snapshot antagonist.
@@ -50,90 +148,72 @@ pub struct Params {
 pub(super) struct SnapshotActor {
     client: oxide::Client,
     project: String,
-    disk_name: String,
+
+    /// The lease on this actor's backing disk, if it has its own (or shares
+    /// one with other snapshot antagonists).
+    backing_disk: Option<super::backing_disk::Lease>,
+
+    /// The disk antagonists' disk this actor snapshots, if it has one.
+    attacked_disk: Option<String>,
     snapshot_name: String,
-    snapshot_name_counter: std::sync::Mutex<u64>,
+
+    /// The counter appended to `snapshot_name` to name the snapshot this actor
+    /// is acting on. Actors sharing a snapshot name share the counter, through
+    /// the generation registry, so that they act on the same snapshot.
+    snapshot_name_counter: u64,
+    weights: Weights,
+    think_time: SleepRange,
+
+    /// The snapshot's state as of the last step, for --state-refresh-steps.
+    state: super::cache::CachedState<SnapshotState>,
+    rng: StdRng,
 }
 
 impl SnapshotActor {
     /// Creates a new snapshot antagonist.
-    pub(super) fn new(params: Params) -> anyhow::Result<Self> {
+    pub(super) fn new(params: Params, rng: StdRng) -> anyhow::Result<Self> {
         Ok(Self {
             client: crate::client::get_client(crate::config())?,
             project: params.project,
-            disk_name: params.disk_name,
+            backing_disk: match &params.disk {
+                SourceDisk::Named(name) => {
+                    Some(super::backing_disk::Lease::new(name.clone()))
+                }
+                SourceDisk::Attacked(_) | SourceDisk::Discovered => None,
+            },
+            attacked_disk: match params.disk {
+                SourceDisk::Attacked(name) => Some(name),
+                SourceDisk::Named(_) | SourceDisk::Discovered => None,
+            },
+            snapshot_name_counter: super::current_generation(
+                &params.snapshot_name,
+            ),
             snapshot_name: params.snapshot_name,
-            snapshot_name_counter: std::sync::Mutex::new(0),
+            weights: params.weights,
+            think_time: params.think_time,
+            state: Default::default(),
+            rng,
         })
     }
 
     fn get_snapshot_name(&self) -> String {
-        format!(
-            "{}{}",
-            self.snapshot_name,
-            self.snapshot_name_counter.lock().unwrap(),
-        )
+        format!("{}{}", self.snapshot_name, self.snapshot_name_counter)
     }
 
-    async fn create_backing_disk(&self) -> Result<(), OxideApiError> {
-        let res = self
-            .client
-            .disk_view()
-            .project(&self.project)
-            .disk(&self.disk_name)
-            .send()
-            .await;
-
-        match res {
-            Ok(_) => Ok(()),
-
-            Err(e) => match &e {
-                oxide::Error::InvalidRequest(_)
-                | oxide::Error::CommunicationError(_)
-                | oxide::Error::InvalidResponsePayload(_, _)
-                | oxide::Error::UnexpectedResponse(_)
-                | oxide::Error::InvalidUpgrade(_)
-                | oxide::Error::ResponseBodyError(_)
-                | oxide::Error::PreHookError(_) => Err(e),
-
-                oxide::Error::ErrorResponse(response_value) => {
-                    let status = response_value.status();
+    /// Points this actor at the current counter for its snapshot name, which
+    /// another actor sharing it may have moved forward.
+    fn refresh_counter(&mut self) {
+        self.snapshot_name_counter =
+            super::current_generation(&self.snapshot_name);
+    }
 
-                    if status == http::StatusCode::NOT_FOUND {
-                        // Create this disk
-                        let body = DiskCreate {
-                            description: self.disk_name.to_owned(),
-                            disk_source: DiskSource::Blank {
-                                block_size: BlockSize::try_from(512_i64)
-                                    .unwrap(),
-                            },
-                            name: Name::try_from(&self.disk_name).unwrap(),
-                            size: ByteCount::from(1024 * 1024 * 1024_u64),
-                        };
-
-                        info!(body = ?body, "sending disk create request");
-                        let res = self
-                            .client
-                            .disk_create()
-                            .project(&self.project)
-                            .body(body)
-                            .send()
-                            .await;
-
-                        if res.is_err() {
-                            warn!(result = ?res, "disk create request returned");
-                        } else {
-                            info!(result = ?res, "disk create request returned");
-                        }
-                        unwrap_oxide_api_error(res)?;
-
-                        Ok(())
-                    } else {
-                        Err(e)
-                    }
-                }
-            },
-        }
+    /// Moves this actor on to the next counter for its snapshot name, unless
+    /// another actor sharing it already has.
+    fn bump_counter(&mut self) {
+        self.snapshot_name_counter = super::retire_generation(
+            &self.snapshot_name,
+            self.snapshot_name_counter,
+        );
     }
 
     /// Gets this actor's snapshot's current state.
@@ -142,20 +222,26 @@ impl SnapshotActor {
     ///
     /// - Ok(Some(state)) if the query succeeded.
     /// - Ok(None) if the query failed with a "not found" error.
-    /// - Err if the query failed for any other reason.
+    /// - Err if the query failed for any other reason, or if its result
+    ///   contradicts the expected-state model.
     async fn get_snapshot_state(
         &self,
-    ) -> Result<Option<SnapshotState>, OxideApiError> {
-        let res = self
-            .client
-            .snapshot_view()
-            .project(&self.project)
-            .snapshot(&self.get_snapshot_name())
-            .send()
-            .await;
-
-        match res {
-            Ok(response_value) => Ok(Some(response_value.into_inner().state)),
+    ) -> Result<Option<SnapshotState>, AntagonistError> {
+        let sent = Instant::now();
+        let res = crate::middleware::call("snapshot_view", || {
+            self.client
+                .snapshot_view()
+                .project(&self.project)
+                .snapshot(&self.get_snapshot_name())
+                .send()
+        })
+        .await;
+
+        let found = match res {
+            Ok(response_value) => {
+                let snapshot = response_value.into_inner();
+                Ok(Some((snapshot.state, snapshot.id)))
+            }
 
             Err(e) => match &e {
                 oxide::Error::InvalidRequest(_)
@@ -178,75 +264,140 @@ impl SnapshotActor {
                     }
                 }
             },
-        }
+        }?;
+
+        let id = match &found {
+            Some((SnapshotState::Destroyed, _)) | None => None,
+            Some((_, id)) => Some(*id),
+        };
+        super::model::observe(
+            ResourceKind::Snapshot,
+            &self.get_snapshot_name(),
+            sent,
+            id,
+        )?;
+        Ok(found.map(|(state, _)| state))
     }
 
-    /// Asks to create this actor's snapshot
-    async fn create_snapshot(&self) -> Result<(), OxideApiError> {
+    /// Asks to create this actor's snapshot, first making room for it if
+    /// --max-snapshots-per-disk is set.
+    async fn create_snapshot(&mut self) -> Result<(), OxideApiError> {
+        let disk_name = match (&self.backing_disk, &self.attacked_disk) {
+            (Some(lease), _) => lease.name().to_owned(),
+            (None, Some(base)) if crate::config().unique_names => {
+                super::generation_name(base, super::current_generation(base))
+            }
+            (None, Some(name)) => name.clone(),
+            (None, None) => match super::find_detached_disk(&mut self.rng) {
+                Some(name) => name,
+                None => {
+                    trace!("no detached disks to snapshot");
+                    return Ok(());
+                }
+            },
+        };
+
+        if let Some(max) = crate::config().max_snapshots_per_disk {
+            super::snapshot_limit::make_room(
+                &self.client,
+                &self.project,
+                &disk_name,
+                &self.get_snapshot_name(),
+                &self.snapshot_name,
+                max,
+            )
+            .await?;
+        }
+
         let body = SnapshotCreate {
             name: Name::try_from(&self.get_snapshot_name()).unwrap(),
-            description: self.get_snapshot_name(),
-            disk: self.disk_name.clone().try_into().unwrap(),
+            description: crate::run_info::description(
+                &self.get_snapshot_name(),
+            ),
+            disk: disk_name.try_into().unwrap(),
         };
 
         info!(body = ?body, "sending snapshot create request");
-        let res = self
-            .client
-            .snapshot_create()
-            .project(&self.project)
-            .body(body)
-            .send()
-            .await;
-
-        if res.is_err() {
-            warn!(result = ?res, "snapshot create request returned");
-        } else {
-            info!(result = ?res, "snapshot create request returned");
-        }
+        let pending = super::model::begin(
+            ResourceKind::Snapshot,
+            &self.get_snapshot_name(),
+            super::model::Op::Create,
+        );
+        let res = crate::middleware::call("snapshot_create", || {
+            self.client
+                .snapshot_create()
+                .project(&self.project)
+                .body(body.clone())
+                .send()
+        })
+        .await;
+        pending.finish_create(res.as_ref().ok().map(|rv| rv.id));
 
         unwrap_oxide_api_error(res)
     }
 
+    /// If --snapshot-create-sla or --measure-transitions is set, waits for
+    /// this actor's snapshot, which was just created by a request sent at
+    /// `sent`, to reach Ready, and records an SLA violation if it takes longer
+    /// than the SLA, or how long it took. Snapshots that fault count as failed
+    /// creates. Gives up without a verdict if the snapshot is changed or
+    /// deleted by another actor in the meantime.
+    async fn settle_create(
+        &self,
+        sent: Instant,
+    ) -> Result<(), AntagonistError> {
+        crate::sla::settle(
+            "snapshot_create",
+            &self.get_snapshot_name(),
+            crate::config().snapshot_create_sla,
+            Some(crate::sla::Measure::since(sent)),
+            || self.get_snapshot_state(),
+            |state| match state {
+                Some(SnapshotState::Ready) => Progress::Done,
+                Some(SnapshotState::Creating) => Progress::Pending,
+                Some(SnapshotState::Faulted) => Progress::Failed,
+                _ => Progress::Interrupted,
+            },
+        )
+        .await
+    }
+
     /// Asks to delete this actor's snapshot.
     async fn delete_snapshot(&self) -> Result<(), OxideApiError> {
         info!("sending snapshot delete request");
-        let res = self
-            .client
-            .snapshot_delete()
-            .project(&self.project)
-            .snapshot(&self.get_snapshot_name())
-            .send()
-            .await;
-
-        if res.is_err() {
-            warn!(result = ?res, "snapshot delete request returned");
-        } else {
-            info!(result = ?res, "snapshot delete request returned");
-        }
+        let pending = super::model::begin(
+            ResourceKind::Snapshot,
+            &self.get_snapshot_name(),
+            super::model::Op::Delete,
+        );
+        let res = crate::middleware::call("snapshot_delete", || {
+            self.client
+                .snapshot_delete()
+                .project(&self.project)
+                .snapshot(&self.get_snapshot_name())
+                .send()
+        })
+        .await;
+        pending.finish(res.is_ok());
 
         unwrap_oxide_api_error(res)
     }
 
     /// Selects an action for this antagonist to take given that its snapshot
     /// was observed to be in the supplied `state`.
-    fn get_next_action(&self, state: SnapshotState) -> Action {
+    fn get_next_action(&mut self, state: SnapshotState) -> Action {
         use rand::prelude::Distribution;
         let actions = [Action::Wait, Action::Create, Action::Delete];
 
         let weights = match state {
-            // If the snapshot is still starting up, favour politely waiting for it
-            // to finish most of the time, but slightly favour asking for it to
-            // be deleted.
-            SnapshotState::Creating => [70, 10, 20],
-
-            // If the snapshot is ready, equally perform any action on it.
-            SnapshotState::Ready => [35, 30, 35],
+            SnapshotState::Creating => self.weights.creating,
+            SnapshotState::Ready => self.weights.ready,
 
-            // If the snapshot is destroyed, bump the name counter, then
-            // equally perform any action on it.
+            // If the snapshot is destroyed, bump the name counter before
+            // acting on it.
             SnapshotState::Destroyed => {
-                *self.snapshot_name_counter.lock().unwrap() += 1;
-                [35, 30, 35]
+                self.bump_counter();
+                self.weights.destroyed
             }
 
             _ => {
@@ -258,24 +409,50 @@ impl SnapshotActor {
 
         // `new` returns an error if the iterator is empty, if any weight is <
         // 0, or if its total value is 0.
-        let dist = rand::distributions::WeightedIndex::new(weights).unwrap();
-        let mut rng = rand::thread_rng();
-        actions[dist.sample(&mut rng)].clone()
+        let dist =
+            rand::distributions::WeightedIndex::new(weights.table()).unwrap();
+        actions[dist.sample(&mut self.rng)].clone()
     }
-}
 
-#[async_trait]
-impl super::Antagonist for SnapshotActor {
-    #[tracing::instrument(level = "info", skip(self), fields(snapshot_name = self.snapshot_name))]
-    async fn antagonize(&self) -> Result<(), AntagonistError> {
-        trace!("querying disk state");
-        self.create_backing_disk().await?;
+    /// Takes one step: views the snapshot (or uses its cached state), then
+    /// acts on it.
+    async fn take_step(&mut self) -> Result<(), AntagonistError> {
+        let counter = self.snapshot_name_counter;
+        self.refresh_counter();
+        if self.snapshot_name_counter != counter {
+            self.state.invalidate();
+        }
+
+        // Steps that use the cached snapshot state also assume the backing
+        // disk is still there.
+        let state = match self.state.get() {
+            Some(state) => {
+                trace!(?state, "using cached snapshot state");
+                state
+            }
+            None => {
+                if let Some(lease) = &self.backing_disk {
+                    trace!("querying disk state");
+                    lease.ensure(&self.client, &self.project).await?;
+                }
 
-        trace!("querying snapshot state");
-        let state = match self.get_snapshot_state().await? {
+                trace!("querying snapshot state");
+                let state = self.get_snapshot_state().await?;
+                self.state.observed(state);
+                state
+            }
+        };
+        let state = match state {
             None => {
                 info!("snapshot doesn't exist, will try to create it");
-                return self.create_snapshot().await.map_err(Into::into);
+                if crate::config().unique_names {
+                    self.bump_counter();
+                }
+
+                let sent = Instant::now();
+                self.create_snapshot().await?;
+                self.state.expect(Some(SnapshotState::Creating));
+                return self.settle_create(sent).await;
             }
             Some(state) => {
                 trace!(?state, "got snapshot state");
@@ -283,10 +460,29 @@ impl super::Antagonist for SnapshotActor {
             }
         };
 
-        sleep_random_ms(100).await;
+        sleep_random(&mut self.rng, self.think_time).await;
 
         let action = self.get_next_action(state);
         trace!(?action, "selected action");
+        crate::journal::note_action(&action, Some(&state));
+        let creating = matches!(action, Action::Create);
+        let deleting = matches!(action, Action::Delete);
+        let deleted_id = deleting
+            .then(|| {
+                super::model::id(
+                    ResourceKind::Snapshot,
+                    &self.get_snapshot_name(),
+                )
+            })
+            .flatten();
+
+        // The state each action should leave the snapshot in, if it succeeds.
+        let expected = match &action {
+            Action::Wait | Action::Bail { .. } => None,
+            Action::Create => Some(Some(SnapshotState::Creating)),
+            Action::Delete => Some(None),
+        };
+        let sent = Instant::now();
         let result = match action {
             Action::Wait => Ok(()),
             Action::Create => self.create_snapshot().await,
@@ -300,9 +496,100 @@ impl super::Antagonist for SnapshotActor {
                 }
             },
         };
+        if let (Some(state), true) = (expected, result.is_ok()) {
+            self.state.expect(state);
+        }
 
-        sleep_random_ms(100).await;
+        if creating && result.is_ok() {
+            self.settle_create(sent).await?;
+        }
+
+        if deleting && result.is_ok() {
+            super::verify::deleted(
+                &self.client,
+                &self.project,
+                ResourceKind::Snapshot,
+                &self.get_snapshot_name(),
+                deleted_id,
+            )
+            .await?;
+        }
+
+        sleep_random(&mut self.rng, self.think_time).await;
 
         result.map_err(Into::into)
     }
 }
+
+#[async_trait]
+impl super::Antagonist for SnapshotActor {
+    fn set_client(&mut self, client: oxide::Client) {
+        self.client = client;
+    }
+
+    fn set_profile(&mut self, profile: crate::profile::Profile) {
+        self.weights = profile.snapshot_weights();
+    }
+
+    #[tracing::instrument(level = "info", skip(self), fields(snapshot_name = self.snapshot_name))]
+    async fn step(&mut self) -> Result<(), AntagonistError> {
+        let result = self.take_step().await;
+        if result.is_err() {
+            self.state.invalidate();
+        }
+
+        result
+    }
+
+    #[tracing::instrument(level = "info", skip(self), fields(snapshot_name = self.snapshot_name))]
+    async fn check_abandoned(
+        &mut self,
+        endpoint: &'static str,
+    ) -> Result<(), AntagonistError> {
+        let name = self.get_snapshot_name();
+        super::abandon::settle(
+            endpoint,
+            &name,
+            || self.get_snapshot_state(),
+            |state| !matches!(state, Some(SnapshotState::Creating)),
+        )
+        .await
+    }
+
+    #[tracing::instrument(level = "info", skip(self), fields(snapshot_name = self.snapshot_name))]
+    async fn drain(&mut self) -> Result<(), AntagonistError> {
+        // Other actors sharing this snapshot may be draining it too, so
+        // requests that fail because its state changed are expected. The
+        // backing disk may be shared with other snapshot antagonists, so only
+        // the last of them to drain deletes it.
+        self.refresh_counter();
+        let start = Instant::now();
+        loop {
+            let state = match self.get_snapshot_state().await? {
+                None | Some(SnapshotState::Destroyed) => break,
+                Some(state) => state,
+            };
+
+            if matches!(state, SnapshotState::Ready | SnapshotState::Faulted) {
+                ok_if_error_response(self.delete_snapshot().await)?;
+            }
+
+            if start.elapsed() > super::DRAIN_TIMEOUT {
+                return Err(AntagonistError::InvalidState(format!(
+                    "snapshot {} not deleted after {:?} (state: {:?})",
+                    self.get_snapshot_name(),
+                    super::DRAIN_TIMEOUT,
+                    state,
+                )));
+            }
+
+            tokio::time::sleep(std::time::Duration::from_secs(1)).await;
+        }
+
+        if let Some(lease) = self.backing_disk.take() {
+            lease.release_and_delete(&self.client, &self.project).await?;
+        }
+
+        Ok(())
+    }
+}
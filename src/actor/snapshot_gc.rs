@@ -0,0 +1,163 @@
+//! A janitor antagonist that periodically deletes old snapshots so that long
+//! runs don't accumulate an unbounded number of them. Its deletes go through
+//! the model (see --check-model) like the snapshot actors' own, so a snapshot
+//! it collects isn't mistaken for a lost update.
+
+use async_trait::async_trait;
+use core::result::Result;
+use futures::TryStreamExt;
+use oxide::types::Snapshot;
+use oxide::ClientSnapshotsExt;
+use std::time::{Duration, Instant};
+use tracing::{info, trace};
+
+use crate::actor::ownership::ResourceKind;
+use crate::actor::AntagonistError;
+use crate::util::unwrap_oxide_api_error;
+use crate::util::OxideApiError;
+
+/// The parameters used to configure a snapshot garbage collector.
+pub struct Params {
+    /// The name of the project whose snapshots should be collected.
+    pub project: String,
+
+    /// How often to look for snapshots to collect.
+    pub interval: Duration,
+
+    /// If set, delete snapshots that were created longer ago than this.
+    pub max_age: Option<Duration>,
+
+    /// If set, delete the oldest snapshots in the project until at most this
+    /// many remain.
+    pub max_count: Option<usize>,
+}
+
+/// The internal state for a snapshot garbage collector.
+#[derive(Debug)]
+pub(super) struct SnapshotGcActor {
+    client: oxide::Client,
+    project: String,
+    interval: Duration,
+    max_age: Option<Duration>,
+    max_count: Option<usize>,
+
+    /// The time at which this collector last ran a collection pass.
+    last_pass: Option<Instant>,
+}
+
+impl SnapshotGcActor {
+    /// Creates a new snapshot garbage collector.
+    pub(super) fn new(params: Params) -> anyhow::Result<Self> {
+        Ok(Self {
+            client: crate::client::get_client(crate::config())?,
+            project: params.project,
+            interval: params.interval,
+            max_age: params.max_age,
+            max_count: params.max_count,
+            last_pass: None,
+        })
+    }
+
+    /// Lists all the snapshots in this collector's project, oldest first.
+    async fn list_snapshots(&self) -> Result<Vec<Snapshot>, OxideApiError> {
+        let mut snapshots: Vec<Snapshot> =
+            crate::middleware::list("snapshot_list", || {
+                self.client
+                    .snapshot_list()
+                    .project(&self.project)
+                    .stream()
+                    .try_collect()
+            })
+            .await?;
+
+        snapshots.sort_by_key(|s| s.time_created);
+        Ok(snapshots)
+    }
+
+    /// Selects the snapshots from `snapshots` (which must be sorted oldest
+    /// first) that should be deleted.
+    fn select_victims<'a>(&self, snapshots: &'a [Snapshot]) -> &'a [Snapshot] {
+        let now = chrono::Utc::now();
+        let expired = match self.max_age {
+            Some(max_age) => snapshots
+                .iter()
+                .take_while(|s| {
+                    (now - s.time_created)
+                        .to_std()
+                        .is_ok_and(|age| age > max_age)
+                })
+                .count(),
+            None => 0,
+        };
+
+        let excess = match self.max_count {
+            Some(max_count) => snapshots.len().saturating_sub(max_count),
+            None => 0,
+        };
+
+        &snapshots[..expired.max(excess)]
+    }
+
+    /// Asks to delete the supplied snapshot.
+    async fn delete_snapshot(
+        &self,
+        snapshot: &Snapshot,
+    ) -> Result<(), OxideApiError> {
+        info!(name = %snapshot.name, "sending snapshot delete request");
+        let pending = super::model::begin(
+            ResourceKind::Snapshot,
+            &snapshot.name,
+            super::model::Op::Delete,
+        );
+        let res = crate::middleware::call("snapshot_delete", || {
+            self.client
+                .snapshot_delete()
+                .project(&self.project)
+                .snapshot(snapshot.id)
+                .send()
+        })
+        .await;
+        pending.finish(res.is_ok());
+
+        unwrap_oxide_api_error(res)
+    }
+}
+
+#[async_trait]
+impl super::Antagonist for SnapshotGcActor {
+    fn set_client(&mut self, client: oxide::Client) {
+        self.client = client;
+    }
+
+    #[tracing::instrument(level = "info", skip(self))]
+    async fn step(&mut self) -> Result<(), AntagonistError> {
+        // Wait in short steps instead of sleeping for the whole interval so
+        // that the actor loop can still pause or halt this actor promptly.
+        let due = match self.last_pass {
+            Some(last) => last.elapsed() >= self.interval,
+            None => true,
+        };
+
+        if !due {
+            tokio::time::sleep(Duration::from_secs(1)).await;
+            return Ok(());
+        }
+
+        self.last_pass = Some(Instant::now());
+
+        trace!("listing snapshots");
+        let snapshots = self.list_snapshots().await?;
+        let victims = self.select_victims(&snapshots);
+        info!(
+            total = snapshots.len(),
+            victims = victims.len(),
+            "collecting snapshots"
+        );
+
+        for snapshot in victims {
+            self.delete_snapshot(snapshot).await?;
+        }
+
+        Ok(())
+    }
+}
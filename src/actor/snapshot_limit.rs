@@ -0,0 +1,146 @@
+//! Keeps snapshot antagonists under a per-disk snapshot limit
+//! (`--max-snapshots-per-disk`). Before each snapshot create, the antagonist
+//! counts the snapshots of the disk it's about to snapshot and deletes the
+//! oldest ones until the new snapshot will fit, so that long runs don't hit
+//! Nexus's per-disk limits and bury real errors under limit errors.
+
+use std::collections::BTreeMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Mutex, OnceLock};
+
+use futures::TryStreamExt;
+use oxide::types::{Snapshot, SnapshotState};
+use oxide::{ClientDisksExt, ClientSnapshotsExt};
+use serde::Serialize;
+use tracing::{info, trace};
+
+use super::ownership::{self, ResourceKind};
+use crate::util::{ok_if_not_found, unwrap_oxide_api_error, OxideApiError};
+
+/// What the snapshot antagonists saw of their disks' snapshots.
+#[derive(Clone, Debug, Default, Serialize)]
+pub struct Summary {
+    /// The most snapshots each disk was seen with before a create, keyed by
+    /// disk name.
+    pub most_per_disk: BTreeMap<String, usize>,
+
+    /// How many snapshots were deleted to make room for new ones.
+    pub pruned: u64,
+}
+
+static MOST_PER_DISK: OnceLock<Mutex<BTreeMap<String, usize>>> =
+    OnceLock::new();
+static PRUNED: AtomicU64 = AtomicU64::new(0);
+
+fn most_per_disk() -> &'static Mutex<BTreeMap<String, usize>> {
+    MOST_PER_DISK.get_or_init(Default::default)
+}
+
+/// Returns what the snapshot antagonists have seen so far.
+pub fn summary() -> Summary {
+    Summary {
+        most_per_disk: most_per_disk().lock().unwrap().clone(),
+        pruned: PRUNED.load(Ordering::Relaxed),
+    }
+}
+
+/// Logs the snapshot limit section of the end-of-run summary.
+pub fn log() {
+    let summary = summary();
+    let Some(most) = summary.most_per_disk.values().max() else {
+        return;
+    };
+
+    info!(
+        disks = summary.most_per_disk.len(),
+        most_per_disk = most,
+        pruned = summary.pruned,
+        "Snapshots per disk"
+    );
+}
+
+/// Makes room for one more snapshot of the disk named `disk_name` in
+/// `project`, deleting its oldest ready snapshots until fewer than `max`
+/// remain. Does nothing if the disk doesn't exist.
+///
+/// The snapshot named `keep` is spared, as are snapshots that belong to live
+/// actors other than the caller, whose snapshots are named `own` plus a
+/// counter.
+///
+/// Antagonists sharing a disk may prune it at the same time, so snapshots
+/// that are already gone are skipped, and a disk can briefly end up with
+/// fewer snapshots than it needs to.
+pub(super) async fn make_room(
+    client: &oxide::Client,
+    project: &str,
+    disk_name: &str,
+    keep: &str,
+    own: &str,
+    max: usize,
+) -> Result<(), OxideApiError> {
+    let res = crate::middleware::call("disk_view", || {
+        client.disk_view().project(project).disk(disk_name).send()
+    })
+    .await;
+    let disk_id = match res {
+        Ok(rv) => rv.into_inner().id,
+        Err(oxide::Error::ErrorResponse(rv))
+            if rv.status() == http::StatusCode::NOT_FOUND =>
+        {
+            return Ok(());
+        }
+        Err(e) => return Err(e),
+    };
+
+    let mut snapshots: Vec<Snapshot> =
+        crate::middleware::list("snapshot_list", || {
+            client.snapshot_list().project(project).stream().try_collect()
+        })
+        .await?;
+    snapshots.retain(|s| s.disk_id == disk_id);
+    snapshots.sort_by_key(|s| s.time_created);
+
+    let count = snapshots.len();
+    trace!(disk_name, count, "counted disk's snapshots");
+    most_per_disk()
+        .lock()
+        .unwrap()
+        .entry(disk_name.to_owned())
+        .and_modify(|most| *most = (*most).max(count))
+        .or_insert(count);
+
+    let excess = (count + 1).saturating_sub(max);
+    let victims = snapshots
+        .iter()
+        .filter(|s| {
+            matches!(s.state, SnapshotState::Ready)
+                && s.name.as_str() != keep
+                && !ownership::is_owned_by_other(
+                    ResourceKind::Snapshot,
+                    &s.name,
+                    own,
+                )
+        })
+        .take(excess);
+    for victim in victims {
+        info!(
+            name = %victim.name,
+            disk_name,
+            "deleting snapshot to stay under --max-snapshots-per-disk"
+        );
+        let pending = super::model::begin(
+            ResourceKind::Snapshot,
+            &victim.name,
+            super::model::Op::Delete,
+        );
+        let res = crate::middleware::call("snapshot_delete", || {
+            client.snapshot_delete().project(project).snapshot(victim.id).send()
+        })
+        .await;
+        pending.finish(res.is_ok());
+        ok_if_not_found(unwrap_oxide_api_error(res))?;
+        PRUNED.fetch_add(1, Ordering::Relaxed);
+    }
+
+    Ok(())
+}
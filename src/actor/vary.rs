@@ -0,0 +1,74 @@
+//! Body variation for instance creates (`--vary-instance-creates`). Each
+//! create randomizes the body's optional fields, within what Nexus should
+//! accept, so that successive creates take different paths through the
+//! instance create saga instead of sending the same payload every time.
+
+use oxide::types::{
+    InstanceCreate, InstanceNetworkInterfaceAttachment,
+    InstanceNetworkInterfaceCreate, Name,
+};
+use rand::Rng;
+
+/// The most NICs an instance can be created with.
+const MAX_NICS: usize = 8;
+
+/// The longest hostname an instance can have.
+const MAX_HOSTNAME_LEN: usize = 63;
+
+/// Randomizes the optional fields of `body`, a create for the instance named
+/// `name`:
+///
+/// - whether the instance is started once it's created;
+/// - whether it gets no NICs, a default NIC, or up to eight NICs of its own
+///   (never no NICs if it asks for an external IP, which needs one);
+/// - whether it gets the creating user's SSH keys or none;
+/// - the length of its hostname.
+pub(super) fn instance_create(
+    body: &mut InstanceCreate,
+    name: &str,
+    rng: &mut impl Rng,
+) {
+    body.start = rng.gen_bool(0.5);
+
+    let needs_nic = !body.external_ips.is_empty();
+    body.network_interfaces = match rng.gen_range(needs_nic as u8..3) {
+        0 => InstanceNetworkInterfaceAttachment::None,
+        1 => InstanceNetworkInterfaceAttachment::Default,
+        _ => InstanceNetworkInterfaceAttachment::Create(
+            (0..rng.gen_range(1..=MAX_NICS))
+                .map(|i| nic(&format!("{}-nic{}", name, i)))
+                .collect(),
+        ),
+    };
+
+    // `None` gives the instance every SSH key the user has, and an empty
+    // list gives it none.
+    body.ssh_public_keys = rng.gen_bool(0.5).then(Vec::new);
+
+    if let Ok(hostname) = hostname(rng).parse() {
+        body.hostname = hostname;
+    }
+}
+
+/// Returns a NIC named `name` in the project's default subnet.
+fn nic(name: &str) -> InstanceNetworkInterfaceCreate {
+    InstanceNetworkInterfaceCreate {
+        description: crate::run_info::description(name),
+        ip: None,
+        name: Name::try_from(name).unwrap(),
+        subnet_name: Name::try_from("default").unwrap(),
+        vpc_name: Name::try_from("default").unwrap(),
+    }
+}
+
+/// Makes up a valid hostname of random length: a letter, then letters and
+/// digits.
+fn hostname(rng: &mut impl Rng) -> String {
+    const CHARS: &[u8] = b"abcdefghijklmnopqrstuvwxyz0123456789";
+    let len = rng.gen_range(1..=MAX_HOSTNAME_LEN);
+    std::iter::once('h')
+        .chain(
+            (1..len).map(|_| char::from(CHARS[rng.gen_range(0..CHARS.len())])),
+        )
+        .collect()
+}
@@ -0,0 +1,159 @@
+//! Verifies that successful deletes take effect (`--verify-deletes`): once a
+//! delete request succeeds, the resource should stop being visible, both by
+//! name and in its project's listing, within the configured deadline.
+//!
+//! Actors share names, so another actor may create a new resource under the
+//! deleted one's name while this waits. Only a resource with the deleted one's
+//! ID counts as still visible, so the ID (taken from the model, which records
+//! what each create returned) has to be captured before the delete is sent.
+
+use std::time::{Duration, Instant};
+
+use futures::TryStreamExt;
+use oxide::{ClientDisksExt, ClientInstancesExt, ClientSnapshotsExt};
+use tracing::{info, trace};
+use uuid::Uuid;
+
+use super::ownership::ResourceKind;
+use super::AntagonistError;
+use crate::util::OxideApiError;
+
+/// How long to wait between checks on a deleted resource.
+const POLL_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Looks up the resource of the supplied `kind` and `name` by name, and
+/// returns its ID if it exists.
+async fn visible(
+    client: &oxide::Client,
+    project: &str,
+    kind: ResourceKind,
+    name: &str,
+) -> Result<Option<Uuid>, OxideApiError> {
+    let res = match kind {
+        ResourceKind::Instance => {
+            crate::middleware::call("instance_view", || {
+                client.instance_view().project(project).instance(name).send()
+            })
+            .await
+            .map(|rv| rv.id)
+        }
+        ResourceKind::Disk => crate::middleware::call("disk_view", || {
+            client.disk_view().project(project).disk(name).send()
+        })
+        .await
+        .map(|rv| rv.id),
+        ResourceKind::Snapshot => {
+            crate::middleware::call("snapshot_view", || {
+                client.snapshot_view().project(project).snapshot(name).send()
+            })
+            .await
+            .map(|rv| rv.id)
+        }
+        ResourceKind::Image => {
+            unreachable!("image deletes aren't verified")
+        }
+    };
+
+    match res {
+        Ok(id) => Ok(Some(id)),
+        Err(oxide::Error::ErrorResponse(rv))
+            if rv.status() == http::StatusCode::NOT_FOUND =>
+        {
+            Ok(None)
+        }
+        Err(e) => Err(e),
+    }
+}
+
+/// Returns true if the project's listing of resources of the supplied `kind`
+/// includes the one with ID `id`.
+async fn listed(
+    client: &oxide::Client,
+    project: &str,
+    kind: ResourceKind,
+    id: Uuid,
+) -> Result<bool, OxideApiError> {
+    let ids: Vec<Uuid> = match kind {
+        ResourceKind::Instance => {
+            crate::middleware::list("instance_list", || {
+                client
+                    .instance_list()
+                    .project(project)
+                    .stream()
+                    .map_ok(|i| i.id)
+                    .try_collect()
+            })
+            .await?
+        }
+        ResourceKind::Disk => {
+            crate::middleware::list("disk_list", || {
+                client
+                    .disk_list()
+                    .project(project)
+                    .stream()
+                    .map_ok(|d| d.id)
+                    .try_collect()
+            })
+            .await?
+        }
+        ResourceKind::Snapshot => {
+            crate::middleware::list("snapshot_list", || {
+                client
+                    .snapshot_list()
+                    .project(project)
+                    .stream()
+                    .map_ok(|s| s.id)
+                    .try_collect()
+            })
+            .await?
+        }
+        ResourceKind::Image => {
+            unreachable!("image deletes aren't verified")
+        }
+    };
+
+    Ok(ids.contains(&id))
+}
+
+/// If --verify-deletes is set, waits for the resource of the supplied `kind`
+/// and `name`, which had the ID `id` and was just deleted successfully, to
+/// disappear. Fails if it's still visible by name or in its project's listing
+/// after the deadline.
+///
+/// Nothing is checked if the ID isn't known, i.e. if this run didn't create the
+/// resource.
+pub(super) async fn deleted(
+    client: &oxide::Client,
+    project: &str,
+    kind: ResourceKind,
+    name: &str,
+    id: Option<Uuid>,
+) -> Result<(), AntagonistError> {
+    let Some(deadline) = crate::config().verify_deletes else {
+        return Ok(());
+    };
+    let Some(id) = id else {
+        trace!(?kind, name, "deleted resource's ID unknown, not verifying");
+        return Ok(());
+    };
+
+    let start = Instant::now();
+    loop {
+        if visible(client, project, kind, name).await? != Some(id)
+            && !listed(client, project, kind, id).await?
+        {
+            trace!(?kind, name, elapsed = ?start.elapsed(), "delete verified");
+            return Ok(());
+        }
+
+        if start.elapsed() >= deadline {
+            return Err(AntagonistError::CheckFailed(format!(
+                "{:?} {} ({}) still visible {:?} after a successful delete",
+                kind, name, id, deadline
+            )));
+        }
+
+        info!(?kind, name, "waiting for deleted resource to disappear");
+        tokio::time::sleep(POLL_INTERVAL).await;
+    }
+}
@@ -0,0 +1,184 @@
+//! Failure artifact bundles, which capture everything the harness knows about
+//! an error while the rack is still in the state that caused it.
+//!
+//! Each bundle is a directory under `--artifact-dir` containing:
+//!
+//! - `run.json`: the run's ID, name prefix, seed, and harness and rack
+//!   software versions.
+//! - `error.txt`: the full error, `Debug`-formatted.
+//! - `response.json`: the error response's status, headers, and body, if the
+//!   request got a response, along with the failed request's ID and which
+//!   Nexus instance and address it went to, where those are known.
+//! - `history.json`: the failing actor's most recent API calls, oldest first.
+//!   The last one is usually the call that failed. (The SDK doesn't hand back
+//!   the requests themselves, so their bodies aren't included.)
+//! - `operations.json`: the failing actor's most recent steps, oldest first:
+//!   the state it found its resource in and the action it picked, the calls
+//!   each step made and their responses, and how each step turned out.
+//! - `resources.json`: the current states of every resource in the stress
+//!   project.
+
+use std::collections::BTreeMap;
+use std::net::SocketAddr;
+use std::path::{Path, PathBuf};
+
+use anyhow::{Context, Result};
+use futures::TryStreamExt;
+use oxide::{
+    ClientDisksExt, ClientImagesExt, ClientInstancesExt, ClientSnapshotsExt,
+};
+use serde::Serialize;
+
+use crate::actor::AntagonistError;
+
+/// What's known about the response to a failed request.
+#[derive(Serialize)]
+struct Response {
+    status: Option<u16>,
+    headers: BTreeMap<String, String>,
+
+    /// The response body, if Nexus returned a well-formed error.
+    body: Option<oxide::types::Error>,
+
+    request_id: Option<String>,
+
+    /// The Nexus instance the request went to.
+    nexus: Option<String>,
+
+    /// The address the request connected to.
+    peer: Option<SocketAddr>,
+}
+
+/// Returns what's known about the response that caused `err`, which the actor
+/// named `actor` hit, if there was one.
+fn response(actor: &str, err: &AntagonistError) -> Option<Response> {
+    let call = crate::request_log::failed_call(actor, err);
+    let (request_id, nexus, peer) = match call {
+        Some(call) => (call.request_id, call.nexus, call.peer),
+        None => (None, None, None),
+    };
+
+    let AntagonistError::ApiError(err) = err else {
+        return None;
+    };
+
+    let headers = |map: &reqwest::header::HeaderMap| {
+        map.iter()
+            .map(|(name, value)| {
+                let value = String::from_utf8_lossy(value.as_bytes());
+                (name.to_string(), value.into_owned())
+            })
+            .collect()
+    };
+
+    match err {
+        oxide::Error::ErrorResponse(rv) => Some(Response {
+            status: Some(rv.status().as_u16()),
+            headers: headers(rv.headers()),
+            body: Some((**rv).clone()),
+            request_id,
+            nexus,
+            peer,
+        }),
+        oxide::Error::UnexpectedResponse(response) => Some(Response {
+            status: Some(response.status().as_u16()),
+            headers: headers(response.headers()),
+            body: None,
+            request_id,
+            nexus,
+            peer,
+        }),
+        _ => None,
+    }
+}
+
+/// The resources in the stress project.
+#[derive(Serialize)]
+struct Resources {
+    instances: Vec<oxide::types::Instance>,
+    disks: Vec<oxide::types::Disk>,
+    snapshots: Vec<oxide::types::Snapshot>,
+    images: Vec<oxide::types::Image>,
+}
+
+/// Lists the resources in `project`.
+async fn resources(client: &oxide::Client, project: &str) -> Result<Resources> {
+    Ok(Resources {
+        instances: client
+            .instance_list()
+            .project(project)
+            .stream()
+            .try_collect()
+            .await
+            .context("listing instances")?,
+        disks: client
+            .disk_list()
+            .project(project)
+            .stream()
+            .try_collect()
+            .await
+            .context("listing disks")?,
+        snapshots: client
+            .snapshot_list()
+            .project(project)
+            .stream()
+            .try_collect()
+            .await
+            .context("listing snapshots")?,
+        images: client
+            .image_list()
+            .project(project)
+            .stream()
+            .try_collect()
+            .await
+            .context("listing images")?,
+    })
+}
+
+/// Writes `value` to `dir/name` as JSON.
+fn write_json(dir: &Path, name: &str, value: &impl Serialize) -> Result<()> {
+    let json = serde_json::to_string_pretty(value)
+        .with_context(|| format!("serializing {}", name))?;
+    std::fs::write(dir.join(name), json)
+        .with_context(|| format!("writing {}", name))
+}
+
+/// Writes an artifact bundle for `err`, which the actor named `actor` hit,
+/// into a new directory under `dir`. Returns the new directory's path.
+pub async fn write(
+    dir: &Path,
+    client: &oxide::Client,
+    project: &str,
+    actor: &str,
+    err: &AntagonistError,
+) -> Result<PathBuf> {
+    let dir = dir.join(format!(
+        "{}-{}",
+        chrono::Utc::now().format("%Y%m%dT%H%M%S%.3fZ"),
+        actor
+    ));
+    std::fs::create_dir_all(&dir)
+        .with_context(|| format!("creating {}", dir.display()))?;
+
+    write_json(&dir, "run.json", &crate::run_info::info())?;
+    std::fs::write(dir.join("error.txt"), format!("{:#?}\n", err))
+        .context("writing error.txt")?;
+    if let Some(response) = response(actor, err) {
+        write_json(&dir, "response.json", &response)?;
+    }
+    write_json(&dir, "history.json", &crate::request_log::history(actor))?;
+    write_json(&dir, "operations.json", &crate::journal::recent(actor))?;
+
+    // Still write the rest of the bundle if the resources can't be listed,
+    // e.g. because Nexus has gone away.
+    match resources(client, project).await {
+        Ok(resources) => write_json(&dir, "resources.json", &resources)?,
+        Err(e) => std::fs::write(
+            dir.join("resources.txt"),
+            format!("couldn't list resources: {:#}\n", e),
+        )
+        .context("writing resources.txt")?,
+    }
+
+    Ok(dir)
+}
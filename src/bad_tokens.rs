@@ -0,0 +1,265 @@
+//! Bad-token injection (`--bad-tokens`), which sends requests with invalid
+//! bearer tokens at a fixed rate alongside the actors' authenticated traffic,
+//! and checks that Nexus turns each one away with a 401, quickly, however
+//! busy it is.
+//!
+//! The requests are project views, sent to each Nexus in turn with each of a
+//! few kinds of bad token, made up once per run: one that's well-formed but
+//! unknown (as an expired or revoked token would be), garbage, an empty token,
+//! and a header with the wrong scheme. A response other than a 401, or one
+//! slower than `--bad-token-max-latency`, is a finding, which the runner
+//! counts against the error budget as a failed check. So is any 401 the
+//! authenticated traffic gets while this is going on, since the bad tokens
+//! shouldn't affect anyone else's; those are the actors' own errors.
+//!
+//! The requests bypass the middleware: their 401s are expected, so they're
+//! kept out of the endpoint statistics, the error groups, and the anomaly
+//! detector, and they never set off the credential refresh that an actor's
+//! 401 does.
+
+use std::collections::BTreeMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::Instant;
+
+use anyhow::{Context, Result};
+use oxide::ClientProjectsExt;
+use rand::Rng;
+use serde::Serialize;
+use tokio::sync::mpsc;
+use tokio::task::JoinHandle;
+use tracing::{info, warn};
+
+use crate::collector::Collector;
+
+/// The most requests that may be outstanding at once.
+const MAX_IN_FLIGHT: usize = 64;
+
+/// The kinds of bad token sent.
+#[derive(Clone, Copy, Debug)]
+enum BadToken {
+    /// A well-formed token that Nexus never issued, or has forgotten.
+    Unknown,
+
+    /// Random characters that don't look like a token at all.
+    Garbage,
+
+    /// A bearer token with nothing in it.
+    Empty,
+
+    /// A header with a scheme other than `Bearer`.
+    WrongScheme,
+}
+
+const BAD_TOKENS: &[BadToken] = &[
+    BadToken::Unknown,
+    BadToken::Garbage,
+    BadToken::Empty,
+    BadToken::WrongScheme,
+];
+
+impl BadToken {
+    /// Makes up an `Authorization` header value with this kind of bad token.
+    fn header(&self, rng: &mut impl Rng) -> String {
+        match self {
+            BadToken::Unknown => {
+                let hex: String = (0..40)
+                    .map(|_| {
+                        char::from_digit(rng.gen_range(0..16), 16).unwrap()
+                    })
+                    .collect();
+                format!("Bearer oxide-token-{}", hex)
+            }
+            BadToken::Garbage => {
+                let garbage: String = (0..32)
+                    .map(|_| char::from(rng.gen_range(b'!'..=b'~')))
+                    .collect();
+                format!("Bearer {}", garbage)
+            }
+            BadToken::Empty => "Bearer ".to_string(),
+            BadToken::WrongScheme => "Basic c3RyZXNzOnN0cmVzcw==".to_string(),
+        }
+    }
+}
+
+/// What the bad-token requests found.
+#[derive(Clone, Debug, Default, Serialize)]
+pub struct Summary {
+    /// The requests sent.
+    pub sent: u64,
+
+    /// The requests rejected with a 401 within --bad-token-max-latency.
+    pub rejected: u64,
+
+    /// The requests rejected with a 401, but too slowly.
+    pub slow: u64,
+
+    /// How many requests got each status other than 401, keyed by status
+    /// code.
+    pub wrong_status: BTreeMap<String, u64>,
+
+    /// The requests that got no response.
+    pub failed: u64,
+
+    /// The slowest 401, in milliseconds.
+    pub max_latency_ms: f64,
+
+    /// How many of the harness's authenticated requests got 401s while bad
+    /// tokens were being sent.
+    pub authenticated_401s: u64,
+}
+
+static SENT: AtomicU64 = AtomicU64::new(0);
+static REJECTED: AtomicU64 = AtomicU64::new(0);
+static SLOW: AtomicU64 = AtomicU64::new(0);
+static FAILED: AtomicU64 = AtomicU64::new(0);
+static MAX_LATENCY_US: AtomicU64 = AtomicU64::new(0);
+
+/// Statuses other than 401, by status code.
+static WRONG_STATUS: Collector<BTreeMap<String, u64>> = Collector::new();
+
+/// Builds a client for each Nexus and kind of bad token that sends that kind
+/// of token.
+fn clients() -> Result<Vec<oxide::Client>> {
+    let mut rng = crate::util::actor_rng("bad-tokens");
+    let mut clients = Vec::new();
+    for endpoint in crate::client::endpoints() {
+        for bad_token in BAD_TOKENS {
+            let auth = reqwest::header::HeaderValue::from_str(
+                &bad_token.header(&mut rng),
+            )?;
+            let client = crate::client::client_builder(endpoint)?
+                .default_headers(
+                    [(http::header::AUTHORIZATION, auth)].into_iter().collect(),
+                )
+                .build()
+                .context("building HTTP client")?;
+            clients.push(oxide::Client::new_with_client(&endpoint.uri, client));
+        }
+    }
+
+    Ok(clients)
+}
+
+/// Sends a view of `project` with `client`'s bad token and records what came
+/// back. Returns a description of the finding, if the response is one.
+async fn probe(client: &oxide::Client, project: &str) -> Option<String> {
+    let max_latency = crate::config().bad_token_max_latency;
+    let start = Instant::now();
+    let result = client.project_view().project(project).send().await;
+    let latency = start.elapsed();
+
+    let status = match &result {
+        Ok(rv) => Some(rv.status()),
+        Err(e) => e.status(),
+    };
+    match status {
+        Some(http::StatusCode::UNAUTHORIZED) => {
+            let micros = u64::try_from(latency.as_micros()).unwrap_or(u64::MAX);
+            MAX_LATENCY_US.fetch_max(micros, Ordering::Relaxed);
+            if latency > max_latency {
+                SLOW.fetch_add(1, Ordering::Relaxed);
+                return Some(format!(
+                    "bad token rejected after {:?}, more than {:?}",
+                    latency, max_latency
+                ));
+            }
+
+            REJECTED.fetch_add(1, Ordering::Relaxed);
+            None
+        }
+        Some(status) => {
+            let mut wrong_status = WRONG_STATUS.lock();
+            *wrong_status.entry(status.as_u16().to_string()).or_default() += 1;
+            Some(format!("bad token got {} instead of 401", status))
+        }
+        None => {
+            let error = result.err().map(|e| e.to_string());
+            warn!(?error, "bad-token request got no response");
+            FAILED.fetch_add(1, Ordering::Relaxed);
+            None
+        }
+    }
+}
+
+/// Starts sending `per_second` bad-token views of `project` per second, and
+/// sends each finding to `findings`. Aborting the returned task stops them,
+/// including any still outstanding.
+pub fn start(
+    project: String,
+    per_second: f64,
+    findings: mpsc::UnboundedSender<String>,
+) -> Result<JoinHandle<()>> {
+    let clients = clients()?;
+    info!(per_second, "Starting bad-token requests");
+
+    // Past the cap, Nexus is already slow to answer, which the outstanding
+    // requests will show, so skipped requests aren't counted.
+    Ok(crate::fixed_rate::start(
+        per_second,
+        MAX_IN_FLIGHT,
+        move |n| {
+            let client = clients[n as usize % clients.len()].clone();
+            let project = project.clone();
+            let findings = findings.clone();
+            SENT.fetch_add(1, Ordering::Relaxed);
+            async move {
+                if let Some(finding) = probe(&client, &project).await {
+                    let _ = findings.send(finding);
+                }
+            }
+        },
+        || {},
+    ))
+}
+
+/// Returns what the bad-token requests have found so far.
+pub fn summary() -> Summary {
+    let authenticated_401s = if crate::config().bad_tokens.is_some() {
+        crate::status_counts::summary()
+            .values()
+            .filter_map(|statuses| statuses.get("401"))
+            .sum()
+    } else {
+        0
+    };
+
+    Summary {
+        sent: SENT.load(Ordering::Relaxed),
+        rejected: REJECTED.load(Ordering::Relaxed),
+        slow: SLOW.load(Ordering::Relaxed),
+        wrong_status: WRONG_STATUS.snapshot(),
+        failed: FAILED.load(Ordering::Relaxed),
+        max_latency_ms: MAX_LATENCY_US.load(Ordering::Relaxed) as f64 / 1000.0,
+        authenticated_401s,
+    }
+}
+
+/// Logs the bad-token section of the end-of-run summary.
+pub fn log() {
+    let Some(per_second) = crate::config().bad_tokens else {
+        return;
+    };
+
+    let Summary {
+        sent,
+        rejected,
+        slow,
+        wrong_status,
+        failed,
+        max_latency_ms,
+        authenticated_401s,
+    } = summary();
+    info!(
+        per_second,
+        sent, rejected, slow, failed, max_latency_ms, "Bad-token requests"
+    );
+    for (status, count) in wrong_status {
+        warn!(status, count, "Bad-token requests got a status other than 401");
+    }
+    if authenticated_401s > 0 {
+        warn!(
+            count = authenticated_401s,
+            "Authenticated requests got 401s while bad tokens were sent"
+        );
+    }
+}
@@ -0,0 +1,256 @@
+//! The chaos proxy (`--chaos-proxy`), an embedded proxy between the actors
+//! and Nexus that injects the kinds of faults a flaky network would, to see
+//! how both Nexus and the harness cope with them:
+//!
+//! - `--chaos-delay`: hold a request for up to `--chaos-max-delay` before
+//!   passing it on.
+//! - `--chaos-drop`: close the connection partway through the response, after
+//!   Nexus has handled the request.
+//! - `--chaos-duplicate`: send the request to Nexus twice at once, answering
+//!   with the first copy's response.
+//!
+//! Each option is the probability that a request gets that fault. There's a
+//! proxy for each Nexus endpoint, listening on a local port for plain
+//! HTTP/1.1 and talking to its endpoint the way the actors otherwise would
+//! (same TLS settings, HTTP version, and so on). Only the actors' requests go
+//! through it; the harness's own setup, checks, and cleanup talk to Nexus
+//! directly.
+//!
+//! A dropped response reaches an actor as a body the SDK couldn't read or
+//! parse, which would otherwise be a disqualifying error. With --chaos-drop,
+//! those errors are taken to be the proxy's doing: they're retried like
+//! communication errors, and any that survive the retries are counted here
+//! instead of against the error budget.
+
+use std::convert::Infallible;
+use std::net::SocketAddr;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::OnceLock;
+
+use anyhow::{Context, Result};
+use hyper::service::{make_service_fn, service_fn};
+use hyper::Body;
+use rand::Rng;
+use serde::Serialize;
+use tracing::{debug, info, warn};
+
+use crate::client::Endpoint;
+use crate::util::OxideApiError;
+
+/// What the chaos proxy did.
+#[derive(Clone, Debug, Default, Serialize)]
+pub struct Summary {
+    /// The requests that went through the proxy.
+    pub requests: u64,
+    pub delayed: u64,
+    pub dropped: u64,
+    pub duplicated: u64,
+
+    /// The dropped responses that reached an actor as an error after any
+    /// retries.
+    pub surfaced: u64,
+}
+
+static REQUESTS: AtomicU64 = AtomicU64::new(0);
+static DELAYED: AtomicU64 = AtomicU64::new(0);
+static DROPPED: AtomicU64 = AtomicU64::new(0);
+static DUPLICATED: AtomicU64 = AtomicU64::new(0);
+static SURFACED: AtomicU64 = AtomicU64::new(0);
+
+/// Each Nexus endpoint and the base URI of the proxy in front of it.
+static PROXIES: OnceLock<Vec<(Endpoint, String)>> = OnceLock::new();
+
+/// Parses a probability between 0 and 1.
+pub fn parse_probability(s: &str) -> Result<f64, String> {
+    match s.parse::<f64>() {
+        Ok(p) if (0.0..=1.0).contains(&p) => Ok(p),
+        _ => Err(format!("expected a probability from 0 to 1, got {:?}", s)),
+    }
+}
+
+/// Returns true with probability `p`.
+fn roll(p: f64) -> bool {
+    p > 0.0 && rand::thread_rng().gen_bool(p)
+}
+
+/// Returns true if `err` is what a response the proxy dropped looks like to
+/// the SDK: a body it couldn't read, or couldn't parse because it was cut
+/// short. Always false unless the proxy is dropping responses.
+pub fn is_injected(err: &OxideApiError) -> bool {
+    let config = crate::config();
+    if !config.chaos_proxy || config.chaos_drop == 0.0 {
+        return false;
+    }
+
+    match err {
+        oxide::Error::ResponseBodyError(_) => true,
+        oxide::Error::InvalidResponsePayload(_, e) => e.is_eof(),
+        _ => false,
+    }
+}
+
+/// Records that a dropped response reached an actor as an error.
+pub fn record_surfaced() {
+    SURFACED.fetch_add(1, Ordering::Relaxed);
+}
+
+/// Where a proxy sends its requests.
+struct Upstream {
+    /// The endpoint's URI, without a trailing slash.
+    base: String,
+    client: reqwest::Client,
+}
+
+impl Upstream {
+    /// Returns the request to send Nexus for the one `parts` describes, with
+    /// `body`.
+    fn request(
+        &self,
+        parts: &http::request::Parts,
+        body: hyper::body::Bytes,
+    ) -> reqwest::Result<reqwest::Request> {
+        let path = parts.uri.path_and_query().map_or("/", |p| p.as_str());
+        let mut headers = parts.headers.clone();
+        headers.remove(http::header::HOST);
+        headers.remove(http::header::CONNECTION);
+        self.client
+            .request(parts.method.clone(), format!("{}{}", self.base, path))
+            .headers(headers)
+            .body(body)
+            .build()
+    }
+}
+
+/// Passes `req` on to Nexus, injecting whatever faults the dice call for.
+/// Failing closes the connection without a response, which is how the proxy
+/// passes on failures to reach Nexus.
+async fn forward(
+    req: hyper::Request<Body>,
+    upstream: &'static Upstream,
+) -> Result<hyper::Response<Body>> {
+    let config = crate::config();
+    REQUESTS.fetch_add(1, Ordering::Relaxed);
+    let (parts, body) = req.into_parts();
+    let body = hyper::body::to_bytes(body).await?;
+
+    if roll(config.chaos_delay) {
+        let max = config.chaos_max_delay;
+        let delay = max.mul_f64(rand::thread_rng().gen_range(0.0..=1.0));
+        debug!(path = %parts.uri, ?delay, "chaos proxy delaying request");
+        DELAYED.fetch_add(1, Ordering::Relaxed);
+        tokio::time::sleep(delay).await;
+    }
+
+    let request = upstream.request(&parts, body)?;
+    if roll(config.chaos_duplicate) {
+        debug!(path = %parts.uri, "chaos proxy duplicating request");
+        DUPLICATED.fetch_add(1, Ordering::Relaxed);
+        let copy = request.try_clone().expect("request bodies are buffered");
+        let path = parts.uri.clone();
+        tokio::spawn(async move {
+            if let Err(e) = upstream.client.execute(copy).await {
+                debug!(%path, "duplicate request failed: {}", e);
+            }
+        });
+    }
+
+    let response = upstream.client.execute(request).await?;
+    let mut builder = hyper::Response::builder().status(response.status());
+    for (name, value) in response.headers() {
+        if name != http::header::TRANSFER_ENCODING
+            && name != http::header::CONNECTION
+        {
+            builder = builder.header(name, value);
+        }
+    }
+    let bytes = response.bytes().await?;
+
+    if !roll(config.chaos_drop) {
+        return Ok(builder.body(Body::from(bytes))?);
+    }
+
+    // Send half the body, then abort it, which closes the connection.
+    debug!(path = %parts.uri, "chaos proxy dropping connection");
+    DROPPED.fetch_add(1, Ordering::Relaxed);
+    let (mut sender, body) = Body::channel();
+    tokio::spawn(async move {
+        let half = bytes.slice(..bytes.len() / 2);
+        let _ = sender.send_data(half).await;
+        sender.abort();
+    });
+    Ok(builder.body(body)?)
+}
+
+/// Starts a proxy on a local port in front of `endpoint`, and returns the
+/// base URI to send requests to it at.
+fn serve(endpoint: &Endpoint) -> Result<String> {
+    let client = crate::client::client_builder(endpoint)?
+        .build()
+        .context("building chaos proxy's HTTP client")?;
+    let upstream: &'static Upstream = Box::leak(Box::new(Upstream {
+        base: endpoint.uri.trim_end_matches('/').to_owned(),
+        client,
+    }));
+
+    let make_service = make_service_fn(move |_| async move {
+        Ok::<_, Infallible>(service_fn(move |req| forward(req, upstream)))
+    });
+
+    let addr = SocketAddr::from(([127, 0, 0, 1], 0));
+    let server = hyper::Server::try_bind(&addr)
+        .context("binding chaos proxy")?
+        .serve(make_service);
+    let addr = server.local_addr();
+    info!(%endpoint, %addr, "Chaos proxy listening");
+    tokio::spawn(async move {
+        if let Err(e) = server.await {
+            warn!("chaos proxy failed: {:#}", e);
+        }
+    });
+
+    Ok(format!("http://{}", addr))
+}
+
+/// Starts a proxy in front of each Nexus endpoint.
+pub fn start() -> Result<()> {
+    let proxies = crate::client::endpoints()
+        .iter()
+        .map(|endpoint| Ok((endpoint.clone(), serve(endpoint)?)))
+        .collect::<Result<Vec<_>>>()?;
+    PROXIES
+        .set(proxies)
+        .map_err(|_| anyhow::anyhow!("chaos proxies already started"))
+}
+
+/// Returns the base URI of the proxy in front of `endpoint`, if the proxies
+/// are running.
+pub fn uri(endpoint: &Endpoint) -> Option<&'static str> {
+    PROXIES
+        .get()?
+        .iter()
+        .find(|(e, _)| e == endpoint)
+        .map(|(_, uri)| uri.as_str())
+}
+
+/// Returns what the chaos proxy has done so far.
+pub fn summary() -> Summary {
+    Summary {
+        requests: REQUESTS.load(Ordering::Relaxed),
+        delayed: DELAYED.load(Ordering::Relaxed),
+        dropped: DROPPED.load(Ordering::Relaxed),
+        duplicated: DUPLICATED.load(Ordering::Relaxed),
+        surfaced: SURFACED.load(Ordering::Relaxed),
+    }
+}
+
+/// Logs the chaos proxy section of the end-of-run summary.
+pub fn log() {
+    if crate::config().chaos_proxy {
+        let Summary { requests, delayed, dropped, duplicated, surfaced } =
+            summary();
+        info!(
+            requests,
+            delayed, dropped, duplicated, surfaced, "Chaos proxy faults"
+        );
+    }
+}
@@ -0,0 +1,199 @@
+//! The `cleanup` subcommand, which deletes everything the harness created in
+//! previous runs: the resources in the stress project, the IP range the harness
+//! added to its IP pool, and the project itself, along with the VPCs that
+//! Nexus won't delete a project without deleting first.
+//!
+//! Only resources whose names start with --name-prefix are deleted, and the
+//! project only if the harness created it (as its description says), so that
+//! cleaning up in a shared project (--project) leaves everything else alone.
+
+use std::time::Duration;
+
+use anyhow::{bail, Context, Result};
+use futures::TryStreamExt;
+use oxide::types::{Vpc, VpcSubnet};
+use oxide::{ClientProjectsExt, ClientSystemNetworkingExt, ClientVpcsExt};
+use tokio::time::Instant;
+use tracing::info;
+
+use crate::config::CleanupArgs;
+use crate::util::ok_if_not_found;
+
+/// How long to wait between cleanup passes while resources are still being
+/// stopped or deleted.
+const PASS_INTERVAL: Duration = Duration::from_secs(2);
+
+/// Deletes the harness's resources from `project`, then the project itself and
+/// the harness's IP range, as allowed by `args`.
+pub async fn run(
+    client: &oxide::Client,
+    project: &str,
+    args: &CleanupArgs,
+) -> Result<()> {
+    let found = match client.project_view().project(project).send().await {
+        Ok(rv) => Some(rv.into_inner()),
+        Err(oxide::Error::ErrorResponse(rv))
+            if rv.status() == http::StatusCode::NOT_FOUND =>
+        {
+            None
+        }
+        Err(e) => return Err(e).context("looking up project"),
+    };
+
+    if let Some(found) = found {
+        delete_resources(client, project, args.timeout).await?;
+
+        if args.keep_project || crate::config().no_create_project {
+            info!(project, "Keeping stress project");
+        } else if !crate::run_info::is_harness_description(&found.description) {
+            info!(project, "Keeping project the harness didn't create");
+        } else {
+            delete_vpcs(client, project).await?;
+            info!(project, "Deleting stress project");
+            ok_if_not_found(
+                client
+                    .project_delete()
+                    .project(project)
+                    .send()
+                    .await
+                    .map(|_| ()),
+            )
+            .context("deleting project")?;
+        }
+    } else {
+        info!(project, "Stress project doesn't exist");
+    }
+
+    if args.keep_ip_range || crate::config().no_ip_pool_setup {
+        info!("Keeping IP range in IP pool");
+    } else {
+        remove_ip_range(client).await?;
+    }
+
+    info!("Cleanup complete");
+    Ok(())
+}
+
+/// Repeatedly sweeps `project` until it has no resources with the run's name
+/// prefix left, or until `timeout` elapses.
+async fn delete_resources(
+    client: &oxide::Client,
+    project: &str,
+    timeout: Duration,
+) -> Result<()> {
+    let deadline = Instant::now() + timeout;
+    loop {
+        let remaining = crate::actor::janitor::sweep(client, project)
+            .await
+            .context("cleaning up project resources")?;
+
+        if remaining.is_empty() {
+            info!(project, "Stress project has no harness resources left");
+            return Ok(());
+        }
+
+        if Instant::now() >= deadline {
+            bail!(
+                "{} resources still in project {} after {:?}: {}",
+                remaining.len(),
+                project,
+                timeout,
+                remaining.join(", ")
+            );
+        }
+
+        info!(
+            remaining = remaining.len(),
+            "Waiting for resources to be deleted"
+        );
+        tokio::time::sleep(PASS_INTERVAL).await;
+    }
+}
+
+/// Deletes every VPC in `project`, including the default VPC Nexus creates
+/// with each project, after deleting the VPC's subnets, since Nexus won't
+/// delete a VPC that has any.
+async fn delete_vpcs(client: &oxide::Client, project: &str) -> Result<()> {
+    let vpcs: Vec<Vpc> = client
+        .vpc_list()
+        .project(project)
+        .stream()
+        .try_collect()
+        .await
+        .context("listing VPCs")?;
+
+    for vpc in vpcs {
+        let subnets: Vec<VpcSubnet> = client
+            .vpc_subnet_list()
+            .project(project)
+            .vpc(vpc.name.clone())
+            .stream()
+            .try_collect()
+            .await
+            .with_context(|| format!("listing subnets of VPC {}", vpc.name))?;
+
+        for subnet in subnets {
+            info!(vpc = %vpc.name, subnet = %subnet.name, "Deleting VPC subnet");
+            ok_if_not_found(
+                client
+                    .vpc_subnet_delete()
+                    .project(project)
+                    .vpc(vpc.name.clone())
+                    .subnet(subnet.name.clone())
+                    .send()
+                    .await
+                    .map(|_| ()),
+            )
+            .with_context(|| {
+                format!("deleting subnet {} of VPC {}", subnet.name, vpc.name)
+            })?;
+        }
+
+        info!(vpc = %vpc.name, "Deleting VPC");
+        ok_if_not_found(
+            client
+                .vpc_delete()
+                .project(project)
+                .vpc(vpc.name.clone())
+                .send()
+                .await
+                .map(|_| ()),
+        )
+        .with_context(|| format!("deleting VPC {}", vpc.name))?;
+    }
+
+    Ok(())
+}
+
+/// Removes the IP range the harness adds to its IP pool, if it's there.
+async fn remove_ip_range(client: &oxide::Client) -> Result<()> {
+    let pool = &crate::config().ip_pool;
+    let ranges = client
+        .ip_pool_range_list()
+        .pool(pool)
+        .send()
+        .await
+        .with_context(|| format!("listing IP ranges in pool {}", pool))?
+        .into_inner();
+
+    let ours = ranges
+        .items
+        .into_iter()
+        .find(|r| crate::is_ip_range(&r.range, crate::ip_range()));
+
+    let Some(ours) = ours else {
+        info!(pool, "IP pool doesn't have the stress IP range");
+        return Ok(());
+    };
+
+    info!(pool, "Removing stress IP range from IP pool");
+    client
+        .ip_pool_range_remove()
+        .pool(pool)
+        .body(ours.range)
+        .send()
+        .await
+        .with_context(|| format!("removing IP range from pool {}", pool))?;
+
+    Ok(())
+}
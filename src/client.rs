@@ -1,9 +1,259 @@
+use std::io::IsTerminal;
+use std::net::SocketAddr;
+use std::sync::atomic::AtomicU64;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Mutex, OnceLock, RwLock, RwLockReadGuard};
+use std::time::{Duration, Instant};
 use std::{collections::HashMap, path::PathBuf};
 
 use anyhow::{Context, Result};
 use serde::{Deserialize, Serialize};
 use tracing::{info, warn};
 
+/// Which HTTP version to talk to Nexus with.
+#[derive(Clone, Copy, Debug, clap::ValueEnum)]
+pub enum HttpVersion {
+    /// Use HTTP/2 if Nexus offers it during the TLS handshake, and HTTP/1.1
+    /// otherwise.
+    Auto,
+
+    /// Only use HTTP/1.1.
+    Http1,
+
+    /// Only use HTTP/2, without negotiating it first. Works with plain HTTP.
+    Http2,
+}
+
+/// A Nexus instance the harness sends requests to.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Endpoint {
+    /// The URI to send requests to.
+    pub uri: String,
+
+    /// The address to connect to for the URI's host name: the first address
+    /// the name resolved to when the run started, or with `--resolve-host-uri`,
+    /// one of the addresses it resolved to. `None` if the host is an IP
+    /// address or the name couldn't be resolved up front.
+    pub addr: Option<SocketAddr>,
+}
+
+impl Endpoint {
+    /// Returns the address this endpoint's requests connect to, if it's
+    /// fixed: the address the endpoint is pinned to, or the URI's host if
+    /// it's an IP address. Requests to a host name that couldn't be resolved
+    /// up front go wherever the connection pool's DNS lookups send them, and
+    /// the SDK doesn't hand back the address a response actually came from,
+    /// so there's no telling which server those reach.
+    pub fn connect_addr(&self) -> Option<SocketAddr> {
+        if self.addr.is_some() {
+            return self.addr;
+        }
+
+        // IPv6 hosts come back in brackets.
+        let url = reqwest::Url::parse(&self.uri).ok()?;
+        let host =
+            url.host_str()?.trim_start_matches('[').trim_end_matches(']');
+        let ip: std::net::IpAddr = host.parse().ok()?;
+        Some(SocketAddr::new(ip, url.port_or_known_default()?))
+    }
+}
+
+impl std::fmt::Display for Endpoint {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self.addr {
+            Some(addr) => write!(f, "{} ({})", self.uri, addr),
+            None => write!(f, "{}", self.uri),
+        }
+    }
+}
+
+static ENDPOINTS: OnceLock<Vec<Endpoint>> = OnceLock::new();
+
+/// How many actors have been assigned an endpoint and identity.
+static NEXT_ACTOR: AtomicUsize = AtomicUsize::new(0);
+
+/// Returns the host URIs from the config, falling back to OXIDE_HOST, or the
+/// mock Nexus's URI with --mock-nexus.
+fn host_uris(config: &crate::config::Config) -> Result<Vec<String>> {
+    if config.mock_nexus {
+        return Ok(vec![crate::mock_nexus::uri().to_owned()]);
+    }
+
+    // Prefer explicitly-passed host URIs to the value of OXIDE_HOST. At least
+    // one of these must be specified.
+    if !config.host_uri.is_empty() {
+        return Ok(config.host_uri.clone());
+    }
+
+    Ok(vec![std::env::var("OXIDE_HOST").context("reading OXIDE_HOST")?])
+}
+
+/// Returns an endpoint for each address that `uri`'s host name resolves to.
+async fn resolve(uri: &str) -> Result<Vec<Endpoint>> {
+    let url = reqwest::Url::parse(uri)
+        .with_context(|| format!("parsing host URI {}", uri))?;
+    let host = url
+        .host_str()
+        .with_context(|| format!("host URI {} has no host", uri))?;
+    let port = url
+        .port_or_known_default()
+        .with_context(|| format!("host URI {} has no port", uri))?;
+
+    let mut addrs: Vec<_> = tokio::net::lookup_host((host, port))
+        .await
+        .with_context(|| format!("resolving {}", host))?
+        .collect();
+    addrs.sort();
+    addrs.dedup();
+    anyhow::ensure!(!addrs.is_empty(), "{} resolved to no addresses", host);
+
+    Ok(addrs
+        .into_iter()
+        .map(|addr| Endpoint { uri: uri.to_owned(), addr: Some(addr) })
+        .collect())
+}
+
+/// Works out which Nexus instances to send requests to: one for each host
+/// URI, pinned to the first address its host name resolves to, or, with
+/// --resolve-host-uri, one for each address each host URI's host name
+/// resolves to. Must be called before any clients are created.
+pub async fn init_endpoints(config: &crate::config::Config) -> Result<()> {
+    let mut endpoints = Vec::new();
+    for uri in host_uris(config)? {
+        if config.resolve_host_uri {
+            endpoints.extend(resolve(&uri).await?);
+            continue;
+        }
+
+        // Pin the host name to one address so that every request's peer is
+        // known. If it can't be resolved now, leave the lookups to the
+        // connection pool rather than failing the run.
+        let mut endpoint = Endpoint { uri, addr: None };
+        if endpoint.connect_addr().is_none() {
+            match resolve(&endpoint.uri).await {
+                Ok(resolved) => endpoint.addr = resolved[0].addr,
+                Err(e) => warn!(
+                    uri = endpoint.uri,
+                    "not pinning host URI to an address: {:#}", e
+                ),
+            }
+        }
+        endpoints.push(endpoint);
+    }
+
+    for endpoint in &endpoints {
+        info!(%endpoint, "Nexus endpoint");
+    }
+
+    ENDPOINTS
+        .set(endpoints)
+        .map_err(|_| anyhow::anyhow!("Nexus endpoints already set"))
+}
+
+pub fn endpoints() -> &'static [Endpoint] {
+    ENDPOINTS.get().expect("Nexus endpoints should be initialized")
+}
+
+/// Returns true if the harness is spreading its actors across more than one
+/// Nexus instance.
+pub fn multiple_endpoints() -> bool {
+    endpoints().len() > 1
+}
+
+/// A user the harness can act as, from the identity pool
+/// (`--identity-profile` and `--token-file`).
+#[derive(Clone)]
+pub struct Identity {
+    /// What to call the identity in logs: its profile name, or where its
+    /// token is in the token file.
+    pub name: String,
+    token: String,
+}
+
+static IDENTITIES: RwLock<Vec<Identity>> = RwLock::new(Vec::new());
+
+/// Reads the identity pool from the profiles and token file in the config.
+/// The pool is empty if neither is set.
+fn read_identities(config: &crate::config::Config) -> Result<Vec<Identity>> {
+    let mut identities = Vec::new();
+    if !config.identity_profile.is_empty() {
+        let login_config = creds_toml_dir(config)
+            .and_then(LoginConfig::try_new)
+            .context("--identity-profile needs a credentials.toml file")?;
+        let profiles = login_config.read_profiles()?;
+        for name in &config.identity_profile {
+            let credential = profiles.get(name).with_context(|| {
+                format!("no profile {} in credentials.toml", name)
+            })?;
+            identities.push(Identity {
+                name: name.clone(),
+                token: credential.token.clone(),
+            });
+        }
+    }
+
+    if let Some(path) = &config.token_file {
+        let contents = std::fs::read_to_string(path)
+            .with_context(|| format!("reading {}", path.display()))?;
+        let before = identities.len();
+        for (i, line) in contents.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+
+            identities.push(Identity {
+                name: format!("{}:{}", path.display(), i + 1),
+                token: line.to_owned(),
+            });
+        }
+
+        anyhow::ensure!(
+            identities.len() > before,
+            "no tokens in {}",
+            path.display()
+        );
+    }
+
+    Ok(identities)
+}
+
+/// Loads the identity pool from the profiles and token file in the config.
+pub fn load_identities(config: &crate::config::Config) -> Result<()> {
+    let identities = read_identities(config)?;
+    if !identities.is_empty() {
+        info!(count = identities.len(), "Loaded identity pool");
+    }
+
+    *IDENTITIES.write().unwrap() = identities;
+    Ok(())
+}
+
+fn identities() -> RwLockReadGuard<'static, Vec<Identity>> {
+    IDENTITIES.read().unwrap()
+}
+
+/// Returns true if the harness is spreading its actors across more than one
+/// identity.
+pub fn multiple_identities() -> bool {
+    identities().len() > 1
+}
+
+/// Picks the endpoint and identity (if there's an identity pool) for a new
+/// actor. Each endpoint gets actors in turn, so that the actors are spread
+/// evenly across them, and each identity gets one round of endpoints in
+/// turn, so that every identity uses every endpoint.
+pub fn assign() -> (Endpoint, Option<Identity>) {
+    let endpoints = endpoints();
+    let identities = identities();
+    let next = NEXT_ACTOR.fetch_add(1, Ordering::Relaxed);
+    let endpoint = endpoints[next % endpoints.len()].clone();
+    let identity = (!identities.is_empty()).then(|| {
+        identities[(next / endpoints.len()) % identities.len()].clone()
+    });
+    (endpoint, identity)
+}
+
 /// The contents of an Oxide CLI `hosts.toml` file.
 #[derive(Debug, Deserialize, Serialize)]
 struct Hosts {
@@ -91,6 +341,18 @@ impl LoginConfig {
         }
     }
 
+    /// Reads the profiles in `credentials.toml`, keyed by profile name.
+    fn read_profiles(&self) -> Result<HashMap<String, Credential>> {
+        let ConfigType::Credentials = self.cfg_ty else {
+            anyhow::bail!("profiles are only supported in credentials.toml");
+        };
+
+        let dir = self.dir.join("credentials.toml");
+        let credentials_content = std::fs::read_to_string(dir)?;
+        let creds: Credentials = toml::from_str(&credentials_content)?;
+        Ok(creds.profile)
+    }
+
     /// Reads the contents of a hosts.toml file located in `dir`.
     fn read_hosts_toml(&self) -> Result<Hosts> {
         let dir = self.dir.join("hosts.toml");
@@ -117,17 +379,9 @@ impl LoginConfig {
     }
 }
 
-/// Gets an Oxide SDK client. See the doc commens in `[crate::config::Config]`
-/// and in the project README for host and token resolution rules.
-pub fn get_client(config: &crate::config::Config) -> Result<oxide::Client> {
-    // Prefer an explicitly-passed host URI to the value of OXIDE_HOST. At least
-    // one of these must be specified.
-    let host = match config.host_uri.as_ref() {
-        Some(host) => host.to_owned(),
-        None => std::env::var("OXIDE_HOST").context("reading OXIDE_HOST")?,
-    };
-    info!(%host, "Nexus URI");
-
+/// Returns the directory to search for a `credentials.toml` or `hosts.toml`
+/// file in, if there is one.
+fn creds_toml_dir(config: &crate::config::Config) -> Option<PathBuf> {
     let config_dir =
         match (&config.credentials_toml_dir, &config.hosts_toml_dir) {
             (Some(creds), _) => Some(creds),
@@ -138,19 +392,31 @@ pub fn get_client(config: &crate::config::Config) -> Result<oxide::Client> {
     // If the config containins a directory to search for login credentials, look
     // there. Otherwise, try to get the current user's home directory and
     // search in its `.config/oxide` subdirectory.
-    let creds_toml_dir = if let Some(dir) = config_dir {
+    if let Some(dir) = config_dir {
         Some(dir.clone())
     } else if let Some(mut path) = dirs::home_dir() {
         path.push(".config/oxide");
         Some(path)
     } else {
         None
-    };
+    }
+}
+
+/// Returns the token to use for `endpoint` when the client isn't acting as an
+/// identity from the pool. See the doc commens in `[crate::config::Config]`
+/// and in the project README for token resolution rules.
+fn default_token(
+    config: &crate::config::Config,
+    endpoint: &Endpoint,
+) -> Result<String> {
+    if config.mock_nexus {
+        return Ok(crate::mock_nexus::TOKEN.to_owned());
+    }
 
     // Attempt to read credentials config and extract a token from it. If this fails
     // for any reason (`credentials.toml/hosts.toml` not found or malformed, or no search path
     // was present), fall back to the OXIDE_TOKEN variable.
-    let token = if let Some(creds_toml_dir) = creds_toml_dir {
+    let token = if let Some(creds_toml_dir) = creds_toml_dir(config) {
         if let Some(login_config) = LoginConfig::try_new(creds_toml_dir.clone())
         {
             info!("reading credentials from {}", login_config.dir.display());
@@ -159,7 +425,13 @@ pub fn get_client(config: &crate::config::Config) -> Result<oxide::Client> {
                 "attempting to read token from {}",
                 login_config.cfg_ty.file_name()
             );
-            match hosts.hosts.get(&host) {
+            // Several endpoints are likely to share a login, so fall back to
+            // the other host URIs' tokens.
+            let uris = host_uris(config)?;
+            let entry = std::iter::once(&endpoint.uri)
+                .chain(&uris)
+                .find_map(|uri| hosts.hosts.get(uri));
+            match entry {
                 Some(entry) => Some(entry.token.clone()),
                 None => {
                     info!("no token found");
@@ -179,25 +451,382 @@ pub fn get_client(config: &crate::config::Config) -> Result<oxide::Client> {
         Some(t) => t,
         None => {
             info!("reading OXIDE_TOKEN from environment");
-            std::env::var("OXIDE_TOKEN").context("reading OXIDE_TOKEN")?
+            match std::env::var("OXIDE_TOKEN") {
+                Ok(t) => t,
+                Err(e) => DEVICE_TOKEN
+                    .lock()
+                    .unwrap()
+                    .clone()
+                    .ok_or(e)
+                    .context("reading OXIDE_TOKEN")?,
+            }
         }
     };
 
-    let auth = format!("Bearer {}", token);
-    let mut auth_value = reqwest::header::HeaderValue::from_str(&auth)?;
-    auth_value.set_sensitive(true);
-
-    // Instance creations can take a while, so pick a relatively generous
-    // timeout.
-    let timeout = std::time::Duration::from_secs(120);
-    let rclient = reqwest::Client::builder()
-        .connect_timeout(timeout)
-        .timeout(timeout)
-        .default_headers(
-            [(http::header::AUTHORIZATION, auth_value)].into_iter().collect(),
-        )
+    Ok(token)
+}
+
+/// The token obtained with the device authorization flow, if no other source
+/// had one.
+static DEVICE_TOKEN: Mutex<Option<String>> = Mutex::new(None);
+
+/// How long to wait between polls for a device authorization to finish,
+/// unless Nexus asks the harness to slow down.
+const DEVICE_AUTH_POLL_INTERVAL: Duration = Duration::from_secs(5);
+
+#[derive(Deserialize)]
+struct DeviceAuthResponse {
+    device_code: String,
+    user_code: String,
+    verification_uri: String,
+    expires_in: u64,
+}
+
+#[derive(Deserialize)]
+struct DeviceAccessToken {
+    access_token: String,
+}
+
+#[derive(Deserialize)]
+struct DeviceAccessTokenError {
+    error: String,
+}
+
+/// Logs in to `endpoint` with the device authorization flow: asks Nexus for
+/// a user code, prints where to enter it, and polls until the user has.
+/// Returns the token Nexus grants.
+async fn device_auth(endpoint: &Endpoint) -> Result<String> {
+    let client = client_builder(endpoint)?
         .build()
-        .unwrap();
+        .context("building device auth client")?;
+    let base = endpoint.uri.trim_end_matches('/');
+    let client_id = uuid::Builder::from_random_bytes(rand::random())
+        .into_uuid()
+        .to_string();
+
+    let response = client
+        .post(format!("{}/device/auth", base))
+        .form(&[("client_id", client_id.as_str())])
+        .send()
+        .await
+        .context("starting device authorization")?
+        .error_for_status()
+        .context("starting device authorization")?;
+    let auth: DeviceAuthResponse =
+        serde_json::from_slice(&response.bytes().await?)
+            .context("parsing device authorization response")?;
+
+    // Say this on stderr as well as in the logs, since the logs may be going
+    // somewhere the user isn't looking.
+    let url = format!("{}?user_code={}", auth.verification_uri, auth.user_code);
+    eprintln!("No API token found. To log in, visit {}", url);
+    info!(url, "waiting for device authorization");
+
+    let deadline = Instant::now() + Duration::from_secs(auth.expires_in);
+    let mut interval = DEVICE_AUTH_POLL_INTERVAL;
+    loop {
+        tokio::time::sleep(interval).await;
+        anyhow::ensure!(
+            Instant::now() < deadline,
+            "device authorization expired before the user logged in"
+        );
+
+        let response = client
+            .post(format!("{}/device/token", base))
+            .form(&[
+                ("grant_type", "urn:ietf:params:oauth:grant-type:device_code"),
+                ("device_code", auth.device_code.as_str()),
+                ("client_id", client_id.as_str()),
+            ])
+            .send()
+            .await
+            .context("polling for device authorization")?;
+        let status = response.status();
+        let body = response.bytes().await?;
+        if status.is_success() {
+            let token: DeviceAccessToken = serde_json::from_slice(&body)
+                .context("parsing device access token")?;
+            info!("Logged in with device authorization");
+            return Ok(token.access_token);
+        }
+
+        let error: DeviceAccessTokenError = serde_json::from_slice(&body)
+            .with_context(|| {
+                format!("device token request failed ({})", status)
+            })?;
+        match error.error.as_str() {
+            "authorization_pending" => {}
+            "slow_down" => interval += DEVICE_AUTH_POLL_INTERVAL,
+            error => anyhow::bail!("device authorization failed: {}", error),
+        }
+    }
+}
+
+/// Makes sure the harness has a token to use for clients that don't act as an
+/// identity from the pool, logging in with the device authorization flow if
+/// no other source has one and the harness is running interactively. Must be
+/// called after `init_endpoints` and `load_identities`.
+pub async fn ensure_token(config: &crate::config::Config) -> Result<()> {
+    let endpoint = &endpoints()[0];
+    if !identities().is_empty() || default_token(config, endpoint).is_ok() {
+        return Ok(());
+    }
+
+    // There's no one to log in if the harness isn't running interactively,
+    // so leave it to `get_client` to report the missing token.
+    if !std::io::stdin().is_terminal() {
+        return Ok(());
+    }
+
+    let token = device_auth(endpoint)
+        .await
+        .context("logging in with device authorization")?;
+    *DEVICE_TOKEN.lock().unwrap() = Some(token);
+    Ok(())
+}
+
+/// The token most recently used by a client that doesn't act as an identity
+/// from the pool.
+static LAST_DEFAULT_TOKEN: Mutex<Option<String>> = Mutex::new(None);
+
+/// How many times the credentials have been refreshed. Clients built before
+/// the latest refresh may have stale tokens.
+static GENERATION: AtomicU64 = AtomicU64::new(0);
+
+/// Serializes credential refreshes, so that many clients getting 401s at once
+/// only refresh the credentials once.
+static REFRESH: OnceLock<tokio::sync::Mutex<()>> = OnceLock::new();
+
+/// Returns how many times the credentials have been refreshed.
+pub fn credentials_generation() -> u64 {
+    GENERATION.load(Ordering::Acquire)
+}
+
+/// Returns true if `err` says that Nexus rejected the request's token.
+pub fn is_unauthorized(err: &crate::actor::AntagonistError) -> bool {
+    let crate::actor::AntagonistError::ApiError(err) = err else {
+        return false;
+    };
+
+    err.status() == Some(http::StatusCode::UNAUTHORIZED)
+}
+
+/// Re-reads the credentials, logging in with the device authorization flow
+/// again if that's where the last token came from. Returns true if any token
+/// changed.
+async fn reload_credentials(config: &crate::config::Config) -> Result<bool> {
+    let mut changed = false;
+    let fresh = read_identities(config)?;
+    {
+        let mut identities = IDENTITIES.write().unwrap();
+        changed |= fresh
+            .iter()
+            .map(|i| &i.token)
+            .ne(identities.iter().map(|i| &i.token));
+        *identities = fresh;
+    }
+
+    let last = LAST_DEFAULT_TOKEN.lock().unwrap().clone();
+    let Some(last) = last else {
+        return Ok(changed);
+    };
+
+    let from_device_auth = DEVICE_TOKEN.lock().unwrap().as_ref() == Some(&last);
+    let token = if from_device_auth {
+        if !std::io::stdin().is_terminal() {
+            return Ok(changed);
+        }
+
+        let token = device_auth(&endpoints()[0])
+            .await
+            .context("logging in with device authorization")?;
+        *DEVICE_TOKEN.lock().unwrap() = Some(token.clone());
+        token
+    } else {
+        default_token(config, &endpoints()[0])?
+    };
+
+    Ok(changed || token != last)
+}
+
+/// Called when a client built at credentials generation `seen` has its token
+/// rejected. Refreshes the credentials if no one else has since then, and
+/// returns true if there are newer credentials to rebuild the client with.
+pub async fn refresh_credentials(seen: u64) -> bool {
+    let _guard = REFRESH.get_or_init(Default::default).lock().await;
+    if credentials_generation() != seen {
+        return true;
+    }
+
+    match reload_credentials(crate::config()).await {
+        Ok(true) => {
+            // Drop the clients with the old tokens.
+            CLIENTS.get_or_init(Default::default).lock().unwrap().clear();
+            GENERATION.fetch_add(1, Ordering::AcqRel);
+            info!("Refreshed credentials after a token was rejected");
+            true
+        }
+        Ok(false) => {
+            warn!("token rejected, but no new credentials were found");
+            false
+        }
+        Err(e) => {
+            warn!("failed to refresh credentials: {:#}", e);
+            false
+        }
+    }
+}
+
+/// Gets an Oxide SDK client. See the doc commens in `[crate::config::Config]`
+/// and in the project README for host and token resolution rules.
+///
+/// Actors' clients send requests to the endpoint their actor was assigned,
+/// as the identity it was assigned if there's an identity pool. Other clients
+/// use the first endpoint and the first identity.
+pub fn get_client(config: &crate::config::Config) -> Result<oxide::Client> {
+    let (endpoint, identity, proxy) = match crate::actor::current_actor() {
+        Some(actor) => {
+            let proxy = crate::chaos_proxy::uri(&actor.nexus);
+            (actor.nexus, actor.identity, proxy)
+        }
+        None => (endpoints()[0].clone(), identities().first().cloned(), None),
+    };
+    info!(%endpoint, "Nexus URI");
+
+    // Look the identity's token up again in case it's been refreshed.
+    let token = match identity {
+        Some(identity) => {
+            info!(identity = identity.name, "using token from identity pool");
+            identities()
+                .iter()
+                .find(|i| i.name == identity.name)
+                .map(|i| i.token.clone())
+                .with_context(|| {
+                    format!(
+                        "identity {} is no longer in the pool",
+                        identity.name
+                    )
+                })?
+        }
+        None => {
+            let token = default_token(config, &endpoint)?;
+            *LAST_DEFAULT_TOKEN.lock().unwrap() = Some(token.clone());
+            token
+        }
+    };
+
+    let rclient = shared_client(config, &endpoint, proxy, &token)?;
+    let uri = proxy.unwrap_or(&endpoint.uri);
+    Ok(oxide::Client::new_with_client(uri, rclient))
+}
+
+/// The HTTP clients for one endpoint and token.
+#[derive(Default)]
+struct ClientPool {
+    clients: Vec<reqwest::Client>,
+
+    /// The index of the client to hand out next.
+    next: usize,
+}
+
+/// The HTTP clients handed out so far, keyed by endpoint (or chaos proxy) and
+/// token.
+static CLIENTS: OnceLock<Mutex<HashMap<(String, String), ClientPool>>> =
+    OnceLock::new();
+
+/// Returns an HTTP client that sends requests to `endpoint` with `token`, or
+/// to `proxy`, the chaos proxy in front of `endpoint`, if it's set.
+///
+/// Each HTTP client has its own connection pool, so rather than build one
+/// per caller, this builds up to --client-pool-size clients for each endpoint
+/// and token and hands them out in turn. Otherwise hundreds of actors would
+/// open hundreds of connection pools, which isn't the load a real client
+/// puts on Nexus.
+fn shared_client(
+    config: &crate::config::Config,
+    endpoint: &Endpoint,
+    proxy: Option<&str>,
+    token: &str,
+) -> Result<reqwest::Client> {
+    let mut clients = CLIENTS.get_or_init(Default::default).lock().unwrap();
+    let target = proxy.map_or_else(|| endpoint.to_string(), str::to_owned);
+    let pool = clients.entry((target, token.to_owned())).or_default();
+
+    if pool.clients.len() < config.client_pool_size.max(1) {
+        let auth = format!("Bearer {}", token);
+        let mut auth_value = reqwest::header::HeaderValue::from_str(&auth)?;
+        auth_value.set_sensitive(true);
+
+        // The chaos proxy only speaks plain HTTP/1.1, and talks to Nexus the
+        // way the client otherwise would.
+        let builder = match proxy {
+            Some(_) => {
+                reqwest::Client::builder().timeout(REQUEST_TIMEOUT).http1_only()
+            }
+            None => client_builder(endpoint)?,
+        };
+        let client = builder
+            .default_headers(
+                [(http::header::AUTHORIZATION, auth_value)]
+                    .into_iter()
+                    .collect(),
+            )
+            .build()
+            .context("building HTTP client")?;
+        pool.clients.push(client.clone());
+        return Ok(client);
+    }
+
+    let client = pool.clients[pool.next % pool.clients.len()].clone();
+    pool.next += 1;
+    Ok(client)
+}
+
+/// How long to wait for a response from Nexus. Instance creations can take a
+/// while, so this is relatively generous.
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(120);
+
+/// Returns a builder for an HTTP client that talks to `endpoint`.
+pub fn client_builder(endpoint: &Endpoint) -> Result<reqwest::ClientBuilder> {
+    let config = crate::config();
+    let mut builder = reqwest::Client::builder()
+        .connect_timeout(config.connect_timeout)
+        .timeout(REQUEST_TIMEOUT);
+
+    // Without idle connections to reuse, every request opens a new one.
+    if config.no_keep_alive {
+        builder = builder.pool_max_idle_per_host(0);
+    } else if let Some(max) = config.max_idle_connections {
+        builder = builder.pool_max_idle_per_host(max);
+    }
+
+    builder = match config.http_version {
+        HttpVersion::Auto => builder,
+        HttpVersion::Http1 => builder.http1_only(),
+        HttpVersion::Http2 => builder.http2_prior_knowledge(),
+    };
+
+    if let Some(path) = &config.cacert {
+        let pem = std::fs::read(path)
+            .with_context(|| format!("reading {}", path.display()))?;
+        let cert = reqwest::Certificate::from_pem(&pem)
+            .with_context(|| format!("parsing {}", path.display()))?;
+        builder = builder.add_root_certificate(cert);
+    }
+
+    if config.insecure_skip_tls_verify {
+        builder = builder.danger_accept_invalid_certs(true);
+    }
+
+    // Connect to the endpoint's address while still using the URI's host
+    // name for TLS.
+    if let Some(addr) = endpoint.addr {
+        let url = reqwest::Url::parse(&endpoint.uri)
+            .with_context(|| format!("parsing host URI {}", endpoint.uri))?;
+        if let Some(host) = url.host_str() {
+            builder = builder.resolve(host, addr);
+        }
+    }
 
-    Ok(oxide::Client::new_with_client(&host, rclient))
+    Ok(builder)
 }
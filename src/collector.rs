@@ -0,0 +1,30 @@
+//! Storage for what a module collects over the run for the end-of-run summary
+//! and report, such as counts by endpoint or the slowest requests.
+//!
+//! Modules that collect something keep it in a `static` `Collector`, add to it
+//! as the run goes (usually from a `crate::middleware` layer), and read it back
+//! in their `summary` and `log` functions.
+
+use std::sync::{Mutex, MutexGuard, OnceLock};
+
+/// A value of type `T` shared by the whole process, which starts out as
+/// `T::default()`.
+pub struct Collector<T>(OnceLock<Mutex<T>>);
+
+impl<T: Default> Collector<T> {
+    pub const fn new() -> Self {
+        Self(OnceLock::new())
+    }
+
+    /// Locks the collected value for reading or changing it.
+    pub fn lock(&self) -> MutexGuard<'_, T> {
+        self.0.get_or_init(Default::default).lock().unwrap()
+    }
+}
+
+impl<T: Default + Clone> Collector<T> {
+    /// Returns a copy of the collected value.
+    pub fn snapshot(&self) -> T {
+        self.lock().clone()
+    }
+}
@@ -1,9 +1,89 @@
-use clap::Parser;
-use std::path::PathBuf;
+use anyhow::Context;
+use clap::parser::ValueSource;
+use clap::{
+    ArgAction, ArgMatches, Args, CommandFactory, FromArgMatches, Parser,
+    Subcommand,
+};
+use std::ffi::OsString;
+use std::net::{Ipv4Addr, SocketAddr};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use crate::profile::Profile;
+use crate::util::SleepRange;
+use crate::workload::Workload;
 
 /// Command-line configuration options.
 #[derive(Parser)]
+#[command(args_override_self = true)]
 pub struct Config {
+    /// What to do. Runs the stress test if not set.
+    #[command(subcommand)]
+    pub command: Option<Command>,
+
+    /// A TOML file containing settings to use instead of the defaults. Each
+    /// top-level key is the name of a command-line option (e.g.
+    /// `num_test_instances = 8` or `server-errors-fatal = true`); options
+    /// given on the command line override the file, replacing all of a list
+    /// option's values, and `--<option>=false` turns off a boolean option the
+    /// file turns on. The file may also contain
+    /// `[[actors]]` groups, which are used as if they were in a scenario file
+    /// if `--scenario` isn't set.
+    #[arg(long)]
+    pub config: Option<PathBuf>,
+
+    /// A TOML file describing the actors to create. If set, the options that
+    /// choose the number and kinds of actors to create are ignored. See
+    /// `src/workload.rs` for the file format.
+    #[arg(long)]
+    pub scenario: Option<PathBuf>,
+
+    /// If true, run a short, fixed smoke test instead of a full stress test:
+    /// one of each kind of actor (other than reachability probes) for two
+    /// minutes, with 5xx responses and model contradictions fatal, draining
+    /// and cleaning up everything at the end, and a one-line verdict. Other
+    /// options on the command line override the smoke test's settings.
+    #[arg(long, conflicts_with_all = ["config", "scenario"])]
+    pub smoke: bool,
+
+    /// If true, tune the defaults for multi-day runs: log only the requests
+    /// that get no response, log progress every 15 minutes, write a
+    /// checkpoint report and audit for leaks every hour, run a janitor, ride
+    /// out outages of up to 15 minutes, and retry and restart actors more
+    /// readily. Other options on the command line or in a --config file
+    /// (which may also set this) override these settings.
+    #[arg(long, conflicts_with = "smoke")]
+    pub soak: bool,
+
+    /// The predefined set of action weights and think times to use for
+    /// antagonists that don't have their own.
+    #[arg(long, value_enum, default_value_t = Profile::Balanced)]
+    pub profile: Profile,
+
+    /// If true, fill the stress project with a baseline of long-lived
+    /// resources (see the --populate-* options) and wait for them to settle
+    /// before starting any antagonists.
+    #[arg(long)]
+    pub populate: bool,
+
+    /// The number of running instances to create when populating the project.
+    #[arg(long, default_value_t = 8)]
+    pub populate_instances: usize,
+
+    /// The number of disks to create when populating the project.
+    #[arg(long, default_value_t = 8)]
+    pub populate_disks: usize,
+
+    /// The number of snapshots to create when populating the project. The
+    /// snapshots are spread across the baseline disks.
+    #[arg(long, default_value_t = 8)]
+    pub populate_snapshots: usize,
+
+    /// How long to wait for the baseline resources to settle before giving up
+    /// on the run.
+    #[arg(long, default_value = "10m", value_parser = humantime::parse_duration)]
+    pub populate_timeout: Duration,
+
     /// The number of test instances to create.
     #[arg(long, default_value_t = 4)]
     pub num_test_instances: usize,
@@ -12,6 +92,53 @@ pub struct Config {
     #[arg(long, default_value_t = 4)]
     pub threads_per_instance: usize,
 
+    /// The number of instances each instance antagonist acts on, picking one
+    /// at random for each step. Each of --num-test-instances becomes a set of
+    /// this many instances that its threads share.
+    #[arg(
+        long,
+        default_value_t = 1,
+        value_parser = clap::builder::RangedU64ValueParser::<usize>::new().range(1..)
+    )]
+    pub instances_per_actor: usize,
+
+    /// How long instance antagonists sleep before and after each action, as a
+    /// maximum (`500ms`) or a range (`10ms..1s`). Use `0` to not sleep at
+    /// all. If not set, the profile's think time is used.
+    #[arg(long)]
+    pub instance_think_time: Option<SleepRange>,
+
+    /// The fraction of instance creates that ask for a default NIC.
+    #[arg(
+        long,
+        default_value_t = 0.0,
+        value_parser = crate::chaos_proxy::parse_probability
+    )]
+    pub instance_nic_fraction: f64,
+
+    /// The fraction of instance creates that ask for an ephemeral external IP
+    /// (and a default NIC to go with it) from the default IP pool.
+    #[arg(
+        long,
+        default_value_t = 0.0,
+        value_parser = crate::chaos_proxy::parse_probability
+    )]
+    pub instance_external_ip_fraction: f64,
+
+    /// If true, vary the optional fields of each instance create at random:
+    /// whether the instance is started, its NICs (overriding
+    /// --instance-nic-fraction), whether it gets the user's SSH keys, and its
+    /// hostname's length.
+    #[arg(long)]
+    pub vary_instance_creates: bool,
+
+    /// The number of additional "storm" antagonist threads to create for each
+    /// instance. Storm antagonists don't query their instance's state or sleep
+    /// between actions; they fire start, stop, and delete requests at it
+    /// back-to-back.
+    #[arg(long, default_value_t = 0)]
+    pub storm_threads_per_instance: usize,
+
     /// The number of test disks to create.
     #[arg(long, default_value_t = 4)]
     pub num_test_disks: usize,
@@ -20,6 +147,22 @@ pub struct Config {
     #[arg(long, default_value_t = 4)]
     pub threads_per_disk: usize,
 
+    /// How long disk antagonists sleep before and after each action. See
+    /// --instance-think-time for the format.
+    #[arg(long)]
+    pub disk_think_time: Option<SleepRange>,
+
+    /// The sizes, in GiB, that disk antagonists create their disks with (e.g.
+    /// `--disk-sizes 1,10,100`). Each create picks one at random. With
+    /// --measure-transitions, disk transitions are recorded for each size.
+    #[arg(
+        long,
+        value_delimiter = ',',
+        default_value = "1",
+        value_parser = clap::builder::RangedU64ValueParser::<u64>::new().range(1..)
+    )]
+    pub disk_sizes: Vec<u64>,
+
     /// The number of test snapshots to create.
     #[arg(long, default_value_t = 4)]
     pub num_test_snapshots: usize,
@@ -29,15 +172,316 @@ pub struct Config {
     #[arg(long)]
     pub snapshots_use_same_disk: bool,
 
+    /// If true, the snapshot antagonists for snapshot `i` snapshot the disk
+    /// antagonists' disk `i`, whatever state it's in, racing snapshot creates
+    /// against the disk antagonists' deletes (overriding
+    /// --snapshots-use-same-disk and --contention-mode). Snapshots beyond
+    /// --num-test-disks have no disk to snapshot.
+    #[arg(long)]
+    pub snapshots_attack_disks: bool,
+
+    /// If true, point antagonists of different kinds at the same resources
+    /// wherever possible instead of giving each kind its own. In particular,
+    /// snapshot antagonists snapshot whichever disks the disk antagonists last
+    /// saw detached (overriding --snapshots-use-same-disk).
+    #[arg(long)]
+    pub contention_mode: bool,
+
     /// The number of antagonist threads to create for each snapshot.
     #[arg(long, default_value_t = 4)]
     pub threads_per_snapshot: usize,
 
-    /// The URI of the Nexus instance the stress test should interact with.
-    /// If not set, falls back to the value of the OXIDE_HOST environment
-    /// variable.
+    /// If set, before each snapshot create, delete the oldest ready snapshots
+    /// of the disk being snapshotted until there's room for the new one
+    /// without the disk having more than this many.
+    #[arg(
+        long,
+        value_parser = clap::builder::RangedU64ValueParser::<usize>::new().range(1..)
+    )]
+    pub max_snapshots_per_disk: Option<usize>,
+
+    /// How long snapshot antagonists sleep before and after each action. See
+    /// --instance-think-time for the format.
+    #[arg(long)]
+    pub snapshot_think_time: Option<SleepRange>,
+
+    /// The number of composite scenario antagonists to create. Each one
+    /// repeatedly creates a disk, an instance using that disk and two NICs,
+    /// and a snapshot of the disk, then tears them all down.
+    #[arg(long, default_value_t = 0)]
+    pub num_scenarios: usize,
+
+    /// If set, scenario antagonists create their disks from the image with
+    /// this ID instead of leaving them blank, so that their instances boot a
+    /// real guest.
+    #[arg(long)]
+    pub boot_image: Option<uuid::Uuid>,
+
+    /// If set, after each scenario instance booted from --boot-image reaches
+    /// Running, watch its serial console for --boot-marker, and count an SLA
+    /// violation if it doesn't show up within this long.
+    #[arg(
+        long,
+        requires = "boot_image",
+        value_parser = humantime::parse_duration
+    )]
+    pub boot_sla: Option<Duration>,
+
+    /// The text on a guest's serial console that shows it has booted, e.g. a
+    /// login prompt or a line cloud-init prints when it finishes.
+    #[arg(long, default_value = "login:")]
+    pub boot_marker: String,
+
+    /// The number of derivation chain antagonists to create. Each one
+    /// repeatedly creates a disk, a snapshot of it, an image from the
+    /// snapshot, and a disk from the image, then deletes them all in a random
+    /// order.
+    #[arg(long, default_value_t = 0)]
+    pub num_chains: usize,
+
+    /// The number of conflict checkers to create. Each one repeatedly races
+    /// identical creates or deletes of the same disk, or a create and a delete
+    /// of it, and checks that the responses are consistent.
+    #[arg(long, default_value_t = 0)]
+    pub num_conflict_checkers: usize,
+
+    /// How many identical requests conflict checkers fire at once when they
+    /// race duplicate creates or deletes.
+    #[arg(
+        long,
+        default_value_t = 2,
+        value_parser = crate::actor::conflict::parse_storm_size
+    )]
+    pub conflict_storm_size: usize,
+
+    /// The number of fuzzers to create. Each one repeatedly sends a
+    /// deliberately invalid disk or instance create request and checks that
+    /// Nexus rejects it with a 4xx and a well-formed error body.
+    #[arg(long, default_value_t = 0)]
+    pub num_fuzzers: usize,
+
+    /// The number of boundary probers to create. Each one repeatedly creates
+    /// a disk or instance with a value at one of Nexus's limits (e.g. the most
+    /// memory or NICs an instance may have), or just past it, and checks that
+    /// the request is accepted or rejected accordingly.
+    #[arg(long, default_value_t = 0)]
+    pub num_boundary_probers: usize,
+
+    /// If true, instance, disk, and snapshot antagonists create each new
+    /// resource under a fresh name instead of reusing their resources' names,
+    /// so that the database accumulates rows for deleted resources over the
+    /// course of the run.
+    #[arg(long)]
+    pub unique_names: bool,
+
+    /// If set, create only actors of these kinds (e.g. `--only snapshot,disk`),
+    /// regardless of the options that choose how many actors of each kind to
+    /// create.
+    #[arg(
+        long,
+        value_delimiter = ',',
+        value_parser = crate::actor::ActorKind::parse_name,
+        conflicts_with = "skip"
+    )]
+    pub only: Vec<String>,
+
+    /// If set, don't create any actors of these kinds (e.g. `--skip instance`).
+    #[arg(
+        long,
+        value_delimiter = ',',
+        value_parser = crate::actor::ActorKind::parse_name
+    )]
+    pub skip: Vec<String>,
+
+    /// If set, run a snapshot garbage collector that deletes snapshots older
+    /// than this.
+    #[arg(long, value_parser = humantime::parse_duration)]
+    pub snapshot_gc_max_age: Option<Duration>,
+
+    /// If set, run a snapshot garbage collector that deletes the oldest
+    /// snapshots in the project until at most this many remain.
+    #[arg(long)]
+    pub snapshot_gc_max_count: Option<usize>,
+
+    /// How often the snapshot garbage collector looks for snapshots to delete.
+    #[arg(long, default_value = "60s", value_parser = humantime::parse_duration)]
+    pub snapshot_gc_interval: Duration,
+
+    /// If set, run a janitor at this interval that deletes resources in the
+    /// stress project that don't belong to any live actor.
+    #[arg(long, value_parser = humantime::parse_duration)]
+    pub janitor_interval: Option<Duration>,
+
+    /// If true, create an antagonist for each test instance that checks that
+    /// the instance is reachable on its external IPs whenever it is running.
+    #[arg(long)]
+    pub probe_instances: bool,
+
+    /// The TCP port reachability probes connect to.
+    #[arg(long, default_value_t = 22)]
+    pub probe_port: u16,
+
+    /// How long a running instance with an external IP may be unreachable
+    /// before its reachability probe reports an error.
+    #[arg(long, default_value = "60s", value_parser = humantime::parse_duration)]
+    pub probe_grace_period: Duration,
+
+    /// The URI of a Nexus instance the stress test should interact with. May
+    /// be given more than once to spread the actors across several Nexus
+    /// instances. If not set, falls back to the value of the OXIDE_HOST
+    /// environment variable.
+    #[arg(long)]
+    pub host_uri: Vec<String>,
+
+    /// If true, resolve the host name in each host URI and treat each address
+    /// it resolves to as a separate Nexus instance, e.g. to spread the actors
+    /// across all the Nexus instances behind one DNS name. Otherwise each host
+    /// name is pinned to the first address it resolves to.
+    #[arg(long)]
+    pub resolve_host_uri: bool,
+
+    /// A PEM file with a CA certificate to trust when connecting to Nexus over
+    /// HTTPS, e.g. for a rack with internally-issued certificates.
     #[arg(long)]
-    pub host_uri: Option<String>,
+    pub cacert: Option<PathBuf>,
+
+    /// If true, don't verify Nexus's TLS certificate at all, e.g. for a rack
+    /// with self-signed certificates.
+    #[arg(long)]
+    pub insecure_skip_tls_verify: bool,
+
+    /// How many HTTP clients, each with its own connection pool, the actors
+    /// sending requests to each Nexus instance as each identity share.
+    #[arg(long, default_value_t = 1)]
+    pub client_pool_size: usize,
+
+    /// If true, don't reuse connections to Nexus, so that every request opens
+    /// a new one.
+    #[arg(long)]
+    pub no_keep_alive: bool,
+
+    /// The most idle connections each HTTP client keeps open to its Nexus
+    /// instance for reuse. Unlimited if not set.
+    #[arg(long, conflicts_with = "no_keep_alive")]
+    pub max_idle_connections: Option<usize>,
+
+    /// Which HTTP version to talk to Nexus with.
+    #[arg(long, value_enum, default_value_t = crate::client::HttpVersion::Auto)]
+    pub http_version: crate::client::HttpVersion,
+
+    /// How long to wait for a connection to Nexus to open.
+    #[arg(
+        long,
+        default_value = "120s",
+        value_parser = humantime::parse_duration
+    )]
+    pub connect_timeout: Duration,
+
+    /// If true, send the actors' requests through an embedded proxy that
+    /// injects network faults (--chaos-delay, --chaos-drop, and
+    /// --chaos-duplicate).
+    #[arg(long)]
+    pub chaos_proxy: bool,
+
+    /// The probability that the chaos proxy holds a request for up to
+    /// --chaos-max-delay before passing it on.
+    #[arg(
+        long,
+        default_value_t = 0.0,
+        requires = "chaos_proxy",
+        value_parser = crate::chaos_proxy::parse_probability
+    )]
+    pub chaos_delay: f64,
+
+    /// The longest the chaos proxy holds a delayed request.
+    #[arg(
+        long,
+        default_value = "5s",
+        value_parser = humantime::parse_duration
+    )]
+    pub chaos_max_delay: Duration,
+
+    /// The probability that the chaos proxy closes the connection partway
+    /// through a response, after Nexus has handled the request.
+    #[arg(
+        long,
+        default_value_t = 0.0,
+        requires = "chaos_proxy",
+        value_parser = crate::chaos_proxy::parse_probability
+    )]
+    pub chaos_drop: f64,
+
+    /// The probability that the chaos proxy sends a request to Nexus twice at
+    /// once, answering with the first copy's response.
+    #[arg(
+        long,
+        default_value_t = 0.0,
+        requires = "chaos_proxy",
+        value_parser = crate::chaos_proxy::parse_probability
+    )]
+    pub chaos_duplicate: f64,
+
+    /// If true, run against an embedded mock of the Oxide API instead of a
+    /// real Nexus, to exercise the harness itself without a rack. The mock
+    /// keeps its resources in memory and ignores credentials.
+    #[arg(long, conflicts_with_all = ["host_uri", "resolve_host_uri"])]
+    pub mock_nexus: bool,
+
+    /// The probability that the mock Nexus answers a request to create,
+    /// change, or delete an instance, disk, or snapshot with
+    /// --mock-error-status instead of handling it.
+    #[arg(
+        long,
+        default_value_t = 0.0,
+        requires = "mock_nexus",
+        value_parser = crate::chaos_proxy::parse_probability
+    )]
+    pub mock_error_rate: f64,
+
+    /// The status code of the mock Nexus's injected error responses.
+    #[arg(
+        long,
+        default_value_t = 500,
+        value_parser = clap::value_parser!(u16).range(400..600)
+    )]
+    pub mock_error_status: u16,
+
+    /// The probability that an actor abandons a write partway through, as a
+    /// client that disconnects before the response arrives would, and then
+    /// checks that its resource settles anyway.
+    #[arg(
+        long,
+        default_value_t = 0.0,
+        value_parser = crate::chaos_proxy::parse_probability
+    )]
+    pub abandon_requests: f64,
+
+    /// The longest an actor waits for a response before abandoning a write
+    /// (--abandon-requests). Each abandoned write waits a random time up to
+    /// this.
+    #[arg(
+        long,
+        default_value = "2s",
+        value_parser = humantime::parse_duration
+    )]
+    pub abandon_max_wait: Duration,
+
+    /// How long a resource may stay in a transitional state (e.g. starting or
+    /// creating) after an actor abandons a write to it before it counts as
+    /// stuck.
+    #[arg(
+        long,
+        default_value = "5m",
+        value_parser = humantime::parse_duration
+    )]
+    pub abandon_settle_timeout: Duration,
+
+    /// If set, every interval, kill a randomly chosen instance, disk,
+    /// snapshot, or conflict actor wherever it is in its step, as if its
+    /// client had crashed, and replace it with a fresh one that has to
+    /// rediscover and adopt its resource.
+    #[arg(long, value_parser = humantime::parse_duration)]
+    pub crash_actors: Option<Duration>,
 
     /// The directory in which to search for a `hosts.toml` file from which to
     /// read an authentication token to supply to Nexus. If not set, defaults to
@@ -55,7 +499,871 @@ pub struct Config {
     #[arg(long)]
     pub credentials_toml_dir: Option<PathBuf>,
 
-    /// Halt omicron-stress if a 500 series error was seen
+    /// The name of a profile in `credentials.toml` to add to the pool of
+    /// identities the actors are spread across. May be given more than once.
+    #[arg(long)]
+    pub identity_profile: Vec<String>,
+
+    /// A file of API tokens, one per line, to add to the pool of identities
+    /// the actors are spread across. Blank lines and lines starting with `#`
+    /// are ignored.
+    #[arg(long)]
+    pub token_file: Option<PathBuf>,
+
+    /// If set, limit all the actors together to this many API requests per
+    /// second.
+    #[arg(long, value_parser = crate::rate_limit::parse_rps)]
+    pub target_rps: Option<f64>,
+
+    /// A `<kind>=<rate>` limit on the API requests per second made by all the
+    /// actors of one kind (e.g. `instance=5`), in addition to --target-rps.
+    /// May be given more than once.
+    #[arg(long, value_parser = crate::rate_limit::parse_kind_rps)]
+    pub kind_rps: Vec<(String, f64)>,
+
+    /// If set, send this many cheap reads of the stress project per second
+    /// (alternating project views and instance listings) in the background,
+    /// independent of the actors, to study how background load affects their
+    /// latency. Errors from these reads don't count against the error budget.
+    #[arg(long, value_parser = crate::rate_limit::parse_rps)]
+    pub read_load: Option<f64>,
+
+    /// If set, send this many requests per second with expired or garbage
+    /// bearer tokens alongside the actors' traffic, and check that Nexus
+    /// rejects each one with a 401 within --bad-token-max-latency.
+    #[arg(long, value_parser = crate::rate_limit::parse_rps)]
+    pub bad_tokens: Option<f64>,
+
+    /// How long Nexus may take to reject a request with a bad token.
+    #[arg(
+        long,
+        default_value = "1s",
+        value_parser = humantime::parse_duration
+    )]
+    pub bad_token_max_latency: Duration,
+
+    /// How many times to retry an actor's API call that fails for a transient
+    /// reason (a communication error or an error response matching
+    /// --retry-status) before reporting the error.
+    #[arg(long, default_value_t = 0)]
+    pub retry_attempts: u32,
+
+    /// How long to wait before the first retry of a call. The wait doubles
+    /// with each further retry, and is randomly shortened by up to half to
+    /// keep actors from retrying in lockstep. A `Retry-After` header in the
+    /// error response overrides it. The jitter comes from each actor's seeded
+    /// random number generator, so it's the same in runs with the same --seed.
+    #[arg(long, default_value = "500ms", value_parser = humantime::parse_duration)]
+    pub retry_backoff: Duration,
+
+    /// The longest to wait before retrying a call, including when a
+    /// `Retry-After` header asks for longer.
+    #[arg(long, default_value = "30s", value_parser = humantime::parse_duration)]
+    pub retry_max_backoff: Duration,
+
+    /// Error responses that make a call worth retrying, in the format used by
+    /// --fatal-status.
+    #[arg(
+        long,
+        value_delimiter = ',',
+        default_value = "429,503",
+        value_parser = crate::status_policy::parse_matcher
+    )]
+    pub retry_status: Vec<crate::status_policy::StatusMatcher>,
+
+    /// If set, when an actor can't reach Nexus at all, pause every actor until
+    /// Nexus responds again instead of failing, as long as the outage lasts
+    /// no longer than this. This lets a run continue across Nexus restarts
+    /// and control plane updates.
+    #[arg(long, value_parser = humantime::parse_duration)]
+    pub tolerate_unavailability: Option<Duration>,
+
+    /// If set, put the harness in maintenance mode whenever a file exists at
+    /// this path: every actor finishes its current step and then idles until
+    /// the file is removed. The maintenance windows are listed when the run
+    /// ends.
+    #[arg(long)]
+    pub maintenance_file: Option<PathBuf>,
+
+    /// If set, serve the control API on this address, for pausing, resuming,
+    /// and halting actors and checking on the run while it's in progress.
+    #[arg(long)]
+    pub control_listen: Option<SocketAddr>,
+
+    /// If set, write a heartbeat to this file every --heartbeat-interval: a
+    /// JSON object with the current time and when each kind of actor's API
+    /// calls last succeeded.
+    #[arg(long)]
+    pub heartbeat_file: Option<PathBuf>,
+
+    /// How often to write --heartbeat-file.
+    #[arg(
+        long,
+        default_value = "10s",
+        value_parser = humantime::parse_duration
+    )]
+    pub heartbeat_interval: Duration,
+
+    /// If set, drive every actor from a pool of this many worker tasks
+    /// instead of giving each actor a task of its own, so that a run can
+    /// simulate thousands of API clients (e.g. with
+    /// `--num-test-instances 5000`). A handful of workers is enough; each one
+    /// runs many actors' steps at once.
+    #[arg(long)]
+    pub actor_workers: Option<usize>,
+
+    /// How long to wait for actors to halt (and drain, with
+    /// --drain-on-exit) before giving up on them. Actors still running after
+    /// this are aborted and listed, along with the call each was stuck on.
+    #[arg(
+        long,
+        default_value = "10m",
+        value_parser = humantime::parse_duration
+    )]
+    pub shutdown_timeout: Duration,
+
+    /// If set, instance, disk, and snapshot actors view their resource only
+    /// once every this many steps, and in between act on the state they last
+    /// saw, updated with what their own successful actions should have done.
+    /// Any error makes the actor view its resource on its next step. This
+    /// keeps views of the actors' own resources from dominating the load.
+    #[arg(long)]
+    pub state_refresh_steps: Option<u32>,
+
+    /// How many times each actor may be restarted after its task dies (e.g.
+    /// because it panicked) or stalls. Once an actor is out of restarts, its
+    /// task ending counts as a disqualifying error.
+    #[arg(long, default_value_t = 0)]
+    pub max_actor_restarts: u32,
+
+    /// If set, an actor that hasn't finished a step in this long (while not
+    /// paused) is considered stalled, and is restarted if it has restarts
+    /// left or removed from the run with an error if it doesn't. Should be
+    /// longer than the slowest step any actor takes, including its SLA
+    /// waits.
+    #[arg(long, value_parser = humantime::parse_duration)]
+    pub actor_stall_timeout: Option<Duration>,
+
+    /// If true, set up the run but don't start any actors until told to over
+    /// the control API (`POST /start`), e.g. by `omicron-stress coordinate`.
+    /// Once the run ends, keep serving its final report at `GET /report`
+    /// until told to quit (`POST /quit`).
+    #[arg(long, requires = "control_listen")]
+    pub wait_for_start: bool,
+
+    /// If true, read commands from stdin for listing, pausing, resuming, and
+    /// halting actors, changing their action weights, and checking on the
+    /// run. Type `help` once the run starts for the list of commands.
+    #[arg(long)]
+    pub interactive: bool,
+
+    /// If set, write a JSON summary of the run (per-actor-kind counters and
+    /// step latencies, errors, leaked resources, and maintenance windows) to
+    /// this path when the run ends.
+    #[arg(long)]
+    pub report_json: Option<PathBuf>,
+
+    /// If set, POST a JSON notification (run ID, recent errors and their
+    /// request IDs, and leaked resources) to this URL when the run fails, and
+    /// when it has used half its error budget. The `text` field is a summary
+    /// line, so Slack-style incoming webhooks work as is.
+    #[arg(long)]
+    pub notify_webhook: Option<reqwest::Url>,
+
+    /// If set, append a line to this file for every API call the actors make,
+    /// recording the time, actor, endpoint, status code, and the request ID
+    /// to look for in Nexus's logs.
+    #[arg(long)]
+    pub request_log: Option<PathBuf>,
+
+    /// If set, append a JSON line to this file for every resource the run
+    /// creates, as soon as it's created, recording its kind, name, ID, and
+    /// creation time, and the actor and request that created it.
+    #[arg(long)]
+    pub manifest: Option<PathBuf>,
+
+    /// If set, when an error ends the run, write a bundle of artifacts about
+    /// it (the error, the response, the actor's recent API calls, and the
+    /// project's resources) into a new directory under this one.
+    #[arg(long)]
+    pub artifact_dir: Option<PathBuf>,
+
+    /// If true, write failure artifacts for every disqualifying error, not
+    /// just the one that ends the run.
+    #[arg(long, requires = "artifact_dir")]
+    pub artifacts_for_all_errors: bool,
+
+    /// How often to log a summary of each kind of actor's progress (steps per
+    /// second, failures, and step latencies) since the last summary. Set to 0
+    /// to turn the summaries off.
+    #[arg(long, default_value = "1m", value_parser = humantime::parse_duration)]
+    pub progress_interval: Duration,
+
+    /// If true, log only the actors' API calls that get no response, rather
+    /// than every write and every error response.
+    #[arg(long)]
+    pub quiet_requests: bool,
+
+    /// If set, write the report so far to --checkpoint-dir this often, so
+    /// that a long run leaves a record behind even if the runner dies.
+    #[arg(long, value_parser = humantime::parse_duration)]
+    pub checkpoint_interval: Option<Duration>,
+
+    /// The directory to write --checkpoint-interval's reports to. Each one is
+    /// named after the run's name prefix and numbered.
+    #[arg(long, default_value = ".")]
+    pub checkpoint_dir: PathBuf,
+
+    /// If set, look for leaked resources this often during the run, and
+    /// report any resource that's stuck or failed in the same state two looks
+    /// in a row.
+    #[arg(long, value_parser = humantime::parse_duration)]
+    pub leak_audit: Option<Duration>,
+
+    /// How often to compare each endpoint's recent mix of status classes with
+    /// its mix over the run so far, warning about sudden shifts. Set to 0 to
+    /// turn the comparisons off.
+    #[arg(long, default_value = "1m", value_parser = humantime::parse_duration)]
+    pub anomaly_window: Duration,
+
+    /// How much an endpoint's share of responses in some status class must
+    /// grow (as a fraction of all its responses, e.g. 0.25 for 25 percentage
+    /// points) to be worth a warning.
+    #[arg(long, default_value_t = 0.25)]
+    pub anomaly_threshold: f64,
+
+    /// If true, have actors check each view of their resources against what
+    /// their own successful creates and deletes say should exist, and report
+    /// contradictions (e.g. a resource that's still there after a successful
+    /// delete, or one whose ID doesn't match the one its create returned) as
+    /// errors.
+    #[arg(long)]
+    pub check_model: bool,
+
+    /// If set, after each successful delete of an instance, disk, or
+    /// snapshot, poll the resource until it's gone (and no longer listed),
+    /// reporting an error if it's still there after this long. Also turns on
+    /// --check-model, which catches deleted resources that come back later.
+    #[arg(long, value_parser = humantime::parse_duration)]
+    pub verify_deletes: Option<Duration>,
+
+    /// If set, after each successful instance stop, wait for the instance to
+    /// reach Stopped, and count an SLA violation if it takes longer than this.
+    #[arg(long, value_parser = humantime::parse_duration)]
+    pub stop_sla: Option<Duration>,
+
+    /// If set, after each successful instance create, wait for the instance
+    /// to reach Running, and count an SLA violation if it takes longer than
+    /// this.
+    #[arg(long, value_parser = humantime::parse_duration)]
+    pub instance_create_sla: Option<Duration>,
+
+    /// If set, after each successful disk create, wait for the disk to reach
+    /// Detached, and count an SLA violation if it takes longer than this.
+    #[arg(long, value_parser = humantime::parse_duration)]
+    pub disk_create_sla: Option<Duration>,
+
+    /// If set, after each successful snapshot create, wait for the snapshot
+    /// to reach Ready, and count an SLA violation if it takes longer than
+    /// this.
+    #[arg(long, value_parser = humantime::parse_duration)]
+    pub snapshot_create_sla: Option<Duration>,
+
+    /// Treat SLA violations as errors instead of just counting them.
+    #[arg(long)]
+    pub fatal_sla_violations: bool,
+
+    /// After each successful instance create, start, or stop, wait for the
+    /// instance to reach Running or Stopped, after each successful disk create
+    /// or delete, for the disk to reach Detached or disappear, and after each
+    /// successful snapshot create, for the snapshot to reach Ready, and record
+    /// how long that took since the request was sent.
+    #[arg(long)]
+    pub measure_transitions: bool,
+
+    /// If set, compare the silo's provisioned vCPUs, memory, and storage
+    /// against the stress project's resources this often, and once more
+    /// after the actors halt, reporting any drift.
+    #[arg(long, value_parser = humantime::parse_duration)]
+    pub utilization_audit: Option<Duration>,
+
+    /// If set, warn about every API call that takes longer than this.
+    #[arg(long, value_parser = humantime::parse_duration)]
+    pub slow_request_threshold: Option<Duration>,
+
+    /// How many of the run's slowest API calls to list when the run ends.
+    #[arg(long, default_value_t = 10)]
+    pub slowest_requests: usize,
+
+    /// If set, start actors gradually over this period instead of all at
+    /// once.
+    #[arg(long, value_parser = humantime::parse_duration)]
+    pub ramp_up: Option<Duration>,
+
+    /// If set, halt actors gradually over this period when the run ends
+    /// because its --duration elapsed.
+    #[arg(long, value_parser = humantime::parse_duration)]
+    pub ramp_down: Option<Duration>,
+
+    /// If true, when the run ends because of Ctrl-C or --duration, have each
+    /// actor finish its current step and then stop and delete its resources
+    /// before exiting, instead of leaving them wherever they happen to be.
+    #[arg(long)]
+    pub drain_on_exit: bool,
+
+    /// If true, after all actors halt, delete every resource the harness
+    /// created (including the baseline and the stress project, as
+    /// `omicron-stress cleanup` would) and wait for the deletions to finish.
+    /// The harness's IP range is only removed if this run added it.
+    #[arg(long)]
+    pub cleanup_on_exit: bool,
+
+    /// How long --cleanup-on-exit waits for resources to be deleted before
+    /// giving up and reporting the ones that are left.
+    #[arg(long, default_value = "10m", value_parser = humantime::parse_duration)]
+    pub cleanup_timeout: Duration,
+
+    /// If set, halt all actors and exit after the stress test has run for this
+    /// long.
+    #[arg(long, value_parser = humantime::parse_duration)]
+    pub duration: Option<Duration>,
+
+    /// The seed from which each actor's random choices are derived. Runs with
+    /// the same seed and the same actors make the same choices. If not set, a
+    /// random seed is chosen and logged at startup.
+    #[arg(long)]
+    pub seed: Option<u64>,
+
+    /// The project to run the stress test in. If not set, the project is named
+    /// `omicron-stress` with --name-prefix applied.
+    #[arg(long)]
+    pub project: Option<String>,
+
+    /// If true, fail instead of creating the stress project if it doesn't
+    /// already exist. The `cleanup` subcommand leaves such a project in place.
+    #[arg(long)]
+    pub no_create_project: bool,
+
+    /// The IP pool the harness makes sure has external IPs in it.
+    #[arg(long, default_value = "default")]
+    pub ip_pool: String,
+
+    /// A `first..last` range of IPv4 addresses to add to --ip-pool if it
+    /// doesn't already contain it. If not set, a small built-in range is added
+    /// to the pool only if the pool is empty.
+    #[arg(long, value_parser = crate::util::parse_ip_range)]
+    pub ip_range: Option<(Ipv4Addr, Ipv4Addr)>,
+
+    /// If true, don't add any IP ranges to --ip-pool, and leave it alone
+    /// during `cleanup`.
+    #[arg(long)]
+    pub no_ip_pool_setup: bool,
+
+    /// A prefix for the names of the stress project and every resource the
+    /// run creates, so that concurrent runs against the same rack don't
+    /// collide. If not set, a random prefix is chosen, logged at startup, and
+    /// recorded in the report and manifest. `cleanup` requires it, to know
+    /// which run to clean up after. Pass an empty prefix to use unprefixed
+    /// names.
+    #[arg(long, value_parser = crate::util::parse_name_prefix)]
+    pub name_prefix: Option<String>,
+
+    /// How many disqualifying errors the run may see before it stops, as `N`
+    /// (stop after more than N errors in total) or `N/window` (stop after more
+    /// than N errors within any window, e.g. `5/10m`). If not set, the run
+    /// stops at the first disqualifying error. Every error is listed when the
+    /// run ends.
+    #[arg(long, value_parser = crate::error_budget::parse_limit)]
+    pub error_budget: Option<crate::error_budget::Limit>,
+
+    /// How many throttled (429 or 503) responses may reach the harness after
+    /// retries run out before the run stops, in the same format as
+    /// --error-budget. Throttled responses never count against the error
+    /// budget unless --fatal-status names their status code. If not set,
+    /// they're only counted.
+    #[arg(long, value_parser = crate::error_budget::parse_limit)]
+    pub throttle_budget: Option<crate::error_budget::Limit>,
+
+    /// Error responses that count as failures, as a comma-separated list of
+    /// status codes (`503`), status classes (`5xx`), and error codes
+    /// (`type:ObjectAlreadyExists`). By default, any error response is
+    /// expected, and only requests that get no response count as failures.
+    #[arg(
+        long,
+        value_delimiter = ',',
+        value_parser = crate::status_policy::parse_matcher
+    )]
+    pub fatal_status: Vec<crate::status_policy::StatusMatcher>,
+
+    /// Error responses that never count as failures, even if they match
+    /// --fatal-status, in the same format.
+    #[arg(
+        long,
+        value_delimiter = ',',
+        value_parser = crate::status_policy::parse_matcher
+    )]
+    pub ignore_status: Vec<crate::status_policy::StatusMatcher>,
+
+    /// A TOML file listing the error signatures of known issues (endpoint,
+    /// status code, and message pattern). Errors that match one are counted
+    /// as that issue instead of as disqualifying errors.
+    #[arg(long)]
+    pub known_issues: Option<PathBuf>,
+
+    /// Halt omicron-stress if a 500 series error was seen. Equivalent to
+    /// `--fatal-status 500`.
     #[arg(long)]
     pub server_errors_fatal: bool,
+
+    /// The actor groups from the config file, if it had any.
+    #[arg(skip)]
+    pub workload: Option<Workload>,
+}
+
+/// The harness's subcommands.
+#[derive(Subcommand)]
+pub enum Command {
+    /// Run the stress test. This is what the harness does if no subcommand is
+    /// given.
+    Run,
+
+    /// Delete every resource the harness created and exit.
+    Cleanup(CleanupArgs),
+
+    /// Summarize a request log written by a previous run's --request-log.
+    Report(ReportArgs),
+
+    /// Check a scenario file and list the actors it would create.
+    ValidateScenario(ValidateScenarioArgs),
+
+    /// List the resources in the stress project.
+    ListResources,
+
+    /// Run the stress test across several workers, each an omicron-stress
+    /// run started with --control-listen and --wait-for-start.
+    Coordinate(CoordinateArgs),
+}
+
+/// Options for the `cleanup` subcommand.
+#[derive(Args)]
+pub struct CleanupArgs {
+    /// How long to keep waiting for the project's resources to be deleted
+    /// before giving up.
+    #[arg(long, default_value = "10m", value_parser = humantime::parse_duration)]
+    pub timeout: Duration,
+
+    /// If true, leave the IP range the harness adds to its IP pool in place.
+    #[arg(long)]
+    pub keep_ip_range: bool,
+
+    /// If true, leave the (empty) stress project in place.
+    #[arg(long)]
+    pub keep_project: bool,
+}
+
+/// Options for the `report` subcommand.
+#[derive(Args)]
+pub struct ReportArgs {
+    /// The request log to summarize.
+    pub journal: PathBuf,
+}
+
+/// Options for the `coordinate` subcommand.
+#[derive(Args)]
+pub struct CoordinateArgs {
+    /// The base URL of a worker's control API, e.g. `http://10.0.0.5:9090`.
+    /// Can be given more than once.
+    #[arg(long = "worker", required = true)]
+    pub workers: Vec<reqwest::Url>,
+}
+
+/// Options for the `validate-scenario` subcommand.
+#[derive(Args)]
+pub struct ValidateScenarioArgs {
+    /// The scenario file to check.
+    pub file: PathBuf,
+}
+
+impl Config {
+    /// Returns how often instance antagonists ask for networking, per
+    /// --instance-nic-fraction and --instance-external-ip-fraction.
+    pub fn instance_networking(&self) -> crate::actor::instance::Networking {
+        crate::actor::instance::Networking {
+            nic: self.instance_nic_fraction,
+            external_ip: self.instance_external_ip_fraction,
+        }
+    }
+
+    /// Returns true if --only and --skip allow actors of the named kind.
+    pub fn actor_kind_enabled(&self, kind: &str) -> bool {
+        (self.only.is_empty() || self.only.iter().any(|k| k == kind))
+            && !self.skip.iter().any(|k| k == kind)
+    }
+
+    /// Parses the command line, first applying any settings from the file
+    /// named by `--config` and under those any implied by `--smoke` or
+    /// `--soak`, whether the command line or the file sets it. Exits the
+    /// process if the configuration is invalid.
+    pub fn load() -> Self {
+        let args: Vec<OsString> = std::env::args_os().collect();
+        let Some(path) = matches(&args).get_one::<PathBuf>("config").cloned()
+        else {
+            return parse_args(with_preset_args(args));
+        };
+
+        let (file_args, workload) = match read_config_file(&path) {
+            Ok(contents) => contents,
+            Err(e) => exit_with(Self::command().error(
+                clap::error::ErrorKind::InvalidValue,
+                format!("{:#}", e),
+            )),
+        };
+
+        let mut config = parse_args(with_file_args(file_args, args));
+        config.workload = workload;
+        config
+    }
+}
+
+/// Returns the command that parses the command line. The top-level options
+/// can go either before or after the subcommand. Boolean options take an
+/// optional value, so that `--<option>=false` can turn off one that a config
+/// file or preset turns on.
+fn command() -> clap::Command {
+    Config::command().mut_args(|arg| {
+        let arg = arg.global(true);
+        if !matches!(arg.get_action(), ArgAction::SetTrue) {
+            return arg;
+        }
+
+        arg.action(ArgAction::Set)
+            .num_args(0..=1)
+            .require_equals(true)
+            .default_value("false")
+            .default_missing_value("true")
+            .value_parser(clap::value_parser!(bool))
+    })
+}
+
+/// Parses `args`, exiting the process if they're invalid.
+fn parse_args(args: Vec<OsString>) -> Config {
+    command()
+        .try_get_matches_from(args)
+        .and_then(|matches| Config::from_arg_matches(&matches))
+        .unwrap_or_else(|e| exit_with(e))
+}
+
+/// Parses `args` just to see which options they set, without checking that
+/// the options go together, since `args` may be only part of the settings.
+/// Exits the process if they can't be parsed at all.
+fn matches(args: &[OsString]) -> ArgMatches {
+    command()
+        .ignore_errors(true)
+        .try_get_matches_from(args)
+        .unwrap_or_else(|e| exit_with(e))
+}
+
+/// Returns true if the option `id` is set in `matches`, rather than left at
+/// its default, either before or after the subcommand.
+fn is_set(matches: &ArgMatches, id: &str) -> bool {
+    matches.value_source(id) == Some(ValueSource::CommandLine)
+        || matches.subcommand().is_some_and(|(_, m)| is_set(m, id))
+}
+
+/// Returns the command line `args` with the settings in `defaults`, another
+/// list of options, under it: each option that `defaults` sets and `args`
+/// doesn't goes right after the program name. An option that both set takes
+/// only the values in `args`, which matters for list options, whose values
+/// would otherwise add up.
+fn under(defaults: Vec<OsString>, args: Vec<OsString>) -> Vec<OsString> {
+    let program = args[0].clone();
+    let defaults = matches(
+        &std::iter::once(program.clone()).chain(defaults).collect::<Vec<_>>(),
+    );
+    let overrides = matches(&args);
+
+    let mut merged = vec![program];
+    for arg in command().get_arguments() {
+        let id = arg.get_id().as_str();
+        let Some(long) = arg.get_long() else {
+            continue;
+        };
+        if !is_set(&defaults, id) || is_set(&overrides, id) {
+            continue;
+        }
+
+        for value in defaults.get_raw(id).into_iter().flatten() {
+            let mut option = OsString::from(format!("--{}=", long));
+            option.push(value);
+            merged.push(option);
+        }
+    }
+
+    merged.extend(args.into_iter().skip(1));
+    merged
+}
+
+/// Prints `e` and exits the process. clap exits with 2 on a usage error,
+/// which would read as a 5xx (see `crate::outcome`), so usage errors exit
+/// with 1 instead. `--help` and `--version` still exit with 0.
+fn exit_with(e: clap::Error) -> ! {
+    let _ = e.print();
+    std::process::exit(if e.use_stderr() { 1 } else { 0 })
+}
+
+/// Sets the config for unit tests that read it, which is the same for all of
+/// them, since it can only be set once.
+#[cfg(test)]
+pub fn init_for_tests() -> &'static Config {
+    crate::CONFIG.get_or_init(|| {
+        parse_args(
+            [
+                "omicron-stress",
+                "--retry-attempts=5",
+                "--retry-backoff=100ms",
+                "--retry-max-backoff=1s",
+                "--state-refresh-steps=3",
+            ]
+            .iter()
+            .map(OsString::from)
+            .collect(),
+        )
+    })
+}
+
+/// The settings `--smoke` stands for.
+const SMOKE_ARGS: &[&str] = &[
+    "--duration=2m",
+    "--num-test-instances=1",
+    "--threads-per-instance=1",
+    "--num-test-disks=1",
+    "--threads-per-disk=1",
+    "--num-test-snapshots=1",
+    "--threads-per-snapshot=1",
+    "--num-scenarios=1",
+    "--num-chains=1",
+    "--num-conflict-checkers=1",
+    "--num-fuzzers=1",
+    "--num-boundary-probers=1",
+    "--snapshot-gc-max-count=8",
+    "--janitor-interval=30s",
+    "--fatal-status=5xx",
+    "--check-model",
+    "--drain-on-exit",
+    "--cleanup-on-exit",
+];
+
+/// The settings `--soak` stands for.
+const SOAK_ARGS: &[&str] = &[
+    "--quiet-requests",
+    "--progress-interval=15m",
+    "--checkpoint-interval=1h",
+    "--leak-audit=1h",
+    "--janitor-interval=10m",
+    "--tolerate-unavailability=15m",
+    "--retry-attempts=3",
+    "--max-actor-restarts=10",
+];
+
+/// Returns the command line `args` with a config file's settings, `file_args`,
+/// under it, and the settings of any preset that either one asks for under
+/// both, since presets only change the defaults.
+fn with_file_args(
+    file_args: Vec<OsString>,
+    args: Vec<OsString>,
+) -> Vec<OsString> {
+    with_preset_args(under(file_args, args))
+}
+
+/// Returns `args` with the settings that `--smoke` or `--soak` stands for
+/// under them (see `under`), if either is set, so that options on the command
+/// line override the preset's.
+fn with_preset_args(args: Vec<OsString>) -> Vec<OsString> {
+    let matches = matches(&args);
+    let preset = if matches.get_one::<bool>("smoke") == Some(&true) {
+        SMOKE_ARGS
+    } else if matches.get_one::<bool>("soak") == Some(&true) {
+        SOAK_ARGS
+    } else {
+        return args;
+    };
+
+    under(preset.iter().map(OsString::from).collect(), args)
+}
+
+/// Reads the config file at `path`, returning the command-line arguments
+/// equivalent to its settings and the workload described by any `[[actors]]`
+/// groups it contains.
+fn read_config_file(
+    path: &Path,
+) -> anyhow::Result<(Vec<OsString>, Option<Workload>)> {
+    let contents = std::fs::read_to_string(path)
+        .with_context(|| format!("reading {}", path.display()))?;
+    let mut table: toml::Table = contents
+        .parse()
+        .with_context(|| format!("parsing {}", path.display()))?;
+
+    let workload = table
+        .remove("actors")
+        .map(|actors| {
+            let mut workload = toml::Table::new();
+            workload.insert("actors".to_owned(), actors);
+            Workload::from_table(workload)
+        })
+        .transpose()
+        .with_context(|| format!("reading actors from {}", path.display()))?;
+
+    let mut args = Vec::new();
+    for (key, value) in table {
+        let flag = format!("--{}", key.replace('_', "-"));
+        let values = match value {
+            toml::Value::Array(values) => values,
+            value => vec![value],
+        };
+
+        for value in values {
+            match value {
+                toml::Value::Boolean(b) => {
+                    args.push(format!("{}={}", flag, b).into());
+                }
+                toml::Value::String(s) => {
+                    args.push(flag.clone().into());
+                    args.push(s.into());
+                }
+                toml::Value::Integer(_)
+                | toml::Value::Float(_)
+                | toml::Value::Datetime(_) => {
+                    args.push(flag.clone().into());
+                    args.push(value.to_string().into());
+                }
+                toml::Value::Array(_) | toml::Value::Table(_) => {
+                    anyhow::bail!(
+                        "{}: unsupported value for {}",
+                        path.display(),
+                        key
+                    );
+                }
+            }
+        }
+    }
+
+    Ok((args, workload))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Parses `args`, after the program name, with any preset they name.
+    fn parse(args: &[&str]) -> Config {
+        let args = std::iter::once("omicron-stress")
+            .chain(args.iter().copied())
+            .map(OsString::from)
+            .collect();
+        parse_args(with_preset_args(args))
+    }
+
+    #[test]
+    fn no_preset_keeps_the_defaults() {
+        let config = parse(&[]);
+        assert_eq!(config.duration, None);
+        assert_eq!(config.num_test_instances, 4);
+        assert!(!config.check_model);
+    }
+
+    #[test]
+    fn smoke_applies_its_settings() {
+        let config = parse(&["--smoke"]);
+        assert_eq!(config.duration, Some(Duration::from_secs(120)));
+        assert_eq!(config.num_test_instances, 1);
+        assert!(config.check_model);
+    }
+
+    #[test]
+    fn options_override_presets_wherever_they_are() {
+        for args in [["--smoke", "--duration=5s"], ["--duration=5s", "--smoke"]]
+        {
+            let config = parse(&args);
+            assert_eq!(config.duration, Some(Duration::from_secs(5)));
+            assert_eq!(config.num_test_instances, 1);
+        }
+
+        let config = parse(&["--soak", "--retry-attempts=1"]);
+        assert!(config.quiet_requests);
+        assert_eq!(config.retry_attempts, 1);
+    }
+
+    #[test]
+    fn file_settings_override_presets() {
+        let parse = |file: &[&str], args: &[&str]| {
+            let args = std::iter::once("omicron-stress")
+                .chain(args.iter().copied())
+                .map(OsString::from)
+                .collect();
+            parse_args(with_file_args(
+                file.iter().map(OsString::from).collect(),
+                args,
+            ))
+        };
+
+        let config = parse(&["--retry-attempts=5"], &["--soak"]);
+        assert!(config.quiet_requests);
+        assert_eq!(config.retry_attempts, 5);
+
+        let config = parse(&["--soak=true", "--retry-attempts=5"], &[]);
+        assert!(config.quiet_requests);
+        assert_eq!(config.retry_attempts, 5);
+
+        let config = parse(&["--soak=true"], &["--retry-attempts=1"]);
+        assert_eq!(config.max_actor_restarts, 10);
+        assert_eq!(config.retry_attempts, 1);
+    }
+
+    #[test]
+    fn options_replace_smoke_settings() {
+        let config = parse(&[
+            "--smoke",
+            "--fatal-status",
+            "503",
+            "--check-model=false",
+            "--cleanup-on-exit=false",
+        ]);
+        let fatal = |status| {
+            config.fatal_status.iter().any(|m| m.matches(status, None))
+        };
+        assert!(fatal(http::StatusCode::SERVICE_UNAVAILABLE));
+        assert!(!fatal(http::StatusCode::INTERNAL_SERVER_ERROR));
+        assert!(!config.check_model);
+        assert!(!config.cleanup_on_exit);
+        assert!(config.drain_on_exit);
+    }
+
+    #[test]
+    fn command_line_replaces_file_settings() {
+        let file = ["--fatal-status=5xx", "--check-model", "--duration", "1h"];
+        let args = ["omicron-stress", "--fatal-status", "503,type:Conflict"]
+            .into_iter()
+            .chain(["--check-model=false", "--num-test-instances=2"]);
+        let config = parse_args(under(
+            file.map(OsString::from).into(),
+            args.map(OsString::from).collect(),
+        ));
+
+        let fatal = |status| {
+            config.fatal_status.iter().any(|m| m.matches(status, None))
+        };
+        assert_eq!(config.fatal_status.len(), 2);
+        assert!(fatal(http::StatusCode::SERVICE_UNAVAILABLE));
+        assert!(!fatal(http::StatusCode::INTERNAL_SERVER_ERROR));
+        assert!(!config.check_model);
+        assert_eq!(config.duration, Some(Duration::from_secs(3600)));
+        assert_eq!(config.num_test_instances, 2);
+    }
+
+    #[test]
+    fn presets_after_double_dash_are_ignored() {
+        let args: Vec<OsString> =
+            ["omicron-stress", "--", "--smoke"].map(OsString::from).into();
+        assert_eq!(with_preset_args(args.clone()), args);
+    }
 }
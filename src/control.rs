@@ -0,0 +1,233 @@
+//! The control API (`--control-listen`), a small HTTP server for steering a
+//! running harness:
+//!
+//! - `GET /healthz`: when each kind of actor's API calls last succeeded, and
+//!   whether the run is going.
+//! - `GET /stats`: per-kind actor stats and per-endpoint latencies so far.
+//! - `GET /actors`: every running actor, its kind, and whether it's paused.
+//! - `GET /report`: the end-of-run report as it would look right now, or once
+//!   the run has ended, its final report.
+//! - `POST /pause`, `POST /resume`, `POST /halt`: pause, resume, or halt
+//!   actors. With `?actor=<name>`, only that actor; with `?kind=<kind>`, only
+//!   actors of that kind; otherwise every actor.
+//! - `POST /start`: start a run that's waiting to be started
+//!   (`--wait-for-start`).
+//! - `POST /stop`: end the run, as if its `--duration` had elapsed.
+//! - `POST /quit`: end the run, as if by Ctrl-C.
+//!
+//! A run started with `--wait-for-start` keeps serving the API after it ends,
+//! so that whoever started it can fetch its final report, and exits on
+//! `POST /quit` (or Ctrl-C).
+//!
+//! Everything but `/healthz` and `/stats` needs the main loop's state, so the
+//! server passes those requests to the main loop as `Command`s and waits for
+//! its reply. The interactive console (`crate::repl`) drives the harness
+//! through the same commands.
+
+use std::convert::Infallible;
+use std::net::SocketAddr;
+
+use anyhow::{Context, Result};
+use hyper::service::{make_service_fn, service_fn};
+use hyper::{Body, Method, StatusCode};
+use tokio::sync::{mpsc, oneshot};
+use tracing::{info, warn};
+
+/// Which actors a command applies to.
+#[derive(Debug)]
+pub enum Target {
+    All,
+    Actor(String),
+    Kind(String),
+}
+
+impl Target {
+    /// Parses a target from a request's query string.
+    fn parse(query: Option<&str>) -> Result<Self, String> {
+        let Some(query) = query.filter(|q| !q.is_empty()) else {
+            return Ok(Target::All);
+        };
+
+        match query.split_once('=') {
+            Some(("actor", name)) => Ok(Target::Actor(name.to_owned())),
+            Some(("kind", kind)) => Ok(Target::Kind(kind.to_owned())),
+            _ => Err(format!(
+                "unrecognized query {:?} (expected actor=<name> or \
+                kind=<kind>)",
+                query
+            )),
+        }
+    }
+
+    /// Returns true if this target includes the actor named `name` of the
+    /// kind named `kind`.
+    pub fn matches(&self, name: &str, kind: &str) -> bool {
+        match self {
+            Target::All => true,
+            Target::Actor(n) => n == name,
+            Target::Kind(k) => k == kind,
+        }
+    }
+}
+
+/// A command for the main loop.
+#[derive(Debug)]
+pub enum Command {
+    ListActors,
+    Report,
+    Pause(Target),
+    Resume(Target),
+    Halt(Target),
+
+    /// Switches the targeted actors to a profile's action weights.
+    SetProfile(Target, crate::profile::Profile),
+
+    /// Starts a run that's waiting to be started.
+    Start,
+
+    /// Ends the run, as if its `--duration` had elapsed.
+    Stop,
+
+    /// Ends the run, as if by Ctrl-C.
+    Quit,
+}
+
+/// The main loop's reply to a command.
+pub struct Reply {
+    pub status: StatusCode,
+    pub body: serde_json::Value,
+}
+
+impl Reply {
+    pub fn ok(body: serde_json::Value) -> Self {
+        Self { status: StatusCode::OK, body }
+    }
+
+    pub fn error(status: StatusCode, message: impl Into<String>) -> Self {
+        Self { status, body: serde_json::json!({ "error": message.into() }) }
+    }
+}
+
+/// A command and the channel to send its reply on.
+pub type Request = (Command, oneshot::Sender<Reply>);
+
+fn respond(reply: Reply) -> hyper::Response<Body> {
+    let body = serde_json::to_string_pretty(&reply.body)
+        .unwrap_or_else(|e| format!("{{\"error\": \"{}\"}}", e));
+    hyper::Response::builder()
+        .status(reply.status)
+        .header(http::header::CONTENT_TYPE, "application/json")
+        .body(Body::from(body))
+        .unwrap()
+}
+
+async fn handle(
+    req: hyper::Request<Body>,
+    commands: mpsc::Sender<Request>,
+) -> Result<hyper::Response<Body>, Infallible> {
+    let target = match Target::parse(req.uri().query()) {
+        Ok(target) => target,
+        Err(e) => return Ok(respond(Reply::error(StatusCode::BAD_REQUEST, e))),
+    };
+
+    let command = match (req.method(), req.uri().path()) {
+        (&Method::GET, "/healthz") => {
+            return Ok(respond(Reply::ok(
+                serde_json::to_value(crate::status::heartbeat()).unwrap(),
+            )));
+        }
+        (&Method::GET, "/stats") => {
+            return Ok(respond(Reply::ok(serde_json::json!({
+                "actors": crate::stats::summary(),
+                "endpoints": crate::stats::endpoint_summary(),
+            }))));
+        }
+        (&Method::GET, "/actors") => Command::ListActors,
+        (&Method::GET, "/report") => Command::Report,
+        (&Method::POST, "/pause") => Command::Pause(target),
+        (&Method::POST, "/resume") => Command::Resume(target),
+        (&Method::POST, "/halt") => Command::Halt(target),
+        (&Method::POST, "/start") => Command::Start,
+        (&Method::POST, "/stop") => Command::Stop,
+        (&Method::POST, "/quit") => Command::Quit,
+        (method, path) => {
+            return Ok(respond(Reply::error(
+                StatusCode::NOT_FOUND,
+                format!("no such endpoint: {} {}", method, path),
+            )));
+        }
+    };
+
+    info!(?command, "Control API request");
+    let (reply_tx, reply_rx) = oneshot::channel();
+    if commands.send((command, reply_tx)).await.is_err() {
+        return Ok(respond(Reply::error(
+            StatusCode::SERVICE_UNAVAILABLE,
+            "harness is shutting down",
+        )));
+    }
+
+    Ok(respond(reply_rx.await.unwrap_or_else(|_| {
+        Reply::error(
+            StatusCode::SERVICE_UNAVAILABLE,
+            "harness is shutting down",
+        )
+    })))
+}
+
+/// Starts serving the control API on `addr`, passing commands to the main loop
+/// through `commands`.
+pub fn start(addr: SocketAddr, commands: mpsc::Sender<Request>) -> Result<()> {
+    let make_service = make_service_fn(move |_| {
+        let commands = commands.clone();
+        async move {
+            Ok::<_, Infallible>(service_fn(move |req| {
+                handle(req, commands.clone())
+            }))
+        }
+    });
+
+    let server = hyper::Server::try_bind(&addr)
+        .with_context(|| format!("binding control API to {}", addr))?
+        .serve(make_service);
+    info!(%addr, "Control API listening");
+    tokio::spawn(async move {
+        if let Err(e) = server.await {
+            warn!("control API server failed: {:#}", e);
+        }
+    });
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_target() {
+        assert!(matches!(Target::parse(None), Ok(Target::All)));
+        assert!(matches!(Target::parse(Some("")), Ok(Target::All)));
+        assert!(matches!(
+            Target::parse(Some("actor=inst0_0")),
+            Ok(Target::Actor(name)) if name == "inst0_0"
+        ));
+        assert!(matches!(
+            Target::parse(Some("kind=disk")),
+            Ok(Target::Kind(kind)) if kind == "disk"
+        ));
+        assert!(Target::parse(Some("actor")).is_err());
+        assert!(Target::parse(Some("name=inst0_0")).is_err());
+    }
+
+    #[test]
+    fn target_matches() {
+        assert!(Target::All.matches("inst0_0", "instance"));
+        let actor = Target::Actor("inst0_0".to_owned());
+        assert!(actor.matches("inst0_0", "instance"));
+        assert!(!actor.matches("inst1_0", "instance"));
+        let kind = Target::Kind("disk".to_owned());
+        assert!(kind.matches("disk0_0", "disk"));
+        assert!(!kind.matches("inst0_0", "instance"));
+    }
+}
@@ -0,0 +1,424 @@
+//! The `coordinate` subcommand, which runs one stress test across several
+//! harness instances ("workers"), so that the load isn't limited by what one
+//! client machine can send.
+//!
+//! Each worker is an ordinary run started with `--control-listen` and
+//! `--wait-for-start`, which sets up its project and then waits. The
+//! coordinator waits for every worker's control API to come up, starts them
+//! all at once (so that their workload phases line up), and logs their
+//! combined progress. The run ends after `--duration`, on Ctrl-C, or as soon
+//! as any worker's own run ends (e.g. because its error budget ran out), so
+//! that the rest don't go on without it. When the run ends, it tells the
+//! workers to stop, waits for each one's final report (which a worker started
+//! with `--wait-for-start` keeps serving until it's told to quit), tells the
+//! workers to quit, and combines the reports into one. The run's verdict is the workers' own: each
+//! worker judges its run against its error budget and checks for leaks and
+//! stuck actors, just as it would alone.
+
+use std::collections::BTreeMap;
+use std::process::ExitCode;
+use std::time::Duration;
+
+use anyhow::{Context, Result};
+use reqwest::Url;
+use serde::Serialize;
+use tokio::sync::mpsc::UnboundedReceiver;
+use tokio::time::Instant;
+use tracing::{info, warn};
+
+use crate::config::CoordinateArgs;
+use crate::outcome::{self, Failure};
+
+/// How long to wait between checks on workers that aren't up yet.
+const READY_INTERVAL: Duration = Duration::from_secs(2);
+
+/// How often to check whether any worker's run has ended.
+const CHECK_INTERVAL: Duration = Duration::from_secs(10);
+
+/// One worker's part of the combined report.
+#[derive(Debug, Serialize)]
+struct WorkerReport {
+    url: String,
+
+    /// The worker's own report, or `None` if it couldn't be fetched.
+    report: Option<serde_json::Value>,
+    error: Option<String>,
+}
+
+/// Step counts summed across workers.
+#[derive(Debug, Default, Serialize)]
+struct Totals {
+    steps: u64,
+    failures: u64,
+    retries: u64,
+}
+
+/// The report the coordinator writes to `--report-json`.
+#[derive(Debug, Serialize)]
+struct CombinedReport {
+    /// When the workers were started, in RFC 3339 format.
+    started_at: String,
+    duration_secs: f64,
+
+    /// What each kind of actor did across all workers, keyed by kind name.
+    actors: BTreeMap<String, Totals>,
+
+    /// How many disqualifying errors the workers saw in all.
+    errors: u64,
+
+    /// Why the run failed, if it did, and the exit code it ended with: the
+    /// first failed worker's failure (in `--worker` order), or an
+    /// infrastructure failure if a worker's final report is missing.
+    failure: Option<Failure>,
+    exit_code: u8,
+    workers: Vec<WorkerReport>,
+}
+
+/// Sums the per-kind counters in `actors`, a map like a report's `actors`
+/// field, into `totals`.
+fn add_totals(
+    totals: &mut BTreeMap<String, Totals>,
+    actors: Option<&serde_json::Value>,
+) {
+    let Some(actors) = actors.and_then(|a| a.as_object()) else {
+        return;
+    };
+
+    for (kind, summary) in actors {
+        let count = |field| summary[field].as_u64().unwrap_or(0);
+        let total = totals.entry(kind.clone()).or_default();
+        total.steps += count("steps");
+        total.failures += count("failures");
+        total.retries += count("retries");
+    }
+}
+
+/// Returns the failure in `report`, a worker's final report, if its run
+/// failed.
+fn failure(report: &serde_json::Value) -> Option<Failure> {
+    serde_json::from_value(report["failure"].clone()).ok().flatten()
+}
+
+/// Returns true if `e`, an error from `request`, only means the worker took
+/// too long to answer, as it does while it's wrapping up its run.
+fn is_timeout(e: &anyhow::Error) -> bool {
+    e.downcast_ref::<reqwest::Error>().is_some_and(|e| e.is_timeout())
+}
+
+/// Sends a request to the worker at `base` and returns its JSON reply.
+async fn request(
+    client: &reqwest::Client,
+    method: reqwest::Method,
+    base: &Url,
+    path: &str,
+) -> Result<serde_json::Value> {
+    let url = base.join(path).with_context(|| format!("bad URL {}", base))?;
+    let response = client
+        .request(method, url.clone())
+        .send()
+        .await
+        .with_context(|| format!("sending request to {}", url))?;
+    let status = response.status();
+    let body = response
+        .bytes()
+        .await
+        .with_context(|| format!("reading reply from {}", url))?;
+    anyhow::ensure!(
+        status.is_success(),
+        "{} returned {}: {}",
+        url,
+        status,
+        String::from_utf8_lossy(&body)
+    );
+    serde_json::from_slice(&body)
+        .with_context(|| format!("parsing reply from {}", url))
+}
+
+/// Sends the same request to every worker at once.
+async fn broadcast(
+    client: &reqwest::Client,
+    method: reqwest::Method,
+    workers: &[Url],
+    path: &str,
+) -> Vec<Result<serde_json::Value>> {
+    futures::future::join_all(
+        workers.iter().map(|w| request(client, method.clone(), w, path)),
+    )
+    .await
+}
+
+/// Tells every worker to quit, logging the ones that couldn't be told.
+async fn quit_all(client: &reqwest::Client, workers: &[Url]) {
+    let results =
+        broadcast(client, reqwest::Method::POST, workers, "quit").await;
+    for (worker, result) in workers.iter().zip(results) {
+        if let Err(e) = result {
+            warn!(%worker, "failed to stop worker: {:#}", e);
+        }
+    }
+}
+
+/// Waits for every worker's final report, asking again every `READY_INTERVAL`
+/// while a worker's run is still wrapping up. Gives up on a worker that
+/// can't be reached, and on all of them on Ctrl-C.
+async fn final_reports(
+    client: &reqwest::Client,
+    workers: &[Url],
+    ctrlc_rx: &mut UnboundedReceiver<()>,
+) -> Vec<Result<serde_json::Value>> {
+    let mut reports: Vec<Option<Result<serde_json::Value>>> =
+        workers.iter().map(|_| None).collect();
+    loop {
+        let pending: Vec<usize> =
+            (0..workers.len()).filter(|&i| reports[i].is_none()).collect();
+        let results = futures::future::join_all(pending.iter().map(|&i| {
+            request(client, reqwest::Method::GET, &workers[i], "report")
+        }))
+        .await;
+        for (i, result) in pending.into_iter().zip(results) {
+            match result {
+                Ok(report) if report["outcome"] == "running" => {}
+                Err(e) if is_timeout(&e) => {}
+                result => reports[i] = Some(result),
+            }
+        }
+
+        let waiting: Vec<_> = workers
+            .iter()
+            .zip(&reports)
+            .filter(|(_, r)| r.is_none())
+            .map(|(w, _)| w.to_string())
+            .collect();
+        if waiting.is_empty() {
+            break;
+        }
+
+        info!(?waiting, "Waiting for workers' final reports");
+        tokio::select! {
+            _ = tokio::time::sleep(READY_INTERVAL) => {}
+            _ = ctrlc_rx.recv() => {
+                info!("got ctrl-c, not waiting for the rest");
+                break;
+            }
+        }
+    }
+
+    reports
+        .into_iter()
+        .map(|r| r.unwrap_or_else(|| Err(anyhow::anyhow!("run didn't finish"))))
+        .collect()
+}
+
+/// Returns the workers whose runs have ended, going by their heartbeats.
+/// Workers that can't be reached are left out; their reports will say what
+/// happened to them.
+async fn ended(client: &reqwest::Client, workers: &[Url]) -> Vec<String> {
+    let results =
+        broadcast(client, reqwest::Method::GET, workers, "healthz").await;
+    workers
+        .iter()
+        .zip(results)
+        .filter(|(_, r)| {
+            r.as_ref().is_ok_and(|heartbeat| heartbeat["running"] == false)
+        })
+        .map(|(w, _)| w.to_string())
+        .collect()
+}
+
+/// Logs the workers' combined progress.
+async fn log_progress(client: &reqwest::Client, workers: &[Url]) {
+    let results =
+        broadcast(client, reqwest::Method::GET, workers, "stats").await;
+    let mut totals = BTreeMap::new();
+    let mut reachable = 0;
+    for (worker, result) in workers.iter().zip(results) {
+        match result {
+            Ok(stats) => {
+                reachable += 1;
+                add_totals(&mut totals, stats.get("actors"));
+            }
+            Err(e) => warn!(%worker, "worker unreachable: {:#}", e),
+        }
+    }
+
+    for (kind, total) in totals {
+        info!(
+            kind,
+            steps = total.steps,
+            failures = total.failures,
+            retries = total.retries,
+            workers = reachable,
+            "Combined progress"
+        );
+    }
+}
+
+/// Runs the stress test across the workers in `args`, ending it after
+/// `--duration`, on Ctrl-C, or when any worker's run ends. Returns the exit
+/// code for how the run went, combined from the workers' own verdicts.
+pub async fn run(
+    args: &CoordinateArgs,
+    ctrlc_rx: &mut UnboundedReceiver<()>,
+) -> Result<ExitCode> {
+    let client = reqwest::Client::builder()
+        .timeout(Duration::from_secs(60))
+        .build()
+        .context("building HTTP client")?;
+    let workers = &args.workers;
+
+    // Wait for every worker to finish setting up.
+    loop {
+        let results =
+            broadcast(&client, reqwest::Method::GET, workers, "actors").await;
+        let waiting: Vec<_> = workers
+            .iter()
+            .zip(&results)
+            .filter(|(_, r)| r.is_err())
+            .map(|(w, _)| w.to_string())
+            .collect();
+        if waiting.is_empty() {
+            break;
+        }
+
+        info!(?waiting, "Waiting for workers");
+        tokio::select! {
+            _ = tokio::time::sleep(READY_INTERVAL) => {}
+            _ = ctrlc_rx.recv() => {
+                info!("got ctrl-c, stopping workers");
+                quit_all(&client, workers).await;
+                return Ok(outcome::exit_code(None, true).into());
+            }
+        }
+    }
+
+    info!(workers = workers.len(), "Starting workers");
+    let started_at = chrono::Utc::now();
+    let results =
+        broadcast(&client, reqwest::Method::POST, workers, "start").await;
+    if let Some(e) = results.into_iter().find_map(Result::err) {
+        quit_all(&client, workers).await;
+        return Err(e.context("starting workers"));
+    }
+
+    let deadline = crate::config().duration.map(|d| Instant::now() + d);
+    let progress_interval = crate::config().progress_interval;
+    let mut progress = tokio::time::interval_at(
+        Instant::now() + progress_interval,
+        progress_interval.max(Duration::from_millis(1)),
+    );
+    let mut check = tokio::time::interval_at(
+        Instant::now() + CHECK_INTERVAL,
+        CHECK_INTERVAL,
+    );
+    let interrupted = loop {
+        tokio::select! {
+            _ = crate::sleep_or_pend(deadline) => {
+                info!("run duration elapsed, stopping workers");
+                break false;
+            }
+
+            _ = ctrlc_rx.recv() => {
+                info!("got ctrl-c, stopping workers");
+                break true;
+            }
+
+            _ = progress.tick(), if !progress_interval.is_zero() => {
+                log_progress(&client, workers).await;
+            }
+
+            _ = check.tick() => {
+                let ended = ended(&client, workers).await;
+                if !ended.is_empty() {
+                    info!(?ended, "worker's run ended, stopping the others");
+                    break false;
+                }
+            }
+        }
+    };
+
+    // End the workers' runs the way this one ended, then fetch their final
+    // reports before they quit and take their control APIs with them.
+    let end = if interrupted { "quit" } else { "stop" };
+    let results = broadcast(&client, reqwest::Method::POST, workers, end).await;
+    for (worker, result) in workers.iter().zip(results) {
+        if let Err(e) = result {
+            warn!(%worker, "failed to stop worker: {:#}", e);
+        }
+    }
+    let reports = final_reports(&client, workers, ctrlc_rx).await;
+    quit_all(&client, workers).await;
+
+    let mut combined = CombinedReport {
+        started_at: started_at.to_rfc3339(),
+        duration_secs: (chrono::Utc::now() - started_at)
+            .to_std()
+            .unwrap_or_default()
+            .as_secs_f64(),
+        actors: BTreeMap::new(),
+        errors: 0,
+        failure: None,
+        exit_code: 0,
+        workers: Vec::new(),
+    };
+
+    // The run failed the way the first failed worker's did, or, if none
+    // failed, for lack of a worker's report.
+    let mut first_failure = None;
+    let mut missing_report = false;
+    let mut interrupted = interrupted;
+    for (worker, result) in workers.iter().zip(reports) {
+        match result {
+            Ok(report) => {
+                add_totals(&mut combined.actors, report.get("actors"));
+                let errors = report["errors"].as_array().map_or(0, Vec::len);
+                combined.errors += errors as u64;
+                first_failure = first_failure.or(failure(&report));
+                interrupted |= report["outcome"] == "interrupted";
+                combined.workers.push(WorkerReport {
+                    url: worker.to_string(),
+                    report: Some(report),
+                    error: None,
+                });
+            }
+            Err(e) => {
+                warn!(%worker, "failed to fetch worker's report: {:#}", e);
+                missing_report = true;
+                combined.workers.push(WorkerReport {
+                    url: worker.to_string(),
+                    report: None,
+                    error: Some(format!("{:#}", e)),
+                });
+            }
+        }
+    }
+
+    for (kind, total) in &combined.actors {
+        info!(
+            kind,
+            steps = total.steps,
+            failures = total.failures,
+            retries = total.retries,
+            "Combined totals"
+        );
+    }
+    info!(errors = combined.errors, "Workers' disqualifying errors");
+
+    combined.failure =
+        first_failure.or(missing_report.then_some(Failure::Infrastructure));
+    combined.exit_code = outcome::exit_code(combined.failure, interrupted);
+
+    if let Some(path) = &crate::config().report_json {
+        info!(path = %path.display(), "Writing combined report");
+        let json = serde_json::to_string_pretty(&combined)
+            .context("serializing report")?;
+        std::fs::write(path, json)
+            .with_context(|| format!("writing report to {}", path.display()))?;
+    }
+
+    info!(
+        failure = ?combined.failure,
+        exit_code = combined.exit_code,
+        "Coordinated run finished"
+    );
+    Ok(combined.exit_code.into())
+}
@@ -0,0 +1,123 @@
+//! Tracks the disqualifying errors a run has seen, so that a long run can ride
+//! out a few of them instead of stopping at the first one.
+
+use std::collections::VecDeque;
+use std::net::SocketAddr;
+use std::time::Duration;
+
+use tokio::time::Instant;
+
+use crate::actor::ErrorContext;
+use crate::outcome::Failure;
+
+/// How many disqualifying errors a run may see before it stops: more than
+/// `errors` in total, or more than `errors` within any `window` if there is
+/// one.
+#[derive(Clone, Copy, Debug)]
+pub struct Limit {
+    pub errors: usize,
+    pub window: Option<Duration>,
+}
+
+/// Parses an `N` or `N/window` error budget from the command line.
+pub fn parse_limit(s: &str) -> Result<Limit, String> {
+    let (errors, window) = match s.split_once('/') {
+        Some((errors, window)) => (errors, Some(window)),
+        None => (s, None),
+    };
+
+    let errors = errors
+        .parse()
+        .map_err(|e| format!("invalid error count {:?}: {}", errors, e))?;
+    let window = window
+        .map(|w| {
+            humantime::parse_duration(w)
+                .map_err(|e| format!("invalid window {:?}: {}", w, e))
+        })
+        .transpose()?;
+
+    Ok(Limit { errors, window })
+}
+
+/// A disqualifying error seen during the run.
+#[derive(Debug)]
+pub struct RecordedError {
+    /// How far into the run the error was seen.
+    pub elapsed: Duration,
+    pub description: String,
+
+    /// What kind of failure the error is.
+    pub failure: Failure,
+
+    /// What the actor that saw the error was doing, if an actor saw it.
+    pub context: Option<ErrorContext>,
+
+    /// The address the failed request connected to, if it's fixed.
+    pub peer: Option<SocketAddr>,
+}
+
+/// The errors seen so far and the limit on them.
+#[derive(Debug)]
+pub struct ErrorBudget {
+    limit: Limit,
+    start: Instant,
+
+    /// The times at which errors were seen, oldest first. If the limit has a
+    /// window, only the errors within the window are kept.
+    recent: VecDeque<Instant>,
+
+    /// Every error seen during the run, in order.
+    errors: Vec<RecordedError>,
+}
+
+impl ErrorBudget {
+    /// Creates a budget that allows no errors at all if `limit` is `None`.
+    pub fn new(limit: Option<Limit>) -> Self {
+        Self {
+            limit: limit.unwrap_or(Limit { errors: 0, window: None }),
+            start: Instant::now(),
+            recent: VecDeque::new(),
+            errors: Vec::new(),
+        }
+    }
+
+    /// Records a disqualifying error, a `failure` of that kind. Returns true if
+    /// the run has now seen more errors than its budget allows.
+    pub fn record(
+        &mut self,
+        failure: Failure,
+        description: String,
+        context: Option<ErrorContext>,
+        peer: Option<SocketAddr>,
+    ) -> bool {
+        let now = Instant::now();
+        self.errors.push(RecordedError {
+            elapsed: now - self.start,
+            description,
+            failure,
+            context,
+            peer,
+        });
+
+        self.recent.push_back(now);
+        if let Some(window) = self.limit.window {
+            while self.recent.front().is_some_and(|t| now - *t > window) {
+                self.recent.pop_front();
+            }
+        }
+
+        self.recent.len() > self.limit.errors
+    }
+
+    /// Returns true if the run has seen at least half as many errors as its
+    /// budget allows (within the budget's window, if it has one). Budgets of
+    /// fewer than two errors are never half spent.
+    pub fn half_spent(&self) -> bool {
+        self.limit.errors > 1 && self.recent.len() * 2 >= self.limit.errors
+    }
+
+    /// Returns every error recorded so far, in order.
+    pub fn errors(&self) -> &[RecordedError] {
+        &self.errors
+    }
+}
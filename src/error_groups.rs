@@ -0,0 +1,137 @@
+//! Groups the errors returned by the actors' API calls, expected or not, by
+//! endpoint, status code, and message pattern, so that a run's errors can be
+//! summarized as counts instead of being read off one log line at a time.
+//!
+//! A message's pattern is the message with the parts that vary from one
+//! resource to the next (quoted names, UUIDs, and numbers) blanked out, so
+//! that e.g. every "instance ... not found" error lands in the same group.
+
+use std::collections::BTreeMap;
+
+use serde::Serialize;
+use tracing::{info, warn};
+
+use crate::collector::Collector;
+use crate::util::OxideApiError;
+
+/// The errors seen in one group.
+#[derive(Clone, Debug, Serialize)]
+pub struct ErrorGroup {
+    pub endpoint: String,
+
+    /// The error response's status code, or `None` if there was no response.
+    pub status: Option<u16>,
+    pub pattern: String,
+    pub count: u64,
+
+    /// The request ID of the most recent error in this group, if it had one.
+    pub last_request_id: Option<String>,
+}
+
+type GroupKey = (String, Option<u16>, String);
+
+static GROUPS: Collector<BTreeMap<GroupKey, ErrorGroup>> = Collector::new();
+
+/// Returns `message` with quoted strings, UUIDs, and numbers replaced with
+/// placeholders.
+fn pattern(message: &str) -> String {
+    let mut out = String::with_capacity(message.len());
+    let mut chars = message.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '"' => {
+                // Skip to the closing quote, if there is one.
+                for c in chars.by_ref() {
+                    if c == '"' {
+                        break;
+                    }
+                }
+                out.push_str("\"…\"");
+            }
+            c if c.is_ascii_hexdigit() => {
+                // Take the whole word, then decide whether it's a number, a
+                // UUID, or just a word that starts with a hex digit.
+                let mut word = String::from(c);
+                while let Some(&c) = chars
+                    .peek()
+                    .filter(|c| c.is_ascii_alphanumeric() || **c == '-')
+                {
+                    word.push(c);
+                    chars.next();
+                }
+
+                if uuid::Uuid::parse_str(&word).is_ok() {
+                    out.push_str("<uuid>");
+                } else if word.chars().all(|c| c.is_ascii_digit()) {
+                    out.push_str("<n>");
+                } else {
+                    out.push_str(&word);
+                }
+            }
+            c => out.push(c),
+        }
+    }
+
+    out
+}
+
+/// Records an error returned by a call to `endpoint`.
+pub fn record(endpoint: &str, err: &OxideApiError) {
+    let (status, message, request_id) = match err {
+        oxide::Error::ErrorResponse(rv) => (
+            Some(rv.status().as_u16()),
+            rv.message.clone(),
+            Some(rv.request_id.clone()),
+        ),
+        err => (err.status().map(|s| s.as_u16()), err.to_string(), None),
+    };
+
+    let pattern = pattern(&message);
+    let mut groups = GROUPS.lock();
+    let group = groups
+        .entry((endpoint.to_owned(), status, pattern.clone()))
+        .or_insert_with(|| ErrorGroup {
+            endpoint: endpoint.to_owned(),
+            status,
+            pattern,
+            count: 0,
+            last_request_id: None,
+        });
+
+    group.count += 1;
+    if request_id.is_some() {
+        group.last_request_id = request_id;
+    }
+}
+
+/// Returns every error group seen so far, most common first.
+pub fn summary() -> Vec<ErrorGroup> {
+    let groups = GROUPS.lock();
+    let mut summary: Vec<_> = groups.values().cloned().collect();
+    summary.sort_by_key(|g| std::cmp::Reverse(g.count));
+    summary
+}
+
+/// Logs the error groups section of the end-of-run summary.
+pub fn log() {
+    let summary = summary();
+    if summary.is_empty() {
+        info!("No API errors");
+        return;
+    }
+
+    warn!(
+        groups = summary.len(),
+        "API errors by endpoint, status, and message"
+    );
+    for group in summary {
+        warn!(
+            endpoint = group.endpoint,
+            status = ?group.status,
+            count = group.count,
+            last_request_id = ?group.last_request_id,
+            "{}",
+            group.pattern
+        );
+    }
+}
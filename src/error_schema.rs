@@ -0,0 +1,173 @@
+//! Checks that error responses match the API's documented `Error` type, with
+//! a message and a request ID (the error code is optional), and keeps track of
+//! the ones that don't separately from ordinary failures.
+//!
+//! The SDK doesn't keep the status of a response whose body it couldn't parse,
+//! so an unparseable body counts as a malformed error body if it isn't JSON
+//! (e.g. an HTML error page from a proxy) or if it's a JSON object with any of
+//! the error type's fields. Other unparseable bodies are more likely to be
+//! successful responses the SDK doesn't understand, which aren't checked here.
+
+use std::collections::BTreeMap;
+
+use serde::Serialize;
+use tracing::{debug, warn};
+
+use crate::collector::Collector;
+use crate::util::OxideApiError;
+
+/// How much of a malformed body to keep as an example.
+const MAX_EXAMPLE_LEN: usize = 512;
+
+/// The malformed error responses seen from one endpoint with one problem.
+#[derive(Clone, Debug, Serialize)]
+pub struct MalformedErrors {
+    pub endpoint: String,
+
+    /// The responses' status code, if the SDK kept it.
+    pub status: Option<u16>,
+
+    /// What's wrong with the responses, e.g. `missing request ID`.
+    pub problem: String,
+    pub count: u64,
+
+    /// The most recent malformed body, truncated. Unparseable bodies are
+    /// prefixed with the parse error.
+    pub example: String,
+}
+
+type Key = (String, Option<u16>, String);
+
+static MALFORMED: Collector<BTreeMap<Key, MalformedErrors>> = Collector::new();
+
+/// Returns what's wrong with `err`'s body, and an example of the body, if
+/// `err` is a malformed error response.
+fn problem(err: &OxideApiError) -> Option<(Option<u16>, String, String)> {
+    // A body the chaos proxy cut short says nothing about Nexus's errors.
+    if crate::chaos_proxy::is_injected(err) {
+        return None;
+    }
+
+    match err {
+        oxide::Error::ErrorResponse(rv) => {
+            let mut missing = Vec::new();
+            if rv.message.is_empty() {
+                missing.push("message");
+            }
+            if rv.request_id.is_empty() {
+                missing.push("request ID");
+            }
+
+            (!missing.is_empty()).then(|| {
+                (
+                    Some(rv.status().as_u16()),
+                    format!("missing {}", missing.join(", ")),
+                    format!("{:?}", rv),
+                )
+            })
+        }
+
+        oxide::Error::InvalidResponsePayload(body, e) => {
+            let looks_like_error =
+                match serde_json::from_slice::<serde_json::Value>(body) {
+                    Ok(serde_json::Value::Object(fields)) => {
+                        ["error_code", "message", "request_id"]
+                            .iter()
+                            .any(|f| fields.contains_key(*f))
+                    }
+                    Ok(_) => false,
+                    Err(_) => true,
+                };
+
+            looks_like_error.then(|| {
+                (
+                    None,
+                    "unparseable body".to_owned(),
+                    format!("{}: {}", e, String::from_utf8_lossy(body)),
+                )
+            })
+        }
+
+        oxide::Error::UnexpectedResponse(response)
+            if !response.status().is_success() =>
+        {
+            Some((
+                Some(response.status().as_u16()),
+                "undocumented status".to_owned(),
+                String::new(),
+            ))
+        }
+
+        _ => None,
+    }
+}
+
+/// Returns what's wrong with `err` if it's a malformed error response, e.g.
+/// `missing request ID`.
+pub fn malformed(err: &OxideApiError) -> Option<String> {
+    problem(err).map(|(_, problem, _)| problem)
+}
+
+/// Checks an error returned by a call to `endpoint`, recording it if it's a
+/// malformed error response.
+pub fn record(endpoint: &str, err: &OxideApiError) {
+    let Some((status, problem, mut example)) = problem(err) else {
+        return;
+    };
+
+    if example.len() > MAX_EXAMPLE_LEN {
+        let mut end = MAX_EXAMPLE_LEN;
+        while !example.is_char_boundary(end) {
+            end -= 1;
+        }
+        example.truncate(end);
+    }
+
+    let mut malformed = MALFORMED.lock();
+    let entry = malformed
+        .entry((endpoint.to_owned(), status, problem.clone()))
+        .or_insert_with(|| MalformedErrors {
+            endpoint: endpoint.to_owned(),
+            status,
+            problem,
+            count: 0,
+            example: String::new(),
+        });
+
+    // Warn about the first of each kind, since the rest are likely the same.
+    entry.count += 1;
+    if entry.count == 1 {
+        warn!(
+            endpoint,
+            status = ?entry.status,
+            problem = entry.problem,
+            example,
+            "Malformed error response"
+        );
+    } else {
+        debug!(endpoint, problem = entry.problem, "malformed error response");
+    }
+    entry.example = example;
+}
+
+/// Returns the malformed error responses seen so far, most common first.
+pub fn summary() -> Vec<MalformedErrors> {
+    let malformed = MALFORMED.lock();
+    let mut summary: Vec<_> = malformed.values().cloned().collect();
+    summary.sort_by_key(|m| std::cmp::Reverse(m.count));
+    summary
+}
+
+/// Logs the malformed error section of the end-of-run summary.
+pub fn log() {
+    for m in summary() {
+        warn!(
+            endpoint = m.endpoint,
+            status = ?m.status,
+            count = m.count,
+            example = m.example,
+            "Malformed error responses: {}",
+            m.problem
+        );
+    }
+}
@@ -0,0 +1,59 @@
+//! Background requests sent at a fixed rate, alongside the actors' traffic
+//! (`--read-load` and `--bad-tokens`).
+//!
+//! Requests are sent on schedule whether or not earlier ones have returned, so
+//! a slow Nexus doesn't lower the offered load, up to a cap on how many may be
+//! outstanding at once. Requests that would exceed the cap are skipped.
+
+use std::future::Future;
+use std::sync::Arc;
+use std::time::Duration;
+
+use tokio::sync::Semaphore;
+use tokio::task::{JoinHandle, JoinSet};
+use tokio::time::MissedTickBehavior;
+
+/// Starts sending `per_second` requests per second, at most `max_in_flight`
+/// of them outstanding at once. `request` is called with the number of each
+/// request, counting from 0, to make it. `skipped` is called whenever a
+/// request is skipped for lack of room. Aborting the returned task stops the
+/// requests, including any still outstanding.
+///
+/// `per_second` must be one that `crate::rate_limit::parse_rps` accepts.
+pub fn start<F, Fut>(
+    per_second: f64,
+    max_in_flight: usize,
+    mut request: F,
+    skipped: fn(),
+) -> JoinHandle<()>
+where
+    F: FnMut(u64) -> Fut + Send + 'static,
+    Fut: Future<Output = ()> + Send + 'static,
+{
+    tokio::spawn(async move {
+        let slots = Arc::new(Semaphore::new(max_in_flight));
+        let mut requests = JoinSet::new();
+        let mut ticks =
+            tokio::time::interval(Duration::from_secs_f64(1.0 / per_second));
+        ticks.set_missed_tick_behavior(MissedTickBehavior::Skip);
+        let mut n = 0;
+        loop {
+            tokio::select! {
+                _ = ticks.tick() => {}
+                Some(_) = requests.join_next() => continue,
+            }
+
+            let Ok(slot) = slots.clone().try_acquire_owned() else {
+                skipped();
+                continue;
+            };
+
+            let future = request(n);
+            n += 1;
+            requests.spawn(async move {
+                future.await;
+                drop(slot);
+            });
+        }
+    })
+}
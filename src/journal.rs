@@ -0,0 +1,109 @@
+//! Keeps each actor's most recent operations, so that a failure artifact shows
+//! the sequence of steps that led up to an error and not just the call that
+//! failed.
+//!
+//! An operation is one step an actor took: the state it found its resource in
+//! and the action it picked (for antagonists that pick actions), the API calls
+//! it made and the responses they got, and how the step turned out.
+
+use std::collections::{BTreeMap, VecDeque};
+use std::fmt::Debug;
+use std::sync::{Mutex, OnceLock};
+use std::time::Duration;
+
+use serde::Serialize;
+
+use crate::request_log::Call;
+
+/// How many of each actor's most recent operations to remember.
+const JOURNAL_LEN: usize = 16;
+
+/// One step an actor took.
+#[derive(Clone, Debug, Default, Serialize)]
+pub struct Operation {
+    /// When the step finished, in RFC 3339 format.
+    pub time: String,
+
+    /// The state the actor found its resource in before picking its action,
+    /// if it looked.
+    pub state: Option<String>,
+
+    /// The action the actor picked, if it picks actions.
+    pub action: Option<String>,
+
+    /// The API calls the step made, oldest first.
+    pub calls: Vec<Call>,
+    pub elapsed_ms: f64,
+
+    /// The step's error, or `None` if it succeeded.
+    pub error: Option<String>,
+}
+
+/// What's known about each actor's operations, keyed by actor name.
+#[derive(Default)]
+struct Journal {
+    /// The step the actor is taking now.
+    current: Operation,
+
+    /// The actor's most recent finished steps, oldest first.
+    finished: VecDeque<Operation>,
+}
+
+static JOURNALS: OnceLock<Mutex<BTreeMap<String, Journal>>> = OnceLock::new();
+
+/// Calls `f` with the journal of the current actor, if this is an actor task.
+fn with_current(f: impl FnOnce(&mut Journal)) {
+    let Some(actor) = crate::actor::current_actor() else {
+        return;
+    };
+
+    let mut journals = JOURNALS.get_or_init(Default::default).lock().unwrap();
+    f(journals.entry(actor.name).or_default());
+}
+
+/// Records that the current actor found its resource in `state`, if it
+/// looked, and picked `action`.
+pub fn note_action(action: &dyn Debug, state: Option<&dyn Debug>) {
+    with_current(|journal| {
+        journal.current.action = Some(format!("{:?}", action));
+        journal.current.state = state.map(|s| format!("{:?}", s));
+    });
+}
+
+/// Records that the current actor made `call` as part of its current step.
+pub fn note_call(call: &Call) {
+    with_current(|journal| journal.current.calls.push(call.clone()));
+}
+
+/// Records that the current actor's step finished after `elapsed`, with
+/// `error` if it failed.
+pub fn finish(elapsed: Duration, error: Option<String>) {
+    with_current(|journal| {
+        let operation = Operation {
+            time: chrono::Utc::now().to_rfc3339(),
+            elapsed_ms: elapsed.as_secs_f64() * 1000.0,
+            error,
+            ..std::mem::take(&mut journal.current)
+        };
+        if journal.finished.len() == JOURNAL_LEN {
+            journal.finished.pop_front();
+        }
+        journal.finished.push_back(operation);
+    });
+}
+
+/// Drops what the current actor noted about a step that was cancelled, since
+/// it didn't really happen.
+pub fn discard() {
+    with_current(|journal| journal.current = Operation::default());
+}
+
+/// Returns the most recent operations of the actor named `actor`, oldest
+/// first.
+pub fn recent(actor: &str) -> Vec<Operation> {
+    let journals = JOURNALS.get_or_init(Default::default).lock().unwrap();
+    journals
+        .get(actor)
+        .map(|journal| journal.finished.iter().cloned().collect())
+        .unwrap_or_default()
+}
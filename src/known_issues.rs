@@ -0,0 +1,142 @@
+//! The known-issue allowlist (`--known-issues`), which keeps errors caused by
+//! bugs that have already been filed from ending a run, so that a run meant to
+//! exercise one area doesn't keep dying on a known bug in another.
+//!
+//! The allowlist is a TOML file listing error signatures:
+//!
+//! ```toml
+//! [[issue]]
+//! name = "omicron#1234"
+//! endpoint = "instance_stop"
+//! status = 500
+//! message = "^unexpected database error"
+//! ```
+//!
+//! An error matches an issue if it matches every field the issue sets.
+//! `message` is a regular expression matched against the error response's
+//! message, or against the whole error for errors other than error responses.
+//! Errors other than API errors have no endpoint or status, so they can only
+//! match issues that don't set those fields. Matching errors are logged and
+//! counted per issue, but don't count against the error budget.
+
+use std::collections::BTreeMap;
+use std::path::Path;
+use std::sync::OnceLock;
+
+use anyhow::{Context, Result};
+use regex::Regex;
+use serde::Deserialize;
+use tracing::info;
+
+use crate::actor::AntagonistError;
+use crate::collector::Collector;
+
+/// An allowlist file.
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct Allowlist {
+    #[serde(default, rename = "issue")]
+    issues: Vec<IssueSpec>,
+}
+
+/// One issue as written in the allowlist file.
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct IssueSpec {
+    name: String,
+    endpoint: Option<String>,
+    status: Option<u16>,
+    message: Option<String>,
+}
+
+/// A known issue's error signature.
+struct KnownIssue {
+    /// The issue's name, e.g. the bug it's filed as.
+    name: String,
+    endpoint: Option<String>,
+    status: Option<u16>,
+    message: Option<Regex>,
+}
+
+static ISSUES: OnceLock<Vec<KnownIssue>> = OnceLock::new();
+
+/// How many errors have matched each issue, keyed by issue name.
+static COUNTS: Collector<BTreeMap<String, u64>> = Collector::new();
+
+/// Loads the allowlist at `path`.
+pub fn load(path: &Path) -> Result<()> {
+    let contents = std::fs::read_to_string(path)
+        .with_context(|| format!("reading {}", path.display()))?;
+    let allowlist: Allowlist = toml::from_str(&contents)
+        .with_context(|| format!("parsing {}", path.display()))?;
+
+    let mut issues = Vec::with_capacity(allowlist.issues.len());
+    for spec in allowlist.issues {
+        anyhow::ensure!(
+            spec.endpoint.is_some()
+                || spec.status.is_some()
+                || spec.message.is_some(),
+            "known issue {} would match every error",
+            spec.name
+        );
+
+        let message = spec
+            .message
+            .map(|m| Regex::new(&m))
+            .transpose()
+            .with_context(|| format!("known issue {}", spec.name))?;
+
+        issues.push(KnownIssue {
+            name: spec.name,
+            endpoint: spec.endpoint,
+            status: spec.status,
+            message,
+        });
+    }
+
+    info!(count = issues.len(), "Loaded known issues");
+    ISSUES
+        .set(issues)
+        .map_err(|_| anyhow::anyhow!("known issues already loaded"))
+}
+
+/// Returns the name of the known issue that `err`, which the actor named
+/// `actor` hit, matches, if it matches one, and counts the match.
+pub fn check(actor: &str, err: &AntagonistError) -> Option<String> {
+    let issues = ISSUES.get()?;
+
+    let (status, message) = match err {
+        AntagonistError::ApiError(oxide::Error::ErrorResponse(rv)) => {
+            (Some(rv.status().as_u16()), rv.message.clone())
+        }
+        AntagonistError::ApiError(e) => {
+            (e.status().map(|s| s.as_u16()), e.to_string())
+        }
+        err => (None, err.to_string()),
+    };
+    let endpoint =
+        crate::request_log::failed_call(actor, err).map(|call| call.endpoint);
+
+    let issue = issues.iter().find(|issue| {
+        issue.endpoint.as_ref().map_or(true, |e| Some(e) == endpoint.as_ref())
+            && issue.status.map_or(true, |s| Some(s) == status)
+            && issue.message.as_ref().map_or(true, |m| m.is_match(&message))
+    })?;
+
+    let mut counts = COUNTS.lock();
+    *counts.entry(issue.name.clone()).or_default() += 1;
+    Some(issue.name.clone())
+}
+
+/// Returns how many errors have matched each known issue so far, keyed by
+/// issue name.
+pub fn summary() -> BTreeMap<String, u64> {
+    COUNTS.snapshot()
+}
+
+/// Logs the known issues section of the end-of-run summary.
+pub fn log() {
+    for (issue, count) in summary() {
+        info!(issue, count, "Known issue hit");
+    }
+}
@@ -0,0 +1,288 @@
+//! The leak report, which lists the resources left in the stress project after
+//! the actors halt that a clean end state wouldn't have.
+//!
+//! In a clean end state, every resource has settled (instances aren't stuck
+//! starting or stopping, disks aren't stuck attaching, and so on) and nothing
+//! has failed. If the actors drained their resources on the way out, the only
+//! resources left are the baseline ones. The harness never creates floating
+//! IPs, so any floating IP in the project counts as leaked.
+//!
+//! With `--leak-audit`, the same check also runs periodically while the actors
+//! are still going. Their resources are busy then, so an audit only reports a
+//! resource that the previous audit also found, in the same state.
+
+use std::collections::{HashMap, HashSet};
+use std::sync::{Mutex, OnceLock};
+use std::time::Duration;
+
+use anyhow::{Context, Result};
+use chrono::{DateTime, Utc};
+use futures::TryStreamExt;
+use oxide::types::{DiskState, InstanceState, SnapshotState};
+use oxide::{
+    ClientDisksExt, ClientFloatingIpsExt, ClientImagesExt, ClientInstancesExt,
+    ClientSnapshotsExt,
+};
+use tracing::{info, warn};
+use uuid::Uuid;
+
+use crate::actor::ownership::ResourceKind;
+use crate::populate::is_baseline;
+
+/// A resource left in the stress project that shouldn't be there, or that's
+/// in a state it shouldn't be in.
+#[derive(Clone, Debug)]
+pub struct LeakedResource {
+    /// The kind of resource, e.g. `instance` or `floating IP`.
+    pub kind: &'static str,
+    pub name: String,
+    pub id: Uuid,
+    pub state: String,
+
+    /// How long ago the resource was created.
+    pub age: Duration,
+
+    /// Why the resource counts as leaked.
+    pub reason: &'static str,
+}
+
+/// Lists the resources in `project` that differ from a clean end state.
+/// `drained` says whether the actors were asked to delete their resources
+/// when they halted.
+pub async fn find(
+    client: &oxide::Client,
+    project: &str,
+    drained: bool,
+) -> Result<Vec<LeakedResource>> {
+    let now = Utc::now();
+    let age = |created: DateTime<Utc>| {
+        (now - created).to_std().unwrap_or(Duration::ZERO)
+    };
+
+    // A settled resource only counts as leaked if it isn't part of the
+    // baseline and the actors should have deleted it.
+    let leftover = |kind: ResourceKind, name: &str| {
+        (drained && !is_baseline(kind, name)).then_some("not drained")
+    };
+
+    let mut leaks = Vec::new();
+
+    let instances: Vec<_> = client
+        .instance_list()
+        .project(project)
+        .stream()
+        .try_collect()
+        .await
+        .context("listing instances")?;
+
+    for instance in instances {
+        let reason = match instance.run_state {
+            InstanceState::Running | InstanceState::Stopped => {
+                leftover(ResourceKind::Instance, &instance.name)
+            }
+            InstanceState::Failed => Some("failed"),
+            _ => Some("stuck"),
+        };
+
+        if let Some(reason) = reason {
+            leaks.push(LeakedResource {
+                kind: "instance",
+                name: instance.name.to_string(),
+                id: instance.id,
+                state: format!("{:?}", instance.run_state),
+                age: age(instance.time_created),
+                reason,
+            });
+        }
+    }
+
+    let disks: Vec<_> = client
+        .disk_list()
+        .project(project)
+        .stream()
+        .try_collect()
+        .await
+        .context("listing disks")?;
+
+    for disk in disks {
+        let reason = match disk.state {
+            DiskState::Detached | DiskState::Attached { .. } => {
+                leftover(ResourceKind::Disk, &disk.name)
+            }
+            DiskState::Faulted => Some("failed"),
+            _ => Some("stuck"),
+        };
+
+        if let Some(reason) = reason {
+            leaks.push(LeakedResource {
+                kind: "disk",
+                name: disk.name.to_string(),
+                id: disk.id,
+                state: format!("{:?}", disk.state),
+                age: age(disk.time_created),
+                reason,
+            });
+        }
+    }
+
+    let snapshots: Vec<_> = client
+        .snapshot_list()
+        .project(project)
+        .stream()
+        .try_collect()
+        .await
+        .context("listing snapshots")?;
+
+    for snapshot in snapshots {
+        let reason = match snapshot.state {
+            SnapshotState::Ready => {
+                leftover(ResourceKind::Snapshot, &snapshot.name)
+            }
+            SnapshotState::Faulted => Some("failed"),
+            _ => Some("stuck"),
+        };
+
+        if let Some(reason) = reason {
+            leaks.push(LeakedResource {
+                kind: "snapshot",
+                name: snapshot.name.to_string(),
+                id: snapshot.id,
+                state: format!("{:?}", snapshot.state),
+                age: age(snapshot.time_created),
+                reason,
+            });
+        }
+    }
+
+    let images: Vec<_> = client
+        .image_list()
+        .project(project)
+        .stream()
+        .try_collect()
+        .await
+        .context("listing images")?;
+
+    for image in images {
+        if let Some(reason) = leftover(ResourceKind::Image, &image.name) {
+            leaks.push(LeakedResource {
+                kind: "image",
+                name: image.name.to_string(),
+                id: image.id,
+                state: "-".to_string(),
+                age: age(image.time_created),
+                reason,
+            });
+        }
+    }
+
+    let floating_ips: Vec<_> = client
+        .floating_ip_list()
+        .project(project)
+        .stream()
+        .try_collect()
+        .await
+        .context("listing floating IPs")?;
+
+    for ip in floating_ips {
+        let state = match ip.instance_id {
+            Some(instance) => format!("{} attached to {}", ip.ip, instance),
+            None => format!("{} unattached", ip.ip),
+        };
+
+        leaks.push(LeakedResource {
+            kind: "floating IP",
+            name: ip.name.to_string(),
+            id: ip.id,
+            state,
+            age: age(ip.time_created),
+            reason: "unexpected",
+        });
+    }
+
+    Ok(leaks)
+}
+
+/// What the periodic leak audits have found.
+#[derive(Default)]
+struct Audits {
+    /// The state of each resource the last audit found, by ID.
+    last: HashMap<Uuid, String>,
+
+    /// The resources reported so far, and their IDs.
+    reported: Vec<LeakedResource>,
+    reported_ids: HashSet<Uuid>,
+}
+
+static AUDITS: OnceLock<Mutex<Audits>> = OnceLock::new();
+
+fn audits() -> &'static Mutex<Audits> {
+    AUDITS.get_or_init(Default::default)
+}
+
+/// Looks for leaks in `project` while the actors are running, and reports
+/// each resource that's been in the same unsettled or failed state since the
+/// last audit.
+pub async fn audit(client: &oxide::Client, project: &str) {
+    let found = match find(client, project, false).await {
+        Ok(found) => found,
+        Err(e) => {
+            warn!("leak audit failed: {:#}", e);
+            return;
+        }
+    };
+
+    let mut audits = audits().lock().unwrap();
+    let last = std::mem::take(&mut audits.last);
+    for leak in found {
+        audits.last.insert(leak.id, leak.state.clone());
+        if last.get(&leak.id) != Some(&leak.state)
+            || !audits.reported_ids.insert(leak.id)
+        {
+            continue;
+        }
+
+        warn!(
+            kind = leak.kind,
+            name = %leak.name,
+            id = %leak.id,
+            state = %leak.state,
+            reason = leak.reason,
+            "Leak audit found a resource stuck since the last audit"
+        );
+        audits.reported.push(leak);
+    }
+
+    info!(
+        resources = audits.last.len(),
+        reported = audits.reported.len(),
+        "Leak audit done"
+    );
+}
+
+/// Returns the resources the periodic leak audits have reported so far.
+pub fn audit_summary() -> Vec<LeakedResource> {
+    audits().lock().unwrap().reported.clone()
+}
+
+/// Logs the leaked resources section of the end-of-run summary.
+pub fn log(leaks: &[LeakedResource]) {
+    if leaks.is_empty() {
+        info!("No leaked resources");
+        return;
+    }
+
+    warn!(count = leaks.len(), "Leaked resources");
+    for leak in leaks {
+        warn!(
+            kind = leak.kind,
+            name = %leak.name,
+            id = %leak.id,
+            state = %leak.state,
+            age = %humantime::format_duration(Duration::from_secs(
+                leak.age.as_secs()
+            )),
+            reason = leak.reason,
+            "Leaked resource"
+        );
+    }
+}
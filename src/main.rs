@@ -1,90 +1,751 @@
-use std::{net::Ipv4Addr, sync::OnceLock};
+use std::{net::Ipv4Addr, process::ExitCode, sync::OnceLock, time::Duration};
 
-use actor::{disk, instance, snapshot, ActorKind};
 use anyhow::{Context, Result};
-use clap::Parser;
-use futures::stream::FuturesUnordered;
 use oxide::{
     builder::ProjectView,
     types::{IpRange, Ipv4Range, Name, ProjectCreate},
     ClientProjectsExt, ClientSystemNetworkingExt,
 };
-use tracing::{error, info};
+use rand::seq::SliceRandom;
+use tokio::signal::unix::{signal, SignalKind};
+use tokio::sync::mpsc;
+use tokio::task::JoinHandle;
+use tokio::time::Instant;
+use tracing::{error, info, warn};
+use tracing_subscriber::fmt::{format, FmtContext, FormatEvent, FormatFields};
 use tracing_subscriber::layer::SubscriberExt;
+use tracing_subscriber::registry::LookupSpan;
 
 mod actor;
+mod artifacts;
+mod bad_tokens;
+mod chaos_proxy;
+mod cleanup;
 mod client;
+mod collector;
 mod config;
+mod control;
+mod coordinator;
+mod error_budget;
+mod error_groups;
+mod error_schema;
+mod fixed_rate;
+mod journal;
+mod known_issues;
+mod leaks;
+mod maintenance;
+mod manifest;
+mod middleware;
+mod mock_nexus;
+mod notify;
+mod outcome;
+mod populate;
+mod profile;
+mod rate_limit;
+mod read_load;
+mod repl;
+mod report;
+mod request_log;
+mod resources;
+mod retry;
+mod run_info;
+mod sla;
+mod slow_requests;
+mod stats;
+mod status;
+mod status_counts;
+mod status_policy;
+mod supervisor;
+mod throttle;
 mod util;
+mod utilization;
+mod workload;
 
-use actor::AntagonistError;
-use util::fail_if_500;
-use util::fail_if_no_response;
+use actor::{ActorError, AntagonistError};
 
 /// The global command-line configuration for a stress runner instance.
 pub static CONFIG: OnceLock<config::Config> = OnceLock::new();
 
-/// The stress test project name. In the future the harness can be expanded to
-/// have actors that create and destroy projects, but for now the harness
-/// focuses on instances.
+/// The default stress test project name, before the run's name prefix is
+/// applied. In the future the harness can be expanded to have actors that
+/// create and destroy projects, but for now the harness focuses on instances.
 const PROJECT_NAME: &str = "omicron-stress";
 
-/// Creates the harness's test project and ensures that there are external IPs
-/// in its IP pool.
-async fn create_test_project(client: &oxide::Client) -> Result<()> {
-    info!("Checking for existing stress project");
-    if ProjectView::new(client).project(PROJECT_NAME).send().await.is_ok() {
+/// Returns the name of this run's stress test project.
+fn project_name() -> String {
+    match &config().project {
+        Some(project) => project.clone(),
+        None => format!("{}{}", util::name_prefix(), PROJECT_NAME),
+    }
+}
+
+/// The first and last addresses of the IP range the harness adds to its IP
+/// pool if the pool is empty and --ip-range isn't set.
+const DEFAULT_IP_RANGE: (Ipv4Addr, Ipv4Addr) =
+    (Ipv4Addr::new(168, 254, 1, 100), Ipv4Addr::new(168, 254, 1, 110));
+
+/// Returns the first and last addresses of the IP range the harness adds to
+/// its IP pool.
+fn ip_range() -> (Ipv4Addr, Ipv4Addr) {
+    config().ip_range.unwrap_or(DEFAULT_IP_RANGE)
+}
+
+/// Returns true if `range` runs from `first` to `last`.
+fn is_ip_range(range: &IpRange, (first, last): (Ipv4Addr, Ipv4Addr)) -> bool {
+    matches!(range, IpRange::V4(r) if r.first == first && r.last == last)
+}
+
+/// Creates the harness's test project and, unless --no-ip-pool-setup is set,
+/// ensures that there are external IPs in its IP pool. Returns true if it added
+/// the harness's IP range to the pool, as opposed to finding IPs there.
+async fn create_test_project(client: &oxide::Client) -> Result<bool> {
+    let project = project_name();
+    info!(project, "Checking for existing stress project");
+    if ProjectView::new(client).project(&project).send().await.is_ok() {
         info!("Project already exists");
+    } else if config().no_create_project {
+        anyhow::bail!(
+            "project {} doesn't exist and --no-create-project is set",
+            project
+        );
     } else {
         info!("Stress project doesn't exist, creating it");
         let body = ProjectCreate {
-            name: Name::try_from(PROJECT_NAME.to_owned()).unwrap(),
-            description: "Omicron stress".to_owned(),
+            name: Name::try_from(&project).map_err(|e| {
+                anyhow::anyhow!("invalid project name {:?}: {}", project, e)
+            })?,
+            description: run_info::description("Omicron stress"),
         };
-        client.project_create().body(body).send().await?;
+        let created = client.project_create().body(body).send().await?;
+        manifest::record_response("project", &created);
         info!("Successfully created test project!");
     }
 
-    info!("Checking for IPs in default IP pool");
+    if config().no_ip_pool_setup {
+        info!("Not setting up IP pool");
+        return Ok(false);
+    }
+
+    // Add the configured range to the pool unless it's already there. If no
+    // range was configured, only add the default range to an empty pool.
+    let pool = &config().ip_pool;
+    info!(pool, "Checking for IPs in IP pool");
     let ranges =
-        client.ip_pool_range_list().pool("default").send().await?.into_inner();
-    if ranges.items.is_empty() {
-        info!("No IPs found in pool, adding some");
-        let range = IpRange::V4(Ipv4Range {
-            first: Ipv4Addr::new(168, 254, 1, 100),
-            last: Ipv4Addr::new(168, 254, 1, 110),
-        });
-        client.ip_pool_range_add().pool("default").body(range).send().await?;
+        client.ip_pool_range_list().pool(pool).send().await?.into_inner();
+    if ranges.items.iter().any(|r| is_ip_range(&r.range, ip_range())) {
+        info!("IP pool already has the stress IP range");
+        Ok(false)
+    } else if config().ip_range.is_none() && !ranges.items.is_empty() {
+        info!("IP pool has IPs, won't add any");
+        Ok(false)
+    } else {
+        let (first, last) = ip_range();
+        info!(%first, %last, "Adding IPs to pool");
+        let range = IpRange::V4(Ipv4Range { first, last });
+        client.ip_pool_range_add().pool(pool).body(range).send().await?;
         info!("Added IPs to pool");
+        Ok(true)
+    }
+}
+
+/// The task that forwards an actor's errors to the main loop, or `None` if the
+/// actor is driven by the worker pool, which forwards its errors itself.
+type Forwarder = Option<JoinHandle<()>>;
+
+/// Creates and starts the actors for the phase with the supplied `index`,
+/// spreading their start times over `ramp_up`. Returns the actors and the tasks
+/// that forward their errors to `error_tx`.
+fn start_phase(
+    index: usize,
+    phase: &workload::Phase,
+    ramp_up: Duration,
+    error_tx: &mpsc::Sender<ActorError>,
+) -> Result<(Vec<actor::Actor>, Vec<Forwarder>)> {
+    info!(
+        phase = index,
+        name = phase.name.as_deref().unwrap_or(""),
+        duration = ?phase.duration,
+        "Starting workload phase"
+    );
+
+    // If ramping up, give each actor a start time spread evenly across the
+    // ramp-up period. Shuffle the start times so that each kind of actor
+    // ramps up at the same pace.
+    let mut specs =
+        phase.actors(&project_name(), util::name_prefix(), config().profile);
+    specs.retain(|(_, kind)| config().actor_kind_enabled(kind.name()));
+    let mut slots: Vec<usize> = (0..specs.len()).collect();
+    slots.shuffle(&mut util::actor_rng("ramp-up"));
+    let num_actors = specs.len();
+
+    let mut actors = Vec::new();
+    let mut forwarders = Vec::new();
+    for ((name, kind), slot) in specs.into_iter().zip(slots) {
+        let start_delay = ramp_up.mul_f64(slot as f64 / num_actors as f64);
+        let (actor, forwarder) =
+            spawn_actor(name, kind, start_delay, error_tx)?;
+        actors.push(actor);
+        forwarders.push(forwarder);
+    }
+
+    Ok((actors, forwarders))
+}
+
+/// Creates and starts an actor that takes its first step after `start_delay`.
+/// Returns the actor and the task that forwards its errors to `error_tx`, or
+/// `None` if the actor is driven by the worker pool, which forwards its errors
+/// itself.
+fn spawn_actor(
+    name: String,
+    kind: actor::ActorKind,
+    start_delay: Duration,
+    error_tx: &mpsc::Sender<ActorError>,
+) -> Result<(actor::Actor, Forwarder)> {
+    let (actor, error_ch) = actor::Actor::new(name, kind, start_delay)?;
+    let Some(mut error_ch) = error_ch else {
+        return Ok((actor, None));
+    };
+
+    let context = actor.error_context();
+    let error_tx = error_tx.clone();
+    let forwarder = tokio::spawn(async move {
+        loop {
+            match error_ch.recv().await {
+                Some(e) => {
+                    let _ = error_tx.send(e).await;
+                }
+
+                None => {
+                    let error = AntagonistError::DisconnectedErrorChannel {
+                        name: context.actor.clone(),
+                    };
+                    let _ = error_tx.send(ActorError { error, context }).await;
+                    break;
+                }
+            }
+        }
+    });
+
+    Ok((actor, Some(forwarder)))
+}
+
+/// Replaces the actor at `index`, whose task has died or stalled, with a new
+/// one made from the same spec in `phase`. The new actor starts after the
+/// supervisor's backoff for it, and is paused if `paused` is true.
+async fn restart_actor(
+    index: usize,
+    phase: &workload::Phase,
+    actors: &mut Vec<actor::Actor>,
+    forwarders: &mut Vec<Forwarder>,
+    error_tx: &mpsc::Sender<ActorError>,
+    paused: bool,
+) -> Result<()> {
+    // Stop forwarding the old actor's errors first, so that its task ending
+    // isn't also reported as its error channel disconnecting.
+    if let Some(forwarder) = forwarders.remove(index) {
+        forwarder.abort();
+    }
+    let old = actors.remove(index);
+    let (name, kind, finished) =
+        (old.name().to_owned(), old.kind(), old.is_finished());
+    let result = old.abort().await;
+    let reason = if finished {
+        supervisor::describe_exit(result)
     } else {
-        info!("Default IP pool has IPs, won't add any");
+        let (_, in_flight) = status::liveness(&name);
+        match in_flight {
+            Some((endpoint, waited)) => format!(
+                "stalled waiting {:?} for a call to {}",
+                waited, endpoint
+            ),
+            None => "stalled".to_owned(),
+        }
+    };
+
+    let start_delay = supervisor::record(&name, kind, reason);
+    let (mut actor, forwarder) =
+        respawn_actor(name, phase, start_delay, error_tx)?;
+    if paused {
+        actor.pause().await;
     }
+    actors.insert(index, actor);
+    forwarders.insert(index, forwarder);
+    Ok(())
+}
 
+/// Kills the actor at `index` on purpose (--crash-actors), wherever it is in
+/// its step, and replaces it with a new one made from the same spec in `phase`
+/// that starts right away.
+async fn crash_actor(
+    index: usize,
+    phase: &workload::Phase,
+    actors: &mut Vec<actor::Actor>,
+    forwarders: &mut Vec<Forwarder>,
+    error_tx: &mpsc::Sender<ActorError>,
+) -> Result<()> {
+    if let Some(forwarder) = forwarders.remove(index) {
+        forwarder.abort();
+    }
+    let old = actors.remove(index);
+    let (name, kind) = (old.name().to_owned(), old.kind());
+    let _ = old.abort().await;
+    supervisor::record_crash(&name, kind);
+    let (actor, forwarder) =
+        respawn_actor(name, phase, Duration::ZERO, error_tx)?;
+    actors.insert(index, actor);
+    forwarders.insert(index, forwarder);
     Ok(())
 }
 
+/// Makes and starts a new actor named `name` from its spec in `phase`, which
+/// takes its first step after `start_delay`.
+fn respawn_actor(
+    name: String,
+    phase: &workload::Phase,
+    start_delay: Duration,
+    error_tx: &mpsc::Sender<ActorError>,
+) -> Result<(actor::Actor, Forwarder)> {
+    let (_, spec) = phase
+        .actors(&project_name(), util::name_prefix(), config().profile)
+        .into_iter()
+        .find(|(spec_name, _)| *spec_name == name)
+        .with_context(|| format!("no actor named {} in this phase", name))?;
+    spawn_actor(name, spec, start_delay, error_tx)
+}
+
+/// Returns the index of the first actor in `actors` that has been working on
+/// a step for longer than `timeout`, if there is one. Paused actors aren't
+/// working on anything, so they never count as stalled.
+fn find_stalled_actor(
+    actors: &[actor::Actor],
+    timeout: Duration,
+) -> Option<usize> {
+    let now = std::time::Instant::now();
+    actors.iter().position(|a| {
+        let (last_step, _) = status::liveness(a.name());
+        let live_since =
+            last_step.map_or(a.live_since(), |at| at.max(a.live_since()));
+        !a.is_paused() && now.saturating_duration_since(live_since) > timeout
+    })
+}
+
+/// Halts the actors from a finished phase and waits for them to stop.
+async fn stop_phase(actors: Vec<actor::Actor>, forwarders: Vec<Forwarder>) {
+    // Stop forwarding these actors' errors first, so that halting them isn't
+    // reported as their error channels disconnecting.
+    for forwarder in forwarders.into_iter().flatten() {
+        forwarder.abort();
+    }
+
+    let mut halting = Vec::new();
+    for a in actors {
+        let name = a.name().to_owned();
+        halting.push((name, a.halt().await));
+    }
+
+    supervisor::join(halting).await;
+}
+
+/// Returns `err` if it should count against the run's error budget, or `None`
+/// if it's an expected kind of error.
+fn disqualifying_error(err: AntagonistError) -> Option<AntagonistError> {
+    match err {
+        AntagonistError::ApiError(err) if chaos_proxy::is_injected(&err) => {
+            chaos_proxy::record_surfaced();
+            None
+        }
+
+        AntagonistError::ApiError(err) => {
+            status_policy::check(err).err().map(AntagonistError::ApiError)
+        }
+
+        AntagonistError::InvalidState(_)
+        | AntagonistError::Unreachable(_)
+        | AntagonistError::CheckFailed(_)
+        | AntagonistError::DisconnectedErrorChannel { .. } => Some(err),
+    }
+}
+
+/// Returns a description of a disqualifying error for the logs and report.
+/// Error responses are summed up by their status and message, since their
+/// request IDs and the rest are in the error's context.
+fn describe_error(err: &AntagonistError) -> String {
+    match err {
+        AntagonistError::ApiError(oxide::Error::ErrorResponse(rv)) => {
+            match &rv.error_code {
+                Some(code) => {
+                    format!("{} ({}): {}", rv.status(), code, rv.message)
+                }
+                None => format!("{}: {}", rv.status(), rv.message),
+            }
+        }
+        AntagonistError::ApiError(err) => format!("{:?}", err),
+        err => err.to_string(),
+    }
+}
+
+/// Writes a failure artifact bundle for `err`, which the actor named `actor`
+/// hit, if --artifact-dir is set.
+async fn write_artifacts(
+    client: &oxide::Client,
+    actor: &str,
+    err: &AntagonistError,
+) {
+    let Some(dir) = &config().artifact_dir else {
+        return;
+    };
+
+    match artifacts::write(dir, client, &project_name(), actor, err).await {
+        Ok(path) => info!(path = %path.display(), "Wrote failure artifacts"),
+        Err(e) => warn!("failed to write failure artifacts: {:#}", e),
+    }
+}
+
+/// Returns true if `err` suggests that Nexus isn't reachable at all, as opposed
+/// to reachable but unhappy.
+fn is_unavailability(err: &AntagonistError) -> bool {
+    matches!(
+        err,
+        AntagonistError::ApiError(oxide::Error::CommunicationError(_))
+    )
+}
+
+/// Pauses every actor, waits for Nexus to start responding to requests again,
+/// and resumes the actors. Fails if Nexus doesn't respond within `limit`.
+async fn ride_out_outage(
+    client: &oxide::Client,
+    actors: &mut [actor::Actor],
+    limit: Duration,
+) -> Result<()> {
+    let start = Instant::now();
+    warn!("Nexus appears to be unavailable, pausing actors");
+    actor::pause_all(actors.iter_mut()).await;
+
+    // Any response at all, even an error, means Nexus is back.
+    let project = project_name();
+    let result = loop {
+        match client.project_view().project(&project).send().await {
+            Ok(_) | Err(oxide::Error::ErrorResponse(_)) => break Ok(()),
+            Err(e) if start.elapsed() >= limit => {
+                break Err(anyhow::anyhow!(
+                    "Nexus unavailable for more than {:?}: {}",
+                    limit,
+                    e
+                ));
+            }
+            Err(e) => {
+                info!(error = %e, "Nexus still unavailable");
+                tokio::time::sleep(Duration::from_secs(1)).await;
+            }
+        }
+    };
+
+    if result.is_ok() {
+        info!(outage = ?start.elapsed(), "Nexus is back, resuming actors");
+    }
+
+    actor::resume_all(actors.iter_mut()).await;
+    result
+}
+
+/// Carries out `command`, a control API command, on `actors`. Halted actors
+/// are removed along with their error `forwarders`, and their tasks are added
+/// to `halted`, tagged with their names.
+async fn control(
+    command: control::Command,
+    actors: &mut Vec<actor::Actor>,
+    forwarders: &mut Vec<Forwarder>,
+    halted: &mut Vec<(String, JoinHandle<()>)>,
+) -> control::Reply {
+    let target = match &command {
+        control::Command::ListActors => {
+            let actors = actors
+                .iter()
+                .map(|a| {
+                    serde_json::json!({
+                        "name": a.name(),
+                        "kind": a.kind(),
+                        "nexus": a.nexus().to_string(),
+                        "paused": a.is_paused(),
+                    })
+                })
+                .collect();
+            return control::Reply::ok(serde_json::Value::Array(actors));
+        }
+        control::Command::Report
+        | control::Command::Start
+        | control::Command::Stop
+        | control::Command::Quit => {
+            unreachable!("the main loop handles {:?} itself", command)
+        }
+        control::Command::Pause(target)
+        | control::Command::Resume(target)
+        | control::Command::Halt(target)
+        | control::Command::SetProfile(target, _) => target,
+    };
+
+    let matched: Vec<usize> = actors
+        .iter()
+        .enumerate()
+        .filter(|(_, a)| target.matches(a.name(), a.kind()))
+        .map(|(i, _)| i)
+        .collect();
+    if matched.is_empty() {
+        return control::Reply::error(
+            http::StatusCode::NOT_FOUND,
+            format!("no running actors match {:?}", target),
+        );
+    }
+
+    let names: Vec<String> =
+        matched.iter().map(|&i| actors[i].name().to_owned()).collect();
+    let action = match &command {
+        control::Command::Pause(_) => {
+            actor::pause_all(
+                actors
+                    .iter_mut()
+                    .filter(|a| target.matches(a.name(), a.kind())),
+            )
+            .await;
+            "paused"
+        }
+        control::Command::Resume(_) => {
+            actor::resume_all(
+                actors
+                    .iter_mut()
+                    .filter(|a| target.matches(a.name(), a.kind())),
+            )
+            .await;
+            "resumed"
+        }
+        control::Command::SetProfile(_, profile) => {
+            for a in
+                actors.iter().filter(|a| target.matches(a.name(), a.kind()))
+            {
+                a.set_profile(*profile);
+            }
+            "reweighted"
+        }
+        _ => {
+            // Stop forwarding each actor's errors first, so that halting it
+            // isn't reported as its error channel disconnecting.
+            for &i in matched.iter().rev() {
+                if let Some(forwarder) = forwarders.remove(i) {
+                    forwarder.abort();
+                }
+                let a = actors.remove(i);
+                let name = a.name().to_owned();
+                halted.push((name, a.halt().await));
+            }
+            "halted"
+        }
+    };
+
+    info!(?target, count = names.len(), "Actors {} by control API", action);
+    control::Reply::ok(serde_json::json!({ action: names }))
+}
+
+/// Waits for a `Start` command from the control API. Returns false if the run
+/// should end instead.
+async fn wait_for_start(
+    control_rx: &mut mpsc::Receiver<control::Request>,
+    ctrlc_rx: &mut mpsc::UnboundedReceiver<()>,
+) -> bool {
+    info!("Waiting to be told to start");
+    loop {
+        tokio::select! {
+            Some((command, reply_tx)) = control_rx.recv() => {
+                let (reply, start) = match command {
+                    control::Command::Start => {
+                        (control::Reply::ok(serde_json::json!("starting")), Some(true))
+                    }
+                    control::Command::Quit => {
+                        (control::Reply::ok(serde_json::json!("quitting")), Some(false))
+                    }
+                    control::Command::ListActors => {
+                        (control::Reply::ok(serde_json::json!([])), None)
+                    }
+                    command => (
+                        control::Reply::error(
+                            http::StatusCode::CONFLICT,
+                            format!("can't {:?} before the run starts", command),
+                        ),
+                        None,
+                    ),
+                };
+                let _ = reply_tx.send(reply);
+                if let Some(start) = start {
+                    return start;
+                }
+            }
+
+            _ = ctrlc_rx.recv() => {
+                info!("got ctrl-c, exiting");
+                return false;
+            }
+        }
+    }
+}
+
+/// Answers the control API's commands after the run has ended, with `report`
+/// as its final report, until told to quit.
+async fn serve_report(
+    report: &report::Report,
+    control_rx: &mut mpsc::Receiver<control::Request>,
+    ctrlc_rx: &mut mpsc::UnboundedReceiver<()>,
+) {
+    info!("Run ended, serving the report until told to quit");
+    loop {
+        tokio::select! {
+            Some((command, reply_tx)) = control_rx.recv() => {
+                let quit = matches!(command, control::Command::Quit);
+                let reply = match command {
+                    control::Command::Report => {
+                        match serde_json::to_value(report) {
+                            Ok(report) => control::Reply::ok(report),
+                            Err(e) => control::Reply::error(
+                                http::StatusCode::INTERNAL_SERVER_ERROR,
+                                format!("serializing report: {}", e),
+                            ),
+                        }
+                    }
+                    control::Command::Quit => {
+                        control::Reply::ok(serde_json::json!("quitting"))
+                    }
+                    control::Command::Stop => {
+                        control::Reply::ok(serde_json::json!("stopped"))
+                    }
+                    control::Command::ListActors => {
+                        control::Reply::ok(serde_json::json!([]))
+                    }
+                    command => control::Reply::error(
+                        http::StatusCode::CONFLICT,
+                        format!("can't {:?} after the run has ended", command),
+                    ),
+                };
+                let _ = reply_tx.send(reply);
+                if quit {
+                    return;
+                }
+            }
+
+            _ = ctrlc_rx.recv() => {
+                info!("got ctrl-c, exiting");
+                return;
+            }
+        }
+    }
+}
+
+/// Builds a report on the run so far, which started at `started_at` and ended
+/// (or is still going) with `outcome`.
+fn build_report(
+    started_at: chrono::DateTime<chrono::Utc>,
+    outcome: &'static str,
+    budget: &error_budget::ErrorBudget,
+    maintenance: Option<&maintenance::Maintenance>,
+) -> report::Report {
+    let mut report = report::Report::new(started_at, outcome);
+    report.errors = budget.errors().iter().map(Into::into).collect();
+    if let Some(maintenance) = maintenance {
+        report.maintenance_windows =
+            maintenance.windows().iter().map(Into::into).collect();
+    }
+
+    report
+}
+
+/// Sleeps until `deadline`, or forever if there isn't one.
+async fn sleep_or_pend(deadline: Option<Instant>) {
+    match deadline {
+        Some(deadline) => tokio::time::sleep_until(deadline).await,
+        None => std::future::pending().await,
+    }
+}
+
+/// Checks the scenario file at `path` and logs the actors each of its phases
+/// would create.
+fn validate_scenario(path: &std::path::Path) -> Result<()> {
+    let workload = workload::Workload::from_file(path)?;
+    workload.check_names(util::name_prefix())?;
+    for (index, phase) in workload.into_phases().iter().enumerate() {
+        let mut specs = phase.actors(
+            &project_name(),
+            util::name_prefix(),
+            config().profile,
+        );
+        specs.retain(|(_, kind)| config().actor_kind_enabled(kind.name()));
+        let mut kinds = std::collections::BTreeMap::<&str, usize>::new();
+        for (_, kind) in &specs {
+            *kinds.entry(kind.name()).or_default() += 1;
+        }
+
+        info!(
+            phase = index,
+            name = phase.name.as_deref().unwrap_or(""),
+            duration = ?phase.duration,
+            actors = specs.len(),
+            ?kinds,
+            "Scenario phase"
+        );
+    }
+
+    info!(path = %path.display(), "Scenario is valid");
+    Ok(())
+}
+
+/// An event format that starts each line with the run ID, so that the lines
+/// of interleaved or archived logs can be told apart.
+struct WithRunId<F>(F);
+
+impl<S, N, F> FormatEvent<S, N> for WithRunId<F>
+where
+    S: tracing::Subscriber + for<'a> LookupSpan<'a>,
+    N: for<'a> FormatFields<'a> + 'static,
+    F: FormatEvent<S, N>,
+{
+    fn format_event(
+        &self,
+        ctx: &FmtContext<'_, S, N>,
+        mut writer: format::Writer<'_>,
+        event: &tracing::Event<'_>,
+    ) -> std::fmt::Result {
+        write!(writer, "run={} ", run_info::run_id())?;
+        self.0.format_event(ctx, writer, event)
+    }
+}
+
 /// Sets a subscriber that emits tracing messages to stdout.
 fn set_tracing_subscriber() {
     let filter = tracing_subscriber::EnvFilter::builder()
         .with_default_directive(tracing::Level::INFO.into());
     let sub =
         tracing_subscriber::Registry::default().with(filter.from_env_lossy());
-    let stdout_log = tracing_subscriber::fmt::layer().with_line_number(true);
+    let stdout_log = tracing_subscriber::fmt::layer().event_format(WithRunId(
+        format::Format::default().with_line_number(true),
+    ));
     let sub = sub.with(stdout_log);
     tracing::subscriber::set_global_default(sub).unwrap();
 }
 
 /// Yields a reference to the global command-line config.
 pub fn config() -> &'static config::Config {
-    CONFIG.get_or_init(config::Config::parse)
+    CONFIG.get_or_init(config::Config::load)
 }
 
 #[tokio::main]
-async fn main() -> Result<()> {
+async fn main() -> Result<ExitCode> {
     // Preload the config (and exit if the command-line options couldn't be
     // parsed) before doing any other work.
     let _ = config();
     set_tracing_subscriber();
+    info!(
+        run_id = %run_info::run_id(),
+        version = run_info::HARNESS_VERSION,
+        "Starting run"
+    );
+    info!(seed = util::seed(), "Using random seed");
+    info!(prefix = util::name_prefix(), "Using resource name prefix");
 
     let (ctrlc_tx, mut ctrlc_rx) = tokio::sync::mpsc::unbounded_channel();
     ctrlc::set_handler(move || {
@@ -92,137 +753,802 @@ async fn main() -> Result<()> {
     })
     .context("setting Ctrl-C handler")?;
 
-    let client = client::get_client(config()).context("getting client")?;
-    create_test_project(&client).await?;
+    // SIGUSR1 logs a status snapshot, and SIGUSR2 pauses or resumes every
+    // actor.
+    let mut status_signal = signal(SignalKind::user_defined1())
+        .context("setting SIGUSR1 handler")?;
+    let mut pause_signal = signal(SignalKind::user_defined2())
+        .context("setting SIGUSR2 handler")?;
 
-    let mut actors = Vec::new();
-    let mut error_channels: Vec<_> = Vec::new();
+    // These subcommands don't talk to Nexus.
+    match &config().command {
+        Some(config::Command::Report(args)) => {
+            let summary = request_log::summarize(&args.journal)?;
+            println!("{}", serde_json::to_string_pretty(&summary)?);
+            return Ok(ExitCode::SUCCESS);
+        }
+        Some(config::Command::ValidateScenario(args)) => {
+            validate_scenario(&args.file)?;
+            return Ok(ExitCode::SUCCESS);
+        }
+        Some(config::Command::Coordinate(args)) => {
+            return coordinator::run(args, &mut ctrlc_rx).await;
+        }
+        _ => {}
+    }
 
-    for inst in 0..config().num_test_instances {
-        for actor_index in 0..config().threads_per_instance {
-            let (actor, error_ch) = actor::Actor::new(
-                format!("inst{}_{}", inst, actor_index),
-                ActorKind::Instance(instance::Params {
-                    project: PROJECT_NAME.to_owned(),
-                    instance_name: format!("inst{}", inst),
-                }),
-            )?;
+    if let Some(path) = &config().request_log {
+        request_log::open(path)?;
+    }
 
-            error_channels.push((actor.name().to_string(), error_ch));
-            actors.push(actor);
-        }
+    if let Some(path) = &config().manifest {
+        manifest::open(path)?;
     }
 
-    for disk in 0..config().num_test_disks {
-        for actor_index in 0..config().threads_per_disk {
-            let (actor, error_ch) = actor::Actor::new(
-                format!("disk{}_{}", disk, actor_index),
-                ActorKind::Disk(disk::Params {
-                    project: PROJECT_NAME.to_owned(),
-                    disk_name: format!("disk{}", disk),
-                }),
-            )?;
+    if let Some(path) = &config().known_issues {
+        known_issues::load(path).context("loading known issues")?;
+    }
 
-            error_channels.push((actor.name().to_string(), error_ch));
-            actors.push(actor);
-        }
+    if config().insecure_skip_tls_verify {
+        warn!("Not verifying Nexus's TLS certificates");
     }
 
-    for snapshot in 0..config().num_test_snapshots {
-        for actor_index in 0..config().threads_per_snapshot {
-            let (actor, error_ch) = actor::Actor::new(
-                format!("snapshot{}_{}", snapshot, actor_index),
-                ActorKind::Snapshot(snapshot::Params {
-                    project: PROJECT_NAME.to_owned(),
-                    disk_name: if config().snapshots_use_same_disk {
-                        format!("disk{}", snapshot)
-                    } else {
-                        format!("disk{}{}", snapshot, actor_index)
-                    },
-                    snapshot_name: format!("snapshot{}", snapshot),
-                }),
-            )?;
+    if config().mock_nexus {
+        mock_nexus::start().context("starting mock Nexus")?;
+    }
 
-            error_channels.push((actor.name().to_string(), error_ch));
-            actors.push(actor);
+    client::init_endpoints(config())
+        .await
+        .context("finding Nexus endpoints")?;
+    client::load_identities(config()).context("loading identity pool")?;
+    client::ensure_token(config()).await?;
+    let client = client::get_client(config()).context("getting client")?;
+    match &config().command {
+        Some(config::Command::Cleanup(args)) => {
+            // A random prefix would match nothing from an earlier run.
+            if config().name_prefix.is_none() {
+                anyhow::bail!(
+                    "cleanup needs the --name-prefix of the run to clean up \
+                    after (it's in the run's report, manifest, and logs)"
+                );
+            }
+            if let Err(e) = cleanup::run(&client, &project_name(), args).await {
+                error!("Cleanup failed: {:#}", e);
+                let failure = outcome::Failure::Leaks;
+                return Ok(outcome::exit_code(Some(failure), false).into());
+            }
+            return Ok(ExitCode::SUCCESS);
+        }
+        Some(config::Command::ListResources) => {
+            resources::list(&client, &project_name()).await?;
+            return Ok(ExitCode::SUCCESS);
         }
+        _ => {}
     }
 
-    let (error_tx, mut error_rx) =
-        tokio::sync::mpsc::channel::<AntagonistError>(1);
-
-    for (name, mut error_ch) in error_channels {
-        let error_tx = error_tx.clone();
-        tokio::spawn(async move {
-            loop {
-                match error_ch.recv().await {
-                    Some(e) => {
-                        let _ = error_tx.send(e).await;
-                    }
+    run_info::fetch_nexus_version(&client).await;
+    if config().chaos_proxy {
+        chaos_proxy::start().context("starting chaos proxy")?;
+    }
 
-                    None => {
-                        let _ = error_tx
-                            .send(AntagonistError::DisconnectedErrorChannel {
-                                name,
-                            })
-                            .await;
-                        break;
-                    }
-                }
+    let workload = match &config().scenario {
+        Some(path) => workload::Workload::from_file(path)
+            .context("loading scenario file")?,
+        None => match &config().workload {
+            Some(workload) => workload.clone(),
+            None => {
+                let workload = workload::Workload::from_config(config());
+                workload.validate()?;
+                workload
             }
-        });
+        },
+    };
+    workload.check_names(util::name_prefix())?;
+
+    let added_ip_range = create_test_project(&client).await?;
+
+    // Hold the claim on the baseline resources until the run ends.
+    let baseline = if config().populate {
+        Some(
+            populate::run(&client, &project_name(), config())
+                .await
+                .context("populating stress project")?,
+        )
+    } else {
+        None
+    };
+
+    // Measure the silo's utilization while the project is still settled.
+    let audit = match config().utilization_audit {
+        Some(_) => Some(
+            utilization::Audit::start(&client, &project_name())
+                .await
+                .context("starting utilization audit")?,
+        ),
+        None => None,
+    };
+
+    let (error_tx, mut error_rx) = tokio::sync::mpsc::channel::<ActorError>(1);
+    if let Some(workers) = config().actor_workers {
+        actor::pool::start(workers, error_tx.clone());
+    }
+
+    // Keep a sender around so that the control channel stays open even if
+    // there's no control API or console to send on it.
+    let (control_tx, mut control_rx) = mpsc::channel::<control::Request>(8);
+    let mut halted = Vec::new();
+    if let Some(addr) = config().control_listen {
+        control::start(addr, control_tx.clone())
+            .context("starting control API")?;
+    }
+    if config().interactive {
+        repl::start(control_tx.clone());
+    }
+
+    if config().wait_for_start
+        && !wait_for_start(&mut control_rx, &mut ctrlc_rx).await
+    {
+        info!("Run ended before it started");
+        return Ok(outcome::exit_code(None, true).into());
     }
 
+    let mut phases = workload.into_phases().into_iter().enumerate();
+    let (mut phase_index, mut phase) =
+        phases.next().expect("workloads always have at least one phase");
+    let ramp_up = config().ramp_up.unwrap_or_default();
+    let (mut actors, mut forwarders) =
+        start_phase(phase_index, &phase, ramp_up, &error_tx)?;
+    let read_load = config()
+        .read_load
+        .map(|rps| read_load::start(client.clone(), project_name(), rps));
+    let (finding_tx, mut finding_rx) = mpsc::unbounded_channel();
+    let bad_tokens = config()
+        .bad_tokens
+        .map(|rps| bad_tokens::start(project_name(), rps, finding_tx))
+        .transpose()
+        .context("starting bad-token requests")?;
+
+    let deadline = async {
+        match config().duration {
+            Some(duration) => tokio::time::sleep(duration).await,
+            None => std::future::pending().await,
+        }
+    };
+    tokio::pin!(deadline);
+
     info!("Starting stress test");
+    let started_at = chrono::Utc::now();
+    status::set_running(true);
+    let mut phase_deadline = phase.duration.map(|d| Instant::now() + d);
+    let mut out_of_time = false;
+    let mut interrupted = false;
+    let mut signal_paused = false;
+    let mut budget = error_budget::ErrorBudget::new(config().error_budget);
+    let mut throttle_budget = config()
+        .throttle_budget
+        .map(|limit| error_budget::ErrorBudget::new(Some(limit)));
+    let mut budget_warned = false;
+    let mut aborted_by = None;
+    let mut maintenance =
+        config().maintenance_file.clone().map(maintenance::Maintenance::new);
+    let mut maintenance_poll = tokio::time::interval(Duration::from_secs(1));
+    let stall_timeout = config().actor_stall_timeout;
+    let mut stall_check = tokio::time::interval(Duration::from_secs(1));
+    let crash_interval = config().crash_actors.unwrap_or_default();
+    let mut crash_check = tokio::time::interval_at(
+        Instant::now() + crash_interval,
+        crash_interval.max(Duration::from_millis(1)),
+    );
+    let mut crash_rng = util::actor_rng("crash");
+    let mut heartbeat = tokio::time::interval(
+        config().heartbeat_interval.max(Duration::from_millis(1)),
+    );
+    // Intervals can't have a zero period, but a zero progress interval turns
+    // the summaries off anyway.
+    let progress_interval = config().progress_interval;
+    let mut progress = tokio::time::interval_at(
+        Instant::now() + progress_interval,
+        progress_interval.max(Duration::from_millis(1)),
+    );
+    let mut last_progress = Instant::now();
+    let anomaly_window = config().anomaly_window;
+    let mut anomaly_check = tokio::time::interval_at(
+        Instant::now() + anomaly_window,
+        anomaly_window.max(Duration::from_millis(1)),
+    );
+    let audit_interval = config().utilization_audit.unwrap_or_default();
+    let mut audit_check = tokio::time::interval_at(
+        Instant::now() + audit_interval,
+        audit_interval.max(Duration::from_millis(1)),
+    );
+    let leak_audit_interval = config().leak_audit.unwrap_or_default();
+    let mut leak_audit_check = tokio::time::interval_at(
+        Instant::now() + leak_audit_interval,
+        leak_audit_interval.max(Duration::from_millis(1)),
+    );
+    let checkpoint_interval = config().checkpoint_interval.unwrap_or_default();
+    let mut checkpoint = tokio::time::interval_at(
+        Instant::now() + checkpoint_interval,
+        checkpoint_interval.max(Duration::from_millis(1)),
+    );
+    let mut checkpoints = 0;
     loop {
         tokio::select! {
             err = error_rx.recv() => {
                 match err {
                     None => {
                         error!("error_rx disconnected!");
+                        aborted_by = Some(outcome::Failure::Other);
                         break;
                     }
 
-                    Some(err) => {
-                        match err {
-                            AntagonistError::ApiError(err) => {
-                                if config().server_errors_fatal {
-                                    if let Err(err) = fail_if_500(err) {
-                                        error!("actor error: {:?}", err);
+                    Some(ActorError { error: err, context }) => {
+                        let actor_name = context.actor.clone();
+
+                        // An actor's error channel disconnects when its task
+                        // dies. Replace the actor if it has restarts left.
+                        let dead = matches!(
+                            err,
+                            AntagonistError::DisconnectedErrorChannel { .. }
+                        )
+                        .then(|| actors.iter().position(|a| a.name() == actor_name))
+                        .flatten();
+                        if let (Some(i), true) =
+                            (dead, supervisor::may_restart(&actor_name))
+                        {
+                            let paused = signal_paused
+                                || maintenance.as_ref().is_some_and(|m| m.active());
+                            match restart_actor(
+                                i,
+                                &phase,
+                                &mut actors,
+                                &mut forwarders,
+                                &error_tx,
+                                paused,
+                            )
+                            .await
+                            {
+                                Ok(()) => continue,
+                                Err(e) => error!("failed to restart actor: {:#}", e),
+                            }
+                        }
+
+                        // Actors are already idle during maintenance, so
+                        // there's no outage to ride out.
+                        let in_maintenance =
+                            maintenance.as_ref().is_some_and(|m| m.active());
+                        if let (Some(limit), false) =
+                            (config().tolerate_unavailability, in_maintenance)
+                        {
+                            if is_unavailability(&err) {
+                                match ride_out_outage(&client, &mut actors, limit).await {
+                                    Ok(()) => continue,
+                                    Err(e) => {
+                                        error!("{:#}", e);
+                                        budget.record(
+                                            outcome::Failure::Infrastructure,
+                                            format!("{:#}", e),
+                                            None,
+                                            None,
+                                        );
+                                        aborted_by =
+                                            Some(outcome::Failure::Infrastructure);
                                         break;
                                     }
-                                } else if let Err(err) = fail_if_no_response(err) {
-                                    error!("actor error: {:?}", err);
-                                    break;
                                 }
                             }
+                        }
 
-                            AntagonistError::InvalidState(_)
-                            | AntagonistError::DisconnectedErrorChannel { .. } => {
-                                error!("{err}");
+                        if throttle::is_throttled_error(&err) {
+                            throttle::record_surfaced();
+                            let exhausted = throttle_budget.as_mut().is_some_and(|b| {
+                                b.record(
+                                    outcome::Failure::Throttled,
+                                    describe_error(&err),
+                                    Some(context.clone()),
+                                    None,
+                                )
+                            });
+                            if exhausted {
+                                let description = describe_error(&err);
+                                error!(
+                                    "throttle budget exhausted, exiting: {} ({})",
+                                    description,
+                                    context
+                                );
+                                budget.record(
+                                    outcome::Failure::Throttled,
+                                    description,
+                                    Some(context),
+                                    None,
+                                );
+                                aborted_by = Some(outcome::Failure::Throttled);
                                 break;
                             }
                         }
+
+                        let Some(err) = disqualifying_error(err) else {
+                            continue;
+                        };
+
+                        if let Some(issue) = known_issues::check(&actor_name, &err) {
+                            warn!(
+                                issue,
+                                "known issue: {} ({})",
+                                describe_error(&err),
+                                context
+                            );
+                            continue;
+                        }
+
+                        let description = describe_error(&err);
+                        let failure = outcome::Failure::of(&err);
+                        let peer = request_log::failed_call(&actor_name, &err)
+                            .and_then(|call| call.peer);
+                        error!(?peer, "actor error: {} ({})", description, context);
+                        let exhausted = budget.record(
+                            failure,
+                            description,
+                            Some(context),
+                            peer,
+                        );
+                        if exhausted || config().artifacts_for_all_errors {
+                            write_artifacts(&client, &actor_name, &err).await;
+                        }
+
+                        if exhausted {
+                            error!("error budget exhausted, exiting");
+                            aborted_by = Some(failure);
+                            break;
+                        }
+
+                        if !budget_warned && budget.half_spent() {
+                            warn!("half of the error budget is spent");
+                            budget_warned = true;
+                            tokio::spawn(notify::send(notify::Notification::new(
+                                notify::Event::ErrorBudgetHalfSpent,
+                                &budget,
+                            )));
+                        }
                     }
                 }
             }
 
+            Some((command, reply_tx)) = control_rx.recv() => {
+                let quit = matches!(command, control::Command::Quit);
+                let stop = matches!(command, control::Command::Stop);
+                let reply = match command {
+                    control::Command::Quit => {
+                        control::Reply::ok(serde_json::json!("quitting"))
+                    }
+                    control::Command::Stop => {
+                        control::Reply::ok(serde_json::json!("stopping"))
+                    }
+                    control::Command::Start => control::Reply::error(
+                        http::StatusCode::CONFLICT,
+                        "the run has already started",
+                    ),
+                    control::Command::Report => {
+                        let report = build_report(
+                            started_at,
+                            "running",
+                            &budget,
+                            maintenance.as_ref(),
+                        );
+                        match serde_json::to_value(report) {
+                            Ok(report) => control::Reply::ok(report),
+                            Err(e) => control::Reply::error(
+                                http::StatusCode::INTERNAL_SERVER_ERROR,
+                                format!("serializing report: {}", e),
+                            ),
+                        }
+                    }
+                    command => {
+                        control(
+                            command,
+                            &mut actors,
+                            &mut forwarders,
+                            &mut halted,
+                        )
+                        .await
+                    }
+                };
+                let _ = reply_tx.send(reply);
+                if quit {
+                    info!("quit requested, exiting");
+                    interrupted = true;
+                    break;
+                }
+                if stop {
+                    info!("stop requested, exiting");
+                    out_of_time = true;
+                    break;
+                }
+            }
+
+            _ = status_signal.recv() => {
+                status::log(&actors);
+            }
+
+            _ = pause_signal.recv() => {
+                signal_paused = !signal_paused;
+                if signal_paused {
+                    info!("got SIGUSR2, pausing actors");
+                    actor::pause_all(actors.iter_mut()).await;
+                    info!("All actors paused, send SIGUSR2 again to resume");
+                } else {
+                    info!("got SIGUSR2, resuming actors");
+                    actor::resume_all(actors.iter_mut()).await;
+                }
+            }
+
             _ = ctrlc_rx.recv() => {
                 info!("got ctrl-c, exiting");
+                interrupted = true;
                 break;
             }
+
+            _ = &mut deadline => {
+                info!("run duration elapsed, exiting");
+                out_of_time = true;
+                break;
+            }
+
+            _ = sleep_or_pend(phase_deadline) => {
+                let Some((next_index, next)) = phases.next() else {
+                    info!("final phase complete, exiting");
+                    out_of_time = true;
+                    break;
+                };
+
+                stop_phase(actors, forwarders).await;
+                (phase_index, phase) = (next_index, next);
+                (actors, forwarders) = start_phase(
+                    phase_index,
+                    &phase,
+                    Duration::ZERO,
+                    &error_tx,
+                )?;
+                phase_deadline = phase.duration.map(|d| Instant::now() + d);
+                if signal_paused
+                    || maintenance.as_ref().is_some_and(|m| m.active())
+                {
+                    actor::pause_all(actors.iter_mut()).await;
+                }
+            }
+
+            _ = progress.tick(), if !progress_interval.is_zero() => {
+                stats::log_progress(last_progress.elapsed());
+                last_progress = Instant::now();
+            }
+
+            _ = anomaly_check.tick(), if !anomaly_window.is_zero() => {
+                status_counts::check(config().anomaly_threshold);
+            }
+
+            _ = audit_check.tick(), if audit.is_some() => {
+                if let Some(audit) = &audit {
+                    audit.check(&client, &project_name(), false).await;
+                }
+            }
+
+            _ = leak_audit_check.tick(), if config().leak_audit.is_some() => {
+                leaks::audit(&client, &project_name()).await;
+            }
+
+            _ = checkpoint.tick(), if config().checkpoint_interval.is_some() => {
+                checkpoints += 1;
+                let path = report::Report::checkpoint_path(checkpoints);
+                let report = build_report(
+                    started_at,
+                    "running",
+                    &budget,
+                    maintenance.as_ref(),
+                );
+                match report.write(&path) {
+                    Ok(()) => info!(
+                        path = %path.display(),
+                        errors = report.errors.len(),
+                        "Wrote checkpoint"
+                    ),
+                    Err(e) => warn!("failed to write checkpoint: {:#}", e),
+                }
+            }
+
+            _ = stall_check.tick(), if stall_timeout.is_some() => {
+                let timeout = stall_timeout.unwrap_or_default();
+                let Some(i) = find_stalled_actor(&actors, timeout) else {
+                    continue;
+                };
+
+                let actor_name = actors[i].name().to_owned();
+                if supervisor::may_restart(&actor_name) {
+                    let paused = signal_paused
+                        || maintenance.as_ref().is_some_and(|m| m.active());
+                    match restart_actor(
+                        i,
+                        &phase,
+                        &mut actors,
+                        &mut forwarders,
+                        &error_tx,
+                        paused,
+                    )
+                    .await
+                    {
+                        Ok(()) => continue,
+                        Err(e) => error!("failed to restart actor: {:#}", e),
+                    }
+                } else {
+                    if let Some(forwarder) = forwarders.remove(i) {
+                        forwarder.abort();
+                    }
+                    halted.push((actor_name.clone(), actors.remove(i).abort()));
+                }
+
+                // The actor is out of the run, which is an error like any
+                // other dead actor.
+                let description = format!(
+                    "actor {} stalled for more than {:?}",
+                    actor_name, timeout
+                );
+                error!(actor = actor_name, "{}", description);
+                let failure = outcome::Failure::Infrastructure;
+                if budget.record(failure, description, None, None) {
+                    error!("error budget exhausted, exiting");
+                    aborted_by = Some(failure);
+                    break;
+                }
+            }
+
+            Some(finding) = finding_rx.recv() => {
+                error!("bad-token check failed: {}", finding);
+                let failure = outcome::Failure::CheckFailed;
+                if budget.record(failure, finding, None, None) {
+                    error!("error budget exhausted, exiting");
+                    aborted_by = Some(failure);
+                    break;
+                }
+            }
+
+            _ = crash_check.tick(), if config().crash_actors.is_some() => {
+                // Paused actors aren't doing anything worth interrupting.
+                let crashable: Vec<usize> = (0..actors.len())
+                    .filter(|&i| {
+                        !actors[i].is_paused()
+                            && supervisor::may_crash(actors[i].kind())
+                    })
+                    .collect();
+                let Some(&i) = crashable.choose(&mut crash_rng) else {
+                    continue;
+                };
+
+                if let Err(e) = crash_actor(
+                    i,
+                    &phase,
+                    &mut actors,
+                    &mut forwarders,
+                    &error_tx,
+                )
+                .await
+                {
+                    error!("failed to replace crashed actor: {:#}", e);
+                }
+            }
+
+            _ = heartbeat.tick(), if config().heartbeat_file.is_some() => {
+                if let Some(path) = &config().heartbeat_file {
+                    if let Err(e) = status::write_heartbeat(path) {
+                        warn!("failed to write heartbeat: {:#}", e);
+                    }
+                }
+            }
+
+            _ = maintenance_poll.tick(), if maintenance.is_some() => {
+                let transition = maintenance.as_mut().and_then(|m| m.poll());
+                match transition {
+                    Some(maintenance::Transition::Enter) => {
+                        info!("Entering maintenance mode, pausing actors");
+                        actor::pause_all(actors.iter_mut()).await;
+                        info!("All actors paused for maintenance");
+                    }
+                    Some(maintenance::Transition::Leave) => {
+                        info!("Leaving maintenance mode, resuming actors");
+                        actor::resume_all(actors.iter_mut()).await;
+                    }
+                    None => {}
+                }
+            }
+        }
+    }
+    status::set_running(false);
+
+    // Stop forwarding errors from actors that are about to be halted. Any
+    // actor that hits an error from here on just stops, since there's no
+    // one left to report it to.
+    for forwarder in forwarders.into_iter().flatten() {
+        forwarder.abort();
+    }
+
+    // Runs that end on purpose, rather than because of an error, can take the
+    // time to clean up after their actors.
+    let drain = config().drain_on_exit && (out_of_time || interrupted);
+    if drain {
+        info!("Draining actors' resources");
+    }
+
+    let mut halting = halted;
+    if let (true, Some(period)) = (out_of_time, config().ramp_down) {
+        info!(?period, "Ramping down actors");
+        let interval = period.div_f64(actors.len().max(1) as f64);
+        actors.shuffle(&mut util::actor_rng("ramp-down"));
+        while let Some(a) = actors.pop() {
+            let name = a.name().to_owned();
+            halting.push((
+                name,
+                if drain { a.drain().await } else { a.halt().await },
+            ));
+            tokio::select! {
+                _ = tokio::time::sleep(interval) => {}
+                _ = ctrlc_rx.recv() => {
+                    info!("got ctrl-c, halting remaining actors");
+                    break;
+                }
+            }
         }
     }
 
-    let join_futures = FuturesUnordered::new();
     info!("Halting actors");
     for a in actors {
-        join_futures.push(a.halt().await);
+        let name = a.name().to_owned();
+        halting
+            .push((name, if drain { a.drain().await } else { a.halt().await }));
     }
 
     info!("Waiting for actors to halt");
-    futures::future::join_all(join_futures).await;
+    let stragglers = supervisor::join(halting).await;
+    if let Some(read_load) = read_load {
+        read_load.abort();
+    }
+    if let Some(bad_tokens) = bad_tokens {
+        bad_tokens.abort();
+    }
+    let cancelled: u64 =
+        stats::summary().values().map(|kind| kind.cancelled).sum();
+    if stragglers.is_empty() {
+        info!(cancelled, "All actors halted");
+    }
 
-    info!("b'bye");
-    Ok(())
+    if let Some(audit) = &audit {
+        audit.check(&client, &project_name(), true).await;
+    }
+
+    // Look for leaks before --cleanup-on-exit deletes the evidence.
+    let leaks = leaks::find(&client, &project_name(), drain).await;
+
+    if !out_of_time && !interrupted {
+        let mut notification =
+            notify::Notification::new(notify::Event::RunFailed, &budget);
+        notification.leaked_resources = leaks
+            .as_ref()
+            .ok()
+            .map(|leaks| leaks.iter().map(Into::into).collect());
+        notify::send(notification).await;
+    }
+
+    let cleanup_result = if config().cleanup_on_exit {
+        // Release the baseline so that it gets deleted along with everything
+        // else.
+        drop(baseline);
+        info!("Cleaning up harness resources");
+        // The IP range may be shared with other runs (or their cleanups), so
+        // only remove it if this run added it.
+        let args = config::CleanupArgs {
+            timeout: config().cleanup_timeout,
+            keep_ip_range: !added_ip_range,
+            keep_project: false,
+        };
+        cleanup::run(&client, &project_name(), &args)
+            .await
+            .context("cleaning up on exit")
+    } else {
+        Ok(())
+    };
+
+    if let Err(e) = &cleanup_result {
+        error!("Cleanup on exit failed: {:#}", e);
+    }
+
+    if let Some(maintenance) = &maintenance {
+        for w in maintenance.windows() {
+            info!(start = ?w.start, end = ?w.end, "Maintenance window");
+        }
+    }
+
+    actor::conflict::log();
+    actor::disk::log();
+    actor::snapshot_limit::log();
+    bad_tokens::log();
+    chaos_proxy::log();
+    error_groups::log();
+    error_schema::log();
+    known_issues::log();
+    slow_requests::log();
+    read_load::log();
+    sla::log();
+    stats::log_transitions();
+    supervisor::log();
+    throttle::log();
+    utilization::log();
+    match &leaks {
+        Ok(leaks) => leaks::log(leaks),
+        Err(e) => error!("Failed to check for leaked resources: {:#}", e),
+    }
+
+    if !budget.errors().is_empty() {
+        error!(count = budget.errors().len(), "Actors reported errors");
+        for e in budget.errors() {
+            match &e.context {
+                Some(context) => error!(
+                    elapsed = ?e.elapsed,
+                    peer = ?e.peer,
+                    "{} ({})",
+                    e.description,
+                    context
+                ),
+                None => error!(elapsed = ?e.elapsed, "{}", e.description),
+            }
+        }
+    }
+
+    // The run's failure is whatever cut it short, if anything did.
+    let failure = aborted_by.or_else(|| {
+        let leaked = leaks.as_ref().is_ok_and(|leaks| !leaks.is_empty());
+        if leaked || cleanup_result.is_err() {
+            Some(outcome::Failure::Leaks)
+        } else if leaks.is_err() || !stragglers.is_empty() {
+            Some(outcome::Failure::Infrastructure)
+        } else {
+            None
+        }
+    });
+    let exit_code = outcome::exit_code(failure, interrupted);
+
+    let outcome = if out_of_time {
+        "completed"
+    } else if interrupted {
+        "interrupted"
+    } else {
+        "failed"
+    };
+    let mut report =
+        build_report(started_at, outcome, &budget, maintenance.as_ref());
+    report.leaked_resources =
+        leaks.as_ref().ok().map(|leaks| leaks.iter().map(Into::into).collect());
+    report.cleanup_error =
+        cleanup_result.as_ref().err().map(|e| format!("{:#}", e));
+    report.stuck_actors = stragglers;
+    report.failure = failure;
+    report.exit_code = Some(exit_code);
+
+    let report_result = match &config().report_json {
+        Some(path) => {
+            info!(path = %path.display(), "Writing report");
+            report.write(path)
+        }
+        None => Ok(()),
+    };
+
+    if config().smoke {
+        println!("{}", outcome::smoke_verdict(failure, interrupted));
+    }
+
+    // Whoever started the run (usually `omicron-stress coordinate`) needs its
+    // final report, so stick around until they've had a chance to get it.
+    if config().wait_for_start {
+        serve_report(&report, &mut control_rx, &mut ctrlc_rx).await;
+    }
+
+    info!(?failure, exit_code, "b'bye");
+    report_result?;
+    Ok(exit_code.into())
 }
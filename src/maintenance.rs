@@ -0,0 +1,71 @@
+//! Maintenance mode, in which every actor finishes its current step and then
+//! idles until maintenance ends. Operators use this to hold the load off while
+//! they update the rack's software, then pick it back up afterward.
+//!
+//! The harness is in maintenance mode whenever the file named by
+//! `--maintenance-file` exists, so entering and leaving maintenance is just a
+//! matter of creating and deleting that file.
+
+use std::path::PathBuf;
+use std::time::Duration;
+
+use tokio::time::Instant;
+
+/// A period the harness spent in maintenance mode, relative to the start of
+/// the run.
+#[derive(Debug)]
+pub struct Window {
+    pub start: Duration,
+
+    /// When maintenance ended, or `None` if the run ended first.
+    pub end: Option<Duration>,
+}
+
+/// A change in whether the harness is in maintenance mode.
+#[derive(Clone, Copy, Debug)]
+pub enum Transition {
+    Enter,
+    Leave,
+}
+
+/// Watches for maintenance mode to start and stop.
+#[derive(Debug)]
+pub struct Maintenance {
+    path: PathBuf,
+    run_start: Instant,
+    windows: Vec<Window>,
+}
+
+impl Maintenance {
+    /// Creates a watcher for the maintenance file at `path`.
+    pub fn new(path: PathBuf) -> Self {
+        Self { path, run_start: Instant::now(), windows: Vec::new() }
+    }
+
+    /// Returns true if the harness is in maintenance mode.
+    pub fn active(&self) -> bool {
+        self.windows.last().is_some_and(|w| w.end.is_none())
+    }
+
+    /// Checks whether the maintenance file's presence has changed since the
+    /// last call, and if so, records the change and returns it.
+    pub fn poll(&mut self) -> Option<Transition> {
+        let elapsed = self.run_start.elapsed();
+        match (self.path.exists(), self.active()) {
+            (true, false) => {
+                self.windows.push(Window { start: elapsed, end: None });
+                Some(Transition::Enter)
+            }
+            (false, true) => {
+                self.windows.last_mut().unwrap().end = Some(elapsed);
+                Some(Transition::Leave)
+            }
+            _ => None,
+        }
+    }
+
+    /// Returns every maintenance window so far, in order.
+    pub fn windows(&self) -> &[Window] {
+        &self.windows
+    }
+}
@@ -0,0 +1,108 @@
+//! The created-resource manifest written by `--manifest`: one JSON line for
+//! every resource the run creates, appended and flushed as soon as the create
+//! succeeds, so that it survives the harness crashing. Each line has the
+//! resource's kind, name, ID, and creation time, the actor that created it (or
+//! `harness` for the project and the baseline), the create request's ID, and
+//! the run ID and name prefix, which `cleanup` needs to find the run's
+//! resources again.
+//!
+//! Disks that a fuzzer or boundary prober creates along with an instance, in
+//! the same request, aren't listed separately.
+
+use std::fs::File;
+use std::io::{LineWriter, Write};
+use std::path::Path;
+use std::sync::{Mutex, OnceLock};
+
+use anyhow::{Context, Result};
+use serde::Serialize;
+use serde_json::Value;
+use tracing::warn;
+
+static MANIFEST: OnceLock<Mutex<LineWriter<File>>> = OnceLock::new();
+
+/// A created resource.
+#[derive(Serialize)]
+struct Entry<'a> {
+    kind: &'a str,
+    name: Option<&'a str>,
+    id: Option<&'a str>,
+
+    /// When Nexus says the resource was created, in RFC 3339 format.
+    time_created: Option<&'a str>,
+    actor: &'a str,
+    request_id: Option<&'a str>,
+    run_id: String,
+    name_prefix: &'static str,
+}
+
+/// Opens the manifest at `path`, appending to it if it exists.
+pub fn open(path: &Path) -> Result<()> {
+    let file = std::fs::OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(path)
+        .with_context(|| format!("opening manifest {}", path.display()))?;
+
+    MANIFEST
+        .set(Mutex::new(LineWriter::new(file)))
+        .map_err(|_| anyhow::anyhow!("manifest already open"))
+}
+
+/// Returns true if there's a manifest to record resources in.
+pub fn is_open() -> bool {
+    MANIFEST.get().is_some()
+}
+
+/// Records that a `kind` of resource (e.g. `disk`) was created by the request
+/// with `request_id`, given the resource as the API returned it.
+pub fn record(kind: &str, resource: &Value, request_id: Option<&str>) {
+    let Some(manifest) = MANIFEST.get() else {
+        return;
+    };
+
+    let actor = crate::actor::current_actor();
+    let entry = Entry {
+        kind,
+        name: resource["name"].as_str(),
+        id: resource["id"].as_str(),
+        time_created: resource["time_created"].as_str(),
+        actor: actor.as_ref().map_or("harness", |a| a.name.as_str()),
+        request_id,
+        run_id: crate::run_info::run_id().to_string(),
+        name_prefix: crate::util::name_prefix(),
+    };
+
+    let mut line = match serde_json::to_string(&entry) {
+        Ok(line) => line,
+        Err(e) => {
+            warn!("failed to serialize manifest entry: {}", e);
+            return;
+        }
+    };
+    line.push('\n');
+
+    let mut manifest = manifest.lock().unwrap();
+    if let Err(e) =
+        manifest.write_all(line.as_bytes()).and_then(|()| manifest.flush())
+    {
+        warn!("failed to write to manifest: {}", e);
+    }
+}
+
+/// Records the resource that `rv`, the response to a successful create of a
+/// `kind` of resource sent outside the middleware, returned.
+pub fn record_response<T: Serialize>(kind: &str, rv: &oxide::ResponseValue<T>) {
+    if !is_open() {
+        return;
+    }
+
+    let request_id = rv
+        .headers()
+        .get(crate::request_log::REQUEST_ID_HEADER)
+        .and_then(|id| id.to_str().ok());
+    match serde_json::to_value(&**rv) {
+        Ok(resource) => record(kind, &resource, request_id),
+        Err(e) => warn!(kind, "failed to serialize created resource: {}", e),
+    }
+}
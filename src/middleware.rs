@@ -0,0 +1,531 @@
+//! The layer every actor API call goes through.
+//!
+//! Actors make their API calls by wrapping them in `call` (or `list` for
+//! paginated listings), which runs each call through a stack of `Layer`s.
+//! Each layer gets a chance to act before the call is sent, e.g. to delay or
+//! fail it, and is told how the call went afterward, e.g. to record its
+//! latency or log its result. Anything that needs to see every call (the rate
+//! limits, stats, the request log, status counters, the slow request watchdog,
+//! error groups, error body validation, the created-resource manifest, and the
+//! status snapshot's in-flight calls) hangs off a layer here rather than being
+//! repeated around every `.send()`.
+//!
+//! An actor's calls that fail for a transient reason (a communication error or
+//! an error response matching --retry-status) are retried here, each attempt
+//! going through the layers again (see `crate::retry`).
+//!
+//! Calls made during an actor's step are abandoned as soon as the actor is
+//! halted (see `cancellable`), so that halting doesn't wait on slow requests.
+//! With --abandon-requests, steps' writes are also abandoned at random, like a
+//! client that disconnects before the response arrives (see `abandonable`).
+
+use std::cell::Cell;
+use std::fmt::Debug;
+use std::future::Future;
+use std::sync::OnceLock;
+use std::time::{Duration, Instant};
+
+use async_trait::async_trait;
+use serde::Serialize;
+use tokio_util::sync::CancellationToken;
+use tracing::{debug, info, warn};
+
+use crate::util::OxideApiError;
+
+/// A finished API call, as the layers see it.
+pub struct Call<'a> {
+    /// The name of the API endpoint called, e.g. `instance_stop`.
+    pub endpoint: &'static str,
+
+    /// How many times the call had been retried before this attempt.
+    pub attempt: u32,
+    pub latency: Duration,
+
+    /// The response's status code, or `None` if there was no response.
+    pub status: Option<http::StatusCode>,
+
+    /// The ID Nexus assigned to the request, if it's known.
+    pub request_id: Option<&'a str>,
+
+    /// What the call returned.
+    pub result: Result<&'a dyn Debug, &'a OxideApiError>,
+
+    /// The resource a successful create returned, as JSON, if there's a
+    /// --manifest to record it in.
+    pub created: Option<&'a serde_json::Value>,
+}
+
+/// A hook around every API call.
+#[async_trait]
+pub trait Layer: Send + Sync {
+    /// Called before a call to `endpoint` is sent. Returning an error fails
+    /// the call without sending it; the error is then passed to every layer's
+    /// `after` like any other failure.
+    async fn before(
+        &self,
+        _endpoint: &'static str,
+    ) -> Result<(), OxideApiError> {
+        Ok(())
+    }
+
+    /// Called after a call finishes.
+    fn after(&self, _call: &Call<'_>) {}
+}
+
+/// Holds each actor call to the rate limits (--target-rps and --kind-rps).
+/// Calls made outside of actors (e.g. the harness's setup and the background
+/// read load) aren't limited.
+struct RateLimit;
+
+#[async_trait]
+impl Layer for RateLimit {
+    async fn before(
+        &self,
+        _endpoint: &'static str,
+    ) -> Result<(), OxideApiError> {
+        if let Some(actor) = crate::actor::current_actor() {
+            crate::rate_limit::acquire(actor.kind).await;
+        }
+        Ok(())
+    }
+}
+
+/// Keeps track of the call each actor is waiting on, for status snapshots, and
+/// of when each kind of actor's calls last succeeded, for the heartbeat.
+struct InFlight;
+
+#[async_trait]
+impl Layer for InFlight {
+    async fn before(
+        &self,
+        endpoint: &'static str,
+    ) -> Result<(), OxideApiError> {
+        crate::status::call_started(endpoint);
+        Ok(())
+    }
+
+    fn after(&self, call: &Call<'_>) {
+        crate::status::call_finished(call.result.is_ok());
+    }
+}
+
+/// Records each call's latency in the endpoint stats.
+struct Stats;
+
+impl Layer for Stats {
+    fn after(&self, call: &Call<'_>) {
+        crate::stats::record_call(call.endpoint, call.status, call.latency);
+    }
+}
+
+/// Records each call in the request log and the actor's call history.
+struct RequestLog;
+
+impl Layer for RequestLog {
+    fn after(&self, call: &Call<'_>) {
+        crate::request_log::record(
+            call.endpoint,
+            call.attempt,
+            call.latency,
+            call.status,
+            call.request_id,
+        );
+    }
+}
+
+/// Counts each call's status code.
+struct StatusCounts;
+
+impl Layer for StatusCounts {
+    fn after(&self, call: &Call<'_>) {
+        crate::status_counts::record(call.endpoint, call.status);
+    }
+}
+
+/// Counts each call's throttled responses.
+struct Throttle;
+
+impl Layer for Throttle {
+    fn after(&self, call: &Call<'_>) {
+        crate::throttle::record(call.endpoint, call.status);
+    }
+}
+
+/// Passes each call's latency to the slow request watchdog.
+struct SlowRequests;
+
+impl Layer for SlowRequests {
+    fn after(&self, call: &Call<'_>) {
+        crate::slow_requests::record(
+            call.endpoint,
+            call.latency,
+            call.request_id,
+        );
+    }
+}
+
+/// Groups failed calls' errors and checks their bodies.
+struct Errors;
+
+impl Layer for Errors {
+    fn after(&self, call: &Call<'_>) {
+        if let Err(e) = call.result {
+            crate::error_groups::record(call.endpoint, e);
+            crate::error_schema::record(call.endpoint, e);
+        }
+    }
+}
+
+/// Records each created resource in the manifest.
+struct Manifest;
+
+impl Layer for Manifest {
+    fn after(&self, call: &Call<'_>) {
+        if let Some(resource) = call.created {
+            let kind = call.endpoint.trim_end_matches("_create");
+            crate::manifest::record(kind, resource, call.request_id);
+        }
+    }
+}
+
+/// Logs each call's result.
+struct Log;
+
+/// Returns true if the endpoint named `endpoint` only reads.
+fn is_read(endpoint: &str) -> bool {
+    endpoint.ends_with("_view") || endpoint.ends_with("_list")
+}
+
+impl Layer for Log {
+    fn after(&self, call: &Call<'_>) {
+        // Actors poll constantly, and reads of resources that don't exist
+        // fail as a matter of course, so only log reads at DEBUG level. With
+        // --quiet-requests, log everything but calls with no response there.
+        let endpoint = call.endpoint;
+        let quiet = is_read(endpoint) || crate::config().quiet_requests;
+        match call.result {
+            Ok(result) if quiet => {
+                debug!(endpoint, ?result, "request returned")
+            }
+            Err(error @ oxide::Error::ErrorResponse(_)) if quiet => {
+                debug!(endpoint, ?error, "request returned")
+            }
+            Err(error) if is_read(endpoint) => {
+                debug!(endpoint, ?error, "request returned")
+            }
+            Ok(result) => info!(endpoint, ?result, "request returned"),
+            Err(error) => {
+                let peer = crate::actor::current_actor()
+                    .and_then(|actor| actor.nexus.connect_addr());
+                warn!(endpoint, ?peer, ?error, "request returned")
+            }
+        }
+    }
+}
+
+static LAYERS: OnceLock<Vec<Box<dyn Layer>>> = OnceLock::new();
+
+/// Returns the layers every call goes through, in order.
+fn layers() -> &'static [Box<dyn Layer>] {
+    LAYERS.get_or_init(|| {
+        vec![
+            Box::new(RateLimit),
+            Box::new(InFlight),
+            Box::new(Stats),
+            Box::new(RequestLog),
+            Box::new(StatusCounts),
+            Box::new(Throttle),
+            Box::new(SlowRequests),
+            Box::new(Errors),
+            Box::new(Manifest),
+            Box::new(Log),
+        ]
+    })
+}
+
+/// Runs each layer's `before` hook for a call to `endpoint`, stopping at the
+/// first one that fails the call.
+async fn before(endpoint: &'static str) -> Result<(), OxideApiError> {
+    for layer in layers() {
+        layer.before(endpoint).await?;
+    }
+
+    Ok(())
+}
+
+fn after(call: &Call<'_>) {
+    for layer in layers() {
+        layer.after(call);
+    }
+}
+
+tokio::task_local! {
+    /// Cancelled when the actor whose step is running is halted.
+    static CANCEL: CancellationToken;
+}
+
+/// Runs `step`, one of an actor's steps, such that its API calls (and its
+/// naps between them) are abandoned once `cancel` is cancelled.
+pub async fn cancellable<F: Future>(
+    cancel: CancellationToken,
+    step: F,
+) -> F::Output {
+    CANCEL.scope(cancel, step).await
+}
+
+/// Waits until the running step is cancelled, or forever if this isn't a
+/// step.
+pub async fn cancelled() {
+    match CANCEL.try_with(Clone::clone) {
+        Ok(cancel) => cancel.cancelled().await,
+        Err(_) => std::future::pending().await,
+    }
+}
+
+/// Records that the running step's call to `endpoint` was abandoned because
+/// the step was cancelled, and returns the error the call fails with. The
+/// call never finished, so the layers aren't told about it, except that it's
+/// no longer in flight.
+fn abandon(endpoint: &'static str) -> OxideApiError {
+    debug!(endpoint, "request cancelled");
+    if let Some(actor) = crate::actor::current_actor() {
+        crate::stats::record_cancelled(actor.kind);
+    }
+    crate::status::call_finished(false);
+    oxide::Error::InvalidRequest(format!("call to {} cancelled", endpoint))
+}
+
+tokio::task_local! {
+    /// The write the running step abandoned on purpose, if it abandoned one.
+    static ABANDONED: Cell<Option<&'static str>>;
+}
+
+/// Runs `step`, one of an actor's steps, such that its writes may be abandoned
+/// at random (--abandon-requests). Returns the step's output and the endpoint
+/// of the write it abandoned, if it abandoned one.
+pub async fn abandonable<F: Future>(
+    step: F,
+) -> (F::Output, Option<&'static str>) {
+    ABANDONED
+        .scope(Cell::new(None), async {
+            let output = step.await;
+            (output, ABANDONED.with(Cell::get))
+        })
+        .await
+}
+
+/// Returns how long to let a call to `endpoint` run before abandoning it, or
+/// `None` if it shouldn't be abandoned. Only writes made during a step are
+/// abandoned, and only with probability --abandon-requests, decided with the
+/// actor's seeded random number generator.
+fn abandon_after(endpoint: &'static str) -> Option<Duration> {
+    use rand::Rng;
+
+    let config = crate::config();
+    if config.abandon_requests == 0.0
+        || is_read(endpoint)
+        || ABANDONED.try_with(|_| ()).is_err()
+    {
+        return None;
+    }
+
+    crate::actor::with_request_rng(|rng| {
+        rng.gen_bool(config.abandon_requests)
+            .then(|| config.abandon_max_wait.mul_f64(rng.gen_range(0.0..=1.0)))
+    })
+    .flatten()
+}
+
+/// Awaits `send`, giving up on it and returning `None` once `wait` has passed,
+/// if it's set.
+async fn within<F: Future>(
+    send: F,
+    wait: Option<Duration>,
+) -> Option<F::Output> {
+    match wait {
+        Some(wait) => tokio::time::timeout(wait, send).await.ok(),
+        None => Some(send.await),
+    }
+}
+
+/// Records that the running step abandoned its call to `endpoint` on purpose,
+/// and returns the error the call fails with. As with a cancelled call, the
+/// layers aren't told about it, except that it's no longer in flight.
+fn abandon_on_purpose(endpoint: &'static str) -> OxideApiError {
+    debug!(endpoint, "request abandoned");
+    if let Some(actor) = crate::actor::current_actor() {
+        crate::stats::record_abandoned(actor.kind);
+    }
+    crate::status::call_finished(false);
+    ABANDONED.with(|abandoned| abandoned.set(Some(endpoint)));
+    oxide::Error::InvalidRequest(format!("call to {} abandoned", endpoint))
+}
+
+/// Waits before retrying an actor's call to `endpoint`, which failed with
+/// `err` after `attempt` previous retries. Returns false if the call shouldn't
+/// be retried: because it isn't an actor's call, the error isn't transient,
+/// retries are used up, or the step was cancelled while waiting.
+async fn retry_wait(
+    endpoint: &'static str,
+    err: &OxideApiError,
+    attempt: u32,
+) -> bool {
+    let Some(actor) = crate::actor::current_actor() else {
+        return false;
+    };
+    let delay = crate::actor::with_request_rng(|rng| {
+        crate::retry::delay(err, attempt, rng)
+    });
+    let Some(delay) = delay.flatten() else {
+        return false;
+    };
+
+    warn!(endpoint, ?delay, attempt = attempt + 1, error = %err, "retrying request");
+    crate::stats::record_retry(actor.kind);
+    tokio::select! {
+        biased;
+        _ = cancelled() => false,
+        _ = tokio::time::sleep(delay) => true,
+    }
+}
+
+/// Sends a request to the API endpoint named `endpoint` (e.g.
+/// `instance_stop`) with `send`, running it through the layers. If the call is
+/// an actor's and fails for a transient reason, it's sent again with `send`,
+/// through the layers again, as --retry-attempts allows.
+pub async fn call<T, F, Fut>(
+    endpoint: &'static str,
+    mut send: F,
+) -> Result<oxide::ResponseValue<T>, OxideApiError>
+where
+    T: Debug + Serialize,
+    F: FnMut() -> Fut,
+    Fut: Future<Output = Result<oxide::ResponseValue<T>, OxideApiError>>,
+{
+    let mut attempt = 0;
+    loop {
+        let result = call_once(endpoint, attempt, send()).await;
+        match &result {
+            Err(e) if retry_wait(endpoint, e, attempt).await => attempt += 1,
+            _ => return result,
+        }
+    }
+}
+
+/// Awaits `send`, attempt `attempt` at a request to `endpoint`, running it
+/// through the layers.
+async fn call_once<T: Debug + Serialize>(
+    endpoint: &'static str,
+    attempt: u32,
+    send: impl Future<Output = Result<oxide::ResponseValue<T>, OxideApiError>>,
+) -> Result<oxide::ResponseValue<T>, OxideApiError> {
+    let start = Instant::now();
+    let abandon_after = abandon_after(endpoint);
+    let result = tokio::select! {
+        biased;
+        _ = cancelled() => return Err(abandon(endpoint)),
+        result = async {
+            if let Err(e) = before(endpoint).await {
+                return Some(Err(e));
+            }
+            within(send, abandon_after).await
+        } => result,
+    };
+    let Some(result) = result else {
+        return Err(abandon_on_purpose(endpoint));
+    };
+    let latency = start.elapsed();
+
+    let (status, request_id) = match &result {
+        Ok(rv) => (
+            Some(rv.status()),
+            rv.headers()
+                .get(crate::request_log::REQUEST_ID_HEADER)
+                .and_then(|id| id.to_str().ok()),
+        ),
+        Err(oxide::Error::ErrorResponse(rv)) => {
+            (Some(rv.status()), Some(rv.request_id.as_str()))
+        }
+        Err(e) => (e.status(), None),
+    };
+    let created = match &result {
+        Ok(rv)
+            if endpoint.ends_with("_create") && crate::manifest::is_open() =>
+        {
+            serde_json::to_value(&**rv).ok()
+        }
+        _ => None,
+    };
+
+    after(&Call {
+        endpoint,
+        attempt,
+        latency,
+        status,
+        request_id,
+        result: result.as_ref().map(|rv| &**rv as &dyn Debug),
+        created: created.as_ref(),
+    });
+    result
+}
+
+/// Like `call`, but for `list`, which makes a paginated listing from the
+/// endpoint named `endpoint`. The recorded latency covers every page, and a
+/// retry starts over from the first page.
+pub async fn list<T, F, Fut>(
+    endpoint: &'static str,
+    mut list: F,
+) -> Result<Vec<T>, OxideApiError>
+where
+    T: Debug,
+    F: FnMut() -> Fut,
+    Fut: Future<Output = Result<Vec<T>, OxideApiError>>,
+{
+    let mut attempt = 0;
+    loop {
+        let result = list_once(endpoint, attempt, list()).await;
+        match &result {
+            Err(e) if retry_wait(endpoint, e, attempt).await => attempt += 1,
+            _ => return result,
+        }
+    }
+}
+
+/// Awaits `list`, attempt `attempt` at a listing from `endpoint`, running it
+/// through the layers.
+async fn list_once<T: Debug>(
+    endpoint: &'static str,
+    attempt: u32,
+    list: impl Future<Output = Result<Vec<T>, OxideApiError>>,
+) -> Result<Vec<T>, OxideApiError> {
+    let start = Instant::now();
+    let result = tokio::select! {
+        biased;
+        _ = cancelled() => return Err(abandon(endpoint)),
+        result = async {
+            before(endpoint).await?;
+            list.await
+        } => result,
+    };
+    let latency = start.elapsed();
+
+    // Listings' request IDs are only available if a page fails.
+    let (status, request_id) = match &result {
+        Ok(_) => (Some(http::StatusCode::OK), None),
+        Err(oxide::Error::ErrorResponse(rv)) => {
+            (Some(rv.status()), Some(rv.request_id.as_str()))
+        }
+        Err(e) => (e.status(), None),
+    };
+
+    after(&Call {
+        endpoint,
+        attempt,
+        latency,
+        status,
+        request_id,
+        result: result.as_ref().map(|items| items as &dyn Debug),
+        created: None,
+    });
+    result
+}
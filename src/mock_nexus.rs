@@ -0,0 +1,890 @@
+//! The mock Nexus (`--mock-nexus`), an embedded stand-in for the parts of the
+//! Oxide API the harness uses, so that the actors' decision logic, the error
+//! policy, shutdown, and reporting can be exercised without a rack.
+//!
+//! The mock keeps projects, instances, disks, snapshots, images, VPCs and
+//! their subnets, and IP pool ranges in memory. Resources in transitional
+//! states (an instance that's starting, a snapshot that's being created, and
+//! so on) settle by the next request. It mimics a few of Nexus's checks: names
+//! are unique within a project, an instance has to be stopped to be deleted, a
+//! disk has to be detached, and a project can't be deleted while it has a VPC
+//! (each new project gets a default VPC with a default subnet, as in Nexus),
+//! nor a VPC while it has a subnet.
+//!
+//! Creates are validated against Nexus's limits on names, sizes, and counts,
+//! so that the fuzzers' and boundary probers' requests are accepted or
+//! rejected the way Nexus would. A body that Nexus couldn't deserialize (a
+//! field of the wrong type, a missing field, an unknown variant) gets a 400
+//! with no error code, as Nexus's HTTP layer sends. The disks and NICs that an
+//! instance create asks for are counted but not created, and instances don't
+//! hold on to the disks they attach.
+//!
+//! Every list fits on one page, there are no floating IPs, and requests for
+//! anything else get a 404.
+//!
+//! With `--mock-error-rate`, requests that create, change, or delete
+//! instances, disks, and snapshots get an error response with
+//! `--mock-error-status` instead of being handled. Views and lists are never
+//! failed, so the harness's own setup and leak check aren't affected. Which
+//! requests fail is drawn from a generator seeded from the run's `--seed`.
+
+use std::collections::BTreeMap;
+use std::convert::Infallible;
+use std::net::SocketAddr;
+use std::sync::{Mutex, OnceLock};
+
+use anyhow::{Context, Result};
+use hyper::service::{make_service_fn, service_fn};
+use hyper::{Body, Method, StatusCode};
+use rand::rngs::StdRng;
+use rand::Rng;
+use serde_json::{json, Value};
+use tracing::{debug, info, warn};
+use uuid::Uuid;
+
+/// The token the harness sends the mock, which doesn't check it.
+pub const TOKEN: &str = "mock-nexus";
+
+const GIB: u64 = 1024 * 1024 * 1024;
+
+/// Nexus's limits on what can be created.
+const MAX_NAME_LEN: usize = 63;
+const MAX_DESCRIPTION_LEN: usize = 512;
+const MAX_HOSTNAME_LEN: usize = 253;
+const MAX_DISK_SIZE: u64 = 1023 * GIB;
+const MIN_INSTANCE_MEMORY: u64 = GIB;
+const MAX_INSTANCE_MEMORY: u64 = 256 * GIB;
+const MAX_VCPUS: u64 = 64;
+const MAX_NICS: usize = 8;
+const MAX_DISKS: usize = 8;
+
+/// The base URI of the running mock.
+static URI: OnceLock<String> = OnceLock::new();
+
+/// Decides which requests get injected errors.
+static RNG: OnceLock<Mutex<StdRng>> = OnceLock::new();
+
+/// The kinds of resources that live in a project.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+enum Kind {
+    Instance,
+    Disk,
+    Snapshot,
+    Image,
+}
+
+impl Kind {
+    /// Returns the kind of resource in the API collection named `collection`,
+    /// e.g. `instances`.
+    fn of_collection(collection: &str) -> Option<Self> {
+        match collection {
+            "instances" => Some(Kind::Instance),
+            "disks" => Some(Kind::Disk),
+            "snapshots" => Some(Kind::Snapshot),
+            "images" => Some(Kind::Image),
+            _ => None,
+        }
+    }
+
+    fn name(self) -> &'static str {
+        match self {
+            Kind::Instance => "instance",
+            Kind::Disk => "disk",
+            Kind::Snapshot => "snapshot",
+            Kind::Image => "image",
+        }
+    }
+}
+
+/// An error response, shaped like the ones Nexus sends.
+struct ApiError {
+    status: StatusCode,
+
+    /// The error code, which Nexus leaves out of errors the HTTP layer
+    /// raises, e.g. for a request body that doesn't deserialize.
+    code: Option<&'static str>,
+    message: String,
+}
+
+impl ApiError {
+    fn invalid(message: impl Into<String>) -> Self {
+        Self {
+            status: StatusCode::BAD_REQUEST,
+            code: Some("InvalidRequest"),
+            message: message.into(),
+        }
+    }
+
+    /// A request that doesn't deserialize, which Nexus rejects without an
+    /// error code.
+    fn bad_request(message: impl Into<String>) -> Self {
+        Self {
+            status: StatusCode::BAD_REQUEST,
+            code: None,
+            message: message.into(),
+        }
+    }
+
+    fn not_found(kind: &str, key: &str) -> Self {
+        Self {
+            status: StatusCode::NOT_FOUND,
+            code: Some("ObjectNotFound"),
+            message: format!("not found: {} with name or id \"{}\"", kind, key),
+        }
+    }
+
+    fn already_exists(kind: Kind, name: &str) -> Self {
+        Self {
+            status: StatusCode::BAD_REQUEST,
+            code: Some("ObjectAlreadyExists"),
+            message: format!("already exists: {} \"{}\"", kind.name(), name),
+        }
+    }
+
+    /// The error the mock injects with --mock-error-rate.
+    fn injected(status: u16) -> Self {
+        let status = StatusCode::from_u16(status)
+            .expect("--mock-error-status is a valid status code");
+        let code = match status {
+            StatusCode::INTERNAL_SERVER_ERROR => "Internal",
+            StatusCode::SERVICE_UNAVAILABLE => "ServiceNotAvailable",
+            StatusCode::TOO_MANY_REQUESTS => "TooManyRequests",
+            _ => "InvalidRequest",
+        };
+        Self {
+            status,
+            code: Some(code),
+            message: "injected by the mock Nexus".into(),
+        }
+    }
+}
+
+/// What a successful request gets back: its status and body, if it has one.
+type Reply = (StatusCode, Option<Value>);
+
+/// Everything the mock knows about.
+#[derive(Default)]
+struct State {
+    /// Projects, keyed by name.
+    projects: BTreeMap<String, Value>,
+
+    /// Instances, disks, snapshots, and images, keyed by project name, kind,
+    /// and name.
+    resources: BTreeMap<(String, Kind, String), Value>,
+
+    /// VPCs, keyed by project name and name.
+    vpcs: BTreeMap<(String, String), Value>,
+
+    /// VPC subnets, keyed by project name, VPC name, and name.
+    subnets: BTreeMap<(String, String, String), Value>,
+
+    /// Each IP pool's ranges, keyed by pool name.
+    ip_ranges: BTreeMap<String, Vec<Value>>,
+}
+
+static STATE: OnceLock<Mutex<State>> = OnceLock::new();
+
+/// Returns the common fields of a new resource named `name`.
+fn identity(name: &str, description: &Value) -> Value {
+    let now = chrono::Utc::now();
+    json!({
+        "id": Uuid::new_v4(),
+        "name": name,
+        "description": description.as_str().unwrap_or_default(),
+        "time_created": now,
+        "time_modified": now,
+    })
+}
+
+/// Returns the `name` field of a create request's `body`.
+fn name_of(body: &Value) -> Result<&str, ApiError> {
+    body["name"].as_str().ok_or_else(|| ApiError::bad_request("missing name"))
+}
+
+/// Returns the `field` of a create request's `body`, which should be a
+/// non-negative integer that fits in `max`.
+fn uint_of(body: &Value, field: &str, max: u64) -> Result<u64, ApiError> {
+    body[field].as_u64().filter(|n| *n <= max).ok_or_else(|| {
+        ApiError::bad_request(format!(
+            "{}: expected an unsigned integer no larger than {}",
+            field, max
+        ))
+    })
+}
+
+/// Checks the name and description of a create request's `body`. Nexus
+/// rejects a bad name when it deserializes the body.
+fn check_identity(body: &Value) -> Result<(), ApiError> {
+    let name = name_of(body)?;
+    let valid = name.len() <= MAX_NAME_LEN
+        && name.starts_with(|c: char| c.is_ascii_lowercase())
+        && name
+            .chars()
+            .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '-');
+    if !valid {
+        return Err(ApiError::bad_request(format!(
+            "name: invalid name {:?}",
+            name
+        )));
+    }
+
+    if body["description"].as_str().map_or(0, str::len) > MAX_DESCRIPTION_LEN {
+        return Err(ApiError::bad_request(format!(
+            "description: longer than {} characters",
+            MAX_DESCRIPTION_LEN
+        )));
+    }
+
+    Ok(())
+}
+
+/// Checks an instance create request's `body` against Nexus's limits.
+fn check_instance(body: &Value) -> Result<(), ApiError> {
+    let ncpus = uint_of(body, "ncpus", u16::MAX.into())?;
+    if ncpus == 0 || ncpus > MAX_VCPUS {
+        return Err(ApiError::invalid(format!(
+            "cannot have {} vCPUs; the limit is {}",
+            ncpus, MAX_VCPUS
+        )));
+    }
+
+    let memory = uint_of(body, "memory", u64::MAX)?;
+    if !(MIN_INSTANCE_MEMORY..=MAX_INSTANCE_MEMORY).contains(&memory)
+        || memory % GIB != 0
+    {
+        return Err(ApiError::invalid(format!(
+            "memory must be a multiple of 1 GiB between {} and {} bytes",
+            MIN_INSTANCE_MEMORY, MAX_INSTANCE_MEMORY
+        )));
+    }
+
+    let hostname = body["hostname"].as_str().unwrap_or_default();
+    let valid = !hostname.is_empty()
+        && hostname.len() <= MAX_HOSTNAME_LEN
+        && hostname.split('.').all(|label| {
+            !label.is_empty()
+                && !label.starts_with('-')
+                && !label.ends_with('-')
+                && label.chars().all(|c| c.is_ascii_alphanumeric() || c == '-')
+        });
+    if !valid {
+        return Err(ApiError::bad_request(format!(
+            "hostname: invalid hostname {:?}",
+            hostname
+        )));
+    }
+
+    let nics = &body["network_interfaces"];
+    let nics = match nics["type"].as_str() {
+        Some("none" | "default") => 0,
+        Some("create") => nics["params"].as_array().map_or(0, Vec::len),
+        _ => {
+            return Err(ApiError::bad_request(
+                "network_interfaces: unknown variant",
+            ))
+        }
+    };
+    if nics > MAX_NICS {
+        return Err(ApiError::invalid(format!(
+            "an instance may not have more than {} network interfaces",
+            MAX_NICS
+        )));
+    }
+
+    if body["disks"].as_array().map_or(0, Vec::len) > MAX_DISKS {
+        return Err(ApiError::invalid(format!(
+            "cannot attach more than {} disks to instance",
+            MAX_DISKS
+        )));
+    }
+
+    Ok(())
+}
+
+/// Checks a disk create request's `body` against Nexus's limits, and returns
+/// the disk's block size.
+fn check_disk(body: &Value) -> Result<u64, ApiError> {
+    let source = &body["disk_source"];
+    let block_size = match source["type"].as_str() {
+        Some("blank" | "importing_blocks") => {
+            match source["block_size"].as_u64() {
+                Some(size @ (512 | 2048 | 4096)) => size,
+                _ => {
+                    return Err(ApiError::bad_request(
+                        "disk_source.block_size: must be 512, 2048, or 4096",
+                    ))
+                }
+            }
+        }
+        Some("snapshot" | "image") => 512,
+        _ => return Err(ApiError::bad_request("disk_source: unknown variant")),
+    };
+
+    let size = uint_of(body, "size", u64::MAX)?;
+    if size % block_size != 0 {
+        return Err(ApiError::invalid(format!(
+            "disk size must be a multiple of the block size ({})",
+            block_size
+        )));
+    }
+    if !(GIB..=MAX_DISK_SIZE).contains(&size) || size % GIB != 0 {
+        return Err(ApiError::invalid(format!(
+            "disk size must be a multiple of 1 GiB between {} and {} bytes",
+            GIB, MAX_DISK_SIZE
+        )));
+    }
+
+    Ok(block_size)
+}
+
+/// Returns a list response holding `items`.
+fn page(items: Vec<Value>) -> Reply {
+    (StatusCode::OK, Some(json!({ "items": items, "next_page": null })))
+}
+
+impl State {
+    /// Moves every resource in a transitional state to the state it's headed
+    /// for.
+    fn settle(&mut self) {
+        let now = chrono::Utc::now();
+        for ((_, kind, _), resource) in &mut self.resources {
+            match kind {
+                Kind::Instance => {
+                    let next = match resource["run_state"].as_str() {
+                        Some("creating" | "starting" | "rebooting") => {
+                            "running"
+                        }
+                        Some("stopping") => "stopped",
+                        _ => continue,
+                    };
+                    resource["run_state"] = json!(next);
+                    resource["time_run_state_updated"] = json!(now);
+                }
+                Kind::Disk if resource["state"]["state"] == "creating" => {
+                    resource["state"] = json!({ "state": "detached" });
+                }
+                Kind::Snapshot if resource["state"] == "creating" => {
+                    resource["state"] = json!("ready");
+                }
+                _ => {}
+            }
+        }
+    }
+
+    /// Returns the name of the project named by `query`, if it exists.
+    fn project(
+        &self,
+        query: &BTreeMap<&str, &str>,
+    ) -> Result<String, ApiError> {
+        let project = query
+            .get("project")
+            .ok_or_else(|| ApiError::bad_request("missing project"))?;
+        self.projects
+            .iter()
+            .find(|(name, p)| name.as_str() == *project || p["id"] == *project)
+            .map(|(name, _)| name.clone())
+            .ok_or_else(|| ApiError::not_found("project", project))
+    }
+
+    /// Returns the key of the resource of `kind` with the name or ID `key`.
+    /// Names are looked up in the project named by `query`; IDs anywhere.
+    fn find(
+        &self,
+        query: &BTreeMap<&str, &str>,
+        kind: Kind,
+        key: &str,
+    ) -> Result<(String, Kind, String), ApiError> {
+        if key.parse::<Uuid>().is_ok() {
+            return self
+                .resources
+                .iter()
+                .find(|((_, k, _), r)| *k == kind && r["id"] == key)
+                .map(|(key, _)| key.clone())
+                .ok_or_else(|| ApiError::not_found(kind.name(), key));
+        }
+
+        let project = self.project(query)?;
+        let key = (project, kind, key.to_owned());
+        if self.resources.contains_key(&key) {
+            Ok(key)
+        } else {
+            Err(ApiError::not_found(kind.name(), &key.2))
+        }
+    }
+
+    /// Returns the key of the VPC in the project named by `query` with the
+    /// name or ID `vpc`.
+    fn find_vpc(
+        &self,
+        query: &BTreeMap<&str, &str>,
+        vpc: &str,
+    ) -> Result<(String, String), ApiError> {
+        let project = self.project(query)?;
+        self.vpcs
+            .iter()
+            .find(|((p, name), v)| {
+                *p == project && (name.as_str() == vpc || v["id"] == vpc)
+            })
+            .map(|(key, _)| key.clone())
+            .ok_or_else(|| ApiError::not_found("vpc", vpc))
+    }
+
+    /// Creates the default VPC and subnet that Nexus gives every new
+    /// `project`.
+    fn create_default_vpc(&mut self, project: &str) {
+        let mut vpc = identity("default", &json!("Default VPC"));
+        vpc["project_id"] = self.projects[project]["id"].clone();
+        let mut subnet = identity("default", &json!("Default VPC subnet"));
+        subnet["vpc_id"] = vpc["id"].clone();
+        let key = (project.to_owned(), "default".to_owned());
+        self.vpcs.insert(key, vpc);
+        let key = (project.to_owned(), "default".into(), "default".into());
+        self.subnets.insert(key, subnet);
+    }
+
+    /// Creates a resource of `kind` in `project` from a create request's
+    /// `body`, with the fields in `fields` besides the common ones.
+    fn create(
+        &mut self,
+        project: String,
+        kind: Kind,
+        body: &Value,
+        fields: Value,
+    ) -> Result<Reply, ApiError> {
+        check_identity(body)?;
+        let name = name_of(body)?;
+        let key = (project, kind, name.to_owned());
+        if self.resources.contains_key(&key) {
+            return Err(ApiError::already_exists(kind, name));
+        }
+
+        let mut resource = identity(name, &body["description"]);
+        resource["project_id"] = self.projects[&key.0]["id"].clone();
+        if let (Some(resource), Value::Object(fields)) =
+            (resource.as_object_mut(), fields)
+        {
+            resource.extend(fields);
+        }
+        self.resources.insert(key, resource.clone());
+        Ok((StatusCode::CREATED, Some(resource)))
+    }
+
+    fn create_instance(
+        &mut self,
+        project: String,
+        body: &Value,
+    ) -> Result<Reply, ApiError> {
+        check_instance(body)?;
+        let start = body["start"].as_bool().unwrap_or(true);
+        let fields = json!({
+            "ncpus": body["ncpus"],
+            "memory": body["memory"],
+            "hostname": body["hostname"],
+            "run_state": if start { "starting" } else { "stopped" },
+            "time_run_state_updated": chrono::Utc::now(),
+            "auto_restart_enabled": false,
+            "boot_disk_id": null,
+        });
+        self.create(project, Kind::Instance, body, fields)
+    }
+
+    fn create_disk(
+        &mut self,
+        project: String,
+        body: &Value,
+    ) -> Result<Reply, ApiError> {
+        let name = name_of(body)?;
+        let block_size = check_disk(body)?;
+        let fields = json!({
+            "size": body["size"],
+            "block_size": block_size,
+            "state": { "state": "creating" },
+            "device_path": format!("/mnt/{}", name),
+            "image_id": null,
+            "snapshot_id": null,
+        });
+        self.create(project, Kind::Disk, body, fields)
+    }
+
+    fn create_snapshot(
+        &mut self,
+        project: String,
+        body: &Value,
+        query: &BTreeMap<&str, &str>,
+    ) -> Result<Reply, ApiError> {
+        let disk = body["disk"]
+            .as_str()
+            .ok_or_else(|| ApiError::bad_request("missing disk"))?;
+        let disk = &self.resources[&self.find(query, Kind::Disk, disk)?];
+        let fields = json!({
+            "disk_id": disk["id"],
+            "size": disk["size"],
+            "state": "creating",
+        });
+        self.create(project, Kind::Snapshot, body, fields)
+    }
+
+    /// Creates an image from the snapshot that a create request's `body`
+    /// names by ID.
+    fn create_image(
+        &mut self,
+        project: String,
+        body: &Value,
+        query: &BTreeMap<&str, &str>,
+    ) -> Result<Reply, ApiError> {
+        let source = &body["source"];
+        let snapshot = match (source["type"].as_str(), source["id"].as_str()) {
+            (Some("snapshot"), Some(id)) => id,
+            _ => return Err(ApiError::bad_request("source: unknown variant")),
+        };
+        let snapshot =
+            &self.resources[&self.find(query, Kind::Snapshot, snapshot)?];
+        let fields = json!({
+            "block_size": 512,
+            "size": snapshot["size"],
+            "os": body["os"],
+            "version": body["version"],
+            "digest": null,
+        });
+        self.create(project, Kind::Image, body, fields)
+    }
+
+    /// Deletes the resource with `key`, if Nexus would let it be deleted.
+    fn delete(
+        &mut self,
+        key: (String, Kind, String),
+    ) -> Result<Reply, ApiError> {
+        let resource = &self.resources[&key];
+        let state = match key.1 {
+            Kind::Instance => resource["run_state"].as_str(),
+            Kind::Disk => resource["state"]["state"].as_str(),
+            Kind::Snapshot | Kind::Image => None,
+        };
+        match (key.1, state) {
+            (Kind::Instance, Some("stopped" | "failed"))
+            | (Kind::Disk, Some("detached" | "faulted"))
+            | (Kind::Snapshot | Kind::Image, _) => {}
+            (kind, state) => {
+                return Err(ApiError::invalid(format!(
+                    "cannot delete {} in state {}",
+                    kind.name(),
+                    state.unwrap_or("unknown")
+                )));
+            }
+        }
+
+        self.resources.remove(&key);
+        Ok((StatusCode::NO_CONTENT, None))
+    }
+
+    /// Starts, stops, or reboots the instance with `key`.
+    fn change_instance(
+        &mut self,
+        key: (String, Kind, String),
+        action: &str,
+    ) -> Result<Reply, ApiError> {
+        let instance = self.resources.get_mut(&key).unwrap();
+        let state = instance["run_state"].as_str().unwrap_or_default();
+        let next = match (action, state) {
+            ("start", "stopped") => "starting",
+            ("start", "starting" | "running") => state,
+            ("stop", "starting" | "running" | "rebooting") => "stopping",
+            ("stop", "stopping" | "stopped") => state,
+            ("reboot", "running") => "rebooting",
+            _ => {
+                return Err(ApiError::invalid(format!(
+                    "instance state cannot be changed from {}",
+                    state
+                )));
+            }
+        };
+
+        if next != state {
+            instance["run_state"] = json!(next);
+            instance["time_run_state_updated"] = json!(chrono::Utc::now());
+        }
+        Ok((StatusCode::ACCEPTED, Some(instance.clone())))
+    }
+
+    /// Handles a request for the API path made of `segments`.
+    fn route(
+        &mut self,
+        method: &Method,
+        segments: &[&str],
+        query: &BTreeMap<&str, &str>,
+        body: &Value,
+    ) -> Result<Reply, ApiError> {
+        self.settle();
+        match (method, segments) {
+            (&Method::GET, ["projects"]) => {
+                Ok(page(self.projects.values().cloned().collect()))
+            }
+            (&Method::POST, ["projects"]) => {
+                let name = name_of(body)?;
+                if self.projects.contains_key(name) {
+                    return Err(ApiError::invalid(format!(
+                        "already exists: project \"{}\"",
+                        name
+                    )));
+                }
+                let project = identity(name, &body["description"]);
+                self.projects.insert(name.to_owned(), project.clone());
+                self.create_default_vpc(name);
+                Ok((StatusCode::CREATED, Some(project)))
+            }
+            (&Method::GET, ["projects", project]) => {
+                let query = BTreeMap::from([("project", *project)]);
+                let name = self.project(&query)?;
+                Ok((StatusCode::OK, Some(self.projects[&name].clone())))
+            }
+            (&Method::DELETE, ["projects", project]) => {
+                let query = BTreeMap::from([("project", *project)]);
+                let name = self.project(&query)?;
+                if self.resources.keys().any(|(p, _, _)| *p == name) {
+                    return Err(ApiError::invalid(
+                        "project to be deleted \
+                        contains resources",
+                    ));
+                }
+                if let Some((_, vpc)) =
+                    self.vpcs.keys().find(|(p, _)| *p == name)
+                {
+                    return Err(ApiError::invalid(format!(
+                        "project to be deleted contains a vpc: {}",
+                        vpc
+                    )));
+                }
+                self.projects.remove(&name);
+                Ok((StatusCode::NO_CONTENT, None))
+            }
+            (&Method::GET, ["vpcs"]) => {
+                let project = self.project(query)?;
+                let items = self
+                    .vpcs
+                    .iter()
+                    .filter(|((p, _), _)| *p == project)
+                    .map(|(_, v)| v.clone())
+                    .collect();
+                Ok(page(items))
+            }
+            (&Method::DELETE, ["vpcs", vpc]) => {
+                let key = self.find_vpc(query, vpc)?;
+                if self
+                    .subnets
+                    .keys()
+                    .any(|(p, v, _)| (p, v) == (&key.0, &key.1))
+                {
+                    return Err(ApiError::invalid(
+                        "VPC cannot be deleted while VPC Subnets exist",
+                    ));
+                }
+                self.vpcs.remove(&key);
+                Ok((StatusCode::NO_CONTENT, None))
+            }
+            (&Method::GET, ["vpc-subnets"]) => {
+                let vpc = query
+                    .get("vpc")
+                    .ok_or_else(|| ApiError::bad_request("missing vpc"))?;
+                let (project, vpc) = self.find_vpc(query, vpc)?;
+                let items = self
+                    .subnets
+                    .iter()
+                    .filter(|((p, v, _), _)| (p, v) == (&project, &vpc))
+                    .map(|(_, s)| s.clone())
+                    .collect();
+                Ok(page(items))
+            }
+            (&Method::DELETE, ["vpc-subnets", subnet]) => {
+                let vpc = query
+                    .get("vpc")
+                    .ok_or_else(|| ApiError::bad_request("missing vpc"))?;
+                let (project, vpc) = self.find_vpc(query, vpc)?;
+                self.subnets
+                    .remove(&(project, vpc, subnet.to_string()))
+                    .ok_or_else(|| ApiError::not_found("vpc-subnet", subnet))?;
+                Ok((StatusCode::NO_CONTENT, None))
+            }
+            (&Method::GET, ["system", "ip-pools", pool, "ranges"]) => {
+                let ranges = self.ip_ranges.get(*pool);
+                Ok(page(ranges.cloned().unwrap_or_default()))
+            }
+            (&Method::POST, ["system", "ip-pools", pool, "ranges", "add"]) => {
+                let range = json!({
+                    "id": Uuid::new_v4(),
+                    "ip_pool_id": Uuid::new_v4(),
+                    "time_created": chrono::Utc::now(),
+                    "range": body,
+                });
+                let ranges = self.ip_ranges.entry(pool.to_string());
+                ranges.or_default().push(range.clone());
+                Ok((StatusCode::CREATED, Some(range)))
+            }
+            (
+                &Method::POST,
+                ["system", "ip-pools", pool, "ranges", "remove"],
+            ) => {
+                if let Some(ranges) = self.ip_ranges.get_mut(*pool) {
+                    ranges.retain(|r| r["range"] != *body);
+                }
+                Ok((StatusCode::NO_CONTENT, None))
+            }
+            (&Method::GET, ["system", "update", "status"]) => Ok((
+                StatusCode::OK,
+                Some(json!({ "target_release": { "version": "mock" } })),
+            )),
+            (&Method::GET, ["floating-ips"]) => Ok(page(vec![])),
+            (&Method::GET, ["instances", instance, "external-ips"]) => {
+                self.find(query, Kind::Instance, instance)?;
+                Ok(page(vec![]))
+            }
+            (&Method::POST, ["instances", instance, action]) => {
+                let key = self.find(query, Kind::Instance, instance)?;
+                self.change_instance(key, action)
+            }
+            (&Method::GET, [collection]) => {
+                let kind =
+                    Kind::of_collection(collection).ok_or_else(|| {
+                        ApiError::not_found("endpoint", collection)
+                    })?;
+                let project = self.project(query)?;
+                let items = self
+                    .resources
+                    .iter()
+                    .filter(|((p, k, _), _)| *p == project && *k == kind)
+                    .map(|(_, r)| r.clone())
+                    .collect();
+                Ok(page(items))
+            }
+            (&Method::POST, [collection]) => {
+                let project = self.project(query)?;
+                match Kind::of_collection(collection) {
+                    Some(Kind::Instance) => self.create_instance(project, body),
+                    Some(Kind::Disk) => self.create_disk(project, body),
+                    Some(Kind::Snapshot) => {
+                        self.create_snapshot(project, body, query)
+                    }
+                    Some(Kind::Image) => {
+                        self.create_image(project, body, query)
+                    }
+                    None => Err(ApiError::not_found("endpoint", collection)),
+                }
+            }
+            (&Method::GET, [collection, key]) => {
+                let kind =
+                    Kind::of_collection(collection).ok_or_else(|| {
+                        ApiError::not_found("endpoint", collection)
+                    })?;
+                let key = self.find(query, kind, key)?;
+                Ok((StatusCode::OK, Some(self.resources[&key].clone())))
+            }
+            (&Method::DELETE, [collection, key]) => {
+                let kind =
+                    Kind::of_collection(collection).ok_or_else(|| {
+                        ApiError::not_found("endpoint", collection)
+                    })?;
+                let key = self.find(query, kind, key)?;
+                self.delete(key)
+            }
+            _ => Err(ApiError::not_found("endpoint", &segments.join("/"))),
+        }
+    }
+}
+
+/// Returns the response for `result`, with a fresh request ID.
+fn respond(result: Result<Reply, ApiError>) -> hyper::Response<Body> {
+    let request_id = Uuid::new_v4().to_string();
+    let (status, body) = match result {
+        Ok(reply) => reply,
+        Err(e) => (
+            e.status,
+            Some(json!({
+                "request_id": request_id,
+                "error_code": e.code,
+                "message": e.message,
+            })),
+        ),
+    };
+
+    let builder = hyper::Response::builder()
+        .status(status)
+        .header(crate::request_log::REQUEST_ID_HEADER, &request_id);
+    match body {
+        Some(body) => builder
+            .header(http::header::CONTENT_TYPE, "application/json")
+            .body(Body::from(body.to_string())),
+        None => builder.body(Body::empty()),
+    }
+    .unwrap()
+}
+
+async fn handle(
+    req: hyper::Request<Body>,
+) -> Result<hyper::Response<Body>, Infallible> {
+    let (parts, body) = req.into_parts();
+    let body = match hyper::body::to_bytes(body).await {
+        Ok(bytes) if bytes.is_empty() => Value::Null,
+        Ok(bytes) => match serde_json::from_slice(&bytes) {
+            Ok(body) => body,
+            Err(e) => {
+                return Ok(respond(Err(ApiError::bad_request(e.to_string()))))
+            }
+        },
+        Err(e) => {
+            return Ok(respond(Err(ApiError::bad_request(e.to_string()))))
+        }
+    };
+
+    let query: BTreeMap<&str, &str> = parts
+        .uri
+        .query()
+        .unwrap_or_default()
+        .split('&')
+        .filter_map(|pair| pair.split_once('='))
+        .collect();
+    let path = parts.uri.path();
+    let segments: Vec<&str> = match path.strip_prefix("/v1/") {
+        Some(rest) => rest.split('/').collect(),
+        None => {
+            return Ok(respond(Err(ApiError::not_found("endpoint", path))));
+        }
+    };
+
+    let config = crate::config();
+    let injectable = parts.method != Method::GET
+        && segments.first().is_some_and(|c| Kind::of_collection(c).is_some());
+    let rng =
+        RNG.get_or_init(|| Mutex::new(crate::util::actor_rng("mock-nexus")));
+    if injectable && rng.lock().unwrap().gen_bool(config.mock_error_rate) {
+        debug!(method = %parts.method, path, "mock Nexus injecting error");
+        return Ok(respond(Err(ApiError::injected(config.mock_error_status))));
+    }
+
+    let mut state = STATE.get_or_init(Default::default).lock().unwrap();
+    Ok(respond(state.route(&parts.method, &segments, &query, &body)))
+}
+
+/// Starts the mock Nexus on a local port.
+pub fn start() -> Result<()> {
+    let make_service =
+        make_service_fn(|_| async { Ok::<_, Infallible>(service_fn(handle)) });
+
+    let addr = SocketAddr::from(([127, 0, 0, 1], 0));
+    let server = hyper::Server::try_bind(&addr)
+        .context("binding mock Nexus")?
+        .serve(make_service);
+    let addr = server.local_addr();
+    info!(%addr, "Mock Nexus listening");
+    tokio::spawn(async move {
+        if let Err(e) = server.await {
+            warn!("mock Nexus failed: {:#}", e);
+        }
+    });
+
+    URI.set(format!("http://{}", addr))
+        .map_err(|_| anyhow::anyhow!("mock Nexus already started"))
+}
+
+/// Returns the mock Nexus's base URI. Panics if it hasn't been started.
+pub fn uri() -> &'static str {
+    URI.get().expect("mock Nexus should be started")
+}
@@ -0,0 +1,128 @@
+//! Webhook notifications (`--notify-webhook`), so that an unattended run that
+//! fails, or is about to, gets someone's attention.
+//!
+//! Each notification is POSTed as a JSON object. Its `text` field is a
+//! one-line summary, which is what chat services' incoming webhooks (e.g.
+//! Slack's) display; the other fields carry the details for anything that
+//! wants to act on them.
+
+use std::time::Duration;
+
+use serde::Serialize;
+use tracing::{info, warn};
+
+use crate::error_budget::ErrorBudget;
+use crate::report::{ReportError, ReportLeak};
+
+/// How many of the run's most recent errors a notification includes.
+const MAX_ERRORS: usize = 10;
+
+/// What a notification is about.
+#[derive(Clone, Copy, Debug, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Event {
+    /// The run has used up half of its error budget.
+    ErrorBudgetHalfSpent,
+
+    /// The run stopped because of an error.
+    RunFailed,
+}
+
+impl Event {
+    fn describe(self) -> &'static str {
+        match self {
+            Event::ErrorBudgetHalfSpent => "has used half its error budget",
+            Event::RunFailed => "failed",
+        }
+    }
+}
+
+/// A notification's body.
+#[derive(Debug, Serialize)]
+pub struct Notification {
+    pub text: String,
+    pub event: Event,
+
+    /// The run's ID, as in its report and logs.
+    pub run_id: String,
+
+    /// The run's name prefix, which identifies the run's resources.
+    pub name_prefix: String,
+    pub project: String,
+    pub seed: u64,
+    pub error_count: usize,
+
+    /// The run's most recent errors, oldest first.
+    pub errors: Vec<ReportError>,
+
+    /// The IDs of the requests behind `errors`, for looking up in Nexus's
+    /// logs.
+    pub request_ids: Vec<String>,
+
+    /// The resources left in the project that a clean end state wouldn't
+    /// have, if they've been looked for.
+    pub leaked_resources: Option<Vec<ReportLeak>>,
+}
+
+impl Notification {
+    /// Creates a notification about `event` with `budget`'s errors.
+    pub fn new(event: Event, budget: &ErrorBudget) -> Self {
+        let all = budget.errors();
+        let first = all.len().saturating_sub(MAX_ERRORS);
+        let errors: Vec<ReportError> =
+            all[first..].iter().map(Into::into).collect();
+        let request_ids =
+            errors.iter().filter_map(|e| e.request_id.clone()).collect();
+
+        let mut text = format!(
+            "omicron-stress run {} {} ({} errors)",
+            crate::util::name_prefix(),
+            event.describe(),
+            all.len()
+        );
+        if let Some(last) = errors.last() {
+            text.push_str(&format!("; last: {}", last.description));
+        }
+
+        Self {
+            text,
+            event,
+            run_id: crate::run_info::run_id().to_string(),
+            name_prefix: crate::util::name_prefix().to_owned(),
+            project: crate::project_name(),
+            seed: crate::util::seed(),
+            error_count: all.len(),
+            errors,
+            request_ids,
+            leaked_resources: None,
+        }
+    }
+}
+
+/// Posts `notification` to the --notify-webhook URL, if there is one. Failures
+/// are logged rather than returned, since they shouldn't affect the run.
+pub async fn send(notification: Notification) {
+    let Some(url) = &crate::config().notify_webhook else {
+        return;
+    };
+
+    let result = async {
+        reqwest::Client::builder()
+            .timeout(Duration::from_secs(30))
+            .build()?
+            .post(url.clone())
+            .header(http::header::CONTENT_TYPE, "application/json")
+            .body(serde_json::to_vec(&notification).unwrap())
+            .send()
+            .await?
+            .error_for_status()
+    }
+    .await;
+
+    match result {
+        Ok(_) => {
+            info!(event = ?notification.event, "Sent webhook notification")
+        }
+        Err(e) => warn!("failed to send webhook notification: {}", e),
+    }
+}
@@ -0,0 +1,150 @@
+//! The exit code contract, which tells CI wrappers why a run failed:
+//!
+//! - 0: The run completed within its error budget and left nothing behind
+//!   that it shouldn't have.
+//! - 1: The harness itself failed (e.g. it couldn't create the stress
+//!   project), or the error budget was exhausted by an error that isn't
+//!   covered below.
+//! - 2: The error budget was exhausted by an error response with a 5xx
+//!   status.
+//! - 3: The error budget was exhausted by a failed correctness check or a
+//!   resource stuck in an invalid state.
+//! - 4: The error budget was exhausted by a connectivity or infrastructure
+//!   failure: a request that got no response, an unreachable instance, a
+//!   stalled actor, or Nexus staying unavailable for longer than
+//!   --tolerate-unavailability. Actors that don't halt in time, and a failure
+//!   to check for leaks, also count.
+//! - 5: The run left leaked resources behind, or --cleanup-on-exit failed.
+//! - 6: The run saw more throttled (429 or 503) responses than
+//!   --throttle-budget allows, or the error budget was exhausted by one.
+//! - 130: The run was interrupted (with Ctrl-C or the control API) with
+//!   nothing else wrong.
+//!
+//! A run that was cut short exits with the code for whatever cut it short,
+//! even if it also leaked resources.
+//!
+//! The `cleanup` subcommand exits with 5 if it fails, and `coordinate` exits
+//! with the code for the first failed worker's run (or 4 if a worker's final
+//! report couldn't be fetched). Invalid command-line options exit with 1.
+
+use serde::{Deserialize, Serialize};
+
+use crate::actor::AntagonistError;
+
+/// Why a run failed.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Failure {
+    /// An error response with a 5xx status.
+    ServerError,
+
+    /// A failed correctness check or a resource in an invalid state.
+    CheckFailed,
+
+    /// A connectivity or infrastructure failure.
+    Infrastructure,
+
+    /// Leaked resources or a failed cleanup.
+    Leaks,
+
+    /// Nexus pushing back with 429 or 503 responses.
+    Throttled,
+
+    /// Anything else.
+    Other,
+}
+
+impl Failure {
+    /// Returns the kind of failure that `err`, a disqualifying error, is.
+    pub fn of(err: &AntagonistError) -> Self {
+        match err {
+            err if crate::throttle::is_throttled_error(err) => {
+                Failure::Throttled
+            }
+            AntagonistError::ApiError(oxide::Error::ErrorResponse(rv))
+                if rv.status().is_server_error() =>
+            {
+                Failure::ServerError
+            }
+            AntagonistError::ApiError(oxide::Error::CommunicationError(_))
+            | AntagonistError::Unreachable(_) => Failure::Infrastructure,
+            AntagonistError::CheckFailed(_)
+            | AntagonistError::InvalidState(_) => Failure::CheckFailed,
+            AntagonistError::ApiError(_)
+            | AntagonistError::DisconnectedErrorChannel { .. } => {
+                Failure::Other
+            }
+        }
+    }
+
+    /// Returns the exit code for this kind of failure.
+    pub fn exit_code(&self) -> u8 {
+        match self {
+            Failure::Other => 1,
+            Failure::ServerError => 2,
+            Failure::CheckFailed => 3,
+            Failure::Infrastructure => 4,
+            Failure::Leaks => 5,
+            Failure::Throttled => 6,
+        }
+    }
+}
+
+/// The exit code for a run that was interrupted with nothing else wrong.
+const INTERRUPTED: u8 = 130;
+
+/// Returns the one-line verdict of a --smoke run that failed with `failure`,
+/// if it failed, and was interrupted if `interrupted` is true.
+pub fn smoke_verdict(failure: Option<Failure>, interrupted: bool) -> String {
+    let code = exit_code(failure, interrupted);
+    match failure {
+        Some(failure) => {
+            format!("smoke test FAILED: {:?} (exit {})", failure, code)
+        }
+        None if interrupted => {
+            format!("smoke test INTERRUPTED (exit {})", code)
+        }
+        None => "smoke test PASSED".to_string(),
+    }
+}
+
+/// Returns the exit code for a run that failed with `failure`, if it failed,
+/// and was interrupted if `interrupted` is true.
+pub fn exit_code(failure: Option<Failure>, interrupted: bool) -> u8 {
+    match failure {
+        Some(failure) => failure.exit_code(),
+        None if interrupted => INTERRUPTED,
+        None => 0,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn exit_code_prefers_the_failure() {
+        assert_eq!(exit_code(None, false), 0);
+        assert_eq!(exit_code(None, true), INTERRUPTED);
+        assert_eq!(exit_code(Some(Failure::ServerError), false), 2);
+        assert_eq!(exit_code(Some(Failure::Throttled), true), 6);
+    }
+
+    #[test]
+    fn failures_have_distinct_exit_codes() {
+        let failures = [
+            Failure::Other,
+            Failure::ServerError,
+            Failure::CheckFailed,
+            Failure::Infrastructure,
+            Failure::Leaks,
+            Failure::Throttled,
+        ];
+        let mut codes: Vec<u8> =
+            failures.iter().map(|f| f.exit_code()).collect();
+        codes.sort();
+        codes.dedup();
+        assert_eq!(codes.len(), failures.len());
+        assert!(!codes.contains(&0) && !codes.contains(&INTERRUPTED));
+    }
+}
@@ -0,0 +1,387 @@
+//! The populate phase, which fills the stress project with a baseline of
+//! long-lived instances, disks, and snapshots before the antagonists start, so
+//! that the control plane is exercised against a realistically full database
+//! instead of an empty project.
+//!
+//! Baseline resources are claimed for the whole run so that janitors leave
+//! them alone. They aren't deleted when the run ends (unless
+//! `--cleanup-on-exit` is set); a later run with the same `--name-prefix`
+//! reuses any that still exist, and `omicron-stress cleanup` removes them.
+
+use std::collections::HashSet;
+use std::time::Duration;
+
+use anyhow::{bail, Context, Result};
+use futures::TryStreamExt;
+use oxide::types::{
+    BlockSize, ByteCount, DiskCreate, DiskSource, DiskState, InstanceCpuCount,
+    InstanceCreate, InstanceNetworkInterfaceAttachment, InstanceState, Name,
+    SnapshotCreate, SnapshotState,
+};
+use oxide::{ClientDisksExt, ClientInstancesExt, ClientSnapshotsExt};
+use tokio::time::Instant;
+use tracing::info;
+
+use crate::actor::ownership::{Claim, OwnedResource, ResourceKind};
+use crate::config::Config;
+
+/// How long to wait between checks on whether the baseline has settled.
+const POLL_INTERVAL: Duration = Duration::from_secs(2);
+
+/// Returns the name of the baseline instance with the supplied index.
+fn instance_name(index: usize) -> String {
+    format!("{}baseline-inst{}", crate::util::name_prefix(), index)
+}
+
+/// Returns the name of the baseline disk with the supplied index.
+fn disk_name(index: usize) -> String {
+    format!("{}baseline-disk{}", crate::util::name_prefix(), index)
+}
+
+/// Returns the name of the baseline snapshot with the supplied index.
+fn snapshot_name(index: usize) -> String {
+    format!("{}baseline-snap{}", crate::util::name_prefix(), index)
+}
+
+/// Returns true if a resource of the supplied `kind` and `name` is part of the
+/// baseline this run was configured to populate.
+pub fn is_baseline(kind: ResourceKind, name: &str) -> bool {
+    let config = crate::config();
+    if !config.populate {
+        return false;
+    }
+
+    match kind {
+        ResourceKind::Instance => {
+            (0..config.populate_instances).any(|i| instance_name(i) == name)
+        }
+        ResourceKind::Disk => {
+            (0..config.populate_disks).any(|i| disk_name(i) == name)
+        }
+        ResourceKind::Snapshot => {
+            (0..config.populate_snapshots).any(|i| snapshot_name(i) == name)
+        }
+        ResourceKind::Image => false,
+    }
+}
+
+/// Creates the baseline resources requested in `config` that don't already
+/// exist in `project`, then waits for all of them to settle: instances running,
+/// disks detached, and snapshots ready. Returns a claim on the baseline that
+/// keeps janitors from deleting it while the claim is held.
+pub async fn run(
+    client: &oxide::Client,
+    project: &str,
+    config: &Config,
+) -> Result<Claim> {
+    let (instances, disks, snapshots) = (
+        config.populate_instances,
+        config.populate_disks,
+        config.populate_snapshots,
+    );
+
+    if snapshots > 0 && disks == 0 {
+        bail!("--populate-snapshots requires at least one baseline disk");
+    }
+
+    info!(instances, disks, snapshots, "Populating stress project");
+    let mut resources = Vec::new();
+    for i in 0..instances {
+        resources
+            .push(OwnedResource::new(ResourceKind::Instance, instance_name(i)));
+    }
+    for i in 0..disks {
+        resources.push(OwnedResource::new(ResourceKind::Disk, disk_name(i)));
+    }
+    for i in 0..snapshots {
+        resources
+            .push(OwnedResource::new(ResourceKind::Snapshot, snapshot_name(i)));
+    }
+    let claim = Claim::new(resources);
+
+    let deadline = Instant::now() + config.populate_timeout;
+    create_disks(client, project, disks).await?;
+    create_instances(client, project, instances).await?;
+
+    // Snapshots can only be taken of disks that have finished creating.
+    wait_for_disks(client, project, disks, deadline).await?;
+    create_snapshots(client, project, snapshots, disks).await?;
+    wait_for_snapshots(client, project, snapshots, deadline).await?;
+    wait_for_instances(client, project, instances, deadline).await?;
+
+    info!("Stress project populated");
+    Ok(claim)
+}
+
+/// Creates whichever of the first `count` baseline disks don't exist yet.
+async fn create_disks(
+    client: &oxide::Client,
+    project: &str,
+    count: usize,
+) -> Result<()> {
+    let existing: HashSet<String> = client
+        .disk_list()
+        .project(project)
+        .stream()
+        .map_ok(|d| d.name.to_string())
+        .try_collect()
+        .await
+        .context("listing disks")?;
+
+    for name in (0..count).map(disk_name) {
+        if existing.contains(&name) {
+            continue;
+        }
+
+        info!(name, "creating baseline disk");
+        let body = DiskCreate {
+            description: crate::run_info::description(&name),
+            disk_source: DiskSource::Blank {
+                block_size: BlockSize::try_from(512_i64).unwrap(),
+            },
+            name: Name::try_from(&name).unwrap(),
+            size: ByteCount::from(1024 * 1024 * 1024_u64),
+        };
+
+        let disk = client
+            .disk_create()
+            .project(project)
+            .body(body)
+            .send()
+            .await
+            .with_context(|| format!("creating disk {}", name))?;
+        crate::manifest::record_response("disk", &disk);
+    }
+
+    Ok(())
+}
+
+/// Creates whichever of the first `count` baseline instances don't exist yet,
+/// asking for each one to be started once it's created.
+async fn create_instances(
+    client: &oxide::Client,
+    project: &str,
+    count: usize,
+) -> Result<()> {
+    let existing: HashSet<String> = client
+        .instance_list()
+        .project(project)
+        .stream()
+        .map_ok(|i| i.name.to_string())
+        .try_collect()
+        .await
+        .context("listing instances")?;
+
+    for name in (0..count).map(instance_name) {
+        if existing.contains(&name) {
+            continue;
+        }
+
+        info!(name, "creating baseline instance");
+        let body = InstanceCreate {
+            description: crate::run_info::description(&name),
+            disks: vec![],
+            external_ips: vec![],
+            hostname: name.parse().unwrap(),
+            memory: ByteCount(1024 * 1024 * 1024),
+            name: Name::try_from(&name).unwrap(),
+            ncpus: InstanceCpuCount(1),
+            network_interfaces: InstanceNetworkInterfaceAttachment::None,
+            start: true,
+            user_data: String::new(),
+            ssh_public_keys: None,
+        };
+
+        let instance = client
+            .instance_create()
+            .project(project)
+            .body(body)
+            .send()
+            .await
+            .with_context(|| format!("creating instance {}", name))?;
+        crate::manifest::record_response("instance", &instance);
+    }
+
+    Ok(())
+}
+
+/// Creates whichever of the first `count` baseline snapshots don't exist yet,
+/// spreading them across the first `disks` baseline disks.
+async fn create_snapshots(
+    client: &oxide::Client,
+    project: &str,
+    count: usize,
+    disks: usize,
+) -> Result<()> {
+    let existing: HashSet<String> = client
+        .snapshot_list()
+        .project(project)
+        .stream()
+        .map_ok(|s| s.name.to_string())
+        .try_collect()
+        .await
+        .context("listing snapshots")?;
+
+    for i in 0..count {
+        let name = snapshot_name(i);
+        if existing.contains(&name) {
+            continue;
+        }
+
+        info!(name, "creating baseline snapshot");
+        let body = SnapshotCreate {
+            description: crate::run_info::description(&name),
+            disk: disk_name(i % disks).try_into().unwrap(),
+            name: Name::try_from(&name).unwrap(),
+        };
+
+        let snapshot = client
+            .snapshot_create()
+            .project(project)
+            .body(body)
+            .send()
+            .await
+            .with_context(|| format!("creating snapshot {}", name))?;
+        crate::manifest::record_response("snapshot", &snapshot);
+    }
+
+    Ok(())
+}
+
+/// Waits for the first `count` baseline disks to become detached.
+async fn wait_for_disks(
+    client: &oxide::Client,
+    project: &str,
+    count: usize,
+    deadline: Instant,
+) -> Result<()> {
+    let wanted: HashSet<String> = (0..count).map(disk_name).collect();
+    loop {
+        let disks: Vec<_> = client
+            .disk_list()
+            .project(project)
+            .stream()
+            .try_collect()
+            .await
+            .context("listing disks")?;
+
+        let mut pending = 0;
+        for disk in disks.iter().filter(|d| wanted.contains(&*d.name)) {
+            match disk.state {
+                DiskState::Detached => {}
+                DiskState::Creating => pending += 1,
+                ref state => {
+                    bail!("baseline disk {} is {:?}", disk.name, state)
+                }
+            }
+        }
+
+        if check_settled("disks", pending, deadline).await? {
+            return Ok(());
+        }
+    }
+}
+
+/// Waits for the first `count` baseline snapshots to become ready.
+async fn wait_for_snapshots(
+    client: &oxide::Client,
+    project: &str,
+    count: usize,
+    deadline: Instant,
+) -> Result<()> {
+    let wanted: HashSet<String> = (0..count).map(snapshot_name).collect();
+    loop {
+        let snapshots: Vec<_> = client
+            .snapshot_list()
+            .project(project)
+            .stream()
+            .try_collect()
+            .await
+            .context("listing snapshots")?;
+
+        let mut pending = 0;
+        for snapshot in snapshots.iter().filter(|s| wanted.contains(&*s.name)) {
+            match snapshot.state {
+                SnapshotState::Ready => {}
+                SnapshotState::Creating => pending += 1,
+                state => {
+                    bail!("baseline snapshot {} is {:?}", snapshot.name, state)
+                }
+            }
+        }
+
+        if check_settled("snapshots", pending, deadline).await? {
+            return Ok(());
+        }
+    }
+}
+
+/// Waits for the first `count` baseline instances to be running, starting any
+/// that are stopped (e.g. because a previous run's antagonists left them that
+/// way).
+async fn wait_for_instances(
+    client: &oxide::Client,
+    project: &str,
+    count: usize,
+    deadline: Instant,
+) -> Result<()> {
+    let wanted: HashSet<String> = (0..count).map(instance_name).collect();
+    loop {
+        let instances: Vec<_> = client
+            .instance_list()
+            .project(project)
+            .stream()
+            .try_collect()
+            .await
+            .context("listing instances")?;
+
+        let mut pending = 0;
+        for instance in instances.iter().filter(|i| wanted.contains(&*i.name)) {
+            match instance.run_state {
+                InstanceState::Running => {}
+                InstanceState::Stopped => {
+                    info!(name = %instance.name, "starting baseline instance");
+                    client
+                        .instance_start()
+                        .project(project)
+                        .instance(instance.id)
+                        .send()
+                        .await
+                        .with_context(|| {
+                            format!("starting instance {}", instance.name)
+                        })?;
+                    pending += 1;
+                }
+                InstanceState::Failed => {
+                    bail!("baseline instance {} failed", instance.name)
+                }
+                _ => pending += 1,
+            }
+        }
+
+        if check_settled("instances", pending, deadline).await? {
+            return Ok(());
+        }
+    }
+}
+
+/// Returns true if no baseline resources of some kind are still `pending`.
+/// Otherwise, fails if `deadline` has passed, or sleeps before the next check
+/// and returns false.
+async fn check_settled(
+    what: &str,
+    pending: usize,
+    deadline: Instant,
+) -> Result<bool> {
+    if pending == 0 {
+        return Ok(true);
+    }
+
+    if Instant::now() >= deadline {
+        bail!("{} baseline {} still not settled", pending, what);
+    }
+
+    info!(pending, "waiting for baseline {} to settle", what);
+    tokio::time::sleep(POLL_INTERVAL).await;
+    Ok(false)
+}
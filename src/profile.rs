@@ -0,0 +1,131 @@
+//! Predefined workload shapes: sets of action weights and think times for every
+//! kind of state-driven antagonist.
+
+use serde::Deserialize;
+
+use crate::actor::{disk, instance, snapshot};
+use crate::util::SleepRange;
+
+/// A named set of action weights and think times.
+#[derive(Clone, Copy, Debug, Default, Deserialize, clap::ValueEnum)]
+#[serde(rename_all = "kebab-case")]
+pub enum Profile {
+    /// The default mix, which gives every operation a reasonable chance.
+    #[default]
+    Balanced,
+
+    /// Mostly create (and start) resources, so that the project fills up.
+    CreateHeavy,
+
+    /// Mostly stop and delete resources, so that the project drains.
+    DeleteHeavy,
+
+    /// Mostly just query resources' states, with short sleeps in between.
+    ReadHeavy,
+
+    /// Cycle resources through their lifecycles as fast as possible, with
+    /// little waiting and almost no sleeping.
+    Churn,
+}
+
+impl Profile {
+    /// Returns the weights instance antagonists use in this profile.
+    pub fn instance_weights(self) -> instance::Weights {
+        use instance::ActionWeights as W;
+        match self {
+            Profile::Balanced => instance::Weights::default(),
+            Profile::CreateHeavy => instance::Weights {
+                starting: W::new(50, 20, 15, 10, 5),
+                running: W::new(30, 15, 25, 25, 5),
+                stopped: W::new(20, 10, 55, 10, 5),
+                ..Default::default()
+            },
+            Profile::DeleteHeavy => instance::Weights {
+                starting: W::new(40, 5, 5, 20, 30),
+                running: W::new(20, 5, 10, 35, 30),
+                stopped: W::new(15, 5, 15, 5, 60),
+                ..Default::default()
+            },
+            Profile::ReadHeavy => instance::Weights {
+                starting: W::new(90, 2, 3, 3, 2),
+                running: W::new(85, 1, 6, 6, 2),
+                stopped: W::new(85, 1, 8, 2, 4),
+                failed: W::new(50, 0, 0, 0, 50),
+            },
+            Profile::Churn => instance::Weights {
+                starting: W::new(10, 10, 25, 25, 30),
+                running: W::new(5, 5, 30, 30, 30),
+                stopped: W::new(5, 5, 45, 5, 40),
+                ..Default::default()
+            },
+        }
+    }
+
+    /// Returns the weights disk antagonists use in this profile.
+    pub fn disk_weights(self) -> disk::Weights {
+        use disk::ActionWeights as W;
+        match self {
+            Profile::Balanced => disk::Weights::default(),
+            Profile::CreateHeavy => disk::Weights {
+                creating: W::new(60, 30, 10),
+                detached: W::new(30, 60, 10),
+                ..Default::default()
+            },
+            Profile::DeleteHeavy => disk::Weights {
+                creating: W::new(40, 10, 50),
+                detached: W::new(20, 20, 60),
+                ..Default::default()
+            },
+            Profile::ReadHeavy => disk::Weights {
+                creating: W::new(90, 3, 7),
+                detached: W::new(85, 7, 8),
+                faulted: W::new(50, 0, 50),
+            },
+            Profile::Churn => disk::Weights {
+                creating: W::new(10, 30, 60),
+                detached: W::new(5, 45, 50),
+                ..Default::default()
+            },
+        }
+    }
+
+    /// Returns the weights snapshot antagonists use in this profile.
+    pub fn snapshot_weights(self) -> snapshot::Weights {
+        use snapshot::ActionWeights as W;
+        match self {
+            Profile::Balanced => snapshot::Weights::default(),
+            Profile::CreateHeavy => snapshot::Weights {
+                creating: W::new(60, 30, 10),
+                ready: W::new(30, 60, 10),
+                destroyed: W::new(30, 60, 10),
+            },
+            Profile::DeleteHeavy => snapshot::Weights {
+                creating: W::new(40, 10, 50),
+                ready: W::new(20, 20, 60),
+                destroyed: W::new(20, 20, 60),
+            },
+            Profile::ReadHeavy => snapshot::Weights {
+                creating: W::new(90, 3, 7),
+                ready: W::new(85, 7, 8),
+                destroyed: W::new(85, 7, 8),
+            },
+            Profile::Churn => snapshot::Weights {
+                creating: W::new(10, 30, 60),
+                ready: W::new(5, 45, 50),
+                destroyed: W::new(5, 45, 50),
+            },
+        }
+    }
+
+    /// Returns how long antagonists sleep before and after each action in
+    /// this profile.
+    pub fn think_time(self) -> SleepRange {
+        match self {
+            Profile::Balanced | Profile::CreateHeavy | Profile::DeleteHeavy => {
+                SleepRange::up_to_ms(100)
+            }
+            Profile::ReadHeavy => SleepRange::up_to_ms(20),
+            Profile::Churn => SleepRange::up_to_ms(10),
+        }
+    }
+}
@@ -0,0 +1,154 @@
+//! Token-bucket rate limits on how quickly actors make API calls.
+//!
+//! Every call an actor makes takes a token first (see the `RateLimit` layer in
+//! `crate::middleware`), retries included, so the limits hold the actors'
+//! request rate itself rather than the rate of the steps that make them.
+
+use std::collections::HashMap;
+use std::sync::{Mutex, OnceLock};
+use std::time::{Duration, Instant};
+
+/// A token bucket that refills at a fixed rate and holds at most one second's
+/// worth of tokens, so that a briefly idle fleet can't build up a large burst.
+#[derive(Debug)]
+pub struct RateLimiter {
+    bucket: Mutex<Bucket>,
+}
+
+#[derive(Debug)]
+struct Bucket {
+    /// Tokens added per second.
+    rate: f64,
+
+    /// The maximum number of tokens the bucket can hold.
+    capacity: f64,
+
+    /// The number of tokens in the bucket as of `last_refill`.
+    tokens: f64,
+    last_refill: Instant,
+}
+
+impl RateLimiter {
+    /// Creates a limiter that allows `per_second` acquisitions per second.
+    pub fn new(per_second: f64) -> Self {
+        let capacity = per_second.max(1.0);
+        Self {
+            bucket: Mutex::new(Bucket {
+                rate: per_second,
+                capacity,
+                tokens: capacity,
+                last_refill: Instant::now(),
+            }),
+        }
+    }
+
+    /// Waits until a token is available, then takes it.
+    pub async fn acquire(&self) {
+        loop {
+            let wait = {
+                let mut bucket = self.bucket.lock().unwrap();
+                let now = Instant::now();
+                let elapsed = (now - bucket.last_refill).as_secs_f64();
+                bucket.tokens = (bucket.tokens + elapsed * bucket.rate)
+                    .min(bucket.capacity);
+                bucket.last_refill = now;
+
+                if bucket.tokens >= 1.0 {
+                    bucket.tokens -= 1.0;
+                    return;
+                }
+
+                Duration::from_secs_f64((1.0 - bucket.tokens) / bucket.rate)
+            };
+
+            tokio::time::sleep(wait).await;
+        }
+    }
+}
+
+/// The rate limits configured for this run.
+#[derive(Debug, Default)]
+struct Limits {
+    /// The limit shared by every actor.
+    global: Option<RateLimiter>,
+
+    /// Limits shared by all the actors of a given kind, keyed by kind name.
+    per_kind: HashMap<String, RateLimiter>,
+}
+
+static LIMITS: OnceLock<Limits> = OnceLock::new();
+
+fn limits() -> &'static Limits {
+    LIMITS.get_or_init(|| {
+        let config = crate::config();
+        Limits {
+            global: config.target_rps.map(RateLimiter::new),
+            per_kind: config
+                .kind_rps
+                .iter()
+                .map(|(kind, rps)| (kind.clone(), RateLimiter::new(*rps)))
+                .collect(),
+        }
+    })
+}
+
+/// Waits until an actor of the supplied kind may make its next API call.
+pub async fn acquire(kind: &str) {
+    let limits = limits();
+    if let Some(limiter) = limits.per_kind.get(kind) {
+        limiter.acquire().await;
+    }
+
+    if let Some(limiter) = &limits.global {
+        limiter.acquire().await;
+    }
+}
+
+/// Parses a `<kind>=<rate>` per-kind rate limit from the command line.
+pub fn parse_kind_rps(s: &str) -> Result<(String, f64), String> {
+    let (kind, rps) = s
+        .split_once('=')
+        .ok_or_else(|| format!("expected <kind>=<rate>, got {:?}", s))?;
+
+    let kind = crate::actor::ActorKind::parse_name(kind)?;
+    let rps = parse_rps(rps)?;
+    Ok((kind, rps))
+}
+
+/// The slowest rate that can be asked for, one request every 1000 seconds.
+/// Much slower, and the time between requests won't fit in a `Duration`.
+const MIN_RPS: f64 = 0.001;
+
+/// The fastest rate that can be asked for. Much faster, and the time between
+/// requests rounds to zero.
+const MAX_RPS: f64 = 1_000_000.0;
+
+/// Parses a rate, in requests per second, from the command line.
+pub fn parse_rps(s: &str) -> Result<f64, String> {
+    match s.parse::<f64>() {
+        Ok(rps) if (MIN_RPS..=MAX_RPS).contains(&rps) => Ok(rps),
+        _ => Err(format!(
+            "expected a rate between {} and {}, got {:?}",
+            MIN_RPS, MAX_RPS, s
+        )),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_rps_accepts_rates_in_bounds() {
+        assert_eq!(parse_rps("2.5"), Ok(2.5));
+        assert_eq!(parse_rps("0.001"), Ok(MIN_RPS));
+        assert_eq!(parse_rps("1000000"), Ok(MAX_RPS));
+    }
+
+    #[test]
+    fn parse_rps_rejects_rates_out_of_bounds() {
+        for s in ["0", "-1", "0.0001", "1e7", "inf", "NaN", "fast", ""] {
+            assert!(parse_rps(s).is_err(), "{:?} was accepted", s);
+        }
+    }
+}
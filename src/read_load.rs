@@ -0,0 +1,106 @@
+//! The background read load (`--read-load`), which issues cheap reads of the
+//! stress project at a fixed rate to keep Nexus busy, independent of what the
+//! actors are doing, so that runs can study how background load affects the
+//! latency of the actors' sagas.
+//!
+//! The load alternates between viewing the project and listing the first page
+//! of its instances. Reads are sent on schedule (see `crate::fixed_rate`), and
+//! reads skipped because too many were outstanding are counted. The reads go
+//! through the same middleware as the actors' calls, so they show up in the
+//! endpoint statistics and error groups, but their errors don't count against
+//! the error budget.
+
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+
+use oxide::{ClientInstancesExt, ClientProjectsExt};
+use serde::Serialize;
+use tokio::task::JoinHandle;
+use tracing::info;
+
+/// The most reads that may be outstanding at once.
+const MAX_IN_FLIGHT: usize = 256;
+
+/// How many instances each listing asks for.
+const PAGE_SIZE: u32 = 10;
+
+/// What the background read load did.
+#[derive(Clone, Debug, Default, Serialize)]
+pub struct Summary {
+    /// The reads sent.
+    pub sent: u64,
+    pub succeeded: u64,
+    pub failed: u64,
+
+    /// The reads skipped because too many were already outstanding.
+    pub skipped: u64,
+}
+
+static SENT: AtomicU64 = AtomicU64::new(0);
+static SUCCEEDED: AtomicU64 = AtomicU64::new(0);
+static FAILED: AtomicU64 = AtomicU64::new(0);
+static SKIPPED: AtomicU64 = AtomicU64::new(0);
+
+/// Starts sending `per_second` reads of `project` per second. Aborting the
+/// returned task stops the load, including any reads still outstanding.
+pub fn start(
+    client: oxide::Client,
+    project: String,
+    per_second: f64,
+) -> JoinHandle<()> {
+    info!(per_second, "Starting background read load");
+    let client = Arc::new(client);
+    let project = Arc::new(project);
+    crate::fixed_rate::start(
+        per_second,
+        MAX_IN_FLIGHT,
+        move |n| {
+            let client = client.clone();
+            let project = project.clone();
+            SENT.fetch_add(1, Ordering::Relaxed);
+            async move {
+                let ok = if n % 2 == 0 {
+                    crate::middleware::call("instance_list", || {
+                        client
+                            .instance_list()
+                            .project(&*project)
+                            .limit(PAGE_SIZE)
+                            .send()
+                    })
+                    .await
+                    .is_ok()
+                } else {
+                    crate::middleware::call("project_view", || {
+                        client.project_view().project(&*project).send()
+                    })
+                    .await
+                    .is_ok()
+                };
+
+                let count = if ok { &SUCCEEDED } else { &FAILED };
+                count.fetch_add(1, Ordering::Relaxed);
+            }
+        },
+        || {
+            SKIPPED.fetch_add(1, Ordering::Relaxed);
+        },
+    )
+}
+
+/// Returns what the background read load has done so far.
+pub fn summary() -> Summary {
+    Summary {
+        sent: SENT.load(Ordering::Relaxed),
+        succeeded: SUCCEEDED.load(Ordering::Relaxed),
+        failed: FAILED.load(Ordering::Relaxed),
+        skipped: SKIPPED.load(Ordering::Relaxed),
+    }
+}
+
+/// Logs the background read load section of the end-of-run summary.
+pub fn log() {
+    if let Some(per_second) = crate::config().read_load {
+        let Summary { sent, succeeded, failed, skipped } = summary();
+        info!(per_second, sent, succeeded, failed, skipped, "Background reads");
+    }
+}
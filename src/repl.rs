@@ -0,0 +1,115 @@
+//! The interactive console (`--interactive`), for poking at a run against a
+//! dev rack by hand. Reads commands from stdin, one per line, and carries them
+//! out through the same main loop commands as the control API
+//! (`crate::control`).
+
+use clap::ValueEnum;
+use tokio::io::AsyncBufReadExt;
+use tokio::sync::{mpsc, oneshot};
+
+use crate::actor::ActorKind;
+use crate::control::{Command, Request, Target};
+use crate::profile::Profile;
+
+const HELP: &str = "\
+commands:
+  actors                     list the running actors
+  stats                      show actor and endpoint stats
+  report                     show the report so far
+  pause [actor|kind]         pause an actor, every actor of a kind, or all
+  resume [actor|kind]        resume an actor, every actor of a kind, or all
+  halt [actor|kind]          halt an actor, every actor of a kind, or all
+  weights [actor|kind] <profile>
+                             switch to a profile's action weights
+  quit                       end the run
+  help                       show this message";
+
+/// Parses an actor or kind name, or every actor if there isn't one.
+fn target(word: Option<&str>) -> Target {
+    match word {
+        None => Target::All,
+        Some(kind) if ActorKind::NAMES.contains(&kind) => {
+            Target::Kind(kind.to_owned())
+        }
+        Some(name) => Target::Actor(name.to_owned()),
+    }
+}
+
+/// Parses a line of input. Returns `Ok(None)` for input that isn't a main
+/// loop command but was handled anyway, e.g. `help`.
+fn parse(line: &str) -> Result<Option<Command>, String> {
+    let words: Vec<&str> = line.split_whitespace().collect();
+    let command = match words.as_slice() {
+        [] => return Ok(None),
+        ["help"] => {
+            println!("{}", HELP);
+            return Ok(None);
+        }
+        ["stats"] => {
+            print_json(&serde_json::json!({
+                "actors": crate::stats::summary(),
+                "endpoints": crate::stats::endpoint_summary(),
+            }));
+            return Ok(None);
+        }
+        ["actors"] => Command::ListActors,
+        ["report"] => Command::Report,
+        ["quit"] => Command::Quit,
+        ["pause", rest @ ..] if rest.len() <= 1 => {
+            Command::Pause(target(rest.first().copied()))
+        }
+        ["resume", rest @ ..] if rest.len() <= 1 => {
+            Command::Resume(target(rest.first().copied()))
+        }
+        ["halt", rest @ ..] if rest.len() <= 1 => {
+            Command::Halt(target(rest.first().copied()))
+        }
+        ["weights", rest @ .., profile] if rest.len() <= 1 => {
+            let profile = Profile::from_str(profile, true)
+                .map_err(|e| format!("invalid profile: {}", e))?;
+            Command::SetProfile(target(rest.first().copied()), profile)
+        }
+        _ => return Err(format!("unrecognized command {:?}", line.trim())),
+    };
+
+    Ok(Some(command))
+}
+
+fn print_json(value: &serde_json::Value) {
+    match serde_json::to_string_pretty(value) {
+        Ok(json) => println!("{}", json),
+        Err(e) => println!("error: {}", e),
+    }
+}
+
+/// Reads and carries out commands from stdin until it closes or the main
+/// loop goes away.
+async fn run(commands: mpsc::Sender<Request>) {
+    let mut lines = tokio::io::BufReader::new(tokio::io::stdin()).lines();
+    println!("{}", HELP);
+    while let Ok(Some(line)) = lines.next_line().await {
+        let command = match parse(&line) {
+            Ok(Some(command)) => command,
+            Ok(None) => continue,
+            Err(e) => {
+                println!("{} (try `help`)", e);
+                continue;
+            }
+        };
+
+        let (reply_tx, reply_rx) = oneshot::channel();
+        if commands.send((command, reply_tx)).await.is_err() {
+            return;
+        }
+        match reply_rx.await {
+            Ok(reply) => print_json(&reply.body),
+            Err(_) => return,
+        }
+    }
+}
+
+/// Starts reading commands from stdin, passing them to the main loop through
+/// `commands`.
+pub fn start(commands: mpsc::Sender<Request>) {
+    tokio::spawn(run(commands));
+}
@@ -0,0 +1,275 @@
+//! The machine-readable end-of-run report written by `--report-json`.
+//!
+//! The report's layout is meant to stay stable so that CI pipelines and
+//! dashboards can rely on it. Fields may be added, but existing ones shouldn't
+//! be renamed or removed without bumping `SCHEMA_VERSION`.
+
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+
+use anyhow::{Context, Result};
+use serde::Serialize;
+
+use crate::actor::ErrorContext;
+use crate::error_budget::RecordedError;
+use crate::error_groups::ErrorGroup;
+use crate::leaks::LeakedResource;
+use crate::maintenance::Window;
+use crate::outcome::Failure;
+use crate::slow_requests::SlowRequest;
+use crate::stats::{EndpointSummary, KindSummary, TransitionSummary};
+
+/// The version of the report's layout.
+const SCHEMA_VERSION: u32 = 1;
+
+/// The end-of-run report.
+#[derive(Debug, Serialize)]
+pub struct Report {
+    pub schema_version: u32,
+
+    /// The run's ID, which is also in its log lines, artifact bundles, and
+    /// created resources' descriptions.
+    pub run_id: String,
+    pub harness_version: &'static str,
+
+    /// The version of the release the rack was running, or `None` if it
+    /// couldn't be found.
+    pub nexus_version: Option<String>,
+    pub project: String,
+    pub name_prefix: String,
+    pub seed: u64,
+
+    /// When the run started, in RFC 3339 format.
+    pub started_at: String,
+    pub duration_secs: f64,
+
+    /// How the run ended: `completed` if it ran for as long as it was meant
+    /// to, `interrupted` if it was stopped with Ctrl-C, or `failed`. Reports
+    /// fetched from the control API while the run is going say `running`.
+    pub outcome: &'static str,
+
+    /// Why the run failed, if it did, and the exit code it ended with. Reports
+    /// fetched from the control API while the run is going have neither.
+    pub failure: Option<Failure>,
+    pub exit_code: Option<u8>,
+
+    /// What each kind of actor did, keyed by kind name.
+    pub actors: BTreeMap<String, KindSummary>,
+
+    /// Latencies of the actors' API calls, by endpoint and status class.
+    pub endpoints: Vec<EndpointSummary>,
+
+    /// How many times each endpoint returned each status code (or `none` for
+    /// calls with no response), keyed by endpoint name.
+    pub status_codes: BTreeMap<String, BTreeMap<String, u64>>,
+
+    /// The run's slowest API calls, slowest first.
+    pub slowest_requests: Vec<SlowRequest>,
+
+    /// Resources that took longer than their SLA to reach the state a
+    /// successful request should have put them in.
+    pub sla_violations: crate::sla::Summary,
+
+    /// How long resources took to reach the state a successful request should
+    /// have put them in, by operation (--measure-transitions).
+    pub transitions: Vec<TransitionSummary>,
+
+    /// Times the silo's provisioned resources didn't match what the stress
+    /// project's resources account for.
+    pub utilization_drift: Vec<crate::utilization::Drift>,
+
+    /// Actors whose tasks died or stalled and were restarted.
+    pub actor_restarts: crate::supervisor::Summary,
+
+    /// What the --bad-tokens requests found.
+    pub bad_tokens: crate::bad_tokens::Summary,
+
+    /// How often the disk antagonists' disks faulted and were replaced.
+    pub faulted_disks: crate::actor::disk::Summary,
+
+    /// How the conflict checkers' storms of identical requests turned out.
+    pub conflict_storms: crate::actor::conflict::Summary,
+
+    /// How many snapshots the snapshot antagonists' disks had, and how many
+    /// were deleted to stay under --max-snapshots-per-disk.
+    pub snapshots_per_disk: crate::actor::snapshot_limit::Summary,
+
+    /// How often Nexus pushed back with 429 or 503 responses.
+    pub throttling: crate::throttle::Summary,
+
+    /// The faults the --chaos-proxy injected.
+    pub chaos_proxy: crate::chaos_proxy::Summary,
+
+    /// What the --read-load background reads did.
+    pub read_load: crate::read_load::Summary,
+    pub errors: Vec<ReportError>,
+
+    /// Every error returned by the actors' API calls, expected or not,
+    /// grouped by endpoint, status code, and message pattern.
+    pub error_groups: Vec<ErrorGroup>,
+
+    /// Error responses whose bodies didn't match the API's error type,
+    /// grouped by endpoint, status code, and problem.
+    pub malformed_errors: Vec<crate::error_schema::MalformedErrors>,
+
+    /// How many errors matched each entry in the --known-issues allowlist,
+    /// keyed by issue name. These errors aren't in `errors`.
+    pub known_issues: BTreeMap<String, u64>,
+
+    /// The resources left in the project that a clean end state wouldn't
+    /// have, or `None` if they couldn't be listed.
+    pub leaked_resources: Option<Vec<ReportLeak>>,
+
+    /// Resources that --leak-audit found in the same unsettled or failed
+    /// state two audits in a row while the run was going.
+    pub leak_audit: Vec<ReportLeak>,
+    pub maintenance_windows: Vec<ReportWindow>,
+
+    /// Actors that hadn't halted when --shutdown-timeout ran out, and so were
+    /// aborted.
+    pub stuck_actors: Vec<crate::supervisor::Straggler>,
+
+    /// Why --cleanup-on-exit failed, if it did.
+    pub cleanup_error: Option<String>,
+}
+
+/// A disqualifying error seen during the run.
+#[derive(Debug, Serialize)]
+pub struct ReportError {
+    /// How far into the run the error was seen.
+    pub elapsed_secs: f64,
+    pub description: String,
+    pub failure: Failure,
+    pub request_id: Option<String>,
+    pub nexus: Option<String>,
+    pub peer: Option<String>,
+    /// What the actor that saw the error was doing, if an actor saw it.
+    pub context: Option<ErrorContext>,
+}
+
+impl From<&RecordedError> for ReportError {
+    fn from(e: &RecordedError) -> Self {
+        Self {
+            elapsed_secs: e.elapsed.as_secs_f64(),
+            description: e.description.clone(),
+            failure: e.failure,
+            request_id: e.context.as_ref().and_then(|c| c.request_id.clone()),
+            nexus: e.context.as_ref().and_then(|c| c.nexus.clone()),
+            peer: e.peer.map(|p| p.to_string()),
+            context: e.context.clone(),
+        }
+    }
+}
+
+/// A leaked resource.
+#[derive(Debug, Serialize)]
+pub struct ReportLeak {
+    pub kind: &'static str,
+    pub name: String,
+    pub id: String,
+    pub state: String,
+    pub age_secs: u64,
+    pub reason: &'static str,
+}
+
+impl From<&LeakedResource> for ReportLeak {
+    fn from(leak: &LeakedResource) -> Self {
+        Self {
+            kind: leak.kind,
+            name: leak.name.clone(),
+            id: leak.id.to_string(),
+            state: leak.state.clone(),
+            age_secs: leak.age.as_secs(),
+            reason: leak.reason,
+        }
+    }
+}
+
+/// A maintenance window, relative to the start of the run.
+#[derive(Debug, Serialize)]
+pub struct ReportWindow {
+    pub start_secs: f64,
+
+    /// When maintenance ended, or `None` if the run ended first.
+    pub end_secs: Option<f64>,
+}
+
+impl From<&Window> for ReportWindow {
+    fn from(w: &Window) -> Self {
+        Self {
+            start_secs: w.start.as_secs_f64(),
+            end_secs: w.end.map(|end| end.as_secs_f64()),
+        }
+    }
+}
+
+impl Report {
+    /// Creates a report with the run's identifying details and the actor
+    /// statistics collected so far. The caller fills in the rest.
+    pub fn new(
+        started_at: chrono::DateTime<chrono::Utc>,
+        outcome: &'static str,
+    ) -> Self {
+        let duration =
+            (chrono::Utc::now() - started_at).to_std().unwrap_or_default();
+
+        Self {
+            schema_version: SCHEMA_VERSION,
+            run_id: crate::run_info::run_id().to_string(),
+            harness_version: crate::run_info::HARNESS_VERSION,
+            nexus_version: crate::run_info::nexus_version(),
+            project: crate::project_name(),
+            name_prefix: crate::util::name_prefix().to_owned(),
+            seed: crate::util::seed(),
+            started_at: started_at.to_rfc3339(),
+            duration_secs: duration.as_secs_f64(),
+            outcome,
+            failure: None,
+            exit_code: None,
+            actors: crate::stats::summary(),
+            endpoints: crate::stats::endpoint_summary(),
+            status_codes: crate::status_counts::summary(),
+            slowest_requests: crate::slow_requests::summary(),
+            sla_violations: crate::sla::summary(),
+            transitions: crate::stats::transition_summary(),
+            utilization_drift: crate::utilization::summary(),
+            actor_restarts: crate::supervisor::summary(),
+            bad_tokens: crate::bad_tokens::summary(),
+            faulted_disks: crate::actor::disk::summary(),
+            conflict_storms: crate::actor::conflict::summary(),
+            snapshots_per_disk: crate::actor::snapshot_limit::summary(),
+            throttling: crate::throttle::summary(),
+            chaos_proxy: crate::chaos_proxy::summary(),
+            read_load: crate::read_load::summary(),
+            errors: Vec::new(),
+            error_groups: crate::error_groups::summary(),
+            malformed_errors: crate::error_schema::summary(),
+            known_issues: crate::known_issues::summary(),
+            leaked_resources: None,
+            leak_audit: crate::leaks::audit_summary()
+                .iter()
+                .map(Into::into)
+                .collect(),
+            maintenance_windows: Vec::new(),
+            stuck_actors: Vec::new(),
+            cleanup_error: None,
+        }
+    }
+
+    /// Returns the path to write the `n`th --checkpoint-interval report to.
+    pub fn checkpoint_path(n: u32) -> PathBuf {
+        crate::config().checkpoint_dir.join(format!(
+            "{}checkpoint-{}.json",
+            crate::util::name_prefix(),
+            n
+        ))
+    }
+
+    /// Writes this report to `path` as JSON.
+    pub fn write(&self, path: &Path) -> Result<()> {
+        let json =
+            serde_json::to_string_pretty(self).context("serializing report")?;
+        std::fs::write(path, json)
+            .with_context(|| format!("writing report to {}", path.display()))
+    }
+}
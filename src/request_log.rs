@@ -0,0 +1,238 @@
+//! Keeps track of the request IDs Nexus assigns to the actors' API calls, so
+//! that a failure seen by the harness can be matched up with Nexus's logs.
+//!
+//! Each call's request ID is recorded in the calling actor's tracing span, so
+//! that it shows up on everything the actor logs about the call, and in the
+//! actor's recent call history and current operation (see `crate::journal`),
+//! which go into failure artifacts. If
+//! `--request-log` is set, every call is also appended to an index file, one
+//! tab-separated line per call: the time, actor, endpoint, status code (or
+//! `-` if there was no response), request ID (or `-` if there wasn't one), the
+//! Nexus instance the call went to (or `-` if it wasn't an actor's call), and
+//! the address of the server that served it (or `-` if that isn't known).
+
+use std::collections::{BTreeMap, VecDeque};
+use std::fs::File;
+use std::io::{LineWriter, Write};
+use std::net::SocketAddr;
+use std::path::Path;
+use std::sync::{Mutex, OnceLock};
+use std::time::Duration;
+
+use anyhow::{Context, Result};
+use serde::Serialize;
+use tracing::warn;
+
+static INDEX: OnceLock<Mutex<LineWriter<File>>> = OnceLock::new();
+
+/// How many of each actor's most recent calls to remember.
+const HISTORY_LEN: usize = 32;
+
+/// An API call made by an actor.
+#[derive(Clone, Debug, Serialize)]
+pub struct Call {
+    /// When the call finished, in RFC 3339 format.
+    pub time: String,
+    pub endpoint: String,
+
+    /// How many times the call had been retried before this attempt.
+    pub attempt: u32,
+
+    /// The response's status code, or `None` if there was no response.
+    pub status: Option<u16>,
+    pub request_id: Option<String>,
+    pub latency_ms: f64,
+
+    /// The Nexus instance the call went to, if it was an actor's call.
+    pub nexus: Option<String>,
+
+    /// The address the call connected to, if it's fixed (see
+    /// `Endpoint::connect_addr`).
+    pub peer: Option<SocketAddr>,
+}
+
+/// Each actor's most recent calls, oldest first, keyed by actor name.
+static HISTORY: OnceLock<Mutex<BTreeMap<String, VecDeque<Call>>>> =
+    OnceLock::new();
+
+/// Returns the most recent calls made by the actor named `actor`, oldest
+/// first.
+pub fn history(actor: &str) -> Vec<Call> {
+    let history = HISTORY.get_or_init(Default::default).lock().unwrap();
+    history
+        .get(actor)
+        .map(|calls| calls.iter().cloned().collect())
+        .unwrap_or_default()
+}
+
+/// Returns the call the actor named `actor` made that failed with `err`: the
+/// call with the error's request ID, or else the actor's most recent call.
+/// Returns `None` if `err` isn't an API error.
+pub fn failed_call(
+    actor: &str,
+    err: &crate::actor::AntagonistError,
+) -> Option<Call> {
+    let crate::actor::AntagonistError::ApiError(_) = err else {
+        return None;
+    };
+
+    let request_id = err.request_id();
+    history(actor).into_iter().rev().find(|call| {
+        request_id.is_none() || call.request_id.as_deref() == request_id
+    })
+}
+
+/// The request ID header Nexus sets on every response.
+pub const REQUEST_ID_HEADER: &str = "x-request-id";
+
+/// Opens the request index file at `path`, appending to it if it exists.
+pub fn open(path: &Path) -> Result<()> {
+    let file = std::fs::OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(path)
+        .with_context(|| format!("opening request log {}", path.display()))?;
+
+    INDEX
+        .set(Mutex::new(LineWriter::new(file)))
+        .map_err(|_| anyhow::anyhow!("request log already open"))
+}
+
+/// Records attempt `attempt` at a call to `endpoint`, which took `latency` and
+/// got a response with the supplied `status` (if any) and `request_id` (if
+/// any).
+pub fn record(
+    endpoint: &str,
+    attempt: u32,
+    latency: Duration,
+    status: Option<http::StatusCode>,
+    request_id: Option<&str>,
+) {
+    let actor = crate::actor::current_actor();
+    let call = Call {
+        time: chrono::Utc::now().to_rfc3339(),
+        endpoint: endpoint.to_owned(),
+        attempt,
+        status: status.map(|s| s.as_u16()),
+        request_id: request_id.map(str::to_owned),
+        latency_ms: latency.as_secs_f64() * 1000.0,
+        nexus: actor.as_ref().map(|a| a.nexus.to_string()),
+        peer: actor.as_ref().and_then(|a| a.nexus.connect_addr()),
+    };
+
+    if let Some(actor) = &actor {
+        if let Some(request_id) = request_id {
+            actor.span.record("request_id", request_id);
+        }
+
+        let mut history = HISTORY.get_or_init(Default::default).lock().unwrap();
+        let calls = history.entry(actor.name.clone()).or_default();
+        if calls.len() == HISTORY_LEN {
+            calls.pop_front();
+        }
+        calls.push_back(call.clone());
+        drop(history);
+        crate::journal::note_call(&call);
+    }
+
+    let Some(index) = INDEX.get() else {
+        return;
+    };
+
+    let line = format!(
+        "{}\t{}\t{}\t{}\t{}\t{}\t{}",
+        call.time,
+        actor.as_ref().map_or("-", |a| a.name.as_str()),
+        endpoint,
+        call.status.map_or_else(|| "-".to_string(), |s| s.to_string()),
+        request_id.unwrap_or("-"),
+        call.nexus.as_deref().unwrap_or("-"),
+        call.peer.map_or_else(|| "-".to_string(), |p| p.to_string()),
+    );
+
+    if let Err(e) = writeln!(index.lock().unwrap(), "{}", line) {
+        warn!(error = %e, "failed to write to request log");
+    }
+}
+
+/// How many failed calls a request log summary lists.
+const MAX_FAILED_CALLS: usize = 100;
+
+/// A call from a request log that got no response or a server error.
+#[derive(Debug, Serialize)]
+pub struct LoggedCall {
+    pub time: String,
+    pub actor: String,
+    pub endpoint: String,
+    pub status: Option<u16>,
+    pub request_id: Option<String>,
+}
+
+/// A summary of a request log, as printed by the `report` subcommand.
+#[derive(Debug, Default, Serialize)]
+pub struct LogSummary {
+    pub calls: u64,
+
+    /// The times of the first and last calls in the log.
+    pub first: Option<String>,
+    pub last: Option<String>,
+
+    /// How many calls each actor made, keyed by actor name.
+    pub actors: BTreeMap<String, u64>,
+
+    /// How many times each endpoint returned each status code (or `none` for
+    /// calls with no response), keyed by endpoint name.
+    pub status_codes: BTreeMap<String, BTreeMap<String, u64>>,
+
+    /// The first calls that got no response or a server error.
+    pub failed_calls: Vec<LoggedCall>,
+}
+
+/// Parses a field from a request log line, where `-` means there's no value.
+fn field(value: &str) -> Option<&str> {
+    (value != "-").then_some(value)
+}
+
+/// Summarizes the request log at `path`.
+pub fn summarize(path: &Path) -> Result<LogSummary> {
+    let contents = std::fs::read_to_string(path)
+        .with_context(|| format!("reading {}", path.display()))?;
+
+    let mut summary = LogSummary::default();
+    for (i, line) in contents.lines().enumerate() {
+        // Logs from older versions of the harness have fewer columns.
+        let fields: Vec<&str> = line.split('\t').collect();
+        let [time, actor, endpoint, status, request_id, ..] = fields[..] else {
+            anyhow::bail!("{}:{}: malformed line", path.display(), i + 1);
+        };
+        let status =
+            field(status).map(str::parse::<u16>).transpose().with_context(
+                || format!("{}:{}: bad status", path.display(), i + 1),
+            )?;
+
+        summary.calls += 1;
+        summary.first.get_or_insert_with(|| time.to_owned());
+        summary.last = Some(time.to_owned());
+        *summary.actors.entry(actor.to_owned()).or_default() += 1;
+        *summary
+            .status_codes
+            .entry(endpoint.to_owned())
+            .or_default()
+            .entry(status.map_or_else(|| "none".to_owned(), |s| s.to_string()))
+            .or_default() += 1;
+
+        if status.map_or(true, |s| s >= 500)
+            && summary.failed_calls.len() < MAX_FAILED_CALLS
+        {
+            summary.failed_calls.push(LoggedCall {
+                time: time.to_owned(),
+                actor: actor.to_owned(),
+                endpoint: endpoint.to_owned(),
+                status,
+                request_id: field(request_id).map(str::to_owned),
+            });
+        }
+    }
+
+    Ok(summary)
+}
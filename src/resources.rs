@@ -0,0 +1,139 @@
+//! The `list-resources` subcommand, which lists what's in the stress project,
+//! e.g. to see what a previous run left behind before cleaning it up.
+
+use std::time::Duration;
+
+use anyhow::{Context, Result};
+use chrono::{DateTime, Utc};
+use futures::TryStreamExt;
+use oxide::{
+    ClientDisksExt, ClientFloatingIpsExt, ClientImagesExt, ClientInstancesExt,
+    ClientSnapshotsExt,
+};
+use tracing::info;
+
+use crate::actor::ownership::ResourceKind;
+use crate::populate::is_baseline;
+
+/// Logs each resource in `project`: its kind, name, ID, state, age, and
+/// whether it's one of the baseline resources (if `--populate` is set).
+pub async fn list(client: &oxide::Client, project: &str) -> Result<()> {
+    let now = Utc::now();
+    let log = |kind: &str,
+               name: &str,
+               id: uuid::Uuid,
+               state: String,
+               created: DateTime<Utc>,
+               baseline: bool| {
+        let age = (now - created).to_std().unwrap_or(Duration::ZERO);
+        info!(
+            kind,
+            name,
+            %id,
+            state,
+            age = %humantime::format_duration(Duration::from_secs(
+                age.as_secs()
+            )),
+            baseline,
+            "Resource"
+        );
+    };
+
+    let instances: Vec<_> = client
+        .instance_list()
+        .project(project)
+        .stream()
+        .try_collect()
+        .await
+        .context("listing instances")?;
+    for i in &instances {
+        log(
+            "instance",
+            &i.name,
+            i.id,
+            format!("{:?}", i.run_state),
+            i.time_created,
+            is_baseline(ResourceKind::Instance, &i.name),
+        );
+    }
+
+    let disks: Vec<_> = client
+        .disk_list()
+        .project(project)
+        .stream()
+        .try_collect()
+        .await
+        .context("listing disks")?;
+    for d in &disks {
+        log(
+            "disk",
+            &d.name,
+            d.id,
+            format!("{:?}", d.state),
+            d.time_created,
+            is_baseline(ResourceKind::Disk, &d.name),
+        );
+    }
+
+    let snapshots: Vec<_> = client
+        .snapshot_list()
+        .project(project)
+        .stream()
+        .try_collect()
+        .await
+        .context("listing snapshots")?;
+    for s in &snapshots {
+        log(
+            "snapshot",
+            &s.name,
+            s.id,
+            format!("{:?}", s.state),
+            s.time_created,
+            is_baseline(ResourceKind::Snapshot, &s.name),
+        );
+    }
+
+    let images: Vec<_> = client
+        .image_list()
+        .project(project)
+        .stream()
+        .try_collect()
+        .await
+        .context("listing images")?;
+    for i in &images {
+        log(
+            "image",
+            &i.name,
+            i.id,
+            "-".to_string(),
+            i.time_created,
+            is_baseline(ResourceKind::Image, &i.name),
+        );
+    }
+
+    let floating_ips: Vec<_> = client
+        .floating_ip_list()
+        .project(project)
+        .stream()
+        .try_collect()
+        .await
+        .context("listing floating IPs")?;
+    for ip in &floating_ips {
+        let state = match ip.instance_id {
+            Some(instance) => format!("{} attached to {}", ip.ip, instance),
+            None => format!("{} unattached", ip.ip),
+        };
+        log("floating IP", &ip.name, ip.id, state, ip.time_created, false);
+    }
+
+    info!(
+        instances = instances.len(),
+        disks = disks.len(),
+        snapshots = snapshots.len(),
+        images = images.len(),
+        floating_ips = floating_ips.len(),
+        project,
+        "Listed stress project resources"
+    );
+    Ok(())
+}
@@ -0,0 +1,158 @@
+//! The policy for retrying actors' API calls that fail for transient reasons,
+//! such as Nexus being briefly unavailable during a rolling restart. The
+//! retries themselves happen in `crate::middleware::call`.
+//!
+//! Retries work on individual calls rather than whole steps, so a step whose
+//! action failed doesn't look at its resource's state again, or pick a
+//! different action, just because the action got a 503.
+
+use std::time::Duration;
+
+use rand::rngs::StdRng;
+use rand::Rng;
+
+use crate::util::OxideApiError;
+
+/// Returns how long to wait before retrying a call that failed with `err`
+/// after `attempt` previous retries, or `None` if the call shouldn't be retried
+/// (because the error isn't transient or because retries are used up).
+///
+/// The delay doubles with each attempt, starting at --retry-backoff, with
+/// random jitter of up to half the delay drawn from `rng`. If the error
+/// response has a `Retry-After` header, its delay is used instead. Either way,
+/// the delay is capped at --retry-max-backoff.
+pub fn delay(
+    err: &OxideApiError,
+    attempt: u32,
+    rng: &mut StdRng,
+) -> Option<Duration> {
+    let config = crate::config();
+    if attempt >= config.retry_attempts {
+        return None;
+    }
+
+    match err {
+        oxide::Error::ErrorResponse(rv) => {
+            let retryable = config
+                .retry_status
+                .iter()
+                .any(|m| m.matches(rv.status(), rv.error_code.as_deref()));
+
+            if !retryable {
+                return None;
+            }
+
+            if let Some(retry_after) = retry_after(rv.headers()) {
+                return Some(retry_after.min(config.retry_max_backoff));
+            }
+        }
+
+        // Nexus might be restarting.
+        oxide::Error::CommunicationError(_) => {}
+
+        // The chaos proxy cut the response short.
+        err if crate::chaos_proxy::is_injected(err) => {}
+
+        _ => return None,
+    }
+
+    let backoff = config
+        .retry_backoff
+        .saturating_mul(2u32.saturating_pow(attempt))
+        .min(config.retry_max_backoff);
+
+    let jitter = rng.gen_range(0.0..=0.5);
+    Some(backoff.mul_f64(1.0 - jitter))
+}
+
+/// Returns the delay requested by a `Retry-After` header in `headers`, if
+/// there is one and it's a number of seconds.
+fn retry_after(headers: &reqwest::header::HeaderMap) -> Option<Duration> {
+    let value = headers.get(reqwest::header::RETRY_AFTER)?;
+    let seconds = value.to_str().ok()?.trim().parse().ok()?;
+    Some(Duration::from_secs(seconds))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::SeedableRng;
+    use reqwest::header::{HeaderMap, HeaderValue, RETRY_AFTER};
+
+    /// Returns an error response with `status` and `headers`.
+    fn error_response(
+        status: http::StatusCode,
+        headers: HeaderMap,
+    ) -> OxideApiError {
+        oxide::Error::ErrorResponse(oxide::ResponseValue::new(
+            oxide::types::Error {
+                error_code: None,
+                message: "test".to_owned(),
+                request_id: "test".to_owned(),
+            },
+            status,
+            headers,
+        ))
+    }
+
+    #[test]
+    fn backoff_doubles_up_to_the_cap() {
+        crate::config::init_for_tests();
+        let mut rng = StdRng::seed_from_u64(0);
+        let err = error_response(
+            http::StatusCode::SERVICE_UNAVAILABLE,
+            HeaderMap::new(),
+        );
+        for (attempt, backoff_ms) in [(0, 100), (1, 200), (2, 400), (4, 1000)] {
+            let backoff = Duration::from_millis(backoff_ms);
+            let delay = delay(&err, attempt, &mut rng).unwrap();
+            assert!(
+                delay <= backoff && delay >= backoff / 2,
+                "attempt {}: {:?}",
+                attempt,
+                delay
+            );
+        }
+    }
+
+    #[test]
+    fn retries_run_out() {
+        crate::config::init_for_tests();
+        let mut rng = StdRng::seed_from_u64(0);
+        let err = error_response(
+            http::StatusCode::TOO_MANY_REQUESTS,
+            HeaderMap::new(),
+        );
+        assert!(delay(&err, 4, &mut rng).is_some());
+        assert_eq!(delay(&err, 5, &mut rng), None);
+    }
+
+    #[test]
+    fn only_transient_errors_are_retried() {
+        crate::config::init_for_tests();
+        let mut rng = StdRng::seed_from_u64(0);
+        let err =
+            error_response(http::StatusCode::BAD_REQUEST, HeaderMap::new());
+        assert_eq!(delay(&err, 0, &mut rng), None);
+        let err = oxide::Error::InvalidRequest("test".to_owned());
+        assert_eq!(delay(&err, 0, &mut rng), None);
+    }
+
+    #[test]
+    fn retry_after_overrides_backoff_up_to_the_cap() {
+        crate::config::init_for_tests();
+        let mut rng = StdRng::seed_from_u64(0);
+        for (retry_after, expected) in [("0", 0), (" 1 ", 1), ("60", 1)] {
+            let mut headers = HeaderMap::new();
+            headers.insert(RETRY_AFTER, HeaderValue::from_static(retry_after));
+            let err =
+                error_response(http::StatusCode::SERVICE_UNAVAILABLE, headers);
+            assert_eq!(
+                delay(&err, 0, &mut rng),
+                Some(Duration::from_secs(expected)),
+                "Retry-After: {:?}",
+                retry_after
+            );
+        }
+    }
+}
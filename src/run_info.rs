@@ -0,0 +1,95 @@
+//! The run's identity: a run ID made up at startup, which goes in every log
+//! line, report, artifact bundle, and created resource's description, along
+//! with the versions of the harness and of the rack it ran against. These are
+//! what tie an old report or artifact to the rack state it came from.
+
+use std::sync::OnceLock;
+
+use serde::Serialize;
+use tracing::{info, warn};
+use uuid::Uuid;
+
+/// This run's ID.
+static RUN_ID: OnceLock<Uuid> = OnceLock::new();
+
+/// Returns this run's ID, which is random.
+pub fn run_id() -> Uuid {
+    *RUN_ID.get_or_init(|| {
+        uuid::Builder::from_random_bytes(rand::random()).into_uuid()
+    })
+}
+
+/// The version of the harness.
+pub const HARNESS_VERSION: &str = env!("CARGO_PKG_VERSION");
+
+/// The version of the rack's software, if it could be found.
+static NEXUS_VERSION: OnceLock<Option<String>> = OnceLock::new();
+
+/// Returns the version of the rack's software that `fetch_nexus_version`
+/// found, if it found one.
+pub fn nexus_version() -> Option<String> {
+    NEXUS_VERSION.get().cloned().flatten()
+}
+
+/// Asks Nexus which release the rack is running and remembers the answer for
+/// the report. The update status needs fleet-level read access, so a run
+/// without it goes on without the version.
+pub async fn fetch_nexus_version(client: &oxide::Client) {
+    let version = match update_status_version(client).await {
+        Ok(version) => version,
+        Err(e) => {
+            warn!("couldn't find the rack's software version: {:#}", e);
+            None
+        }
+    };
+
+    info!(version = version.as_deref(), "Rack software version");
+    let _ = NEXUS_VERSION.set(version);
+}
+
+/// Returns the target release's version from the rack's update status.
+async fn update_status_version(
+    client: &oxide::Client,
+) -> anyhow::Result<Option<String>> {
+    let url = format!(
+        "{}/v1/system/update/status",
+        client.baseurl().trim_end_matches('/')
+    );
+    let response = client.client().get(url).send().await?.error_for_status()?;
+    let status: serde_json::Value =
+        serde_json::from_slice(&response.bytes().await?)?;
+    Ok(status["target_release"]["version"].as_str().map(str::to_owned))
+}
+
+/// Returns the description to give a resource this run creates, given what
+/// the resource is for.
+pub fn description(what: &str) -> String {
+    format!("{} (omicron-stress run {})", what, run_id())
+}
+
+/// Returns true if `description` is one that `description` gave a resource in
+/// this run or an earlier one.
+pub fn is_harness_description(description: &str) -> bool {
+    description.contains("(omicron-stress run ")
+}
+
+/// The run's identity, as written into artifact bundles.
+#[derive(Debug, Serialize)]
+pub struct RunInfo {
+    pub run_id: String,
+    pub name_prefix: String,
+    pub seed: u64,
+    pub harness_version: &'static str,
+    pub nexus_version: Option<String>,
+}
+
+/// Returns the run's identity.
+pub fn info() -> RunInfo {
+    RunInfo {
+        run_id: run_id().to_string(),
+        name_prefix: crate::util::name_prefix().to_owned(),
+        seed: crate::util::seed(),
+        harness_version: HARNESS_VERSION,
+        nexus_version: nexus_version(),
+    }
+}
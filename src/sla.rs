@@ -0,0 +1,236 @@
+//! Checks the service-level limits on how long the control plane may take to
+//! carry out a request it has accepted, e.g. how long an instance may take to
+//! reach Stopped once a stop request succeeds (`--stop-sla`), and tracks the
+//! violations. The same polling measures how long those transitions take
+//! (`--measure-transitions`).
+
+use std::collections::BTreeMap;
+use std::fmt::Debug;
+use std::future::Future;
+use std::time::{Duration, Instant};
+
+use serde::Serialize;
+use tracing::{info, trace, warn};
+
+use crate::actor::AntagonistError;
+use crate::collector::Collector;
+
+/// How long to wait between checks on a resource.
+const POLL_INTERVAL: Duration = Duration::from_secs(1);
+
+/// How long to keep waiting for a resource whose transition is only being
+/// measured (--measure-transitions), or whose SLA has already been violated,
+/// before giving up on it.
+const MAX_WAIT: Duration = Duration::from_secs(10 * 60);
+
+/// How many violations to keep the details of. Past this, violations are
+/// only counted.
+const MAX_KEPT: usize = 100;
+
+/// A resource that didn't reach its expected state in time.
+#[derive(Clone, Debug, Serialize)]
+pub struct Violation {
+    /// The operation whose SLA was violated, e.g. `instance_stop`.
+    pub operation: &'static str,
+    pub resource: String,
+    pub limit_secs: f64,
+
+    /// The state the resource was last seen in.
+    pub last_state: String,
+
+    /// When the violation was detected, in RFC 3339 format.
+    pub time: String,
+    pub actor: Option<String>,
+}
+
+/// The run's SLA violations.
+#[derive(Clone, Debug, Default, Serialize)]
+pub struct Summary {
+    /// How many violations there were of each operation's SLA.
+    pub counts: BTreeMap<&'static str, u64>,
+
+    /// The first violations of the run, oldest first.
+    pub violations: Vec<Violation>,
+}
+
+static VIOLATIONS: Collector<Summary> = Collector::new();
+
+/// What the latest look at a resource says about an operation on it.
+pub enum Progress {
+    /// The resource reached the state the operation should put it in.
+    Done,
+
+    /// The operation may still be in progress.
+    Pending,
+
+    /// The resource reached a state that means the operation failed, e.g. a
+    /// disk that faulted while it was being created.
+    Failed,
+
+    /// Something else happened to the resource, e.g. another actor deleted
+    /// it, so the operation's SLA can't be judged.
+    Interrupted,
+}
+
+/// What to record about a transition with --measure-transitions.
+#[derive(Clone, Copy, Debug)]
+pub struct Measure {
+    /// When the request that started the transition was sent.
+    sent: Instant,
+
+    /// The size of the resource in bytes, for kinds of resources whose
+    /// transitions are recorded separately for each size.
+    size: Option<u64>,
+}
+
+impl Measure {
+    /// Measures a transition started by a request sent at `sent`.
+    pub fn since(sent: Instant) -> Self {
+        Self { sent, size: None }
+    }
+
+    /// Records the transition under the resource's size, `bytes`.
+    pub fn with_size(self, bytes: u64) -> Self {
+        Self { size: Some(bytes), ..self }
+    }
+}
+
+/// Polls a resource with `poll` until `progress` says that a successful
+/// `operation` on it is done, failed, or was interrupted, and records a
+/// violation if that takes longer than `limit`, if set. Returns an error for
+/// the violation if --fatal-sla-violations is set.
+///
+/// With --measure-transitions, a resource that gets to where `operation`
+/// should put it also has how long that took since the request was sent
+/// recorded in the stats, per `measure`. The measurement is only as fine as
+/// the poll interval (1s). Operations that fail are counted as failures; ones
+/// that are interrupted or take longer than `MAX_WAIT` aren't counted at all.
+pub async fn settle<S, F>(
+    operation: &'static str,
+    resource: &str,
+    limit: Option<Duration>,
+    measure: Option<Measure>,
+    mut poll: impl FnMut() -> F,
+    mut progress: impl FnMut(Option<&S>) -> Progress,
+) -> Result<(), AntagonistError>
+where
+    S: Debug,
+    F: Future<Output = Result<Option<S>, AntagonistError>>,
+{
+    let measure = measure.filter(|_| crate::config().measure_transitions);
+    if limit.is_none() && measure.is_none() {
+        return Ok(());
+    }
+
+    let start = Instant::now();
+    let mut late = false;
+    loop {
+        let state = poll().await?;
+        match progress(state.as_ref()) {
+            Progress::Done => {
+                trace!(operation, resource, elapsed = ?start.elapsed(), "SLA met");
+                if let Some(measure) = measure {
+                    crate::stats::record_transition(
+                        operation,
+                        measure.size,
+                        Some(measure.sent.elapsed()),
+                    );
+                }
+                return Ok(());
+            }
+            Progress::Failed => {
+                trace!(operation, resource, ?state, "operation failed");
+                if let Some(measure) = measure {
+                    crate::stats::record_transition(
+                        operation,
+                        measure.size,
+                        None,
+                    );
+                }
+                return Ok(());
+            }
+            Progress::Interrupted => {
+                trace!(operation, resource, ?state, "SLA check interrupted");
+                return Ok(());
+            }
+            Progress::Pending => {}
+        }
+
+        let elapsed = start.elapsed();
+        if let Some(limit) = limit.filter(|&limit| !late && elapsed >= limit) {
+            let last_state = match &state {
+                Some(state) => format!("{:?}", state),
+                None => "gone".to_owned(),
+            };
+            violated(operation, resource, limit, last_state)?;
+            if measure.is_none() {
+                return Ok(());
+            }
+
+            // Keep going to find out how long it does take.
+            late = true;
+        }
+
+        if elapsed >= limit.unwrap_or_default().max(MAX_WAIT) {
+            warn!(operation, resource, ?state, "gave up waiting on resource");
+            return Ok(());
+        }
+
+        tokio::time::sleep(POLL_INTERVAL).await;
+    }
+}
+
+/// Records that `resource` was still in `last_state` `limit` after a
+/// successful `operation`. Returns an error for the violation if
+/// --fatal-sla-violations is set.
+pub fn violated(
+    operation: &'static str,
+    resource: &str,
+    limit: Duration,
+    last_state: String,
+) -> Result<(), AntagonistError> {
+    record(operation, resource, limit, last_state);
+    if crate::config().fatal_sla_violations {
+        return Err(AntagonistError::CheckFailed(format!(
+            "{} of {} took longer than its {:?} SLA",
+            operation, resource, limit
+        )));
+    }
+
+    Ok(())
+}
+
+fn record(
+    operation: &'static str,
+    resource: &str,
+    limit: Duration,
+    last_state: String,
+) {
+    warn!(operation, resource, ?limit, last_state, "SLA violated");
+
+    let mut violations = VIOLATIONS.lock();
+    *violations.counts.entry(operation).or_default() += 1;
+    if violations.violations.len() < MAX_KEPT {
+        violations.violations.push(Violation {
+            operation,
+            resource: resource.to_owned(),
+            limit_secs: limit.as_secs_f64(),
+            last_state,
+            time: chrono::Utc::now().to_rfc3339(),
+            actor: crate::actor::current_actor().map(|a| a.name),
+        });
+    }
+}
+
+/// Returns the SLA violations so far.
+pub fn summary() -> Summary {
+    VIOLATIONS.snapshot()
+}
+
+/// Logs the SLA section of the end-of-run summary.
+pub fn log() {
+    let summary = summary();
+    for (operation, count) in summary.counts {
+        info!(operation, count, "SLA violations");
+    }
+}
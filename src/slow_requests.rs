@@ -0,0 +1,105 @@
+//! A watchdog for tail latency: keeps the slowest API calls of the run, and
+//! warns as soon as a call takes longer than `--slow-request-threshold`, so
+//! that the outlier can be looked into while the rack is still in the state
+//! that caused it.
+
+use std::cmp::Reverse;
+use std::collections::BinaryHeap;
+use std::time::Duration;
+
+use serde::Serialize;
+use tracing::{info, warn};
+
+use crate::collector::Collector;
+
+/// A slow API call.
+#[derive(Clone, Debug, Serialize, PartialEq, Eq, PartialOrd, Ord)]
+pub struct SlowRequest {
+    // Calls are ordered by latency first, so this has to be the first field.
+    #[serde(skip)]
+    latency: Duration,
+    pub latency_ms: u64,
+    pub endpoint: String,
+
+    /// When the call finished, in RFC 3339 format.
+    pub time: String,
+    pub actor: Option<String>,
+    pub request_id: Option<String>,
+}
+
+/// The slowest calls so far, kept in a min-heap so that the fastest of them
+/// is the one to go when a slower call comes along.
+static SLOWEST: Collector<BinaryHeap<Reverse<SlowRequest>>> = Collector::new();
+
+/// Records a call to `endpoint` that took `latency`, warning if it was slower
+/// than the configured threshold.
+pub fn record(endpoint: &str, latency: Duration, request_id: Option<&str>) {
+    let config = crate::config();
+    let keep = config.slowest_requests;
+    let threshold = config.slow_request_threshold;
+    let mut slowest = SLOWEST.lock();
+
+    let too_slow = threshold.is_some_and(|t| latency > t);
+    let one_of_slowest = keep > 0
+        && (slowest.len() < keep
+            || slowest.peek().is_some_and(|Reverse(r)| latency > r.latency));
+
+    if !too_slow && !one_of_slowest {
+        return;
+    }
+
+    let request = SlowRequest {
+        latency,
+        latency_ms: u64::try_from(latency.as_millis()).unwrap_or(u64::MAX),
+        endpoint: endpoint.to_owned(),
+        time: chrono::Utc::now().to_rfc3339(),
+        actor: crate::actor::current_actor().map(|a| a.name),
+        request_id: request_id.map(str::to_owned),
+    };
+
+    if too_slow {
+        warn!(
+            endpoint,
+            ?latency,
+            threshold = ?threshold.unwrap(),
+            request_id = ?request.request_id,
+            "Slow request"
+        );
+    }
+
+    if one_of_slowest {
+        if slowest.len() == keep {
+            slowest.pop();
+        }
+        slowest.push(Reverse(request));
+    }
+}
+
+/// Returns the slowest calls so far, slowest first.
+pub fn summary() -> Vec<SlowRequest> {
+    let slowest = SLOWEST.lock();
+    let mut summary: Vec<_> =
+        slowest.iter().map(|Reverse(r)| r.clone()).collect();
+    summary.sort_by(|a, b| b.cmp(a));
+    summary
+}
+
+/// Logs the slowest requests section of the end-of-run summary.
+pub fn log() {
+    let summary = summary();
+    if summary.is_empty() {
+        return;
+    }
+
+    info!(count = summary.len(), "Slowest requests");
+    for r in summary {
+        info!(
+            endpoint = r.endpoint,
+            latency = ?r.latency,
+            time = r.time,
+            actor = ?r.actor,
+            request_id = ?r.request_id,
+            "Slow request"
+        );
+    }
+}
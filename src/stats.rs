@@ -0,0 +1,406 @@
+//! Counters and step latencies for each kind of actor, and latencies for each
+//! API endpoint the actors call, kept for the periodic progress summaries and
+//! the end-of-run report.
+//!
+//! Each API call's latency is recorded by the call middleware
+//! (`crate::middleware`) under the endpoint's name and the response's status
+//! class.
+
+use std::collections::BTreeMap;
+use std::sync::{Mutex, OnceLock};
+use std::time::Duration;
+
+use hdrhistogram::Histogram;
+use serde::Serialize;
+use tracing::info;
+
+/// Step counts and latencies over some period.
+struct Counts {
+    steps: u64,
+    failures: u64,
+    retries: u64,
+    cancelled: u64,
+    abandoned: u64,
+
+    /// How long each step took, in microseconds.
+    latency: Histogram<u64>,
+}
+
+impl Counts {
+    fn new() -> Self {
+        Self {
+            steps: 0,
+            failures: 0,
+            retries: 0,
+            cancelled: 0,
+            abandoned: 0,
+            latency: Histogram::new(3).unwrap(),
+        }
+    }
+}
+
+/// What's been recorded so far about one kind of actor.
+struct KindStats {
+    /// Everything recorded during the run.
+    total: Counts,
+
+    /// Everything recorded since the last progress summary.
+    recent: Counts,
+}
+
+static STATS: OnceLock<Mutex<BTreeMap<&'static str, KindStats>>> =
+    OnceLock::new();
+
+fn stats() -> &'static Mutex<BTreeMap<&'static str, KindStats>> {
+    STATS.get_or_init(Default::default)
+}
+
+fn with_counts(kind: &'static str, f: impl Fn(&mut Counts)) {
+    let mut stats = stats().lock().unwrap();
+    let stats = stats.entry(kind).or_insert_with(|| KindStats {
+        total: Counts::new(),
+        recent: Counts::new(),
+    });
+    f(&mut stats.total);
+    f(&mut stats.recent);
+}
+
+/// Records that an actor of the supplied `kind` took a step that lasted
+/// `latency` and either succeeded or failed.
+pub fn record_step(kind: &'static str, latency: Duration, succeeded: bool) {
+    let micros = u64::try_from(latency.as_micros()).unwrap_or(u64::MAX);
+    with_counts(kind, |counts| {
+        counts.steps += 1;
+        if !succeeded {
+            counts.failures += 1;
+        }
+
+        counts.latency.saturating_record(micros);
+    });
+}
+
+/// Records that an actor of the supplied `kind` is about to retry a failed
+/// API call.
+pub fn record_retry(kind: &'static str) {
+    with_counts(kind, |counts| counts.retries += 1);
+}
+
+/// Records that an actor of the supplied `kind` abandoned an API call because
+/// it was halted.
+pub fn record_cancelled(kind: &'static str) {
+    with_counts(kind, |counts| counts.cancelled += 1);
+}
+
+/// Records that an actor of the supplied `kind` abandoned an API call on
+/// purpose (--abandon-requests).
+pub fn record_abandoned(kind: &'static str) {
+    with_counts(kind, |counts| counts.abandoned += 1);
+}
+
+/// Step latency percentiles, in milliseconds.
+#[derive(Debug, Serialize)]
+pub struct Percentiles {
+    pub p50: f64,
+    pub p95: f64,
+    pub p99: f64,
+    pub max: f64,
+}
+
+impl Percentiles {
+    /// Returns the percentiles of `latency`, or `None` if it's empty.
+    fn of(latency: &Histogram<u64>) -> Option<Self> {
+        if latency.is_empty() {
+            return None;
+        }
+
+        let ms = |q| latency.value_at_quantile(q) as f64 / 1000.0;
+        Some(Self {
+            p50: ms(0.5),
+            p95: ms(0.95),
+            p99: ms(0.99),
+            max: latency.max() as f64 / 1000.0,
+        })
+    }
+}
+
+/// A summary of what one kind of actor did over some period.
+#[derive(Debug, Serialize)]
+pub struct KindSummary {
+    /// How many steps actors of this kind took, including failed ones.
+    pub steps: u64,
+    pub failures: u64,
+
+    /// How many API calls actors of this kind retried.
+    pub retries: u64,
+
+    /// How many API calls actors of this kind abandoned because they were
+    /// halted partway through a step.
+    pub cancelled: u64,
+
+    /// How many writes actors of this kind abandoned on purpose, before their
+    /// responses arrived (--abandon-requests).
+    pub abandoned: u64,
+
+    /// Step latencies, or `None` if no steps were taken.
+    pub step_latency_ms: Option<Percentiles>,
+}
+
+impl From<&Counts> for KindSummary {
+    fn from(counts: &Counts) -> Self {
+        Self {
+            steps: counts.steps,
+            failures: counts.failures,
+            retries: counts.retries,
+            cancelled: counts.cancelled,
+            abandoned: counts.abandoned,
+            step_latency_ms: Percentiles::of(&counts.latency),
+        }
+    }
+}
+
+/// The latencies of the calls to one endpoint whose responses fell in one
+/// status class, in microseconds.
+struct EndpointStats {
+    /// Every call during the run.
+    total: Histogram<u64>,
+
+    /// The calls since the last progress summary.
+    recent: Histogram<u64>,
+}
+
+/// Endpoint latencies, keyed by endpoint name and status class.
+type EndpointMap = BTreeMap<(&'static str, &'static str), EndpointStats>;
+
+static ENDPOINTS: OnceLock<Mutex<EndpointMap>> = OnceLock::new();
+
+fn endpoints() -> &'static Mutex<EndpointMap> {
+    ENDPOINTS.get_or_init(Default::default)
+}
+
+/// Returns the class (e.g. `5xx`) of the supplied status code.
+fn status_class(status: http::StatusCode) -> &'static str {
+    match status.as_u16() / 100 {
+        1 => "1xx",
+        2 => "2xx",
+        3 => "3xx",
+        4 => "4xx",
+        _ => "5xx",
+    }
+}
+
+/// Records that a call to `endpoint` got a response with `status` (or none)
+/// after `latency`.
+pub fn record_call(
+    endpoint: &'static str,
+    status: Option<http::StatusCode>,
+    latency: Duration,
+) {
+    let class = status.map_or("none", status_class);
+    let micros = u64::try_from(latency.as_micros()).unwrap_or(u64::MAX);
+    let mut endpoints = endpoints().lock().unwrap();
+    let stats =
+        endpoints.entry((endpoint, class)).or_insert_with(|| EndpointStats {
+            total: Histogram::new(3).unwrap(),
+            recent: Histogram::new(3).unwrap(),
+        });
+
+    stats.total.saturating_record(micros);
+    stats.recent.saturating_record(micros);
+}
+
+/// A summary of the calls to one endpoint that got responses in one status
+/// class.
+#[derive(Debug, Serialize)]
+pub struct EndpointSummary {
+    pub endpoint: &'static str,
+
+    /// The responses' status class (e.g. `2xx`), or `none` for calls that
+    /// didn't get a response.
+    pub status_class: &'static str,
+    pub calls: u64,
+    pub latency_ms: Percentiles,
+}
+
+/// Summarizes each of the histograms that `select` picks out of the endpoint
+/// map, skipping empty ones.
+fn summarize_endpoints(
+    select: impl Fn(&mut EndpointStats) -> Histogram<u64>,
+) -> Vec<EndpointSummary> {
+    let mut endpoints = endpoints().lock().unwrap();
+    endpoints
+        .iter_mut()
+        .filter_map(|(&(endpoint, status_class), stats)| {
+            let latency = select(stats);
+            Some(EndpointSummary {
+                endpoint,
+                status_class,
+                calls: latency.len(),
+                latency_ms: Percentiles::of(&latency)?,
+            })
+        })
+        .collect()
+}
+
+/// Returns a summary of the whole run's calls to each endpoint, grouped by
+/// status class.
+pub fn endpoint_summary() -> Vec<EndpointSummary> {
+    summarize_endpoints(|stats| stats.total.clone())
+}
+
+/// Returns a summary of the calls to each endpoint since the last call, grouped
+/// by status class, and starts a new period.
+fn take_recent_endpoints() -> Vec<EndpointSummary> {
+    summarize_endpoints(|stats| {
+        std::mem::replace(&mut stats.recent, Histogram::new(3).unwrap())
+    })
+}
+
+/// The transitions measured for one operation (and resource size).
+struct TransitionStats {
+    /// How long the transitions that completed took, in microseconds.
+    latency: Histogram<u64>,
+
+    /// How many transitions ended in a state that means the operation failed.
+    failures: u64,
+}
+
+/// Measured transitions (--measure-transitions), keyed by operation and, for
+/// resources whose transitions are measured per size, size in bytes.
+type TransitionMap = BTreeMap<(&'static str, Option<u64>), TransitionStats>;
+
+static TRANSITIONS: OnceLock<Mutex<TransitionMap>> = OnceLock::new();
+
+fn transitions() -> &'static Mutex<TransitionMap> {
+    TRANSITIONS.get_or_init(Default::default)
+}
+
+/// Records that a resource of `size` bytes (if its transitions are measured
+/// per size) got to where a successful `operation` should have put it (e.g.
+/// Running, for `instance_start`) `latency` after the request was sent, or,
+/// if `latency` is `None`, that the operation failed instead.
+pub fn record_transition(
+    operation: &'static str,
+    size: Option<u64>,
+    latency: Option<Duration>,
+) {
+    let mut transitions = transitions().lock().unwrap();
+    let stats = transitions.entry((operation, size)).or_insert_with(|| {
+        TransitionStats { latency: Histogram::new(3).unwrap(), failures: 0 }
+    });
+
+    match latency {
+        Some(latency) => {
+            let micros = u64::try_from(latency.as_micros()).unwrap_or(u64::MAX);
+            stats.latency.saturating_record(micros);
+        }
+        None => stats.failures += 1,
+    }
+}
+
+/// A summary of one operation's transitions.
+#[derive(Debug, Serialize)]
+pub struct TransitionSummary {
+    pub operation: &'static str,
+
+    /// The size of the resources in bytes, for operations whose transitions
+    /// are measured per size.
+    pub size_bytes: Option<u64>,
+    pub count: u64,
+    pub failures: u64,
+
+    /// `None` if every transition failed.
+    pub latency_ms: Option<Percentiles>,
+}
+
+/// Returns a summary of the transitions measured so far, by operation.
+pub fn transition_summary() -> Vec<TransitionSummary> {
+    let transitions = transitions().lock().unwrap();
+    transitions
+        .iter()
+        .map(|(&(operation, size_bytes), stats)| TransitionSummary {
+            operation,
+            size_bytes,
+            count: stats.latency.len(),
+            failures: stats.failures,
+            latency_ms: Percentiles::of(&stats.latency),
+        })
+        .collect()
+}
+
+/// Logs the transition latency section of the end-of-run summary.
+pub fn log_transitions() {
+    for summary in transition_summary() {
+        let latency = summary.latency_ms;
+        info!(
+            operation = summary.operation,
+            size_bytes = summary.size_bytes,
+            count = summary.count,
+            failures = summary.failures,
+            p50_ms = latency.as_ref().map(|l| l.p50),
+            p95_ms = latency.as_ref().map(|l| l.p95),
+            p99_ms = latency.as_ref().map(|l| l.p99),
+            max_ms = latency.as_ref().map(|l| l.max),
+            "Transition latency"
+        );
+    }
+}
+
+/// Returns a summary of the whole run so far for each kind of actor that has
+/// taken a step, keyed by kind name.
+pub fn summary() -> BTreeMap<String, KindSummary> {
+    let stats = stats().lock().unwrap();
+    stats
+        .iter()
+        .map(|(kind, stats)| (kind.to_string(), (&stats.total).into()))
+        .collect()
+}
+
+/// Returns a summary of what each kind of actor has done since the last call,
+/// keyed by kind name, and starts a new period.
+fn take_recent() -> BTreeMap<String, KindSummary> {
+    let mut stats = stats().lock().unwrap();
+    stats
+        .iter_mut()
+        .map(|(kind, stats)| {
+            let recent = std::mem::replace(&mut stats.recent, Counts::new());
+            (kind.to_string(), (&recent).into())
+        })
+        .collect()
+}
+
+/// Logs a progress summary line for each kind of actor and each endpoint,
+/// covering what happened over the last `period` (i.e. since the last call).
+pub fn log_progress(period: Duration) {
+    let recent = take_recent();
+    if recent.is_empty() {
+        info!("Progress: no actor has taken a step yet");
+        return;
+    }
+
+    for (kind, summary) in recent {
+        let steps_per_sec = summary.steps as f64 / period.as_secs_f64();
+        let latency = summary.step_latency_ms;
+        info!(
+            kind,
+            steps_per_sec = format!("{:.2}", steps_per_sec),
+            failures = summary.failures,
+            retries = summary.retries,
+            p50_ms = latency.as_ref().map(|l| l.p50),
+            p95_ms = latency.as_ref().map(|l| l.p95),
+            p99_ms = latency.as_ref().map(|l| l.p99),
+            "Progress"
+        );
+    }
+    for summary in take_recent_endpoints() {
+        let latency = summary.latency_ms;
+        info!(
+            endpoint = summary.endpoint,
+            status_class = summary.status_class,
+            calls = summary.calls,
+            p50_ms = latency.p50,
+            p95_ms = latency.p95,
+            p99_ms = latency.p99,
+            "Endpoint latency"
+        );
+    }
+}
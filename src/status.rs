@@ -0,0 +1,200 @@
+//! Keeps track of what each actor is doing, for the status snapshot the
+//! harness logs when it gets SIGUSR1: how many steps each actor has taken,
+//! how its last step went, which API call it's waiting on (if any), and its
+//! most recent calls.
+//!
+//! Also keeps the heartbeat (`--heartbeat-file` and the control API's
+//! `/healthz`): when each kind of actor's API calls last succeeded, so that a
+//! supervisor can tell a wedged harness (e.g. every actor stuck on a hung
+//! request) from a working one, and whether the run is still going.
+
+use std::collections::BTreeMap;
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Mutex, OnceLock};
+use std::time::Instant;
+
+use anyhow::{Context, Result};
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use tracing::info;
+
+/// How many of an actor's most recent calls to include in a snapshot.
+const RECENT_CALLS: usize = 5;
+
+/// What's known about one actor.
+#[derive(Default)]
+struct ActorStatus {
+    steps: u64,
+    failures: u64,
+
+    /// When the actor's last step ended and whether it succeeded.
+    last_step: Option<(Instant, bool)>,
+
+    /// The endpoint of the API call the actor is waiting on, and when the
+    /// call was sent.
+    in_flight: Option<(&'static str, Instant)>,
+}
+
+static STATUS: OnceLock<Mutex<BTreeMap<String, ActorStatus>>> = OnceLock::new();
+
+/// When each kind of actor's API calls last succeeded, keyed by kind name.
+static LAST_SUCCESS: OnceLock<Mutex<BTreeMap<&'static str, DateTime<Utc>>>> =
+    OnceLock::new();
+
+/// Applies `f` to the current actor's status. Does nothing if this isn't an
+/// actor task.
+fn with_current(f: impl FnOnce(&mut ActorStatus)) {
+    let Some(actor) = crate::actor::current_actor() else {
+        return;
+    };
+
+    let mut status = STATUS.get_or_init(Default::default).lock().unwrap();
+    f(status.entry(actor.name).or_default());
+}
+
+/// Records that the current actor finished a step that either succeeded or
+/// failed.
+pub fn record_step(succeeded: bool) {
+    with_current(|status| {
+        status.steps += 1;
+        if !succeeded {
+            status.failures += 1;
+        }
+        status.last_step = Some((Instant::now(), succeeded));
+    });
+}
+
+/// Records that the current actor is sending a call to `endpoint`.
+pub fn call_started(endpoint: &'static str) {
+    with_current(|status| status.in_flight = Some((endpoint, Instant::now())));
+}
+
+/// Records that the current actor's call finished, and either succeeded or
+/// failed.
+pub fn call_finished(succeeded: bool) {
+    with_current(|status| status.in_flight = None);
+    if let (true, Some(actor)) = (succeeded, crate::actor::current_actor()) {
+        let mut last =
+            LAST_SUCCESS.get_or_init(Default::default).lock().unwrap();
+        last.insert(actor.kind, Utc::now());
+    }
+}
+
+/// Returns when the actor named `actor` last finished a step and, if it's
+/// waiting on an API call, that call's endpoint and how long it's been
+/// waiting.
+pub fn liveness(
+    actor: &str,
+) -> (Option<Instant>, Option<(&'static str, std::time::Duration)>) {
+    let status = STATUS.get_or_init(Default::default).lock().unwrap();
+    let Some(status) = status.get(actor) else {
+        return (None, None);
+    };
+
+    (
+        status.last_step.map(|(at, _)| at),
+        status.in_flight.map(|(endpoint, at)| (endpoint, at.elapsed())),
+    )
+}
+
+/// Whether the run is going, i.e. it has started and not yet ended.
+static RUNNING: AtomicBool = AtomicBool::new(false);
+
+/// Records whether the run is going, for the heartbeat.
+pub fn set_running(running: bool) {
+    RUNNING.store(running, Ordering::Relaxed);
+}
+
+/// The harness's heartbeat.
+#[derive(Debug, Serialize)]
+pub struct Heartbeat {
+    /// When the heartbeat was taken, in RFC 3339 format.
+    pub time: String,
+
+    /// Whether the run is going: false before it starts (e.g. while it waits
+    /// for `--wait-for-start`) and once it has ended.
+    pub running: bool,
+
+    /// When each kind of actor's API calls last succeeded, in RFC 3339
+    /// format, keyed by kind name. Kinds that haven't had a successful call
+    /// yet are left out.
+    pub last_success: BTreeMap<&'static str, String>,
+}
+
+/// Returns the harness's current heartbeat.
+pub fn heartbeat() -> Heartbeat {
+    let last = LAST_SUCCESS.get_or_init(Default::default).lock().unwrap();
+    Heartbeat {
+        time: Utc::now().to_rfc3339(),
+        running: RUNNING.load(Ordering::Relaxed),
+        last_success: last.iter().map(|(k, t)| (*k, t.to_rfc3339())).collect(),
+    }
+}
+
+/// Writes the current heartbeat to `path` as JSON. The file is replaced
+/// atomically, so readers never see a partial heartbeat.
+pub fn write_heartbeat(path: &Path) -> Result<()> {
+    let json = serde_json::to_string_pretty(&heartbeat())
+        .context("serializing heartbeat")?;
+    let mut tmp = path.as_os_str().to_owned();
+    tmp.push(".tmp");
+    std::fs::write(&tmp, json)
+        .with_context(|| format!("writing {}", Path::new(&tmp).display()))?;
+    std::fs::rename(&tmp, path)
+        .with_context(|| format!("replacing {}", path.display()))
+}
+
+/// Logs a status snapshot of `actors`: each kind's counters, then each actor's
+/// counters, last step, in-flight call, and most recent calls.
+pub fn log(actors: &[crate::actor::Actor]) {
+    info!(actors = actors.len(), "Status snapshot");
+    for (kind, summary) in crate::stats::summary() {
+        info!(
+            kind,
+            steps = summary.steps,
+            failures = summary.failures,
+            retries = summary.retries,
+            "Status: actor kind"
+        );
+    }
+
+    let status = STATUS.get_or_init(Default::default).lock().unwrap();
+    for actor in actors {
+        let status = status.get(actor.name());
+        let last_step = status.and_then(|s| s.last_step).map(|(at, ok)| {
+            format!(
+                "{} {:?} ago",
+                if ok { "succeeded" } else { "failed" },
+                at.elapsed()
+            )
+        });
+        let in_flight = status
+            .and_then(|s| s.in_flight)
+            .map(|(e, at)| format!("{} for {:?}", e, at.elapsed()));
+        let history = crate::request_log::history(actor.name());
+        let recent: Vec<String> = history
+            .iter()
+            .rev()
+            .take(RECENT_CALLS)
+            .map(|call| {
+                let status = call
+                    .status
+                    .map_or_else(|| "-".to_string(), |s| s.to_string());
+                format!("{} {} {}", call.time, call.endpoint, status)
+            })
+            .collect();
+
+        info!(
+            actor = actor.name(),
+            kind = actor.kind(),
+            paused = actor.is_paused(),
+            steps = status.map_or(0, |s| s.steps),
+            failures = status.map_or(0, |s| s.failures),
+            last_step,
+            in_flight,
+            ?recent,
+            "Status: actor"
+        );
+    }
+}
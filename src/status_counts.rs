@@ -0,0 +1,118 @@
+//! Counts the status codes each endpoint returns, and warns when the share of
+//! an endpoint's responses in some status class shifts sharply away from what
+//! it's been for the run so far (e.g. a sudden burst of 503s). This surfaces
+//! partial degradation even when the status codes involved aren't fatal.
+//!
+//! Counts are kept in windows of `--anomaly-window`. At the end of each window,
+//! the share of each status class in the window is compared to its share over
+//! all the earlier windows, and then the window's counts are folded into the
+//! run's totals.
+
+use std::collections::BTreeMap;
+
+use tracing::warn;
+
+use crate::collector::Collector;
+
+/// How many calls an endpoint needs to have gotten, both in the window and
+/// before it, for its status mix to be compared. Fewer calls than this give
+/// too noisy a picture.
+const MIN_CALLS: u64 = 20;
+
+/// The status code of a call, or `None` if it got no response.
+type Status = Option<u16>;
+
+/// One endpoint's status code counts.
+#[derive(Default)]
+struct EndpointCounts {
+    /// Counts from the windows before the current one.
+    baseline: BTreeMap<Status, u64>,
+
+    /// Counts from the current window.
+    window: BTreeMap<Status, u64>,
+}
+
+static COUNTS: Collector<BTreeMap<&'static str, EndpointCounts>> =
+    Collector::new();
+
+/// Returns the class (e.g. `5xx`) of a status, or `none` for no response.
+fn class(status: Status) -> String {
+    match status {
+        Some(code) => format!("{}xx", code / 100),
+        None => "none".to_string(),
+    }
+}
+
+/// Returns the share of the calls in `counts` that fell in each status class.
+fn class_shares(
+    counts: &BTreeMap<Status, u64>,
+) -> (u64, BTreeMap<String, f64>) {
+    let total: u64 = counts.values().sum();
+    let mut shares = BTreeMap::new();
+    for (status, count) in counts {
+        *shares.entry(class(*status)).or_default() +=
+            *count as f64 / total as f64;
+    }
+
+    (total, shares)
+}
+
+/// Records that a call to `endpoint` got a response with the supplied status,
+/// or no response if `status` is `None`.
+pub fn record(endpoint: &'static str, status: Option<http::StatusCode>) {
+    let mut counts = COUNTS.lock();
+    let endpoint = counts.entry(endpoint).or_default();
+    *endpoint.window.entry(status.map(|s| s.as_u16())).or_default() += 1;
+}
+
+/// Ends the current window: warns about each endpoint whose share of some
+/// status class grew by at least `threshold` (a fraction of all its calls)
+/// compared to the earlier windows, then folds the window into the totals.
+pub fn check(threshold: f64) {
+    let mut counts = COUNTS.lock();
+    for (endpoint, counts) in counts.iter_mut() {
+        let (window_calls, window) = class_shares(&counts.window);
+        let (baseline_calls, baseline) = class_shares(&counts.baseline);
+        if window_calls >= MIN_CALLS && baseline_calls >= MIN_CALLS {
+            for (class, share) in &window {
+                let baseline_share =
+                    baseline.get(class).copied().unwrap_or(0.0);
+                if share - baseline_share >= threshold {
+                    warn!(
+                        endpoint,
+                        class,
+                        share = format!("{:.1}%", share * 100.0),
+                        baseline_share =
+                            format!("{:.1}%", baseline_share * 100.0),
+                        calls = window_calls,
+                        "Status class share jumped"
+                    );
+                }
+            }
+        }
+
+        for (status, count) in std::mem::take(&mut counts.window) {
+            *counts.baseline.entry(status).or_default() += count;
+        }
+    }
+}
+
+/// Returns how many times each endpoint has returned each status code (or
+/// `none` for calls with no response), keyed by endpoint name.
+pub fn summary() -> BTreeMap<String, BTreeMap<String, u64>> {
+    let counts = COUNTS.lock();
+    counts
+        .iter()
+        .map(|(endpoint, counts)| {
+            let mut statuses = BTreeMap::new();
+            for (status, count) in counts.baseline.iter().chain(&counts.window)
+            {
+                let status =
+                    status.map_or("none".to_string(), |s| s.to_string());
+                *statuses.entry(status).or_default() += count;
+            }
+
+            (endpoint.to_string(), statuses)
+        })
+        .collect()
+}
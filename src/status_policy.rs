@@ -0,0 +1,128 @@
+//! The policy that decides which error responses from Nexus count as bugs.
+//!
+//! By default, actors expect any error response (e.g. a 400 for trying to stop
+//! an instance that's already stopped) and only fail if a request gets no
+//! response at all. `--fatal-status` makes matching error responses count as
+//! failures too, and `--ignore-status` exempts matching responses, so that an
+//! investigation can decide, say, that 503s are expected while Nexus is being
+//! upgraded but fatal otherwise.
+//!
+//! Throttled responses (429s and 503s) are Nexus pushing back rather than
+//! breaking, so a status class like `5xx` doesn't make them fatal; only naming
+//! their status code does. `--throttle-budget` limits them separately (see
+//! `crate::throttle`).
+
+use crate::util::OxideApiError;
+
+/// A pattern that matches error responses.
+#[derive(Clone, Debug)]
+pub enum StatusMatcher {
+    /// Matches responses with this exact status code (e.g. `503`).
+    Code(u16),
+
+    /// Matches responses whose status code is in this class, expressed as its
+    /// first digit (e.g. `5xx`).
+    Class(u16),
+
+    /// Matches responses whose body has this error code (e.g.
+    /// `type:ObjectAlreadyExists`).
+    ErrorCode(String),
+}
+
+impl StatusMatcher {
+    /// Returns true if a response with the supplied `status` and body
+    /// `error_code` matches this pattern.
+    pub fn matches(
+        &self,
+        status: http::StatusCode,
+        error_code: Option<&str>,
+    ) -> bool {
+        match self {
+            StatusMatcher::Code(code) => status.as_u16() == *code,
+            StatusMatcher::Class(class) => status.as_u16() / 100 == *class,
+            StatusMatcher::ErrorCode(code) => error_code == Some(code.as_str()),
+        }
+    }
+}
+
+/// Parses a status code (`503`), status class (`5xx`), or error code
+/// (`type:ObjectAlreadyExists`) from the command line.
+pub fn parse_matcher(s: &str) -> Result<StatusMatcher, String> {
+    if let Some(code) = s.strip_prefix("type:") {
+        if code.is_empty() {
+            return Err("expected an error code after \"type:\"".to_string());
+        }
+
+        return Ok(StatusMatcher::ErrorCode(code.to_owned()));
+    }
+
+    if let Some(class) = s.strip_suffix("xx") {
+        return match class.parse::<u16>() {
+            Ok(class) if (1..=5).contains(&class) => {
+                Ok(StatusMatcher::Class(class))
+            }
+            _ => Err(format!("invalid status class {:?}", s)),
+        };
+    }
+
+    match s.parse::<u16>() {
+        Ok(code) if (100..600).contains(&code) => Ok(StatusMatcher::Code(code)),
+        _ => Err(format!(
+            "expected a status code, a status class like 5xx, or \
+            type:<error code>, got {:?}",
+            s
+        )),
+    }
+}
+
+/// Returns `Err` if `e` counts as a failure under the configured policy:
+///
+/// - Error responses fail if they match --fatal-status (or are 500s and
+///   --server-errors-fatal is set) and don't match --ignore-status. Throttled
+///   responses only match --fatal-status by status code or error code.
+/// - Requests that got no response, or an unexpected or malformed one, always
+///   fail.
+/// - Requests that couldn't be built fail unless a fatal status is configured.
+///
+/// Returns `Ok` otherwise.
+pub fn check(e: OxideApiError) -> Result<(), OxideApiError> {
+    let config = crate::config();
+    let fatal_status_set =
+        config.server_errors_fatal || !config.fatal_status.is_empty();
+
+    match &e {
+        oxide::Error::ErrorResponse(rv) => {
+            let status = rv.status();
+            let error_code = rv.error_code.as_deref();
+            let matches = |m: &StatusMatcher| m.matches(status, error_code);
+
+            if config.ignore_status.iter().any(matches) {
+                return Ok(());
+            }
+
+            let throttled = crate::throttle::is_throttled(status);
+            let fatal = config
+                .fatal_status
+                .iter()
+                .filter(|m| {
+                    !(throttled && matches!(m, StatusMatcher::Class(_)))
+                })
+                .any(matches)
+                || (config.server_errors_fatal
+                    && status == http::StatusCode::INTERNAL_SERVER_ERROR);
+
+            if fatal {
+                Err(e)
+            } else {
+                Ok(())
+            }
+        }
+
+        oxide::Error::InvalidRequest(_) if fatal_status_set => Ok(()),
+
+        // There was a communication error, an error reading or deserializing
+        // the response, an unexpected response, an error upgrading the
+        // connection, or an error processing a request pre-hook.
+        _ => Err(e),
+    }
+}
@@ -0,0 +1,197 @@
+//! The actor supervisor, which restarts actors whose tasks die (e.g. because
+//! an antagonist panicked) or stall, instead of letting them drop out of the
+//! run (`--max-actor-restarts`, `--actor-stall-timeout`).
+//!
+//! Each actor may be restarted up to `--max-actor-restarts` times. Each
+//! restart waits twice as long as the last before the new actor takes its
+//! first step, so that an actor that dies as soon as it starts doesn't spin.
+//! Restarts are logged, counted, and listed in the report.
+//!
+//! The supervisor also bounds how long halting actors can take
+//! (`--shutdown-timeout`): actors that haven't stopped by then are aborted and
+//! reported, along with the call each one was stuck on.
+//!
+//! With `--crash-actors`, the supervisor also kills actors on purpose, as if
+//! their client had crashed, and replaces each with a fresh one right away.
+//! The fresh actor knows nothing of what its predecessor was doing, so it has
+//! to rediscover its resource and adopt it in whatever state it was left in.
+//! Only the kinds of actors that look up their resource's state before acting
+//! on it are crashed; the others keep track of the resources they create
+//! across steps, and would trip over their predecessors' leftovers. Crashes
+//! don't count against `--max-actor-restarts`.
+
+use std::collections::BTreeMap;
+use std::sync::{Mutex, OnceLock};
+use std::time::Duration;
+
+use serde::Serialize;
+use tokio::task::JoinHandle;
+use tracing::{info, warn};
+
+/// How long to wait before an actor's first restart.
+const INITIAL_BACKOFF: Duration = Duration::from_secs(1);
+
+/// The longest to wait before restarting an actor.
+const MAX_BACKOFF: Duration = Duration::from_secs(60);
+
+/// How many restarts to keep the details of. Past this, restarts are only
+/// counted.
+const MAX_KEPT: usize = 100;
+
+/// The kinds of actors that --crash-actors may crash.
+const CRASHABLE_KINDS: &[&str] = &["instance", "disk", "snapshot", "conflict"];
+
+/// An actor restart.
+#[derive(Clone, Debug, Serialize)]
+pub struct Restart {
+    pub actor: String,
+    pub kind: &'static str,
+
+    /// Why the actor was restarted, e.g. the panic message.
+    pub reason: String,
+
+    /// When the actor was restarted, in RFC 3339 format.
+    pub time: String,
+}
+
+/// The run's actor restarts.
+#[derive(Clone, Debug, Default, Serialize)]
+pub struct Summary {
+    /// How many times each actor was restarted, keyed by actor name.
+    pub counts: BTreeMap<String, u32>,
+
+    /// The first restarts of the run, oldest first.
+    pub restarts: Vec<Restart>,
+
+    /// How many times each actor was crashed on purpose (--crash-actors),
+    /// keyed by actor name.
+    pub crashes: BTreeMap<String, u32>,
+}
+
+static RESTARTS: OnceLock<Mutex<Summary>> = OnceLock::new();
+
+fn restarts() -> &'static Mutex<Summary> {
+    RESTARTS.get_or_init(Default::default)
+}
+
+/// Returns true if the actor named `actor` may be restarted again.
+pub fn may_restart(actor: &str) -> bool {
+    let restarts = restarts().lock().unwrap();
+    let count = restarts.counts.get(actor).copied().unwrap_or(0);
+    count < crate::config().max_actor_restarts
+}
+
+/// Records that the actor named `actor`, of kind `kind`, is being restarted
+/// because of `reason`, and returns how long the new actor should wait before
+/// its first step.
+pub fn record(actor: &str, kind: &'static str, reason: String) -> Duration {
+    let mut restarts = restarts().lock().unwrap();
+    let count = restarts.counts.entry(actor.to_owned()).or_default();
+    let backoff =
+        INITIAL_BACKOFF.saturating_mul(1 << (*count).min(16)).min(MAX_BACKOFF);
+    *count += 1;
+
+    warn!(actor, kind, restart = *count, ?backoff, reason, "Restarting actor");
+    if restarts.restarts.len() < MAX_KEPT {
+        restarts.restarts.push(Restart {
+            actor: actor.to_owned(),
+            kind,
+            reason,
+            time: chrono::Utc::now().to_rfc3339(),
+        });
+    }
+
+    backoff
+}
+
+/// Returns true if --crash-actors may crash actors of kind `kind`.
+pub fn may_crash(kind: &str) -> bool {
+    CRASHABLE_KINDS.contains(&kind)
+}
+
+/// Records that the actor named `actor`, of kind `kind`, was crashed on
+/// purpose.
+pub fn record_crash(actor: &str, kind: &'static str) {
+    let mut restarts = restarts().lock().unwrap();
+    let count = restarts.crashes.entry(actor.to_owned()).or_default();
+    *count += 1;
+    info!(actor, kind, crash = *count, "Crashing actor");
+}
+
+/// An actor that didn't halt within --shutdown-timeout.
+#[derive(Clone, Debug, Serialize)]
+pub struct Straggler {
+    pub actor: String,
+
+    /// The API call the actor was waiting on and for how long, if it was
+    /// waiting on one.
+    pub in_flight: Option<String>,
+}
+
+/// Waits for the tasks in `halting`, each tagged with the name of the actor
+/// that's halting, to finish. If --shutdown-timeout passes first, aborts the
+/// tasks that are left and returns their actors.
+pub async fn join(
+    mut halting: Vec<(String, JoinHandle<()>)>,
+) -> Vec<Straggler> {
+    let timeout = crate::config().shutdown_timeout;
+    let tasks = futures::future::join_all(halting.iter_mut().map(|(_, t)| t));
+    if tokio::time::timeout(timeout, tasks).await.is_ok() {
+        return Vec::new();
+    }
+
+    let mut stragglers = Vec::new();
+    for (actor, task) in halting {
+        if task.is_finished() {
+            continue;
+        }
+
+        let (_, in_flight) = crate::status::liveness(&actor);
+        let in_flight = in_flight
+            .map(|(endpoint, waited)| format!("{} for {:?}", endpoint, waited));
+        warn!(
+            actor,
+            ?in_flight,
+            ?timeout,
+            "Actor didn't halt in time, aborting"
+        );
+        task.abort();
+        stragglers.push(Straggler { actor, in_flight });
+    }
+
+    stragglers
+}
+
+/// Returns a description of why an actor's task ended, given the result of
+/// joining it.
+pub fn describe_exit(result: Result<(), tokio::task::JoinError>) -> String {
+    match result {
+        Ok(()) => "task exited".to_owned(),
+        Err(e) if e.is_panic() => {
+            let panic = e.into_panic();
+            let message = panic
+                .downcast_ref::<&str>()
+                .map(|s| s.to_string())
+                .or_else(|| panic.downcast_ref::<String>().cloned())
+                .unwrap_or_else(|| "unknown panic".to_owned());
+            format!("panicked: {}", message)
+        }
+        Err(e) => e.to_string(),
+    }
+}
+
+/// Returns the actor restarts so far.
+pub fn summary() -> Summary {
+    restarts().lock().unwrap().clone()
+}
+
+/// Logs the actor restart section of the end-of-run summary.
+pub fn log() {
+    let Summary { counts, crashes, .. } = summary();
+    for (actor, count) in counts {
+        info!(actor, count, "Actor restarts");
+    }
+    for (actor, count) in crashes {
+        info!(actor, count, "Actor crashes");
+    }
+}
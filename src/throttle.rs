@@ -0,0 +1,107 @@
+//! Tracks Nexus pushing back on load, i.e. 429 (Too Many Requests) and 503
+//! (Service Unavailable) responses, separately from other error responses, so
+//! that a run can tell "Nexus pushed back" apart from "Nexus broke".
+//!
+//! Every throttled response an API call gets is counted, including ones that
+//! the retry policy rides out, and so is every one that reaches the harness
+//! after retries run out. The error policy never treats throttling as an
+//! ordinary failure (see `crate::status_policy`); `--throttle-budget` puts a
+//! separate limit on it instead.
+
+use std::collections::BTreeMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use serde::Serialize;
+use tracing::info;
+
+use crate::actor::AntagonistError;
+use crate::collector::Collector;
+
+/// Returns true if `status` means Nexus is pushing back rather than failing.
+pub fn is_throttled(status: http::StatusCode) -> bool {
+    status == http::StatusCode::TOO_MANY_REQUESTS
+        || status == http::StatusCode::SERVICE_UNAVAILABLE
+}
+
+/// Returns true if `err` is a throttled error response.
+pub fn is_throttled_error(err: &AntagonistError) -> bool {
+    matches!(
+        err,
+        AntagonistError::ApiError(oxide::Error::ErrorResponse(rv))
+            if is_throttled(rv.status())
+    )
+}
+
+/// How many throttled responses each endpoint got, by status code.
+type Counts = BTreeMap<&'static str, BTreeMap<u16, u64>>;
+
+static RESPONSES: Collector<Counts> = Collector::new();
+static SURFACED: AtomicU64 = AtomicU64::new(0);
+
+/// Records that a call to `endpoint` got a response with `status`, if that's
+/// a throttled one.
+pub fn record(endpoint: &'static str, status: Option<http::StatusCode>) {
+    let Some(status) = status.filter(|s| is_throttled(*s)) else {
+        return;
+    };
+
+    *RESPONSES
+        .lock()
+        .entry(endpoint)
+        .or_default()
+        .entry(status.as_u16())
+        .or_default() += 1;
+}
+
+/// Records that a throttled response reached the harness after the actor
+/// that got it ran out of retries.
+pub fn record_surfaced() {
+    SURFACED.fetch_add(1, Ordering::Relaxed);
+}
+
+/// How much Nexus pushed back during the run.
+#[derive(Clone, Debug, Serialize)]
+pub struct Summary {
+    /// Every throttled response an API call got, including retried ones.
+    pub responses: u64,
+
+    /// How many throttled responses each endpoint got, keyed by endpoint name
+    /// and then status code.
+    pub by_endpoint: BTreeMap<String, BTreeMap<u16, u64>>,
+
+    /// The throttled responses that reached the harness after retries ran
+    /// out. These are what --throttle-budget limits.
+    pub surfaced: u64,
+}
+
+/// Returns how much Nexus has pushed back so far.
+pub fn summary() -> Summary {
+    let responses = RESPONSES.lock();
+    Summary {
+        responses: responses.values().flat_map(|codes| codes.values()).sum(),
+        by_endpoint: responses
+            .iter()
+            .map(|(endpoint, codes)| (endpoint.to_string(), codes.clone()))
+            .collect(),
+        surfaced: SURFACED.load(Ordering::Relaxed),
+    }
+}
+
+/// Logs the throttling section of the end-of-run summary.
+pub fn log() {
+    let summary = summary();
+    if summary.responses == 0 {
+        return;
+    }
+
+    for (endpoint, codes) in &summary.by_endpoint {
+        for (status, count) in codes {
+            info!(endpoint, status, count, "Throttled responses");
+        }
+    }
+    info!(
+        responses = summary.responses,
+        surfaced = summary.surfaced,
+        "Throttling"
+    );
+}
@@ -1,15 +1,174 @@
-use rand::Rng;
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use std::net::Ipv4Addr;
+use std::sync::OnceLock;
+use std::time::Duration;
 use tracing::trace;
 
-/// Sleeps for [0..max_millis] milliseconds.
-pub async fn sleep_random_ms(max_millis: u64) {
-    let duration = {
-        let mut rng = rand::thread_rng();
-        std::time::Duration::from_millis(rng.gen_range(0..=max_millis))
-    };
+/// The seed from which every actor's random number generator is derived.
+static SEED: OnceLock<u64> = OnceLock::new();
+
+/// Returns this run's random seed: the one passed with `--seed`, or a randomly
+/// chosen one if there wasn't one.
+pub fn seed() -> u64 {
+    *SEED.get_or_init(|| crate::config().seed.unwrap_or_else(rand::random))
+}
+
+/// The prefix applied to the names of every resource this run creates.
+static NAME_PREFIX: OnceLock<String> = OnceLock::new();
+
+/// Returns this run's resource name prefix: the one passed with
+/// `--name-prefix`, or a randomly chosen one if there wasn't one.
+pub fn name_prefix() -> &'static str {
+    NAME_PREFIX.get_or_init(|| {
+        crate::config()
+            .name_prefix
+            .clone()
+            .unwrap_or_else(|| format!("st{:04x}-", rand::random::<u16>()))
+    })
+}
+
+/// Checks that a `--name-prefix` can start an Oxide resource name: it must be
+/// empty or start with a lowercase letter, and contain only lowercase letters,
+/// digits, and dashes.
+pub fn parse_name_prefix(s: &str) -> Result<String, String> {
+    let valid_start = s.chars().next().map_or(true, |c| c.is_ascii_lowercase());
+    let valid_chars = s
+        .chars()
+        .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '-');
+
+    if !valid_start || !valid_chars {
+        return Err(format!(
+            "{:?} must start with a lowercase letter and contain only \
+            lowercase letters, digits, and dashes",
+            s
+        ));
+    }
+
+    if s.len() > 32 {
+        return Err(format!("{:?} is longer than 32 characters", s));
+    }
+
+    Ok(s.to_owned())
+}
+
+/// Parses a `first..last` IPv4 address range from the command line.
+pub fn parse_ip_range(s: &str) -> Result<(Ipv4Addr, Ipv4Addr), String> {
+    let (first, last) = s
+        .split_once("..")
+        .ok_or_else(|| format!("expected <first>..<last>, got {:?}", s))?;
 
+    let first: Ipv4Addr =
+        first.parse().map_err(|e| format!("{:?}: {}", first, e))?;
+    let last: Ipv4Addr =
+        last.parse().map_err(|e| format!("{:?}: {}", last, e))?;
+
+    if first > last {
+        return Err(format!("{} is after {}", first, last));
+    }
+
+    Ok((first, last))
+}
+
+/// Returns a random number generator for the actor named `name`.
+///
+/// The generator's output depends only on the run's seed and the actor's name,
+/// so every actor makes the same sequence of random choices in two runs with
+/// the same seed and actor mix. (The runs can still diverge if the control
+/// plane responds differently.)
+pub fn actor_rng(name: &str) -> StdRng {
+    // Hash the name with FNV-1a, whose output (unlike that of the standard
+    // library's hashers) is guaranteed not to change between releases.
+    let mut hash: u64 = 0xcbf2_9ce4_8422_2325;
+    for b in name.bytes() {
+        hash ^= u64::from(b);
+        hash = hash.wrapping_mul(0x0000_0100_0000_01b3);
+    }
+
+    StdRng::seed_from_u64(seed() ^ hash)
+}
+
+/// Sleeps for `duration`, waking early if the actor's step is cancelled.
+pub async fn nap(duration: Duration) {
     trace!(?duration, "taking a nap");
-    tokio::time::sleep(duration).await;
+    tokio::select! {
+        _ = tokio::time::sleep(duration) => {}
+        _ = crate::middleware::cancelled() => {}
+    }
+}
+
+/// Sleeps for [0..max_millis] milliseconds.
+pub async fn sleep_random_ms(rng: &mut impl Rng, max_millis: u64) {
+    let duration = Duration::from_millis(rng.gen_range(0..=max_millis));
+
+    nap(duration).await;
+}
+
+/// An inclusive range of durations from which an actor picks how long to sleep
+/// between actions.
+///
+/// Parsed from strings of the form `<max>` or `<min>..<max>`, where each bound
+/// is a humantime duration (e.g. `100ms` or `10ms..2s`). A bound without a
+/// unit takes the other bound's unit, so `0..500ms` means zero to 500
+/// milliseconds.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, serde::Deserialize)]
+#[serde(try_from = "String")]
+pub struct SleepRange {
+    pub min: Duration,
+    pub max: Duration,
+}
+
+impl SleepRange {
+    /// Returns the range from zero to `max_millis` milliseconds.
+    pub const fn up_to_ms(max_millis: u64) -> Self {
+        Self { min: Duration::ZERO, max: Duration::from_millis(max_millis) }
+    }
+}
+
+impl std::str::FromStr for SleepRange {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (min, max) = match s.split_once("..") {
+            Some((min, max)) => (min.trim(), max.trim()),
+            None => ("0", s.trim()),
+        };
+
+        let unit_of = |bound: &str| {
+            bound.trim_start_matches(|c: char| c.is_ascii_digit()).to_owned()
+        };
+        let parse = |bound: &str, other: &str| {
+            let bound = if bound.chars().all(|c| c.is_ascii_digit()) {
+                format!("{}{}", bound, unit_of(other))
+            } else {
+                bound.to_owned()
+            };
+            humantime::parse_duration(&bound)
+                .map_err(|e| anyhow::anyhow!("invalid duration {bound:?}: {e}"))
+        };
+
+        let range = Self { min: parse(min, max)?, max: parse(max, min)? };
+        anyhow::ensure!(
+            range.min <= range.max,
+            "sleep range {s:?} has a minimum greater than its maximum"
+        );
+        Ok(range)
+    }
+}
+
+impl TryFrom<String> for SleepRange {
+    type Error = anyhow::Error;
+
+    fn try_from(s: String) -> Result<Self, Self::Error> {
+        s.parse()
+    }
+}
+
+/// Sleeps for a random duration in the supplied range.
+pub async fn sleep_random(rng: &mut impl Rng, range: SleepRange) {
+    let duration = rng.gen_range(range.min..=range.max);
+
+    nap(duration).await;
 }
 
 pub type OxideApiError = oxide::Error<oxide::types::Error>;
@@ -20,63 +179,29 @@ pub fn unwrap_oxide_api_error<T>(
     result.map(|_| ())
 }
 
-/// Given an error response from an Oxide API call, returns:
-///
-/// - `Ok` if the call failed but produced an error response value, irrespective
-///   of the type of error response.
-/// - `Err` if the call failed without producing an error response value, e.g.
-///   because the connection to Nexus was interrupted or because a malformed
-///   response was received.
-pub fn fail_if_no_response<U>(
-    e: oxide::Error<U>,
-) -> core::result::Result<(), oxide::Error<U>>
-where
-    U: std::fmt::Debug + Send + Sync,
-{
-    match e {
-        oxide::Error::ErrorResponse(_) => Ok(()),
-        _ => Err(e),
+/// Treats any error response from an Oxide API call as success. Useful when
+/// other actors may be acting on the same resource, so that a request can fail
+/// because the resource changed state underneath it.
+pub fn ok_if_error_response(
+    result: core::result::Result<(), OxideApiError>,
+) -> core::result::Result<(), OxideApiError> {
+    match result {
+        Err(oxide::Error::ErrorResponse(_)) => Ok(()),
+        result => result,
     }
 }
 
-/// Given an error response from an Oxide API call, returns:
-///
-/// - `Err` if the call failed but produced an error response value, if it is a
-///   500 error.
-///
-/// - `Err` if the call failed without producing an error response value, e.g.
-///   because the connection to Nexus was interrupted or because a malformed
-///   or unexpected response was received.
-///
-/// - `Ok` otherwise
-pub fn fail_if_500<U>(
-    e: oxide::Error<U>,
-) -> core::result::Result<(), oxide::Error<U>>
-where
-    U: std::fmt::Debug + Send + Sync,
-{
-    match &e {
-        oxide::Error::ErrorResponse(r) => match r.status() {
-            // The call returned an error response
-            reqwest::StatusCode::INTERNAL_SERVER_ERROR => Err(e),
-
-            _ => Ok(()),
-        },
-
-        // There was a communication error
-        oxide::Error::CommunicationError(_)
-        // or there was an error reading the response
-        | oxide::Error::ResponseBodyError(_)
-        // or deserialization failed
-        | oxide::Error::InvalidResponsePayload(_, _)
-        // or an unexpected response was received
-        | oxide::Error::UnexpectedResponse(_)
-        // or there was an error upgrading the connection
-        | oxide::Error::InvalidUpgrade(_)
-        // or there was an error processing a request pre-hook
-        | oxide::Error::PreHookError(_) => Err(e),
-
-        // The request was invalid
-        oxide::Error::InvalidRequest(_) => Ok(()),
+/// Treats a "not found" error from an Oxide API call as success. Useful for
+/// deleting resources that may already have been deleted by someone else.
+pub fn ok_if_not_found(
+    result: core::result::Result<(), OxideApiError>,
+) -> core::result::Result<(), OxideApiError> {
+    match result {
+        Err(oxide::Error::ErrorResponse(rv))
+            if rv.status() == http::StatusCode::NOT_FOUND =>
+        {
+            Ok(())
+        }
+        result => result,
     }
 }
@@ -0,0 +1,290 @@
+//! The utilization audit (`--utilization-audit`), which checks the silo's
+//! provisioned vCPUs, memory, and storage against what the resources in the
+//! stress project should account for, to catch provisioning counters that
+//! drift when requests race.
+//!
+//! The silo may hold resources other than the stress project's, so the audit
+//! measures how much is provisioned before the actors start and only expects
+//! the total to change by as much as the project's resources do. Resources in
+//! transitional states (e.g. a starting instance or a creating disk) may or
+//! may not be counted yet, so the audit expects the total to fall between
+//! what the project's settled resources account for and what all of them
+//! would. Anything else provisioning in the same silo during the run shows up
+//! as drift too.
+
+use anyhow::{Context, Result};
+use futures::TryStreamExt;
+use oxide::types::{DiskState, InstanceState, SnapshotState};
+use oxide::{
+    ClientDisksExt, ClientInstancesExt, ClientSilosExt, ClientSnapshotsExt,
+};
+use serde::Serialize;
+use tracing::{info, trace, warn};
+
+use crate::collector::Collector;
+
+/// How many drift reports to keep. Past this, drift is only logged.
+const MAX_KEPT: usize = 100;
+
+/// Provisioned vCPUs, memory, and storage.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize)]
+pub struct Counts {
+    pub cpus: i64,
+    pub memory_bytes: i64,
+    pub storage_bytes: i64,
+}
+
+impl Counts {
+    fn of(counts: &oxide::types::VirtualResourceCounts) -> Self {
+        Self {
+            cpus: counts.cpus,
+            memory_bytes: bytes(*counts.memory),
+            storage_bytes: bytes(*counts.storage),
+        }
+    }
+
+    fn plus(self, other: Self) -> Self {
+        Self {
+            cpus: self.cpus + other.cpus,
+            memory_bytes: self.memory_bytes + other.memory_bytes,
+            storage_bytes: self.storage_bytes + other.storage_bytes,
+        }
+    }
+
+    fn minus(self, other: Self) -> Self {
+        Self {
+            cpus: self.cpus - other.cpus,
+            memory_bytes: self.memory_bytes - other.memory_bytes,
+            storage_bytes: self.storage_bytes - other.storage_bytes,
+        }
+    }
+
+    /// Returns true if each of these counts is between the matching counts
+    /// in `low` and `high`.
+    fn between(&self, low: &Self, high: &Self) -> bool {
+        (low.cpus..=high.cpus).contains(&self.cpus)
+            && (low.memory_bytes..=high.memory_bytes)
+                .contains(&self.memory_bytes)
+            && (low.storage_bytes..=high.storage_bytes)
+                .contains(&self.storage_bytes)
+    }
+}
+
+fn bytes(count: u64) -> i64 {
+    i64::try_from(count).unwrap_or(i64::MAX)
+}
+
+/// A silo utilization that didn't match the stress project's resources.
+#[derive(Clone, Debug, Serialize)]
+pub struct Drift {
+    /// When the drift was seen, in RFC 3339 format.
+    pub time: String,
+
+    /// True if the drift was seen after the actors halted, when nothing
+    /// should have been changing.
+    pub after_halt: bool,
+    pub provisioned: Counts,
+    pub expected_min: Counts,
+    pub expected_max: Counts,
+}
+
+static DRIFTS: Collector<Vec<Drift>> = Collector::new();
+
+/// Returns the range of provisioned counts that the resources in `project`
+/// could account for: the low end counts only settled resources, and the
+/// high end counts every resource that might be provisioned.
+async fn tally(
+    client: &oxide::Client,
+    project: &str,
+) -> Result<(Counts, Counts)> {
+    let mut low = Counts::default();
+    let mut high = Counts::default();
+
+    let instances: Vec<_> = client
+        .instance_list()
+        .project(project)
+        .stream()
+        .try_collect()
+        .await
+        .context("listing instances")?;
+
+    for instance in instances {
+        let counts = Counts {
+            cpus: i64::from(instance.ncpus.0),
+            memory_bytes: bytes(*instance.memory),
+            storage_bytes: 0,
+        };
+
+        match instance.run_state {
+            InstanceState::Running
+            | InstanceState::Rebooting
+            | InstanceState::Migrating => {
+                low = low.plus(counts);
+                high = high.plus(counts);
+            }
+            InstanceState::Stopped | InstanceState::Destroyed => {}
+            _ => high = high.plus(counts),
+        }
+    }
+
+    let disks: Vec<_> = client
+        .disk_list()
+        .project(project)
+        .stream()
+        .try_collect()
+        .await
+        .context("listing disks")?;
+
+    for disk in disks {
+        let counts =
+            Counts { storage_bytes: bytes(*disk.size), ..Default::default() };
+        match disk.state {
+            DiskState::Detached | DiskState::Attached { .. } => {
+                low = low.plus(counts);
+                high = high.plus(counts);
+            }
+            DiskState::Destroyed => {}
+            _ => high = high.plus(counts),
+        }
+    }
+
+    let snapshots: Vec<_> = client
+        .snapshot_list()
+        .project(project)
+        .stream()
+        .try_collect()
+        .await
+        .context("listing snapshots")?;
+
+    for snapshot in snapshots {
+        let counts = Counts {
+            storage_bytes: bytes(*snapshot.size),
+            ..Default::default()
+        };
+        match snapshot.state {
+            SnapshotState::Ready => {
+                low = low.plus(counts);
+                high = high.plus(counts);
+            }
+            SnapshotState::Destroyed => {}
+            _ => high = high.plus(counts),
+        }
+    }
+
+    Ok((low, high))
+}
+
+async fn provisioned(client: &oxide::Client) -> Result<Counts> {
+    let utilization = client
+        .utilization_view()
+        .send()
+        .await
+        .context("fetching silo utilization")?;
+    Ok(Counts::of(&utilization.provisioned))
+}
+
+/// An audit of the silo's utilization against the stress project.
+pub struct Audit {
+    /// What the silo had provisioned that the stress project's resources
+    /// don't account for when the audit started.
+    offset: Counts,
+}
+
+impl Audit {
+    /// Starts an audit of `project`. Should be called while the project's
+    /// resources are settled, i.e. before the actors start.
+    pub async fn start(client: &oxide::Client, project: &str) -> Result<Self> {
+        let provisioned = provisioned(client).await?;
+        let (low, high) = tally(client, project).await?;
+        if low != high {
+            warn!(
+                ?low,
+                ?high,
+                "stress project has unsettled resources, utilization audit \
+                may report drift that isn't there"
+            );
+        }
+
+        let offset = provisioned.minus(low);
+        info!(?provisioned, ?offset, "Starting utilization audit");
+        Ok(Self { offset })
+    }
+
+    /// Checks the silo's utilization against the resources in `project`,
+    /// recording any drift. `after_halt` says whether the actors have
+    /// halted. Errors are logged rather than returned, since they don't say
+    /// anything about the control plane's accounting.
+    pub async fn check(
+        &self,
+        client: &oxide::Client,
+        project: &str,
+        after_halt: bool,
+    ) {
+        if let Err(e) = self.try_check(client, project, after_halt).await {
+            warn!("Utilization audit failed: {:#}", e);
+        }
+    }
+
+    async fn try_check(
+        &self,
+        client: &oxide::Client,
+        project: &str,
+        after_halt: bool,
+    ) -> Result<()> {
+        // The listing and the utilization can't be fetched atomically, so
+        // fetch the utilization on both sides of the listing, and skip the
+        // check if it changed in between.
+        let before = provisioned(client).await?;
+        let (low, high) = tally(client, project).await?;
+        let after = provisioned(client).await?;
+        if before != after {
+            trace!(?before, ?after, "utilization changed during audit");
+            return Ok(());
+        }
+
+        let expected_min = self.offset.plus(low);
+        let expected_max = self.offset.plus(high);
+        if after.between(&expected_min, &expected_max) {
+            trace!(provisioned = ?after, "utilization matches");
+            return Ok(());
+        }
+
+        warn!(
+            provisioned = ?after,
+            ?expected_min,
+            ?expected_max,
+            after_halt,
+            "Silo utilization drifted from stress project resources"
+        );
+
+        let mut drifts = DRIFTS.lock();
+        if drifts.len() < MAX_KEPT {
+            drifts.push(Drift {
+                time: chrono::Utc::now().to_rfc3339(),
+                after_halt,
+                provisioned: after,
+                expected_min,
+                expected_max,
+            });
+        }
+
+        Ok(())
+    }
+}
+
+/// Returns the drift seen so far, oldest first.
+pub fn summary() -> Vec<Drift> {
+    DRIFTS.snapshot()
+}
+
+/// Logs the utilization section of the end-of-run summary.
+pub fn log() {
+    let drifts = summary();
+    if !drifts.is_empty() {
+        info!(
+            count = drifts.len(),
+            after_halt = drifts.iter().any(|d| d.after_halt),
+            "Utilization drift"
+        );
+    }
+}
@@ -0,0 +1,808 @@
+//! Describes the mix of actors a stress run creates.
+//!
+//! By default the mix is derived from the command-line options, which can only
+//! express "N resources of each kind with M antagonists apiece." A scenario
+//! file (passed with `--scenario`) instead lists groups of actors one by one,
+//! each with its own counts, names, action weights, and think times. For
+//! example:
+//!
+//! ```toml
+//! # Two instances, each hammered by eight antagonists that mostly stop them
+//! # whenever they're running.
+//! [[actors]]
+//! kind = "instance"
+//! count = 2
+//! threads = 8
+//! think_time = "0..20ms"
+//!
+//! [actors.weights.running]
+//! wait = 10
+//! stop = 80
+//! destroy = 10
+//!
+//! # One antagonist that snapshots whichever disks the disk antagonists last
+//! # saw detached.
+//! [[actors]]
+//! kind = "snapshot"
+//! disk = "discover"
+//!
+//! [[actors]]
+//! kind = "disk"
+//! count = 4
+//!
+//! [[actors]]
+//! kind = "janitor"
+//! interval = "5m"
+//! ```
+//!
+//! Resource `i` in a group is named `{name}{i}`, where `name` defaults to the
+//! name the command-line options would use for that kind of resource. Groups
+//! that don't list their own `weights` or `think_time` take them from the
+//! workload's profile, which can be set with a top-level `profile` key (e.g.
+//! `profile = "churn"`) or with `--profile`.
+//!
+//! Instead of a single set of actors, a scenario file can list phases, each
+//! with its own actors and (optionally) profile. At the end of each phase the
+//! harness halts the phase's actors and starts the next phase's. A phase with
+//! no actors idles. Every phase but the last needs a `duration`; the run ends
+//! when the last phase's duration elapses, or runs on if it has none:
+//!
+//! ```toml
+//! [[phases]]
+//! name = "fill"
+//! duration = "10m"
+//! profile = "create-heavy"
+//! actors = [{ kind = "instance", count = 8, threads = 2 }]
+//!
+//! [[phases]]
+//! name = "drain"
+//! duration = "10m"
+//! profile = "delete-heavy"
+//! actors = [{ kind = "instance", count = 8, threads = 2 }]
+//!
+//! [[phases]]
+//! name = "idle"
+//! duration = "5m"
+//! ```
+
+use anyhow::Context;
+use serde::Deserialize;
+use std::path::Path;
+use std::time::Duration;
+
+use crate::actor::{
+    backing_disk, boundary, chain, conflict, disk, fuzz, instance, janitor,
+    reachability, scenario, snapshot, snapshot_gc, ActorKind,
+};
+use crate::config::Config;
+use crate::profile::Profile;
+use crate::util::SleepRange;
+
+/// The full set of actors to create for a stress run.
+#[derive(Clone, Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Workload {
+    /// The profile that supplies weights and think times for groups that
+    /// don't specify their own. If not set, the profile chosen on the command
+    /// line is used.
+    pub profile: Option<Profile>,
+
+    #[serde(default)]
+    pub actors: Vec<ActorGroup>,
+
+    /// The phases of a multi-phase workload. If there are any, `actors` must
+    /// be empty.
+    #[serde(default)]
+    pub phases: Vec<Phase>,
+}
+
+/// One phase of a workload.
+#[derive(Clone, Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Phase {
+    pub name: Option<String>,
+
+    /// How long this phase lasts. If not set, the phase lasts until the run
+    /// ends.
+    #[serde(default, with = "humantime_serde")]
+    pub duration: Option<Duration>,
+
+    /// The profile for this phase's groups. If not set, the workload's
+    /// profile is used.
+    pub profile: Option<Profile>,
+
+    #[serde(default)]
+    pub actors: Vec<ActorGroup>,
+}
+
+/// How a group of snapshot antagonists chooses the disks they snapshot.
+#[derive(Clone, Copy, Debug, Default, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum SnapshotDisk {
+    /// Each antagonist snapshots its own disk, named
+    /// `{disk_name}{i}{thread}`.
+    #[default]
+    PerThread,
+
+    /// All the antagonists for snapshot `i` snapshot the disk named
+    /// `{disk_name}{i}`.
+    Shared,
+
+    /// Antagonists snapshot whichever disks disk antagonists last saw
+    /// detached.
+    Discover,
+
+    /// All the antagonists for snapshot `i` snapshot the disk named
+    /// `{disk_name}{i}` that disk antagonists are creating and deleting,
+    /// whatever state it's in, to race snapshots against disk deletes on
+    /// purpose. `disk_name` should name a disk group with at least as many
+    /// disks as there are snapshots.
+    Attack,
+}
+
+/// A group of actors of a single kind.
+#[derive(Clone, Debug, Deserialize)]
+#[serde(tag = "kind", rename_all = "kebab-case", deny_unknown_fields)]
+pub enum ActorGroup {
+    /// `count` sets of `per_actor` instances, each set with `threads` instance
+    /// antagonists that pick one of its instances at random for each step.
+    /// Set `i`'s instances are numbered from `i * per_actor`.
+    Instance {
+        name: Option<String>,
+        #[serde(default = "one")]
+        count: usize,
+        #[serde(default = "one")]
+        threads: usize,
+        #[serde(default = "one")]
+        per_actor: usize,
+        #[serde(default)]
+        storm: bool,
+        weights: Option<instance::Weights>,
+        #[serde(default)]
+        networking: instance::Networking,
+        think_time: Option<SleepRange>,
+    },
+
+    /// `count` disks, each with `threads` disk antagonists.
+    Disk {
+        name: Option<String>,
+        #[serde(default = "one")]
+        count: usize,
+        #[serde(default = "one")]
+        threads: usize,
+        weights: Option<disk::Weights>,
+        think_time: Option<SleepRange>,
+    },
+
+    /// `count` snapshot names, each with `threads` snapshot antagonists.
+    Snapshot {
+        name: Option<String>,
+        #[serde(default = "one")]
+        count: usize,
+        #[serde(default = "one")]
+        threads: usize,
+        #[serde(default)]
+        disk: SnapshotDisk,
+        disk_name: Option<String>,
+        weights: Option<snapshot::Weights>,
+        think_time: Option<SleepRange>,
+    },
+
+    /// `count` composite scenario antagonists.
+    Scenario {
+        name: Option<String>,
+        #[serde(default = "one")]
+        count: usize,
+    },
+
+    /// `count` derivation chain antagonists.
+    Chain {
+        name: Option<String>,
+        #[serde(default = "one")]
+        count: usize,
+    },
+
+    /// `count` conflict checkers.
+    Conflict {
+        name: Option<String>,
+        #[serde(default = "one")]
+        count: usize,
+    },
+
+    /// `count` fuzzers.
+    Fuzz {
+        name: Option<String>,
+        #[serde(default = "one")]
+        count: usize,
+    },
+
+    /// `count` boundary probers.
+    Boundary {
+        name: Option<String>,
+        #[serde(default = "one")]
+        count: usize,
+    },
+
+    /// Reachability probes for the first `count` instances named
+    /// `{target}{i}`.
+    Reachability {
+        target: Option<String>,
+        #[serde(default = "one")]
+        count: usize,
+        #[serde(default = "default_probe_port")]
+        port: u16,
+        #[serde(
+            default = "default_probe_grace_period",
+            with = "humantime_serde"
+        )]
+        grace_period: Duration,
+    },
+
+    /// A snapshot garbage collector.
+    SnapshotGc {
+        #[serde(default = "default_gc_interval", with = "humantime_serde")]
+        interval: Duration,
+        #[serde(default, with = "humantime_serde")]
+        max_age: Option<Duration>,
+        max_count: Option<usize>,
+    },
+
+    /// A janitor.
+    Janitor {
+        #[serde(with = "humantime_serde")]
+        interval: Duration,
+    },
+}
+
+fn one() -> usize {
+    1
+}
+
+fn default_probe_port() -> u16 {
+    22
+}
+
+fn default_probe_grace_period() -> Duration {
+    Duration::from_secs(60)
+}
+
+fn default_gc_interval() -> Duration {
+    Duration::from_secs(60)
+}
+
+/// The longest a resource name may be.
+const MAX_NAME_LEN: usize = 63;
+
+/// How much room to leave at the end of each resource name for what actors
+/// append to it: a generation or snapshot counter, or a `-nicN` or `-diskN`
+/// suffix for what an instance is created with.
+const NAME_SUFFIX_ROOM: usize = 8;
+
+impl Workload {
+    /// Returns an error if the name of any resource an actor would manage,
+    /// starting with `prefix`, could grow too long for Nexus once the actor
+    /// appends a suffix to it. Checking up front keeps the actors from
+    /// failing to build names partway through the run.
+    pub fn check_names(&self, prefix: &str) -> anyhow::Result<()> {
+        for (i, phase) in self.clone().into_phases().iter().enumerate() {
+            for (actor, kind) in phase.actors("", prefix, Profile::default()) {
+                for resource in kind.owned_resources() {
+                    anyhow::ensure!(
+                        resource.name.len() + NAME_SUFFIX_ROOM <= MAX_NAME_LEN,
+                        "phase {}: actor {}'s {} name {:?} is too long: \
+                        names may have {} characters, and this leaves fewer \
+                        than {} for suffixes (try a shorter --name-prefix \
+                        or group name)",
+                        i,
+                        actor,
+                        resource.kind.name(),
+                        resource.name,
+                        MAX_NAME_LEN,
+                        NAME_SUFFIX_ROOM,
+                    );
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Reads a workload from the scenario file at `path`.
+    pub fn from_file(path: &Path) -> anyhow::Result<Self> {
+        let contents = std::fs::read_to_string(path)
+            .with_context(|| format!("reading {}", path.display()))?;
+        let workload: Self = toml::from_str(&contents)
+            .with_context(|| format!("parsing {}", path.display()))?;
+        workload.validate()?;
+        Ok(workload)
+    }
+
+    /// Reads a workload from an already-parsed TOML table.
+    pub fn from_table(table: toml::Table) -> anyhow::Result<Self> {
+        let workload: Self = toml::Value::Table(table).try_into()?;
+        workload.validate()?;
+        Ok(workload)
+    }
+
+    /// Builds the workload described by the command-line options.
+    pub fn from_config(config: &Config) -> Self {
+        let mut actors = vec![ActorGroup::Instance {
+            name: None,
+            count: config.num_test_instances,
+            threads: config.threads_per_instance,
+            per_actor: config.instances_per_actor,
+            storm: false,
+            weights: None,
+            networking: config.instance_networking(),
+            think_time: config.instance_think_time,
+        }];
+
+        if config.storm_threads_per_instance > 0 {
+            actors.push(ActorGroup::Instance {
+                name: None,
+                count: config.num_test_instances,
+                threads: config.storm_threads_per_instance,
+                per_actor: config.instances_per_actor,
+                storm: true,
+                weights: None,
+                networking: config.instance_networking(),
+                think_time: None,
+            });
+        }
+
+        if config.probe_instances {
+            actors.push(ActorGroup::Reachability {
+                target: None,
+                count: config.num_test_instances,
+                port: config.probe_port,
+                grace_period: config.probe_grace_period,
+            });
+        }
+
+        actors.push(ActorGroup::Disk {
+            name: None,
+            count: config.num_test_disks,
+            threads: config.threads_per_disk,
+            weights: None,
+            think_time: config.disk_think_time,
+        });
+
+        actors.push(ActorGroup::Snapshot {
+            name: None,
+            count: config.num_test_snapshots,
+            threads: config.threads_per_snapshot,
+            disk: if config.snapshots_attack_disks {
+                SnapshotDisk::Attack
+            } else if config.contention_mode
+                && config.num_test_disks > 0
+                && config.actor_kind_enabled("disk")
+            {
+                // Snapshot the disks the disk antagonists are busy creating
+                // and deleting.
+                SnapshotDisk::Discover
+            } else if config.snapshots_use_same_disk {
+                SnapshotDisk::Shared
+            } else {
+                SnapshotDisk::PerThread
+            },
+            disk_name: None,
+            weights: None,
+            think_time: config.snapshot_think_time,
+        });
+
+        actors.push(ActorGroup::Scenario {
+            name: None,
+            count: config.num_scenarios,
+        });
+        actors.push(ActorGroup::Chain { name: None, count: config.num_chains });
+        actors.push(ActorGroup::Conflict {
+            name: None,
+            count: config.num_conflict_checkers,
+        });
+        actors.push(ActorGroup::Fuzz { name: None, count: config.num_fuzzers });
+        actors.push(ActorGroup::Boundary {
+            name: None,
+            count: config.num_boundary_probers,
+        });
+
+        if config.snapshot_gc_max_age.is_some()
+            || config.snapshot_gc_max_count.is_some()
+        {
+            actors.push(ActorGroup::SnapshotGc {
+                interval: config.snapshot_gc_interval,
+                max_age: config.snapshot_gc_max_age,
+                max_count: config.snapshot_gc_max_count,
+            });
+        }
+
+        if let Some(interval) = config.janitor_interval {
+            actors.push(ActorGroup::Janitor { interval });
+        }
+
+        Self { profile: None, actors, phases: Vec::new() }
+    }
+
+    /// Returns an error if this workload can't be used to create actors.
+    pub fn validate(&self) -> anyhow::Result<()> {
+        validate_groups(&self.actors)?;
+        if self.phases.is_empty() {
+            return Ok(());
+        }
+
+        anyhow::ensure!(
+            self.actors.is_empty(),
+            "a workload with phases can't have top-level actors"
+        );
+
+        for (i, phase) in self.phases.iter().enumerate() {
+            let last = i == self.phases.len() - 1;
+            anyhow::ensure!(
+                last || phase.duration.is_some(),
+                "phase {} needs a duration, since it isn't the last phase",
+                i
+            );
+            validate_groups(&phase.actors)
+                .with_context(|| format!("phase {}", i))?;
+        }
+
+        Ok(())
+    }
+
+    /// Returns this workload's phases, in order. A workload without phases
+    /// has a single phase containing all its actors that lasts until the run
+    /// ends.
+    pub fn into_phases(self) -> Vec<Phase> {
+        if self.phases.is_empty() {
+            return vec![Phase {
+                name: None,
+                duration: None,
+                profile: self.profile,
+                actors: self.actors,
+            }];
+        }
+
+        let profile = self.profile;
+        self.phases
+            .into_iter()
+            .map(|phase| Phase { profile: phase.profile.or(profile), ..phase })
+            .collect()
+    }
+}
+
+/// Returns an error if any group's settings can't be used to create actors.
+fn validate_groups(groups: &[ActorGroup]) -> anyhow::Result<()> {
+    for (i, group) in groups.iter().enumerate() {
+        let res = match group {
+            ActorGroup::Instance { weights, networking, per_actor, .. } => {
+                anyhow::ensure!(
+                    *per_actor > 0,
+                    "actor group {}: per_actor must be at least 1",
+                    i
+                );
+                networking
+                    .validate()
+                    .and_then(|()| weights.map_or(Ok(()), |w| w.validate()))
+            }
+            ActorGroup::Disk { weights: Some(weights), .. } => {
+                weights.validate()
+            }
+            ActorGroup::Snapshot { weights: Some(weights), .. } => {
+                weights.validate()
+            }
+            ActorGroup::Reachability { target, .. } => {
+                let target = target.as_deref().unwrap_or("inst");
+                let probeable = groups.iter().any(|group| {
+                    matches!(
+                        group,
+                        ActorGroup::Instance { name, networking, .. }
+                            if name.as_deref().unwrap_or("inst") == target
+                                && networking.external_ip > 0.0
+                    )
+                });
+                if probeable {
+                    Ok(())
+                } else {
+                    Err(anyhow::anyhow!(
+                        "no instance group named {:?} asks for external IPs, \
+                        so there's nothing to probe (set the group's \
+                        networking.external_ip, or pass \
+                        --instance-external-ip-fraction)",
+                        target
+                    ))
+                }
+            }
+            ActorGroup::SnapshotGc { max_age, max_count, .. } => {
+                if max_age.is_none() && max_count.is_none() {
+                    Err(anyhow::anyhow!(
+                        "snapshot-gc needs a max_age or max_count"
+                    ))
+                } else {
+                    Ok(())
+                }
+            }
+            ActorGroup::Disk { weights: None, .. }
+            | ActorGroup::Snapshot { weights: None, .. }
+            | ActorGroup::Scenario { .. }
+            | ActorGroup::Chain { .. }
+            | ActorGroup::Conflict { .. }
+            | ActorGroup::Fuzz { .. }
+            | ActorGroup::Boundary { .. }
+            | ActorGroup::Janitor { .. } => Ok(()),
+        };
+
+        res.with_context(|| format!("actor group {}", i))?;
+    }
+
+    Ok(())
+}
+
+impl Phase {
+    /// Returns the name and kind of every actor in this phase, with all
+    /// resources created in `project` and their names starting with `prefix`.
+    /// Groups that don't specify their own weights or think times get them
+    /// from this phase's profile, or from `default_profile` if it doesn't have
+    /// one.
+    pub fn actors(
+        &self,
+        project: &str,
+        prefix: &str,
+        default_profile: Profile,
+    ) -> Vec<(String, ActorKind)> {
+        let profile = self.profile.unwrap_or(default_profile);
+        let mut actors = Vec::new();
+        for group in &self.actors {
+            group.expand(project, prefix, profile, &mut actors);
+        }
+
+        actors
+    }
+}
+
+impl ActorGroup {
+    /// Appends the name and kind of every actor in this group to `actors`.
+    /// Actor names are the same from run to run, so that actors' random
+    /// choices depend only on the seed; only the names of the resources they
+    /// manage get `prefix`.
+    fn expand(
+        &self,
+        project: &str,
+        prefix: &str,
+        profile: Profile,
+        actors: &mut Vec<(String, ActorKind)>,
+    ) {
+        let project = project.to_owned();
+        match self {
+            ActorGroup::Instance {
+                name,
+                count,
+                threads,
+                per_actor,
+                storm,
+                weights,
+                networking,
+                think_time,
+            } => {
+                let name = name.as_deref().unwrap_or("inst");
+                let weights =
+                    weights.unwrap_or_else(|| profile.instance_weights());
+                let think_time = think_time.unwrap_or(profile.think_time());
+                let suffix = if *storm { "storm" } else { "" };
+                for inst in 0..*count {
+                    for actor_index in 0..*threads {
+                        actors.push((
+                            format!(
+                                "{}{}_{}{}",
+                                name, inst, suffix, actor_index
+                            ),
+                            ActorKind::Instance(instance::Params {
+                                project: project.clone(),
+                                instance_names: (inst * per_actor
+                                    ..(inst + 1) * per_actor)
+                                    .map(|i| format!("{}{}{}", prefix, name, i))
+                                    .collect(),
+                                storm: *storm,
+                                weights,
+                                networking: *networking,
+                                think_time,
+                            }),
+                        ));
+                    }
+                }
+            }
+
+            ActorGroup::Disk { name, count, threads, weights, think_time } => {
+                let name = name.as_deref().unwrap_or("disk");
+                let weights = weights.unwrap_or_else(|| profile.disk_weights());
+                let think_time = think_time.unwrap_or(profile.think_time());
+                for disk in 0..*count {
+                    for actor_index in 0..*threads {
+                        actors.push((
+                            format!("{}{}_{}", name, disk, actor_index),
+                            ActorKind::Disk(disk::Params {
+                                project: project.clone(),
+                                disk_name: format!(
+                                    "{}{}{}",
+                                    prefix, name, disk
+                                ),
+                                weights,
+                                think_time,
+                            }),
+                        ));
+                    }
+                }
+            }
+
+            ActorGroup::Snapshot {
+                name,
+                count,
+                threads,
+                disk,
+                disk_name,
+                weights,
+                think_time,
+            } => {
+                let name = name.as_deref().unwrap_or("snapshot");
+                let disk_name = disk_name.as_deref().unwrap_or("disk");
+                let weights =
+                    weights.unwrap_or_else(|| profile.snapshot_weights());
+                let think_time = think_time.unwrap_or(profile.think_time());
+                for snapshot in 0..*count {
+                    for actor_index in 0..*threads {
+                        let disk = match disk {
+                            SnapshotDisk::PerThread => {
+                                snapshot::SourceDisk::Named(backing_disk::name(
+                                    prefix,
+                                    disk_name,
+                                    snapshot,
+                                    Some(actor_index),
+                                ))
+                            }
+                            SnapshotDisk::Shared => {
+                                snapshot::SourceDisk::Named(backing_disk::name(
+                                    prefix, disk_name, snapshot, None,
+                                ))
+                            }
+                            SnapshotDisk::Attack => {
+                                snapshot::SourceDisk::Attacked(
+                                    backing_disk::name(
+                                        prefix, disk_name, snapshot, None,
+                                    ),
+                                )
+                            }
+                            SnapshotDisk::Discover => {
+                                snapshot::SourceDisk::Discovered
+                            }
+                        };
+
+                        actors.push((
+                            format!("{}{}_{}", name, snapshot, actor_index),
+                            ActorKind::Snapshot(snapshot::Params {
+                                project: project.clone(),
+                                disk,
+                                snapshot_name: format!(
+                                    "{}{}{}",
+                                    prefix, name, snapshot
+                                ),
+                                weights,
+                                think_time,
+                            }),
+                        ));
+                    }
+                }
+            }
+
+            ActorGroup::Scenario { name, count } => {
+                let name = name.as_deref().unwrap_or("scn");
+                for scenario in 0..*count {
+                    let scenario_name = format!("{}{}", name, scenario);
+                    actors.push((
+                        scenario_name.clone(),
+                        ActorKind::Scenario(scenario::Params {
+                            project: project.clone(),
+                            scenario_name: format!(
+                                "{}{}",
+                                prefix, scenario_name
+                            ),
+                        }),
+                    ));
+                }
+            }
+
+            ActorGroup::Chain { name, count } => {
+                let name = name.as_deref().unwrap_or("chain");
+                for chain in 0..*count {
+                    let chain_name = format!("{}{}", name, chain);
+                    actors.push((
+                        chain_name.clone(),
+                        ActorKind::Chain(chain::Params {
+                            project: project.clone(),
+                            chain_name: format!("{}{}", prefix, chain_name),
+                        }),
+                    ));
+                }
+            }
+
+            ActorGroup::Conflict { name, count } => {
+                let name = name.as_deref().unwrap_or("conflict");
+                for checker in 0..*count {
+                    let checker_name = format!("{}{}", name, checker);
+                    actors.push((
+                        checker_name.clone(),
+                        ActorKind::Conflict(conflict::Params {
+                            project: project.clone(),
+                            disk_name: format!("{}{}", prefix, checker_name),
+                        }),
+                    ));
+                }
+            }
+
+            ActorGroup::Fuzz { name, count } => {
+                let name = name.as_deref().unwrap_or("fuzz");
+                for fuzzer in 0..*count {
+                    let fuzzer_name = format!("{}{}", name, fuzzer);
+                    actors.push((
+                        fuzzer_name.clone(),
+                        ActorKind::Fuzz(fuzz::Params {
+                            project: project.clone(),
+                            resource_name: format!("{}{}", prefix, fuzzer_name),
+                        }),
+                    ));
+                }
+            }
+
+            ActorGroup::Boundary { name, count } => {
+                let name = name.as_deref().unwrap_or("boundary");
+                for prober in 0..*count {
+                    let prober_name = format!("{}{}", name, prober);
+                    actors.push((
+                        prober_name.clone(),
+                        ActorKind::Boundary(boundary::Params {
+                            project: project.clone(),
+                            resource_name: format!("{}{}", prefix, prober_name),
+                        }),
+                    ));
+                }
+            }
+
+            ActorGroup::Reachability { target, count, port, grace_period } => {
+                let target = target.as_deref().unwrap_or("inst");
+                for inst in 0..*count {
+                    actors.push((
+                        format!("{}{}_probe", target, inst),
+                        ActorKind::Reachability(reachability::Params {
+                            project: project.clone(),
+                            instance_name: format!(
+                                "{}{}{}",
+                                prefix, target, inst
+                            ),
+                            port: *port,
+                            grace_period: *grace_period,
+                        }),
+                    ));
+                }
+            }
+
+            ActorGroup::SnapshotGc { interval, max_age, max_count } => {
+                actors.push((
+                    "snapshot_gc".to_string(),
+                    ActorKind::SnapshotGc(snapshot_gc::Params {
+                        project,
+                        interval: *interval,
+                        max_age: *max_age,
+                        max_count: *max_count,
+                    }),
+                ));
+            }
+
+            ActorGroup::Janitor { interval } => {
+                actors.push((
+                    "janitor".to_string(),
+                    ActorKind::Janitor(janitor::Params {
+                        project,
+                        interval: *interval,
+                    }),
+                ));
+            }
+        }
+    }
+}
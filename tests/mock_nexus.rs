@@ -0,0 +1,135 @@
+//! Runs the harness end to end against its embedded mock Nexus
+//! (`--mock-nexus`), to check the harness's own behavior without a rack: that
+//! the actors make progress and shut down, that the error policy decides how
+//! the run ends, and that the report says so.
+
+use std::process::Command;
+
+use serde_json::Value;
+
+/// How a run against the mock ended.
+struct Run {
+    exit_code: Option<i32>,
+    report: Value,
+    stderr: String,
+}
+
+/// Runs the harness against the mock for a few seconds with `args` on top of
+/// the defaults, and returns how it ended. `name` keeps concurrent runs'
+/// reports apart.
+fn run(name: &str, args: &[&str]) -> Run {
+    let report = std::env::temp_dir().join(format!(
+        "omicron-stress-{}-{}.json",
+        name,
+        std::process::id()
+    ));
+    let output = Command::new(env!("CARGO_BIN_EXE_omicron-stress"))
+        .args(["--mock-nexus", "--duration", "3s", "--seed", "1"])
+        .args(["--instance-think-time", "10ms"])
+        .args(["--disk-think-time", "10ms"])
+        .args(["--snapshot-think-time", "10ms"])
+        .arg("--report-json")
+        .arg(&report)
+        .args(args)
+        .env_remove("OXIDE_HOST")
+        .env_remove("OXIDE_TOKEN")
+        .output()
+        .expect("running omicron-stress");
+
+    let stderr = String::from_utf8_lossy(&output.stderr).into_owned();
+    let json = std::fs::read(&report).unwrap_or_else(|e| {
+        panic!("reading report {}: {}\n{}", report.display(), e, stderr)
+    });
+    let _ = std::fs::remove_file(&report);
+    Run {
+        exit_code: output.status.code(),
+        report: serde_json::from_slice(&json).expect("parsing report"),
+        stderr,
+    }
+}
+
+#[test]
+fn clean_run_completes() {
+    let run = run("clean", &[]);
+    assert_eq!(run.exit_code, Some(0), "{}", run.stderr);
+    assert_eq!(run.report["outcome"], "completed");
+    assert_eq!(run.report["failure"], Value::Null);
+    for kind in ["instance", "disk", "snapshot"] {
+        let steps = run.report["actors"][kind]["steps"].as_u64();
+        assert!(steps > Some(0), "{} actors took no steps", kind);
+    }
+    assert_eq!(run.report["leaked_resources"], serde_json::json!([]));
+}
+
+#[test]
+fn error_responses_are_expected_by_default() {
+    let run = run("expected", &["--mock-error-rate", "0.5"]);
+    assert_eq!(run.exit_code, Some(0), "{}", run.stderr);
+    assert_eq!(run.report["errors"], serde_json::json!([]));
+    let counts = &run.report["status_codes"];
+    assert!(
+        counts
+            .as_object()
+            .is_some_and(|c| c.values().any(|s| s["500"].as_u64() > Some(0))),
+        "no 500s counted: {}",
+        counts
+    );
+}
+
+#[test]
+fn fatal_status_fails_the_run() {
+    let run =
+        run("fatal", &["--mock-error-rate", "1", "--fatal-status", "5xx"]);
+    assert_eq!(run.exit_code, Some(2), "{}", run.stderr);
+    assert_eq!(run.report["outcome"], "failed");
+    assert_eq!(run.report["failure"], "server_error");
+
+    // The error carries its actor's context.
+    let error = &run.report["errors"][0];
+    assert_eq!(error["context"]["status"], 500);
+    assert!(error["context"]["actor"].is_string());
+}
+
+#[test]
+fn throttling_past_its_budget_fails_the_run() {
+    let run = run(
+        "throttled",
+        &[
+            "--mock-error-rate",
+            "1",
+            "--mock-error-status",
+            "503",
+            "--throttle-budget",
+            "0",
+        ],
+    );
+    assert_eq!(run.exit_code, Some(6), "{}", run.stderr);
+    assert_eq!(run.report["failure"], "throttled");
+    assert!(run.report["throttling"]["surfaced"].as_u64() > Some(0));
+}
+
+#[test]
+fn drained_run_leaves_nothing_behind() {
+    let run = run("drained", &["--drain-on-exit"]);
+    assert_eq!(run.exit_code, Some(0), "{}", run.stderr);
+    assert_eq!(run.report["leaked_resources"], serde_json::json!([]));
+}
+
+#[test]
+fn smoke_passes() {
+    // --smoke runs one of each actor, including the fuzzers and boundary
+    // probers, under a strict error policy with the model check on, so this
+    // only passes if the mock accepts and rejects creates the way Nexus does.
+    let run = run("smoke", &["--smoke", "--duration", "5s"]);
+    assert_eq!(run.exit_code, Some(0), "{}", run.stderr);
+    assert_eq!(run.report["outcome"], "completed");
+    assert_eq!(run.report["errors"], serde_json::json!([]));
+}
+
+#[test]
+fn cleanup_on_exit_deletes_the_project() {
+    // Nexus won't delete a project that still has a VPC, so this only passes
+    // if cleanup deletes the project's default VPC and its subnet first.
+    let run = run("cleanup", &["--cleanup-on-exit"]);
+    assert_eq!(run.exit_code, Some(0), "{}", run.stderr);
+}